@@ -397,6 +397,9 @@ pub const GPE_VXLAN_FLAGS_INSTANCE_MASK: u8 = 0x8;
 pub const GPE_VXLAN_FLAGS_PROTOCOL_MASK: u8 = 0x4;
 pub const GPE_VXLAN_FLAGS_VERSION_MASK: u8 = 0x30;
 
+pub const GPE_VXLAN_NEXT_PROTOCOL_IPV4: u8 = 1;
+pub const GPE_VXLAN_NEXT_PROTOCOL_IPV6: u8 = 2;
+pub const GPE_VXLAN_NEXT_PROTOCOL_ETHERNET: u8 = 3;
 pub const GPE_VXLAN_NEXT_PROTOCOL_NSH: u8 = 4;
 
 pub const GPE_VXLAN_FLAGS_OFFSET: usize = 0;
@@ -428,6 +431,8 @@ pub const NSH_FLAGS_BITS_MASK: u16 = 0xf000;
 pub const NSH_FLAGS_LENGTH_MASK: u16 = 0x3f;
 
 pub const NSH_NEXT_PROTOCOL_IPV4: u8 = 1;
+pub const NSH_NEXT_PROTOCOL_IPV6: u8 = 2;
+pub const NSH_NEXT_PROTOCOL_ETHERNET: u8 = 3;
 
 pub const GRE4_PROTO_OFFSET: usize = IPV4_PACKET_SIZE + erspan::GRE_PROTO_OFFSET;
 pub const GRE6_PROTO_OFFSET: usize = IPV6_PACKET_SIZE + erspan::GRE_PROTO_OFFSET;
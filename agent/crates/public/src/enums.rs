@@ -438,6 +438,7 @@ pub enum FieldType {
     PayloadJson,
     PayloadXml,
     PayloadHessian2,
+    PayloadRegex,
 }
 
 #[cfg(test)]
@@ -77,6 +77,7 @@ pub enum L7Protocol {
     DNS = 120,
     TLS = 121,
     Ping = 122,
+    QUIC = 123,
 
     Custom = 127,
 
@@ -132,6 +133,7 @@ impl From<String> for L7Protocol {
             "oracle" => Self::Oracle,
             "tls" => Self::TLS,
             "ping" => Self::Ping,
+            "quic" => Self::QUIC,
             "some/ip" | "someip" => Self::SomeIp,
             _ => Self::Unknown,
         }
@@ -28,6 +28,10 @@ const TICK_PER_SECOND: u64 =
     (Duration::from_secs(1).as_millis() / TICK_INTERVAL.as_millis()) as u64;
 const BURST_MULTIPLE: u64 = 10;
 
+fn tick_quantity(rate: u64) -> u64 {
+    1.max(rate / TICK_PER_SECOND)
+}
+
 pub struct LeakyBucket {
     rate: Arc<AtomicU64>,
     token: Arc<AtomicU64>,
@@ -60,7 +64,7 @@ impl LeakyBucket {
                             thread::park();
                             continue;
                         }
-                        quantity_per_tick = 1.max(rate / TICK_PER_SECOND);
+                        quantity_per_tick = tick_quantity(rate);
                         full = quantity_per_tick * BURST_MULTIPLE;
                         token.store(full, Ordering::Release);
                     }
@@ -91,6 +95,14 @@ impl LeakyBucket {
         self.handle.thread().unpark();
     }
 
+    // tokens of a full bucket, acquiring more than this never succeeds
+    pub fn capacity(&self) -> u64 {
+        match self.rate.load(Ordering::Relaxed) {
+            0 => u64::MAX,
+            rate => tick_quantity(rate) * BURST_MULTIPLE,
+        }
+    }
+
     pub fn acquire(&self, size: u64) -> bool {
         if self.rate.load(Ordering::Relaxed) == 0 {
             return true;
@@ -106,6 +118,20 @@ impl LeakyBucket {
             })
             .is_ok()
     }
+
+    // returns tokens acquired but not used, never filling beyond capacity
+    pub fn release(&self, size: u64) {
+        if self.rate.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let full = self.capacity();
+        let _ = self
+            .token
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |t| {
+                Some(full.min(t.saturating_add(size)))
+            });
+    }
 }
 
 impl Default for LeakyBucket {
@@ -142,6 +168,7 @@ mod tests {
                 rate
             );
             assert!(!bucket.acquire(1), "failed leaking for rate {}", rate);
+            assert_eq!(bucket.capacity(), BURST_MULTIPLE * rate / TICK_PER_SECOND);
             thread::sleep(TICK_INTERVAL + TICK_INTERVAL / 10);
             for _ in 0..10 {
                 assert!(
@@ -157,6 +184,18 @@ mod tests {
             );
         }
 
+        // capacity is rounded down to whole ticks
+        let bucket = LeakyBucket::new(Some(1 << 17));
+        thread::sleep(TICK_INTERVAL / 10);
+        assert!(bucket.capacity() < 1 << 17);
+        assert!(bucket.acquire(bucket.capacity()));
+
+        // released tokens can be acquired again
+        assert!(!bucket.acquire(1));
+        bucket.release(1 << 10);
+        assert!(bucket.acquire(1 << 10));
+        assert!(!bucket.acquire(1));
+
         let unlimited = LeakyBucket::new(None);
         for _ in 0..1000 {
             assert!(
@@ -34,6 +34,7 @@ pub mod segment_map;
 pub mod sender;
 pub mod throttle;
 pub mod utils;
+pub mod zmtp;
 
 #[cfg(target_os = "linux")]
 pub mod netns;
@@ -53,6 +53,8 @@ impl Drop for Packet<'_> {
 
 pub struct MiniPacket {
     pub packet: Vec<u8>,
+    // length of the packet on the wire, `packet` may be sliced
+    pub packet_len: u32,
     pub timestamp: Duration,
     pub flow_id: u64,
     pub acl_gids: Vec<u16>,
@@ -62,7 +64,8 @@ pub struct MiniPacket {
 impl fmt::Debug for MiniPacket {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("MiniPacket")
-            .field("packet_len", &self.packet.len())
+            .field("captured_len", &self.packet.len())
+            .field("packet_len", &self.packet_len)
             .field("timestamp", &self.timestamp)
             .field("second_in_minute", &self.second_in_minute)
             .field("flow_id", &self.flow_id)
@@ -45,10 +45,10 @@ pub enum SendMessageType {
     OpenTelemetry = 6,
     Prometheus = 7,
     Telegraf = 8,
-    PacketSequenceBlock = 9, // Enterprise Edition Feature: packet-sequence
+    PacketSequenceBlock = 9,
     DeepflowStats = 10,
     OpenTelemetryCompressed = 11,
-    RawPcap = 12, // Enterprise Edition Feature: pcap
+    RawPcap = 12,
     Profile = 13,
     ProcEvents = 14,
     AlarmEvent = 15,
@@ -71,10 +71,10 @@ impl fmt::Display for SendMessageType {
             Self::OpenTelemetry => write!(f, "open_telemetry"),
             Self::Prometheus => write!(f, "prometheus"),
             Self::Telegraf => write!(f, "telegraf"),
-            Self::PacketSequenceBlock => write!(f, "packet_sequence_block"), // Enterprise Edition Feature: packet-sequence
+            Self::PacketSequenceBlock => write!(f, "packet_sequence_block"),
             Self::DeepflowStats => write!(f, "deepflow_stats"),
             Self::OpenTelemetryCompressed => write!(f, "open_telemetry compressed"),
-            Self::RawPcap => write!(f, "raw_pcap"), // Enterprise Edition Feature: pcap
+            Self::RawPcap => write!(f, "raw_pcap"),
             Self::Profile => write!(f, "profile"),
             Self::ProcEvents => write!(f, "proc_events"),
             Self::AlarmEvent => write!(f, "alarm_event"),
@@ -0,0 +1,38 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// ZMTP 3.x wire format shared by the ZMTP parser and the NPB ZeroMQ sender,
+// https://rfc.zeromq.org/spec/23/
+
+// greeting: signature (10 bytes), version (2), mechanism (20), as-server (1), filler (31)
+pub const GREETING_SIZE: usize = 64;
+pub const SIGNATURE_SIZE: usize = 10;
+pub const SIGNATURE_START: u8 = 0xff;
+pub const SIGNATURE_END: u8 = 0x7f;
+pub const MAJOR_VERSION: u8 = 3;
+pub const MINOR_VERSION: u8 = 0;
+pub const VERSION_OFFSET: usize = SIGNATURE_SIZE;
+pub const MECHANISM_OFFSET: usize = VERSION_OFFSET + 2;
+pub const MECHANISM_SIZE: usize = 20;
+pub const MECHANISM_NULL: &[u8] = b"NULL";
+pub const FILLER_SIZE: usize = 31;
+
+// flags of the first byte of a frame
+pub const FLAG_MORE: u8 = 0x01;
+pub const FLAG_LONG: u8 = 0x02;
+pub const FLAG_COMMAND: u8 = 0x04;
+
+pub const PROPERTY_SOCKET_TYPE: &str = "Socket-Type";
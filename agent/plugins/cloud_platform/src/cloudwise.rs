@@ -14,10 +14,45 @@
  * limitations under the License.
  */
 
-//! Enterprise Edition Feature: cloudwise
-
 // CloudWise Trace format:
-// CLOUDWISE: JAVA:0:0:0:0:{trace-id}:0:0:0
-pub fn decode_trace_id(_: &str) -> Option<&str> {
-    None
+// CLOUDWISE: JAVA:0:0:0:0:{trace-id}:{span-id}:0:0
+// fields are separated by ':', the first field is the agent language
+const TRACE_ID_INDEX: usize = 5;
+const SPAN_ID_INDEX: usize = 6;
+
+fn decode_field(value: &str, index: usize) -> Option<&str> {
+    let mut segs = value.split(':');
+    if segs.next()?.is_empty() {
+        return None;
+    }
+    segs.nth(index - 1)
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+}
+
+pub fn decode_trace_id(value: &str) -> Option<&str> {
+    decode_field(value, TRACE_ID_INDEX)
+}
+
+pub fn decode_span_id(value: &str) -> Option<&str> {
+    decode_field(value, SPAN_ID_INDEX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let value = "JAVA:0:6904424666057469:6865509588089802:3363838533311866:00000000-332d-351f-ffff-ffff84afb1ba:7502612320163056:00000000-39d7-c9b2-ffff-ffff93cf32e0:ffffffff-df3a-bfff-0000-000001c43ef4:dilinkapp_dilinkapp-vehicle-provide-test:-1:-1";
+        assert_eq!(
+            decode_trace_id(value),
+            Some("00000000-332d-351f-ffff-ffff84afb1ba")
+        );
+        assert_eq!(decode_span_id(value), Some("7502612320163056"));
+        assert_eq!(decode_trace_id("JAVA:0:0:0:0:abc:0:0:0"), Some("abc"));
+        assert_eq!(decode_trace_id("JAVA:0:0:0:0"), None);
+        assert_eq!(decode_trace_id(":0:0:0:0:abc"), None);
+        assert_eq!(decode_span_id("JAVA:0:0:0:0:abc:"), None);
+    }
 }
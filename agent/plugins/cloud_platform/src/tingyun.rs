@@ -14,8 +14,53 @@
  * limitations under the License.
  */
 
-//! Enterprise Edition Feature: tingyun
+use std::borrow::Cow;
 
-pub fn decode_trace_id<'a, 'b>(_: &'a str, _: &'b str) -> Option<std::borrow::Cow<'a, str>> {
-    None
+// Tingyun Trace format:
+// X-Tingyun: c=B|{account-key};x={transaction-id};e={...};s={...};p={...}
+// key-value pairs are separated by ';', `sub_tag` selects which key to extract,
+// e.g. `x-tingyun.x` for the transaction id, defaults to `x` if not set
+const DEFAULT_SUB_TAG: &str = "x";
+
+pub fn decode_trace_id<'a>(value: &'a str, sub_tag: &str) -> Option<Cow<'a, str>> {
+    let sub_tag = if sub_tag.is_empty() {
+        DEFAULT_SUB_TAG
+    } else {
+        sub_tag
+    };
+    value.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        let value = value.trim();
+        if key.trim().eq_ignore_ascii_case(sub_tag) && !value.is_empty() {
+            Some(Cow::Borrowed(value))
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_trace_id() {
+        let value = "c=B|4Nl_NnGbjwY;x=f2dd3e9d3c6a4d22;e=1a2b3c;s=9;p=0";
+        assert_eq!(
+            decode_trace_id(value, "").as_deref(),
+            Some("f2dd3e9d3c6a4d22")
+        );
+        assert_eq!(
+            decode_trace_id(value, "x").as_deref(),
+            Some("f2dd3e9d3c6a4d22")
+        );
+        assert_eq!(decode_trace_id(value, "e").as_deref(), Some("1a2b3c"));
+        assert_eq!(
+            decode_trace_id(value, "c").as_deref(),
+            Some("B|4Nl_NnGbjwY")
+        );
+        assert_eq!(decode_trace_id(value, "y"), None);
+        assert_eq!(decode_trace_id("c=B|4Nl_NnGbjwY; x= ;", "x"), None);
+        assert_eq!(decode_trace_id("", "x"), None);
+    }
 }
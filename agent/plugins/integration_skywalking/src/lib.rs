@@ -14,21 +14,38 @@
  * limitations under the License.
  */
 
-use hyper::{Body, Response, StatusCode};
-use prost::EncodeError;
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::{
+    body::{Bytes, HttpBody},
+    header::{HeaderValue, CONTENT_TYPE},
+    Body, HeaderMap, Response, StatusCode,
+};
+use log::{debug, warn};
+use prost::{EncodeError, Message};
 use public::{
     proto::flow_log,
     queue::DebugSender,
     sender::{SendMessageType, Sendable},
 };
-use std::net::SocketAddr;
+
+// HTTP endpoint of the SkyWalking JSON reporter, other routes are gRPC
+const HTTP_SEGMENTS_PATH: &str = "/v3/segments";
+
+const GRPC_FRAME_HEADER_LEN: usize = 5;
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const GRPC_STATUS: &str = "grpc-status";
+const GRPC_STATUS_OK: &str = "0";
+const GRPC_STATUS_INTERNAL: &str = "13";
+// An uncompressed gRPC frame carrying an empty `Commands` message
+const GRPC_EMPTY_COMMANDS: &[u8] = &[0, 0, 0, 0, 0];
 
 #[derive(Debug, PartialEq)]
 pub struct SkyWalkingExtra(pub flow_log::ThirdPartyTrace);
 
 impl Sendable for SkyWalkingExtra {
-    fn encode(self, _: &mut Vec<u8>) -> Result<usize, EncodeError> {
-        return Ok(0);
+    fn encode(self, buf: &mut Vec<u8>) -> Result<usize, EncodeError> {
+        self.0.encode(buf).map(|_| self.0.encoded_len())
     }
 
     fn message_type(&self) -> SendMessageType {
@@ -36,26 +53,343 @@ impl Sendable for SkyWalkingExtra {
     }
 }
 
-pub async fn handle_skywalking_request(
-    _: SocketAddr,
-    _: Vec<u8>,
-    _: &str,
-    _: DebugSender<SkyWalkingExtra>,
-) -> Response<Body> {
+impl SkyWalkingExtra {
+    fn new(peer_addr: SocketAddr, data: Vec<u8>, uri: &str) -> Self {
+        Self(flow_log::ThirdPartyTrace {
+            data,
+            peer_ip: match peer_addr.ip() {
+                IpAddr::V4(ip4) => ip4.octets().to_vec(),
+                IpAddr::V6(ip6) => ip6.octets().to_vec(),
+            },
+            uri: uri.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+fn send_segment(
+    sender: &DebugSender<SkyWalkingExtra>,
+    peer_addr: SocketAddr,
+    data: Vec<u8>,
+    uri: &str,
+) {
+    if let Err(e) = sender.send(SkyWalkingExtra::new(peer_addr, data, uri)) {
+        warn!("skywalking_sender failed to send data, because {:?}", e);
+    }
+}
+
+// Splits the length-prefixed messages of a gRPC stream, which may arrive in arbitrary chunks.
+//
+// Each frame is a 1 byte compressed flag, a 4 bytes big endian length and the message itself.
+#[derive(Default)]
+struct GrpcFrameDecoder {
+    buf: Vec<u8>,
+    offset: usize,
+}
+
+impl GrpcFrameDecoder {
+    fn extend(&mut self, data: &[u8]) {
+        if self.offset > 0 {
+            self.buf.drain(..self.offset);
+            self.offset = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    // Returns the compressed flag and the message of the next complete frame
+    fn next_frame(&mut self) -> Option<(bool, &[u8])> {
+        let remain = &self.buf[self.offset..];
+        if remain.len() < GRPC_FRAME_HEADER_LEN {
+            return None;
+        }
+        let len = u32::from_be_bytes(remain[1..GRPC_FRAME_HEADER_LEN].try_into().unwrap()) as usize;
+        if remain.len() < GRPC_FRAME_HEADER_LEN + len {
+            return None;
+        }
+        let start = self.offset + GRPC_FRAME_HEADER_LEN;
+        self.offset = start + len;
+        Some((
+            self.buf[start - GRPC_FRAME_HEADER_LEN] != 0,
+            &self.buf[start..start + len],
+        ))
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.offset
+    }
+}
+
+// Forwards every complete segment in the decoder, returns the number of segments forwarded
+fn forward_frames(
+    decoder: &mut GrpcFrameDecoder,
+    peer_addr: SocketAddr,
+    uri: &str,
+    sender: &DebugSender<SkyWalkingExtra>,
+) -> usize {
+    let mut count = 0;
+    while let Some((compressed, segment)) = decoder.next_frame() {
+        if compressed {
+            // SkyWalking agents do not compress segments unless configured to, which is not supported
+            warn!(
+                "compressed skywalking segment from {} is not supported, dropped",
+                peer_addr
+            );
+            continue;
+        }
+        send_segment(sender, peer_addr, segment.to_vec(), uri);
+        count += 1;
+    }
+    count
+}
+
+async fn grpc_response(status: &'static str) -> Response<Body> {
+    let (mut tx, body) = Body::channel();
+    if status == GRPC_STATUS_OK {
+        let _ = tx.try_send_data(Bytes::from_static(GRPC_EMPTY_COMMANDS));
+    }
+    let mut trailers = HeaderMap::new();
+    trailers.insert(GRPC_STATUS, HeaderValue::from_static(status));
+    let _ = tx.send_trailers(trailers).await;
     Response::builder()
-        .status(StatusCode::NOT_FOUND)
-        .body(Body::empty())
+        .header(CONTENT_TYPE, GRPC_CONTENT_TYPE)
+        .body(body)
         .unwrap()
 }
 
+/// Handles the JSON reporter (`/v3/segments`) and the unary gRPC `collectInSync` call,
+/// `data` being the whole request body.
+pub async fn handle_skywalking_request(
+    peer_addr: SocketAddr,
+    data: Vec<u8>,
+    uri: &str,
+    sender: DebugSender<SkyWalkingExtra>,
+) -> Response<Body> {
+    if uri == HTTP_SEGMENTS_PATH {
+        // the body is a JSON array of segments and forwarded as is
+        send_segment(&sender, peer_addr, data, uri);
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let mut decoder = GrpcFrameDecoder::default();
+    decoder.extend(&data);
+    forward_frames(&mut decoder, peer_addr, uri, &sender);
+    if decoder.remaining() > 0 {
+        warn!(
+            "skywalking request from {} has {} bytes of truncated grpc frame",
+            peer_addr,
+            decoder.remaining()
+        );
+        return grpc_response(GRPC_STATUS_INTERNAL).await;
+    }
+    grpc_response(GRPC_STATUS_OK).await
+}
+
+/// Handles the client streaming gRPC `collect` call, segments are forwarded as soon as
+/// their frames are complete instead of waiting for the end of the stream.
 pub async fn handle_skywalking_streaming_request(
-    _: SocketAddr,
-    _: Body,
-    _: &str,
-    _: DebugSender<SkyWalkingExtra>,
+    peer_addr: SocketAddr,
+    mut body: Body,
+    uri: &str,
+    sender: DebugSender<SkyWalkingExtra>,
 ) -> Response<Body> {
-    Response::builder()
-        .status(StatusCode::NOT_FOUND)
-        .body(Body::empty())
-        .unwrap()
+    let mut decoder = GrpcFrameDecoder::default();
+    let mut count = 0;
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => {
+                decoder.extend(&chunk);
+                count += forward_frames(&mut decoder, peer_addr, uri, &sender);
+            }
+            Err(e) => {
+                warn!("read skywalking stream from {} failed: {}", peer_addr, e);
+                return grpc_response(GRPC_STATUS_INTERNAL).await;
+            }
+        }
+    }
+    debug!("received {} skywalking segments from {}", count, peer_addr);
+    if decoder.remaining() > 0 {
+        warn!(
+            "skywalking stream from {} has {} bytes of truncated grpc frame",
+            peer_addr,
+            decoder.remaining()
+        );
+        return grpc_response(GRPC_STATUS_INTERNAL).await;
+    }
+    grpc_response(GRPC_STATUS_OK).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{convert::Infallible, time::Duration};
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Client, Method, Request, Server,
+    };
+    use public::{
+        debug::QueueDebugger,
+        queue::{bounded_with_debug, Receiver},
+    };
+
+    fn grpc_frame(message: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(message);
+        frame
+    }
+
+    // A minimal SkyWalking collector routing requests like integration_collector does
+    async fn start_collector(sender: DebugSender<SkyWalkingExtra>) -> SocketAddr {
+        let make_svc = make_service_fn(move |_| {
+            let sender = sender.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let sender = sender.clone();
+                    async move {
+                        let peer_addr = "10.1.2.3:12345".parse().unwrap();
+                        let (part, body) = req.into_parts();
+                        let resp = if part.uri.path().ends_with("/collect") {
+                            handle_skywalking_streaming_request(
+                                peer_addr,
+                                body,
+                                part.uri.path(),
+                                sender,
+                            )
+                            .await
+                        } else {
+                            let data = hyper::body::to_bytes(body).await.unwrap().to_vec();
+                            handle_skywalking_request(peer_addr, data, part.uri.path(), sender)
+                                .await
+                        };
+                        Ok::<_, Infallible>(resp)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    fn recv_segment(receiver: &Receiver<SkyWalkingExtra>) -> flow_log::ThirdPartyTrace {
+        receiver.recv(Some(Duration::from_secs(1))).unwrap().0
+    }
+
+    #[test]
+    fn grpc_frame_decoder() {
+        let mut stream = grpc_frame(b"segment-1");
+        stream.extend(grpc_frame(b""));
+        stream.extend(grpc_frame(b"segment-2"));
+
+        let mut decoder = GrpcFrameDecoder::default();
+        let mut frames = vec![];
+        // feed byte by byte to cover frames split across chunks
+        for b in stream.iter() {
+            decoder.extend(&[*b]);
+            while let Some((compressed, message)) = decoder.next_frame() {
+                assert!(!compressed);
+                frames.push(message.to_vec());
+            }
+        }
+        assert_eq!(
+            frames,
+            vec![b"segment-1".to_vec(), vec![], b"segment-2".to_vec()]
+        );
+        assert_eq!(decoder.remaining(), 0);
+
+        decoder.extend(&[0, 0, 0, 0, 8, 1, 2]);
+        assert!(decoder.next_frame().is_none());
+        assert_eq!(decoder.remaining(), 7);
+    }
+
+    #[tokio::test]
+    async fn skywalking_client() {
+        let queue_debugger = QueueDebugger::new();
+        let (sender, receiver, _) = bounded_with_debug(16, "skywalking-test", &queue_debugger);
+        let addr = start_collector(sender).await;
+
+        // JSON reporter over HTTP/1.1
+        let json = br#"[{"traceId":"a.1.2","segmentId":"b.3.4","spans":[]}]"#;
+        let resp = Client::new()
+            .request(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("http://{}/v3/segments", addr))
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(&json[..]))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let trace = recv_segment(&receiver);
+        assert_eq!(trace.data, json.to_vec());
+        assert_eq!(trace.peer_ip, vec![10, 1, 2, 3]);
+        assert_eq!(trace.uri, "/v3/segments");
+
+        // gRPC streaming reporter, frames split across data chunks
+        let client = Client::builder().http2_only(true).build_http::<Body>();
+        let (mut tx, body) = Body::channel();
+        let mut stream = grpc_frame(b"segment-1");
+        stream.extend(grpc_frame(b"segment-2"));
+        let request = client.request(
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!(
+                    "http://{}/skywalking.v3.TraceSegmentReportService/collect",
+                    addr
+                ))
+                .header(CONTENT_TYPE, GRPC_CONTENT_TYPE)
+                .body(body)
+                .unwrap(),
+        );
+        let request = tokio::spawn(request);
+        for chunk in stream.chunks(4) {
+            tx.send_data(Bytes::copy_from_slice(chunk)).await.unwrap();
+        }
+        drop(tx);
+
+        let mut resp = request.await.unwrap().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let data = resp.data().await.unwrap().unwrap();
+        assert_eq!(&data[..], GRPC_EMPTY_COMMANDS);
+        let trailers = resp.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers.get(GRPC_STATUS).unwrap(), GRPC_STATUS_OK);
+        for expected in [&b"segment-1"[..], &b"segment-2"[..]] {
+            let trace = recv_segment(&receiver);
+            assert_eq!(trace.data, expected);
+            assert_eq!(
+                trace.uri,
+                "/skywalking.v3.TraceSegmentReportService/collect"
+            );
+        }
+
+        // gRPC unary reporter with a truncated frame
+        let mut data = grpc_frame(b"segment-3");
+        data.truncate(data.len() - 1);
+        let mut resp = client
+            .request(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri(format!(
+                        "http://{}/TraceSegmentReportService/collectInSync",
+                        addr
+                    ))
+                    .header(CONTENT_TYPE, GRPC_CONTENT_TYPE)
+                    .body(Body::from(data))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(resp.data().await.is_none());
+        let trailers = resp.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers.get(GRPC_STATUS).unwrap(), GRPC_STATUS_INTERNAL);
+        assert!(receiver.recv(Some(Duration::from_millis(100))).is_err());
+    }
 }
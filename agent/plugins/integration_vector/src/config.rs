@@ -0,0 +1,418 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// The pipeline definition, a subset of the vector.dev configuration format. Options of vector
+// that are not supported here are ignored.
+
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::glob::Pattern;
+use crate::remap::Program;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("invalid pipeline config: {0}")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("component id {0} is used more than once")]
+    DuplicateId(String),
+    #[error("component {0} has no inputs")]
+    NoInputs(String),
+    #[error("input {input} of component {id} does not exist")]
+    UnknownInput { id: String, input: String },
+    #[error("transform {0} depends on itself")]
+    Cycle(String),
+    #[error("no sinks configured")]
+    NoSinks,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadFrom {
+    Beginning,
+    // there are no checkpoints, reading from the beginning would resend files on every restart
+    #[default]
+    End,
+}
+
+fn default_glob_cooldown_ms() -> u64 {
+    1000
+}
+
+fn default_max_line_bytes() -> usize {
+    102400
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FileConfig {
+    pub include: Vec<Pattern>,
+    #[serde(default)]
+    pub exclude: Vec<Pattern>,
+    #[serde(default)]
+    pub read_from: ReadFrom,
+    #[serde(default = "default_glob_cooldown_ms")]
+    pub glob_minimum_cooldown_ms: u64,
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+}
+
+fn default_journalctl_path() -> String {
+    "journalctl".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct JournaldConfig {
+    #[serde(default)]
+    pub include_units: Vec<String>,
+    #[serde(default)]
+    pub exclude_units: Vec<String>,
+    #[serde(default = "default_true")]
+    pub current_boot_only: bool,
+    #[serde(default = "default_journalctl_path")]
+    pub journalctl_path: String,
+}
+
+fn default_pod_log_patterns() -> Vec<Pattern> {
+    vec![Pattern::new("/var/log/pods/**/*.log").unwrap()]
+}
+
+// Pod metadata comes from the log paths, there is no kubernetes API access
+#[derive(Clone, Debug, Deserialize)]
+pub struct KubernetesLogsConfig {
+    #[serde(default = "default_pod_log_patterns")]
+    pub include_paths_glob_patterns: Vec<Pattern>,
+    #[serde(default)]
+    pub exclude_paths_glob_patterns: Vec<Pattern>,
+    #[serde(default)]
+    pub read_from: ReadFrom,
+    #[serde(default = "default_glob_cooldown_ms")]
+    pub glob_minimum_cooldown_ms: u64,
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SourceConfig {
+    File(FileConfig),
+    Journald(JournaldConfig),
+    KubernetesLogs(KubernetesLogsConfig),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RemapConfig {
+    pub inputs: Vec<String>,
+    pub source: Program,
+    #[serde(default)]
+    pub drop_on_error: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransformConfig {
+    Remap(RemapConfig),
+}
+
+impl TransformConfig {
+    pub fn inputs(&self) -> &[String] {
+        match self {
+            Self::Remap(c) => &c.inputs,
+        }
+    }
+}
+
+fn default_max_events() -> usize {
+    1000
+}
+
+fn default_timeout_secs() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BatchConfig {
+    #[serde(default = "default_max_events")]
+    pub max_events: usize,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: f64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_events: default_max_events(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl BatchConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.timeout_secs.max(0.01))
+    }
+}
+
+// Sends events as application logs, same as vector's http sink to the `/api/v1/log` endpoint
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApplicationLogConfig {
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub batch: BatchConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    ApplicationLog(ApplicationLogConfig),
+}
+
+impl SinkConfig {
+    pub fn inputs(&self) -> &[String] {
+        match self {
+            Self::ApplicationLog(c) => &c.inputs,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PipelineConfig {
+    #[serde(default)]
+    pub sources: BTreeMap<String, SourceConfig>,
+    #[serde(default)]
+    pub transforms: BTreeMap<String, TransformConfig>,
+    #[serde(default)]
+    pub sinks: BTreeMap<String, SinkConfig>,
+}
+
+impl PipelineConfig {
+    pub fn from_yaml(value: &serde_yaml::Value) -> Result<Self, ConfigError> {
+        let config: Self = serde_yaml::from_value(value.clone())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.sinks.is_empty() {
+            return Err(ConfigError::NoSinks);
+        }
+        let mut ids = HashSet::new();
+        for id in self
+            .sources
+            .keys()
+            .chain(self.transforms.keys())
+            .chain(self.sinks.keys())
+        {
+            if !ids.insert(id.as_str()) {
+                return Err(ConfigError::DuplicateId(id.clone()));
+            }
+        }
+        let consumers = self
+            .transforms
+            .iter()
+            .map(|(id, t)| (id, t.inputs()))
+            .chain(self.sinks.iter().map(|(id, s)| (id, s.inputs())));
+        for (id, inputs) in consumers {
+            if inputs.is_empty() {
+                return Err(ConfigError::NoInputs(id.clone()));
+            }
+            for input in inputs {
+                if !self.sources.contains_key(input) && !self.transforms.contains_key(input) {
+                    return Err(ConfigError::UnknownInput {
+                        id: id.clone(),
+                        input: input.clone(),
+                    });
+                }
+            }
+        }
+        for id in self.transforms.keys() {
+            self.check_cycle(id, id, &mut HashSet::new())?;
+        }
+        Ok(())
+    }
+
+    fn check_cycle<'a>(
+        &'a self,
+        start: &str,
+        id: &'a str,
+        visited: &mut HashSet<&'a str>,
+    ) -> Result<(), ConfigError> {
+        let Some(transform) = self.transforms.get(id) else {
+            return Ok(());
+        };
+        if !visited.insert(id) {
+            return Ok(());
+        }
+        for input in transform.inputs() {
+            if input == start {
+                return Err(ConfigError::Cycle(start.to_string()));
+            }
+            self.check_cycle(start, input, visited)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<PipelineConfig, ConfigError> {
+        PipelineConfig::from_yaml(&serde_yaml::from_str(s).unwrap())
+    }
+
+    #[test]
+    fn pipeline() {
+        let config = parse(
+            r#"
+data_dir: /ignored
+sources:
+  host_logs:
+    type: file
+    include: [/var/log/*.log]
+    read_from: beginning
+  journal:
+    type: journald
+    include_units: [kubelet.service]
+  pods:
+    type: kubernetes_logs
+    extra_label_selector: "app=deepflow"
+transforms:
+  remap_logs:
+    type: remap
+    inputs: [host_logs, pods]
+    source: |-
+      .app_service = .kubernetes.container_name
+sinks:
+  deepflow:
+    type: application_log
+    inputs: [remap_logs, journal]
+    batch:
+      max_events: 10
+"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &config.sources["host_logs"],
+            SourceConfig::File(FileConfig {
+                read_from: ReadFrom::Beginning,
+                glob_minimum_cooldown_ms: 1000,
+                ..
+            })
+        ));
+        assert!(matches!(
+            &config.sources["journal"],
+            SourceConfig::Journald(JournaldConfig {
+                current_boot_only: true,
+                ..
+            })
+        ));
+        match &config.sources["pods"] {
+            SourceConfig::KubernetesLogs(c) => {
+                assert_eq!(c.read_from, ReadFrom::End);
+                assert_eq!(c.include_paths_glob_patterns.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+        let SinkConfig::ApplicationLog(sink) = &config.sinks["deepflow"];
+        assert_eq!(sink.batch.max_events, 10);
+        assert_eq!(sink.batch.timeout_secs, 1.0);
+    }
+
+    #[test]
+    fn examples() {
+        // the yaml blocks in the description of the `inputs.vector` section
+        let template = include_str!("../../../../server/agent_config/template.yaml");
+        let end = template.find("\n  vector:\n").unwrap();
+        let start = template[..end].rfind("# type: section").unwrap();
+        let mut examples = vec![];
+        let mut example: Option<String> = None;
+        for line in template[start..end].lines() {
+            let line = line.trim_start().trim_start_matches('#');
+            match (line.trim(), example.as_mut()) {
+                ("```yaml", None) => example = Some(String::new()),
+                ("```", Some(_)) => examples.extend(example.take()),
+                (_, Some(example)) => {
+                    example.push_str(line.get(5..).unwrap_or_default());
+                    example.push('\n');
+                }
+                _ => (),
+            }
+        }
+        // host logs and kubernetes logs, in english and chinese
+        assert_eq!(examples.len(), 4);
+        for example in examples {
+            if let Err(e) = parse(&example) {
+                panic!("{}: {}", e, example);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(parse("sources: {}\n"), Err(ConfigError::NoSinks)));
+
+        let sink = "sinks:\n  s:\n    type: application_log\n    inputs: [t]\n";
+        for (config, expected) in [
+            (
+                "sources:\n  t:\n    type: unknown\n",
+                "invalid pipeline config: unknown variant `unknown`",
+            ),
+            (
+                "transforms:\n  t:\n    type: remap\n    inputs: [x]\n    source: .a = 1\n",
+                "input x of component t does not exist",
+            ),
+            (
+                "transforms:\n  t:\n    type: remap\n    inputs: [u]\n    source: .a = 1\n  u:\n    type: remap\n    inputs: [t]\n    source: .a = 1\n",
+                "transform t depends on itself",
+            ),
+            (
+                "transforms:\n  t:\n    type: remap\n    inputs: [t]\n    source: .a = upcase(\n",
+                "invalid pipeline config: line 1: unexpected end of program",
+            ),
+            (
+                "transforms:\n  t:\n    type: remap\n    inputs: [t]\n    source: abort\n",
+                "invalid pipeline config: line 1: unsupported keyword abort",
+            ),
+            (
+                "sources:\n  t:\n    type: file\n    include: [var/*.log]\n",
+                "invalid pipeline config: var/*.log is not an absolute path",
+            ),
+            (
+                "sources:\n  t:\n    type: journald\ntransforms:\n  t:\n    type: remap\n    inputs: [t]\n    source: .a = 1\n",
+                "component id t is used more than once",
+            ),
+        ] {
+            let config = format!("{}{}", config, sink);
+            match parse(&config) {
+                Ok(_) => panic!("{} should be invalid", config),
+                Err(e) => assert!(
+                    e.to_string().starts_with(expected),
+                    "{}: {} does not start with {}",
+                    config,
+                    e,
+                    expected
+                ),
+            }
+        }
+    }
+}
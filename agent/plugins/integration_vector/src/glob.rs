@@ -0,0 +1,178 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+
+const MAX_DEPTH: usize = 16;
+
+/// A path pattern supporting `*`, `?` and `**` for any number of directories
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pattern: String,
+    regex: Regex,
+    // the longest leading part without wildcards, where the directory walk starts
+    base: PathBuf,
+    recursive: bool,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        if !pattern.starts_with('/') {
+            return Err(format!("{} is not an absolute path", pattern));
+        }
+        let mut re = String::from("^");
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        re.push_str("(?:.*/)?");
+                    } else {
+                        re.push_str(".*");
+                    }
+                }
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                c => re.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        re.push('$');
+        let regex = Regex::new(&re).map_err(|e| format!("invalid pattern {}: {}", pattern, e))?;
+
+        let literal_end = pattern.find(['*', '?']).unwrap_or(pattern.len());
+        let base = match pattern[..literal_end].rfind('/') {
+            Some(0) => PathBuf::from("/"),
+            Some(i) => PathBuf::from(&pattern[..i]),
+            None => PathBuf::from("/"),
+        };
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+            base,
+            recursive: pattern.contains("**"),
+        })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        path.to_str()
+            .map(|p| self.regex.is_match(p))
+            .unwrap_or_default()
+    }
+
+    fn max_depth(&self) -> usize {
+        if self.recursive {
+            return MAX_DEPTH;
+        }
+        let rest = self.pattern[self.base.as_os_str().len()..].trim_start_matches('/');
+        rest.split('/').count()
+    }
+
+    fn walk(&self, dir: &Path, depth: usize, out: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // do not follow symlinked directories to avoid loops, symlinked files are fine
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if depth < self.max_depth() {
+                    self.walk(&path, depth + 1, out);
+                }
+            } else if self.matches(&path) && path.is_file() {
+                out.push(path);
+            }
+        }
+    }
+
+    /// Returns the existing files matching the pattern
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = vec![];
+        if !self.pattern.contains(['*', '?']) {
+            let path = PathBuf::from(&self.pattern);
+            if path.is_file() {
+                files.push(path);
+            }
+        } else {
+            self.walk(&self.base, 1, &mut files);
+        }
+        files
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Pattern::new(&pattern).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let p = Pattern::new("/var/log/pods/**/*.log").unwrap();
+        assert_eq!(p.base, Path::new("/var/log/pods"));
+        assert!(p.matches(Path::new("/var/log/pods/ns_pod_uid/c/0.log")));
+        assert!(p.matches(Path::new("/var/log/pods/0.log")));
+        assert!(!p.matches(Path::new("/var/log/pods/ns_pod_uid/c/0.log.gz")));
+
+        let p = Pattern::new("/var/log/*.log").unwrap();
+        assert_eq!(p.base, Path::new("/var/log"));
+        assert!(p.matches(Path::new("/var/log/syslog.log")));
+        assert!(!p.matches(Path::new("/var/log/nginx/access.log")));
+
+        let p = Pattern::new("/var/log/app?/[x].log").unwrap();
+        assert!(p.matches(Path::new("/var/log/app1/[x].log")));
+        assert!(!p.matches(Path::new("/var/log/app12/[x].log")));
+
+        assert!(Pattern::new("relative/*.log").is_err());
+    }
+
+    #[test]
+    fn files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        for f in ["x.log", "y.txt", "a/z.log", "a/b/w.log"] {
+            fs::write(root.join(f), "").unwrap();
+        }
+        let sorted = |p: &str| {
+            let mut files = Pattern::new(&format!("{}/{}", root.display(), p))
+                .unwrap()
+                .files();
+            files.sort();
+            files
+                .into_iter()
+                .map(|f| f.strip_prefix(root).unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted("*.log"), vec!["x.log"]);
+        assert_eq!(sorted("**/*.log"), vec!["a/b/w.log", "a/z.log", "x.log"]);
+        assert_eq!(sorted("*/*.log"), vec!["a/z.log"]);
+        assert_eq!(sorted("y.txt"), vec!["y.txt"]);
+    }
+}
@@ -0,0 +1,140 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::{atomic::Ordering, Arc};
+
+use log::{debug, info};
+use tokio::sync::{mpsc, watch};
+
+use crate::config::{PipelineConfig, TransformConfig};
+use crate::vector_component::VectorCounter;
+use crate::{sink, source, Event};
+
+const CHANNEL_SIZE: usize = 1024;
+
+// Sends an encoded batch of application logs, returns false if it is dropped
+pub(crate) type Output = Arc<dyn Fn(Vec<u8>) -> bool + Send + Sync>;
+
+/// Sends events to all the components consuming them
+#[derive(Default)]
+pub(crate) struct Fanout(Vec<mpsc::Sender<Event>>);
+
+impl Fanout {
+    pub(crate) async fn send(&self, event: Event) {
+        let Some((last, others)) = self.0.split_last() else {
+            return;
+        };
+        for sender in others {
+            let _ = sender.send(event.clone()).await;
+        }
+        let _ = last.send(event).await;
+    }
+}
+
+async fn run_transform(
+    id: String,
+    config: TransformConfig,
+    mut receiver: mpsc::Receiver<Event>,
+    fanout: Fanout,
+    counter: Arc<VectorCounter>,
+) {
+    while let Some(mut event) = receiver.recv().await {
+        match &config {
+            TransformConfig::Remap(remap) => {
+                if let Err(e) = remap.source.run(&mut event) {
+                    counter.remap_errors.fetch_add(1, Ordering::Relaxed);
+                    debug!("remap transform {} failed: {}", id, e);
+                    if remap.drop_on_error {
+                        continue;
+                    }
+                }
+            }
+        }
+        fanout.send(event).await;
+    }
+}
+
+/// Runs the pipeline until `stop` changes
+///
+/// Sources stop first, the other components exit after their inputs are closed so that no
+/// event in the pipeline is lost.
+pub(crate) async fn run(
+    config: PipelineConfig,
+    output: Output,
+    counter: Arc<VectorCounter>,
+    stop: watch::Receiver<bool>,
+) {
+    let mut fanouts: HashMap<String, Fanout> = HashMap::new();
+    let mut transform_receivers = vec![];
+    let mut tasks = vec![];
+
+    for (id, sink) in config.sinks {
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        for input in sink.inputs() {
+            fanouts
+                .entry(input.clone())
+                .or_default()
+                .0
+                .push(sender.clone());
+        }
+        tasks.push(tokio::spawn(sink::run(
+            id,
+            sink,
+            receiver,
+            output.clone(),
+            counter.clone(),
+        )));
+    }
+    for (id, transform) in config.transforms {
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        for input in transform.inputs() {
+            fanouts
+                .entry(input.clone())
+                .or_default()
+                .0
+                .push(sender.clone());
+        }
+        transform_receivers.push((id, transform, receiver));
+    }
+    for (id, transform, receiver) in transform_receivers {
+        let fanout = fanouts.remove(&id).unwrap_or_default();
+        tasks.push(tokio::spawn(run_transform(
+            id,
+            transform,
+            receiver,
+            fanout,
+            counter.clone(),
+        )));
+    }
+    for (id, source) in config.sources {
+        let fanout = fanouts.remove(&id).unwrap_or_default();
+        tasks.push(tokio::spawn(source::run(
+            id,
+            source,
+            fanout,
+            counter.clone(),
+            stop.clone(),
+        )));
+    }
+    drop(fanouts);
+    info!("vector pipeline started with {} components", tasks.len());
+
+    for task in tasks {
+        let _ = task.await;
+    }
+    info!("vector pipeline stopped");
+}
@@ -0,0 +1,1374 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// A subset of the Vector Remap Language, https://vector.dev/docs/reference/vrl/
+//
+// Supported:
+//   - event paths `.a.b` / `."a.b"`, the event root `.`, local variables `x` / `x.a`
+//   - assignments, `if` / `else if` / `else` and function calls as statements
+//   - literals: strings, raw regex `r'...'`, integers, floats, booleans, null, `{...}` and `[...]`
+//   - operators: `!`, `==`, `!=`, `&&`, `||` and the error coalescing `??`
+//   - the functions listed in `Function::from_name`, `name!(...)` is the same as `name(...)`
+//
+// Everything else is rejected by `Program::parse`, which runs when the config is loaded:
+//   - other syntax, e.g. indexing `.a[0]`, arithmetic, closures, `abort` / `return` and the other
+//     keywords, `x, err = ...` assignments
+//   - unknown functions, wrong numbers of arguments and named arguments
+//   - reading a variable before it is assigned
+//   - `if` conditions, function arguments and values assigned to `.` whose type is known to be wrong
+//
+// Errors that depend on the event, like `upcase(.missing)`, abort the program at runtime unless
+// handled by `??`, the event is then left unchanged.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use chrono::{SecondsFormat, Utc};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{Map, Number, Value};
+
+use crate::Event;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Newline,
+    Ident(String),
+    // function name, the `!` of infallible calls is ignored
+    Call(String),
+    Path(Vec<String>),
+    Var(String, Vec<String>),
+    Str(String),
+    Regex(String),
+    Number(Number),
+    Assign,
+    Eq,
+    Ne,
+    Not,
+    And,
+    Or,
+    Coalesce,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Comma,
+    Colon,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    tokens: Vec<(Token, usize)>,
+}
+
+impl Lexer {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            line: 1,
+            tokens: vec![],
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn push(&mut self, token: Token, len: usize) {
+        self.tokens.push((token, self.line));
+        self.pos += len;
+    }
+
+    fn ident(&mut self) -> String {
+        let start = self.pos;
+        while self.peek(0).map(is_ident_char).unwrap_or_default() {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        // skip the opening quote
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek(0) {
+                None | Some('\n') => return self.error("unterminated string"),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    let c = match self.peek(1) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c @ ('"' | '\\' | '\'')) => c,
+                        _ => return self.error("invalid escape sequence in string"),
+                    };
+                    s.push(c);
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    // path segments following an event path or a variable, starting at a `.`
+    fn segments(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut segments = vec![];
+        while self.peek(0) == Some('.') {
+            match self.peek(1) {
+                Some('"') => {
+                    self.pos += 1;
+                    segments.push(self.string()?);
+                }
+                Some(c) if is_ident_char(c) => {
+                    self.pos += 1;
+                    segments.push(self.ident());
+                }
+                _ => break,
+            }
+        }
+        Ok(segments)
+    }
+
+    fn number(&mut self) -> Result<Number, ParseError> {
+        let start = self.pos;
+        if self.peek(0) == Some('-') {
+            self.pos += 1;
+        }
+        while self
+            .peek(0)
+            .map(|c| c.is_ascii_digit() || c == '.' || c == '_')
+            .unwrap_or_default()
+        {
+            self.pos += 1;
+        }
+        let s: String = self.chars[start..self.pos]
+            .iter()
+            .filter(|c| **c != '_')
+            .collect();
+        if let Ok(i) = s.parse::<i64>() {
+            return Ok(i.into());
+        }
+        match s.parse::<f64>().ok().and_then(Number::from_f64) {
+            Some(n) => Ok(n),
+            None => self.error(format!("invalid number {}", s)),
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<(Token, usize)>, ParseError> {
+        while let Some(c) = self.peek(0) {
+            let next = self.peek(1);
+            match c {
+                ' ' | '\t' | '\r' => self.pos += 1,
+                '#' => {
+                    while self.peek(0).map(|c| c != '\n').unwrap_or_default() {
+                        self.pos += 1;
+                    }
+                }
+                '\n' | ';' => {
+                    self.push(Token::Newline, 1);
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                }
+                '.' => {
+                    let line = self.line;
+                    let segments = self.segments()?;
+                    if segments.is_empty() {
+                        // the event root
+                        self.pos += 1;
+                    }
+                    self.tokens.push((Token::Path(segments), line));
+                }
+                '"' => {
+                    let s = self.string()?;
+                    self.tokens.push((Token::Str(s), self.line));
+                }
+                'r' if next == Some('\'') => {
+                    self.pos += 2;
+                    let start = self.pos;
+                    while self.peek(0).map(|c| c != '\'').unwrap_or_default() {
+                        self.pos += 1;
+                    }
+                    if self.peek(0).is_none() {
+                        return self.error("unterminated regex");
+                    }
+                    let s = self.chars[start..self.pos].iter().collect();
+                    self.push(Token::Regex(s), 1);
+                }
+                c if c.is_ascii_digit()
+                    || c == '-' && next.map(|c| c.is_ascii_digit()) == Some(true) =>
+                {
+                    let n = self.number()?;
+                    self.tokens.push((Token::Number(n), self.line));
+                }
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let name = self.ident();
+                    let token = match (self.peek(0), self.peek(1)) {
+                        (Some('('), _) => Token::Call(name),
+                        (Some('!'), Some('(')) => {
+                            self.pos += 1;
+                            Token::Call(name)
+                        }
+                        (Some('.'), _) => {
+                            let segments = self.segments()?;
+                            if segments.is_empty() {
+                                Token::Ident(name)
+                            } else {
+                                Token::Var(name, segments)
+                            }
+                        }
+                        _ => Token::Ident(name),
+                    };
+                    self.tokens.push((token, self.line));
+                }
+                '=' if next == Some('=') => self.push(Token::Eq, 2),
+                '=' => self.push(Token::Assign, 1),
+                '!' if next == Some('=') => self.push(Token::Ne, 2),
+                '!' => self.push(Token::Not, 1),
+                '&' if next == Some('&') => self.push(Token::And, 2),
+                '|' if next == Some('|') => self.push(Token::Or, 2),
+                '?' if next == Some('?') => self.push(Token::Coalesce, 2),
+                '(' => self.push(Token::LParen, 1),
+                ')' => self.push(Token::RParen, 1),
+                '{' => self.push(Token::LBrace, 1),
+                '}' => self.push(Token::RBrace, 1),
+                '[' => self.push(Token::LBracket, 1),
+                ']' => self.push(Token::RBracket, 1),
+                ',' => self.push(Token::Comma, 1),
+                ':' => self.push(Token::Colon, 1),
+                c => return self.error(format!("unexpected character {:?}", c)),
+            }
+        }
+        Ok(self.tokens)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    Event(Vec<String>),
+    Var(String, Vec<String>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+    Contains,
+    Del,
+    Downcase,
+    Exists,
+    IsJson,
+    IsNull,
+    IsObject,
+    IsString,
+    Match,
+    Merge,
+    Now,
+    ParseJson,
+    ParseRegex,
+    Replace,
+    String,
+    StripWhitespace,
+    ToInt,
+    ToString,
+    Upcase,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        let f = match name {
+            "contains" => Self::Contains,
+            "del" => Self::Del,
+            "downcase" => Self::Downcase,
+            "exists" => Self::Exists,
+            "is_json" => Self::IsJson,
+            "is_null" => Self::IsNull,
+            "is_object" => Self::IsObject,
+            "is_string" => Self::IsString,
+            "match" => Self::Match,
+            "merge" => Self::Merge,
+            "now" => Self::Now,
+            "parse_json" => Self::ParseJson,
+            "parse_regex" => Self::ParseRegex,
+            "replace" => Self::Replace,
+            "string" => Self::String,
+            "strip_whitespace" => Self::StripWhitespace,
+            "to_int" => Self::ToInt,
+            "to_string" => Self::ToString,
+            "upcase" => Self::Upcase,
+            _ => return None,
+        };
+        Some(f)
+    }
+
+    fn params(self) -> &'static [Kind] {
+        match self {
+            Self::Contains => &[Kind::String, Kind::String],
+            Self::Del | Self::Exists => &[Kind::Path],
+            Self::Downcase
+            | Self::ParseJson
+            | Self::String
+            | Self::StripWhitespace
+            | Self::Upcase => &[Kind::String],
+            Self::IsJson | Self::IsNull | Self::IsObject | Self::IsString => &[Kind::Any],
+            Self::Match | Self::ParseRegex => &[Kind::String, Kind::Regex],
+            Self::Merge => &[Kind::Object, Kind::Object],
+            Self::Now => &[],
+            Self::Replace => &[Kind::String, Kind::StringOrRegex, Kind::String],
+            Self::ToInt | Self::ToString => &[Kind::Scalar],
+        }
+    }
+
+    fn returns(self) -> Option<&'static str> {
+        match self {
+            Self::Contains
+            | Self::Exists
+            | Self::IsJson
+            | Self::IsNull
+            | Self::IsObject
+            | Self::IsString
+            | Self::Match => Some("boolean"),
+            Self::Downcase
+            | Self::Now
+            | Self::Replace
+            | Self::String
+            | Self::StripWhitespace
+            | Self::ToString
+            | Self::Upcase => Some("string"),
+            Self::ToInt => Some("integer"),
+            Self::Merge | Self::ParseRegex => Some("object"),
+            Self::Del | Self::ParseJson => None,
+        }
+    }
+}
+
+// argument types checked when parsing
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Any,
+    Path,
+    Regex,
+    String,
+    StringOrRegex,
+    Object,
+    Scalar,
+}
+
+impl Kind {
+    fn accepts(self, type_name: &str) -> bool {
+        match self {
+            Self::Any | Self::Path => true,
+            Self::Regex => type_name == "regex",
+            Self::String => type_name == "string",
+            Self::StringOrRegex => type_name == "string" || type_name == "regex",
+            Self::Object => type_name == "object",
+            Self::Scalar => !matches!(type_name, "regex" | "object" | "array"),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Any => write!(f, "any value"),
+            Self::Path => write!(f, "a path"),
+            Self::Regex => write!(f, "a regex r'...'"),
+            Self::String => write!(f, "a string"),
+            Self::StringOrRegex => write!(f, "a string or a regex r'...'"),
+            Self::Object => write!(f, "an object"),
+            Self::Scalar => write!(f, "a string, number, boolean or null"),
+        }
+    }
+}
+
+// VRL keywords, only `if`, `else`, `true`, `false` and `null` are supported
+const KEYWORDS: [&str; 31] = [
+    "abort",
+    "array",
+    "as",
+    "bool",
+    "break",
+    "continue",
+    "else",
+    "false",
+    "float",
+    "for",
+    "if",
+    "impl",
+    "in",
+    "int",
+    "let",
+    "loop",
+    "null",
+    "object",
+    "regex",
+    "return",
+    "self",
+    "std",
+    "string",
+    "then",
+    "this",
+    "timestamp",
+    "true",
+    "type",
+    "until",
+    "use",
+    "while",
+];
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(Value),
+    Regex(Regex),
+    Target(Target),
+    Object(Vec<(String, Expr)>),
+    Array(Vec<Expr>),
+    Call(Function, Vec<Expr>),
+    Not(Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Coalesce(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    // the type of the value if it is known without running the program
+    fn type_name(&self) -> Option<&'static str> {
+        match self {
+            Self::Literal(v) => Some(type_name(v)),
+            Self::Regex(_) => Some("regex"),
+            Self::Object(_) => Some("object"),
+            Self::Array(_) => Some("array"),
+            Self::Call(f, _) => f.returns(),
+            Self::Not(_) | Self::Eq(..) | Self::Ne(..) | Self::And(..) => Some("boolean"),
+            Self::Target(_) | Self::Or(..) | Self::Coalesce(..) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Assign(Target, Expr),
+    If(Vec<(Expr, Vec<Statement>)>, Vec<Statement>),
+    Expr(Expr),
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    // local variables assigned so far
+    vars: HashSet<String>,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map(|(_, l)| *l)
+            .unwrap_or(1)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ParseError> {
+        Err(ParseError {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        t
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ParseError> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            match self.peek() {
+                Some(t) => self.error(format!("expected {:?}, found {:?}", token, t)),
+                None => self.error(format!("expected {:?}, found end of program", token)),
+            }
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.eat(&Token::Newline) {}
+    }
+
+    // statements until `}` or the end of the program
+    fn statements(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = vec![];
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                None | Some(Token::RBrace) => return Ok(statements),
+                _ => (),
+            }
+            statements.push(self.statement()?);
+            match self.peek() {
+                None | Some(Token::Newline) | Some(Token::RBrace) => (),
+                t => return self.error(format!("unexpected {:?} after statement", t)),
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        self.expect(Token::LBrace)?;
+        let statements = self.statements()?;
+        self.expect(Token::RBrace)?;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        if self.peek() == Some(&Token::Ident("if".to_string())) {
+            return self.if_statement();
+        }
+        let target = match self.peek() {
+            Some(Token::Path(p)) => Some(Target::Event(p.clone())),
+            Some(Token::Var(name, p)) => Some(Target::Var(name.clone(), p.clone())),
+            Some(Token::Ident(name)) => Some(Target::Var(name.clone(), vec![])),
+            _ => None,
+        };
+        let Some(target) = target else {
+            return Ok(Statement::Expr(self.expr()?));
+        };
+        if self.tokens.get(self.pos + 1).map(|(t, _)| t) != Some(&Token::Assign) {
+            return Ok(Statement::Expr(self.expr()?));
+        }
+        match &target {
+            Target::Var(name, _) if KEYWORDS.contains(&name.as_str()) => {
+                return self.error(format!("unsupported keyword {}", name));
+            }
+            _ => (),
+        }
+        self.pos += 2;
+        let expr = self.expr()?;
+        match (&target, expr.type_name()) {
+            (Target::Event(path), Some(t)) if path.is_empty() && t != "object" => {
+                return self.error(format!("the event root must be an object, got {}", t));
+            }
+            (Target::Var(name, _), _) => {
+                self.vars.insert(name.clone());
+            }
+            _ => (),
+        }
+        Ok(Statement::Assign(target, expr))
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+        let mut branches = vec![];
+        let mut otherwise = vec![];
+        loop {
+            // skip `if`
+            self.pos += 1;
+            let condition = self.expr()?;
+            match condition.type_name() {
+                Some(t) if t != "boolean" => {
+                    return self.error(format!("if condition must be a boolean, got {}", t));
+                }
+                _ => (),
+            }
+            branches.push((condition, self.block()?));
+            if self.peek() != Some(&Token::Ident("else".to_string())) {
+                break;
+            }
+            self.pos += 1;
+            if self.peek() == Some(&Token::Ident("if".to_string())) {
+                continue;
+            }
+            otherwise = self.block()?;
+            break;
+        }
+        Ok(Statement::If(branches, otherwise))
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.or()?;
+        while self.eat(&Token::Coalesce) {
+            left = Expr::Coalesce(Box::new(left), Box::new(self.or()?));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.and()?;
+        while self.eat(&Token::Or) {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.equality()?;
+        while self.eat(&Token::And) {
+            left = Expr::And(Box::new(left), Box::new(self.equality()?));
+        }
+        Ok(left)
+    }
+
+    fn equality(&mut self) -> Result<Expr, ParseError> {
+        let left = self.unary()?;
+        if self.eat(&Token::Eq) {
+            return Ok(Expr::Eq(Box::new(left), Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::Ne) {
+            return Ok(Expr::Ne(Box::new(left), Box::new(self.unary()?)));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let expr = match self.next() {
+            Some(Token::Str(s)) => Expr::Literal(Value::String(s)),
+            Some(Token::Number(n)) => Expr::Literal(Value::Number(n)),
+            Some(Token::Regex(r)) => match Regex::new(&r) {
+                Ok(r) => Expr::Regex(r),
+                Err(e) => return self.error(format!("invalid regex: {}", e)),
+            },
+            Some(Token::Path(p)) => Expr::Target(Target::Event(p)),
+            Some(Token::Var(name, p)) => self.variable(name, p)?,
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                "if" | "else" => return self.error(format!("unexpected {}", name)),
+                _ => self.variable(name, vec![])?,
+            },
+            Some(Token::Call(name)) => return self.call(name),
+            Some(Token::LParen) => {
+                let expr = self.expr()?;
+                self.expect(Token::RParen)?;
+                expr
+            }
+            Some(Token::LBrace) => {
+                let mut fields = vec![];
+                loop {
+                    self.skip_newlines();
+                    if self.eat(&Token::RBrace) {
+                        break;
+                    }
+                    let Some(Token::Str(key)) = self.next() else {
+                        self.pos -= 1;
+                        return self.error("expected string key in object");
+                    };
+                    self.expect(Token::Colon)?;
+                    self.skip_newlines();
+                    fields.push((key, self.expr()?));
+                    self.skip_newlines();
+                    if !self.eat(&Token::Comma) {
+                        self.skip_newlines();
+                        self.expect(Token::RBrace)?;
+                        break;
+                    }
+                }
+                Expr::Object(fields)
+            }
+            Some(Token::LBracket) => {
+                let mut items = vec![];
+                loop {
+                    self.skip_newlines();
+                    if self.eat(&Token::RBracket) {
+                        break;
+                    }
+                    items.push(self.expr()?);
+                    self.skip_newlines();
+                    if !self.eat(&Token::Comma) {
+                        self.skip_newlines();
+                        self.expect(Token::RBracket)?;
+                        break;
+                    }
+                }
+                Expr::Array(items)
+            }
+            Some(t) => {
+                self.pos -= 1;
+                return self.error(format!("unexpected {:?}", t));
+            }
+            None => return self.error("unexpected end of program"),
+        };
+        Ok(expr)
+    }
+
+    fn variable(&mut self, name: String, path: Vec<String>) -> Result<Expr, ParseError> {
+        if KEYWORDS.contains(&name.as_str()) {
+            self.pos -= 1;
+            return self.error(format!("unsupported keyword {}", name));
+        }
+        if !self.vars.contains(&name) {
+            self.pos -= 1;
+            return self.error(format!("undefined variable {}", name));
+        }
+        Ok(Expr::Target(Target::Var(name, path)))
+    }
+
+    fn call(&mut self, name: String) -> Result<Expr, ParseError> {
+        let Some(function) = Function::from_name(&name) else {
+            return self.error(format!("unsupported function {}", name));
+        };
+        self.expect(Token::LParen)?;
+        let mut args = vec![];
+        loop {
+            self.skip_newlines();
+            if self.eat(&Token::RParen) {
+                break;
+            }
+            if self.tokens.get(self.pos + 1).map(|(t, _)| t) == Some(&Token::Colon) {
+                return self.error("named arguments are not supported");
+            }
+            args.push(self.expr()?);
+            self.skip_newlines();
+            if !self.eat(&Token::Comma) {
+                self.skip_newlines();
+                self.expect(Token::RParen)?;
+                break;
+            }
+        }
+        let params = function.params();
+        if args.len() != params.len() {
+            return self.error(format!(
+                "function {} takes {} arguments, {} given",
+                name,
+                params.len(),
+                args.len()
+            ));
+        }
+        for (i, (kind, arg)) in params.iter().zip(args.iter()).enumerate() {
+            let accepted = match (kind, arg.type_name()) {
+                (Kind::Path, _) => matches!(arg, Expr::Target(_)),
+                (Kind::Regex, _) => matches!(arg, Expr::Regex(_)),
+                (_, Some(t)) => kind.accepts(t),
+                (_, None) => true,
+            };
+            if !accepted {
+                return self.error(format!(
+                    "argument {} of function {} must be {}",
+                    i + 1,
+                    name,
+                    kind
+                ));
+            }
+        }
+        Ok(Expr::Call(function, args))
+    }
+}
+
+fn type_name(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn expect_string(v: Value) -> Result<String, String> {
+    match v {
+        Value::String(s) => Ok(s),
+        v => Err(format!("expected string, got {}", type_name(&v))),
+    }
+}
+
+fn expect_object(v: Value) -> Result<Map<String, Value>, String> {
+    match v {
+        Value::Object(o) => Ok(o),
+        v => Err(format!("expected object, got {}", type_name(&v))),
+    }
+}
+
+fn truthy(v: &Value) -> bool {
+    !matches!(v, Value::Null | Value::Bool(false))
+}
+
+fn get_path<'a>(mut value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    for segment in path {
+        value = value.as_object()?.get(segment)?;
+    }
+    Some(value)
+}
+
+fn set_path(value: &mut Value, path: &[String], new: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        *value = new;
+        return;
+    };
+    let mut current = value;
+    for segment in parents {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current
+            .as_object_mut()
+            .unwrap()
+            .entry(segment.clone())
+            .or_insert(Value::Null);
+    }
+    if !current.is_object() {
+        *current = Value::Object(Map::new());
+    }
+    current.as_object_mut().unwrap().insert(last.clone(), new);
+}
+
+fn remove_path(value: &mut Value, path: &[String]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+    let mut current = value;
+    for segment in parents {
+        current = current.as_object_mut()?.get_mut(segment)?;
+    }
+    current.as_object_mut()?.remove(last)
+}
+
+struct State<'a> {
+    // the event is kept as a value so that the root can be handled as any other path
+    event: &'a mut Value,
+    vars: HashMap<String, Value>,
+}
+
+impl<'a> State<'a> {
+    fn get(&self, target: &Target) -> Option<&Value> {
+        match target {
+            Target::Event(path) => get_path(self.event, path),
+            Target::Var(name, path) => get_path(self.vars.get(name)?, path),
+        }
+    }
+
+    fn set(&mut self, target: &Target, value: Value) -> Result<(), String> {
+        match target {
+            Target::Event(path) if path.is_empty() => {
+                if !value.is_object() {
+                    return Err(format!(
+                        "the event root must be an object, got {}",
+                        type_name(&value)
+                    ));
+                }
+                *self.event = value;
+            }
+            Target::Event(path) => set_path(self.event, path, value),
+            Target::Var(name, path) => {
+                set_path(self.vars.entry(name.clone()).or_default(), path, value)
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, target: &Target) -> Option<Value> {
+        match target {
+            Target::Event(path) if path.is_empty() => {
+                Some(std::mem::replace(self.event, Value::Object(Map::new())))
+            }
+            Target::Event(path) => remove_path(self.event, path),
+            Target::Var(name, path) if path.is_empty() => self.vars.remove(name),
+            Target::Var(name, path) => remove_path(self.vars.get_mut(name)?, path),
+        }
+    }
+
+    fn execute(&mut self, statements: &[Statement]) -> Result<(), String> {
+        for statement in statements {
+            match statement {
+                Statement::Assign(target, expr) => {
+                    let value = self.eval(expr)?;
+                    self.set(target, value)?;
+                }
+                Statement::If(branches, otherwise) => {
+                    let mut matched = false;
+                    for (condition, block) in branches {
+                        match self.eval(condition)? {
+                            Value::Bool(true) => {
+                                self.execute(block)?;
+                                matched = true;
+                                break;
+                            }
+                            Value::Bool(false) | Value::Null => (),
+                            v => {
+                                return Err(format!(
+                                    "if condition must be a boolean, got {}",
+                                    type_name(&v)
+                                ))
+                            }
+                        }
+                    }
+                    if !matched {
+                        self.execute(otherwise)?;
+                    }
+                }
+                Statement::Expr(expr) => {
+                    self.eval(expr)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        let value = match expr {
+            Expr::Literal(v) => v.clone(),
+            Expr::Regex(r) => Value::String(r.as_str().to_string()),
+            Expr::Target(t) => self.get(t).cloned().unwrap_or_default(),
+            Expr::Object(fields) => {
+                let mut object = Map::new();
+                for (key, expr) in fields {
+                    object.insert(key.clone(), self.eval(expr)?);
+                }
+                Value::Object(object)
+            }
+            Expr::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|e| self.eval(e))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Call(function, args) => self.call(*function, args)?,
+            Expr::Not(e) => Value::Bool(!truthy(&self.eval(e)?)),
+            Expr::Eq(l, r) => Value::Bool(self.eval(l)? == self.eval(r)?),
+            Expr::Ne(l, r) => Value::Bool(self.eval(l)? != self.eval(r)?),
+            Expr::And(l, r) => {
+                let left = self.eval(l)?;
+                if truthy(&left) {
+                    Value::Bool(truthy(&self.eval(r)?))
+                } else {
+                    Value::Bool(false)
+                }
+            }
+            // returns the left value if it is neither null nor false, like `tags.message || tags.msg`
+            Expr::Or(l, r) => {
+                let left = self.eval(l)?;
+                if truthy(&left) {
+                    left
+                } else {
+                    self.eval(r)?
+                }
+            }
+            Expr::Coalesce(l, r) => match self.eval(l) {
+                Ok(v) => v,
+                Err(_) => self.eval(r)?,
+            },
+        };
+        Ok(value)
+    }
+
+    fn call(&mut self, function: Function, args: &[Expr]) -> Result<Value, String> {
+        // functions taking a path instead of a value
+        match (function, args.first()) {
+            (Function::Del, Some(Expr::Target(t))) => {
+                return Ok(self.remove(t).unwrap_or_default())
+            }
+            (Function::Exists, Some(Expr::Target(t))) => {
+                return Ok(Value::Bool(self.get(t).is_some()))
+            }
+            _ => (),
+        }
+        let regex = match args.get(1) {
+            Some(Expr::Regex(r)) => Some(r),
+            _ => None,
+        };
+        let mut values = vec![];
+        for arg in args {
+            values.push(self.eval(arg)?);
+        }
+        let mut values = values.into_iter();
+        let mut arg = || values.next().unwrap_or_default();
+        let value = match function {
+            Function::Contains => {
+                let s = expect_string(arg())?;
+                Value::Bool(s.contains(&expect_string(arg())?))
+            }
+            Function::Downcase => Value::String(expect_string(arg())?.to_lowercase()),
+            Function::Upcase => Value::String(expect_string(arg())?.to_uppercase()),
+            Function::StripWhitespace => Value::String(expect_string(arg())?.trim().to_string()),
+            Function::IsJson => match arg() {
+                Value::String(s) => Value::Bool(serde_json::from_str::<Value>(&s).is_ok()),
+                _ => Value::Bool(false),
+            },
+            Function::IsNull => Value::Bool(arg().is_null()),
+            Function::IsObject => Value::Bool(arg().is_object()),
+            Function::IsString => Value::Bool(arg().is_string()),
+            Function::Match => {
+                let s = expect_string(arg())?;
+                Value::Bool(regex.map(|r| r.is_match(&s)).unwrap_or_default())
+            }
+            Function::Merge => {
+                let mut to = expect_object(arg())?;
+                to.extend(expect_object(arg())?);
+                Value::Object(to)
+            }
+            Function::Now => Value::String(Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true)),
+            Function::ParseJson => {
+                let s = expect_string(arg())?;
+                serde_json::from_str(&s).map_err(|e| format!("unable to parse json: {}", e))?
+            }
+            Function::ParseRegex => {
+                let s = expect_string(arg())?;
+                let regex = regex.unwrap();
+                let Some(captures) = regex.captures(&s) else {
+                    return Err("could not find any pattern matches".to_string());
+                };
+                let mut object = Map::new();
+                for name in regex.capture_names().flatten() {
+                    if let Some(m) = captures.name(name) {
+                        object.insert(name.to_string(), Value::String(m.as_str().to_string()));
+                    }
+                }
+                Value::Object(object)
+            }
+            Function::Replace => {
+                let s = expect_string(arg())?;
+                let pattern = arg();
+                let with = expect_string(arg())?;
+                match regex {
+                    Some(r) => Value::String(r.replace_all(&s, with.as_str()).into_owned()),
+                    None => Value::String(s.replace(&expect_string(pattern)?, &with)),
+                }
+            }
+            Function::String => Value::String(expect_string(arg())?),
+            Function::ToInt => match arg() {
+                Value::Null => Value::from(0),
+                Value::Bool(b) => Value::from(b as i64),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => Value::from(i),
+                    None => Value::from(n.as_f64().unwrap_or_default() as i64),
+                },
+                Value::String(s) => s
+                    .trim()
+                    .parse::<i64>()
+                    .map(Value::from)
+                    .map_err(|e| format!("unable to parse {:?} as integer: {}", s, e))?,
+                v => return Err(format!("unable to convert {} to integer", type_name(&v))),
+            },
+            Function::ToString => match arg() {
+                Value::String(s) => Value::String(s),
+                Value::Null => Value::String(String::new()),
+                Value::Bool(b) => Value::String(b.to_string()),
+                Value::Number(n) => Value::String(n.to_string()),
+                v => return Err(format!("unable to convert {} to string", type_name(&v))),
+            },
+            Function::Del | Function::Exists => unreachable!(),
+        };
+        Ok(value)
+    }
+}
+
+/// A parsed remap program
+#[derive(Clone, Debug)]
+pub struct Program {
+    statements: Vec<Statement>,
+}
+
+impl Program {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            vars: HashSet::new(),
+        };
+        let statements = parser.statements()?;
+        if let Some(t) = parser.peek() {
+            return parser.error(format!("unexpected {:?}", t));
+        }
+        Ok(Self { statements })
+    }
+
+    /// Runs the program on the event, the event is left unmodified on error
+    pub fn run(&self, event: &mut Event) -> Result<(), String> {
+        let mut value = Value::Object(std::mem::take(event));
+        let original = value.clone();
+        let mut state = State {
+            event: &mut value,
+            vars: HashMap::new(),
+        };
+        let result = state.execute(&self.statements);
+        let value = if result.is_ok() { value } else { original };
+        if let Value::Object(o) = value {
+            *event = o;
+        }
+        result
+    }
+}
+
+impl<'de> Deserialize<'de> for Program {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Program::parse(&source).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use serde_json::json;
+
+    use super::*;
+
+    fn run(source: &str, event: Value) -> (Result<(), String>, Value) {
+        let program = Program::parse(source).unwrap();
+        let mut event = match event {
+            Value::Object(o) => o,
+            _ => unreachable!(),
+        };
+        let result = program.run(&mut event);
+        (result, Value::Object(event))
+    }
+
+    #[test]
+    fn assignments() {
+        let (result, event) = run(
+            r#"
+            # comment
+            .app_service = .kubernetes.container_name; .level = upcase("warn")
+            ."a.b" = 1
+            .nested.x = [true, null, 1.5, -2]
+            del(.kubernetes)
+            "#,
+            json!({"message": "m", "kubernetes": {"container_name": "c"}}),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            event,
+            json!({
+                "message": "m",
+                "app_service": "c",
+                "level": "WARN",
+                "a.b": 1,
+                "nested": {"x": [true, null, 1.5, -2]},
+            })
+        );
+    }
+
+    // the remap program of server/agent_config/vector_k8s_logs.yaml
+    const K8S_LOGS: &str = r#"
+        if is_string(.message) && is_json(string!(.message)) {
+            tags = parse_json(.message) ?? {}
+            ._df_log_type = tags._df_log_type
+            .org_id = to_int(tags.org_id) ?? 0
+            .user_id = to_int(tags.user_id) ?? 0
+            .message = tags.message || tags.msg
+            del(tags._df_log_type)
+            del(tags.org_id)
+            del(tags.user_id)
+            del(tags.message)
+            del(tags.msg)
+            .json = tags
+        }
+        if !exists(.level) {
+           if exists(.json) {
+              .level = to_string!(.json.level)
+              del(.json.level)
+           } else {
+             level_tags = parse_regex(.message, r'[\[\\<](?<level>(?i)INFOR?(MATION)?|WARN(ING)?|DEBUG?|ERROR?|TRACE|FATAL|CRIT(ICAL)?)[\]\\>]') ?? {}
+             if !exists(level_tags.level) {
+                level_tags = parse_regex(.message, r'[\s](?<level>INFOR?(MATION)?|WARN(ING)?|DEBUG?|ERROR?|TRACE|FATAL|CRIT(ICAL)?)[\s]') ?? {}
+             }
+             if exists(level_tags.level) {
+                level_tags.level = upcase(string!(level_tags.level))
+                if level_tags.level == "INFORMATION" || level_tags.level == "INFOMATION" {
+                    level_tags.level = "INFO"
+                }
+                if level_tags.level == "WARNING" {
+                    level_tags.level = "WARN"
+                }
+                .level = level_tags.level
+             }
+           }
+        }
+        if !exists(._df_log_type) {
+            ._df_log_type = "system"
+        }
+        if !exists(.app_service) {
+            .app_service = .kubernetes.container_name
+        }
+    "#;
+
+    #[test]
+    fn k8s_logs() {
+        let (result, event) = run(
+            K8S_LOGS,
+            json!({
+                "message": r#"{"msg":"hello","level":"error","org_id":"2","path":"/"}"#,
+                "kubernetes": {"container_name": "server"},
+            }),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            event,
+            json!({
+                "message": "hello",
+                "level": "error",
+                // the field exists with a null value, same as vector
+                "_df_log_type": null,
+                "org_id": 2,
+                "user_id": 0,
+                "json": {"path": "/"},
+                "app_service": "server",
+                "kubernetes": {"container_name": "server"},
+            })
+        );
+
+        let (result, event) = run(
+            K8S_LOGS,
+            json!({"message": "2024-01-01 [Warning] disk almost full", "app_service": "a"}),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(event["level"], "WARN");
+        assert_eq!(event["app_service"], "a");
+
+        let (result, event) = run(K8S_LOGS, json!({"message": "no level here"}));
+        assert_eq!(result, Ok(()));
+        assert!(event.get("level").is_none());
+        assert_eq!(event["app_service"], Value::Null);
+    }
+
+    #[test]
+    fn functions() {
+        let (result, event) = run(
+            r#"
+            .message = replace(string!(.message), r'\u001B\[([0-9]{1,3}(;[0-9]{1,3})*)?m', "")
+            .plain = replace(.message, "o", "0")
+            .matched = match(.message, r'^hel+o')
+            . = merge(., {"merged": true, "count": to_int("12")})
+            if contains(.message, "x") {
+                .branch = 1
+            } else if .matched == true {
+                .branch = 2
+            } else {
+                .branch = 3
+            }
+            "#,
+            json!({"message": "\u{1b}[32mhello\u{1b}[0m"}),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            event,
+            json!({
+                "message": "hello",
+                "plain": "hell0",
+                "matched": true,
+                "merged": true,
+                "count": 12,
+                "branch": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn now() {
+        let (result, event) = run(".timestamp = now()", json!({}));
+        assert_eq!(result, Ok(()));
+        let timestamp = event["timestamp"].as_str().unwrap();
+        assert!(DateTime::parse_from_rfc3339(timestamp).is_ok());
+    }
+
+    #[test]
+    fn runtime_error() {
+        let (result, event) = run(
+            ".a = 1\n.b = upcase(.missing)",
+            json!({"message": "unchanged"}),
+        );
+        assert_eq!(result, Err("expected string, got null".to_string()));
+        assert_eq!(event, json!({"message": "unchanged"}));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (source, line) in [
+            (".a = ", 1),
+            (".a = unknown_fn(1)", 1),
+            ("\n.a = upcase(1, 2)", 2),
+            ("del(1)", 1),
+            (".a = parse_regex(.m, \"x\")", 1),
+            (".a = r'('", 1),
+            ("if true { .a = 1", 1),
+            (".a = \"unterminated", 1),
+            (".a = 1 .b = 2", 1),
+            (".a = .b[0]", 1),
+            (".a = .b + 1", 1),
+            ("abort", 1),
+            ("if .a {\n  return\n}", 2),
+            ("\n.a = x", 2),
+            ("x = x", 1),
+            ("if .a { x = 1 }\n.b = y.a", 2),
+            ("if \"a\" { .a = 1 }", 1),
+            (".a = upcase(1)", 1),
+            (".a = merge(., [1])", 1),
+            (".a = to_int({})", 1),
+            (". = \"a\"", 1),
+            (".a = parse_json(value: .b)", 1),
+        ] {
+            match Program::parse(source) {
+                Ok(_) => panic!("{:?} should not parse", source),
+                Err(e) => assert_eq!(e.line, line, "{:?}: {}", source, e),
+            }
+        }
+    }
+}
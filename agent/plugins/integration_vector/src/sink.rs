@@ -0,0 +1,203 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::{atomic::Ordering, Arc};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, info};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::config::SinkConfig;
+use crate::pipeline::Output;
+use crate::vector_component::VectorCounter;
+use crate::Event;
+
+// fields of the application log entry decoded by the server
+const STRING_FIELDS: [&str; 3] = ["level", "app_service", "_df_log_type"];
+const INTEGER_FIELDS: [&str; 2] = ["user_id", "org_id"];
+const KUBERNETES_FIELDS: [&str; 2] = ["pod_name", "pod_ip"];
+
+fn to_string(value: Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s),
+        v => Some(v.to_string()),
+    }
+}
+
+// Makes the event decodable as an application log entry, returns None for events without message
+fn to_entry(mut event: Event) -> Option<Value> {
+    let message = event.remove("message").and_then(to_string)?;
+    if message.is_empty() {
+        return None;
+    }
+    event.insert("message".into(), Value::String(message));
+
+    let timestamp = match event.get("timestamp") {
+        Some(Value::String(s)) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.with_timezone(&Utc)),
+        _ => None,
+    }
+    .unwrap_or_else(Utc::now);
+    event.insert(
+        "timestamp".into(),
+        Value::String(timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)),
+    );
+
+    for key in STRING_FIELDS {
+        if let Some(value) = event.remove(key).and_then(to_string) {
+            event.insert(key.into(), Value::String(value));
+        }
+    }
+    for key in INTEGER_FIELDS {
+        let value = match event.remove(key) {
+            Some(Value::Number(n)) => n.as_i64(),
+            Some(Value::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+        if let Some(value) = value {
+            event.insert(key.into(), Value::from(value));
+        }
+    }
+    match event.get_mut("kubernetes") {
+        Some(Value::Object(kubernetes)) => {
+            for key in KUBERNETES_FIELDS {
+                if let Some(value) = kubernetes.remove(key).and_then(to_string) {
+                    kubernetes.insert(key.into(), Value::String(value));
+                }
+            }
+        }
+        Some(_) => {
+            event.remove("kubernetes");
+        }
+        None => (),
+    }
+    Some(Value::Object(event))
+}
+
+struct Batch {
+    entries: Vec<Value>,
+    output: Output,
+    counter: Arc<VectorCounter>,
+}
+
+impl Batch {
+    fn flush(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let count = self.entries.len() as u64;
+        let sent = match serde_json::to_vec(&self.entries) {
+            Ok(data) => (self.output)(data),
+            Err(e) => {
+                debug!("encode application logs failed: {}", e);
+                false
+            }
+        };
+        self.entries.clear();
+        if sent {
+            self.counter.events_out.fetch_add(count, Ordering::Relaxed);
+        } else {
+            self.counter.dropped.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+}
+
+pub(crate) async fn run(
+    id: String,
+    config: SinkConfig,
+    mut receiver: mpsc::Receiver<Event>,
+    output: Output,
+    counter: Arc<VectorCounter>,
+) {
+    let SinkConfig::ApplicationLog(config) = config;
+    let max_events = config.batch.max_events.max(1);
+    let mut batch = Batch {
+        entries: Vec::with_capacity(max_events),
+        output,
+        counter,
+    };
+    let mut ticker = time::interval(config.batch.timeout());
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            event = receiver.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                match to_entry(event) {
+                    Some(entry) => batch.entries.push(entry),
+                    None => {
+                        batch.counter.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                if batch.entries.len() >= max_events {
+                    batch.flush();
+                }
+            }
+            _ = ticker.tick() => batch.flush(),
+        }
+    }
+    batch.flush();
+    info!("vector sink {} stopped", id);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn entry(event: Value) -> Option<Value> {
+        match event {
+            Value::Object(o) => to_entry(o),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn entries() {
+        assert_eq!(
+            entry(json!({
+                "message": {"k": "v"},
+                "timestamp": "2024-03-01T08:00:00+08:00",
+                "level": null,
+                "app_service": 1,
+                "org_id": "2",
+                "user_id": "x",
+                "kubernetes": {"pod_name": "p", "pod_ip": null, "container_name": "c"},
+                "json": {"a": "b"},
+            })),
+            Some(json!({
+                "message": "{\"k\":\"v\"}",
+                "timestamp": "2024-03-01T00:00:00.000000Z",
+                "app_service": "1",
+                "org_id": 2,
+                "kubernetes": {"pod_name": "p", "container_name": "c"},
+                "json": {"a": "b"},
+            }))
+        );
+        assert_eq!(entry(json!({"message": ""})), None);
+        assert_eq!(entry(json!({"level": "INFO"})), None);
+        let e =
+            entry(json!({"message": "m", "timestamp": "yesterday", "kubernetes": "x"})).unwrap();
+        assert!(DateTime::parse_from_rfc3339(e["timestamp"].as_str().unwrap()).is_ok());
+        assert!(e.get("kubernetes").is_none());
+    }
+}
@@ -0,0 +1,44 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod file;
+mod journald;
+mod kubernetes_logs;
+
+use std::sync::Arc;
+
+use log::info;
+use tokio::sync::watch;
+
+use crate::config::SourceConfig;
+use crate::pipeline::Fanout;
+use crate::vector_component::VectorCounter;
+
+pub(crate) async fn run(
+    id: String,
+    config: SourceConfig,
+    fanout: Fanout,
+    counter: Arc<VectorCounter>,
+    stop: watch::Receiver<bool>,
+) {
+    info!("vector source {} started", id);
+    match config {
+        SourceConfig::File(c) => file::run(c, fanout, counter, stop).await,
+        SourceConfig::Journald(c) => journald::run(c, fanout, counter, stop).await,
+        SourceConfig::KubernetesLogs(c) => kubernetes_logs::run(c, fanout, counter, stop).await,
+    }
+    info!("vector source {} stopped", id);
+}
@@ -0,0 +1,302 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::Ordering, Arc};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use serde_json::Value;
+use tokio::sync::watch;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::config::{FileConfig, ReadFrom};
+use crate::glob::Pattern;
+use crate::pipeline::Fanout;
+use crate::vector_component::VectorCounter;
+use crate::{now, Event};
+
+pub(super) const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// bytes read from one file in one poll, the rest is read in the next polls
+const MAX_READ_BYTES: u64 = 4 << 20;
+
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.ino()
+}
+
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> u64 {
+    0
+}
+
+#[derive(Debug, Default)]
+struct FileState {
+    id: u64,
+    offset: u64,
+    // the incomplete last line, at most `max_line_bytes`
+    partial: Vec<u8>,
+}
+
+/// Follows the lines appended to files matching the patterns, handling rotation and truncation
+pub(super) struct Tailer {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    read_from: ReadFrom,
+    glob_cooldown: Duration,
+    max_line_bytes: usize,
+    files: HashMap<PathBuf, FileState>,
+    last_glob: Option<Instant>,
+}
+
+impl Tailer {
+    pub(super) fn new(
+        include: Vec<Pattern>,
+        exclude: Vec<Pattern>,
+        read_from: ReadFrom,
+        glob_cooldown_ms: u64,
+        max_line_bytes: usize,
+    ) -> Self {
+        Self {
+            include,
+            exclude,
+            read_from,
+            glob_cooldown: Duration::from_millis(glob_cooldown_ms),
+            max_line_bytes: max_line_bytes.max(1),
+            files: HashMap::new(),
+            last_glob: None,
+        }
+    }
+
+    fn discover(&mut self) {
+        let first = match self.last_glob {
+            Some(last) if last.elapsed() < self.glob_cooldown => return,
+            Some(_) => false,
+            None => true,
+        };
+        self.last_glob = Some(Instant::now());
+
+        let mut found = HashSet::new();
+        for pattern in self.include.iter() {
+            for path in pattern.files() {
+                if !self.exclude.iter().any(|p| p.matches(&path)) {
+                    found.insert(path);
+                }
+            }
+        }
+        self.files.retain(|path, _| found.contains(path));
+        for path in found {
+            if self.files.contains_key(&path) {
+                continue;
+            }
+            let Ok(meta) = fs::metadata(&path) else {
+                continue;
+            };
+            // files created after startup are always read from the beginning
+            let offset = if first && self.read_from == ReadFrom::End {
+                meta.len()
+            } else {
+                0
+            };
+            debug!("vector start following {} from {}", path.display(), offset);
+            self.files.insert(
+                path,
+                FileState {
+                    id: file_id(&meta),
+                    offset,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    fn push_line(state: &mut FileState, path: &Path, lines: &mut Vec<(PathBuf, String)>) {
+        let mut line = mem::take(&mut state.partial);
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        lines.push((
+            path.to_path_buf(),
+            String::from_utf8_lossy(&line).into_owned(),
+        ));
+    }
+
+    fn read(
+        path: &PathBuf,
+        state: &mut FileState,
+        max_line_bytes: usize,
+        lines: &mut Vec<(PathBuf, String)>,
+    ) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        let meta = file.metadata()?;
+        let id = file_id(&meta);
+        if id != state.id || meta.len() < state.offset {
+            debug!("vector file {} rotated or truncated", path.display());
+            *state = FileState {
+                id,
+                ..Default::default()
+            };
+        }
+        if meta.len() == state.offset {
+            return Ok(());
+        }
+        file.seek(SeekFrom::Start(state.offset))?;
+        let mut buf = vec![];
+        file.take(MAX_READ_BYTES).read_to_end(&mut buf)?;
+        state.offset += buf.len() as u64;
+
+        // lines longer than `max_line_bytes` are truncated
+        let append = |partial: &mut Vec<u8>, data: &[u8]| {
+            let room = max_line_bytes.saturating_sub(partial.len());
+            partial.extend_from_slice(&data[..room.min(data.len())]);
+        };
+        let mut rest = buf.as_slice();
+        while let Some(i) = rest.iter().position(|b| *b == b'\n') {
+            append(&mut state.partial, &rest[..i]);
+            Self::push_line(state, path, lines);
+            rest = &rest[i + 1..];
+        }
+        append(&mut state.partial, rest);
+        Ok(())
+    }
+
+    /// Returns the new complete lines with their file paths
+    pub(super) fn poll(&mut self, counter: &VectorCounter) -> Vec<(PathBuf, String)> {
+        self.discover();
+        let mut lines = vec![];
+        for (path, state) in self.files.iter_mut() {
+            if let Err(e) = Self::read(path, state, self.max_line_bytes, &mut lines) {
+                counter.read_errors.fetch_add(1, Ordering::Relaxed);
+                debug!("vector read {} failed: {}", path.display(), e);
+            }
+        }
+        lines
+    }
+}
+
+/// Polls the tailer in a blocking thread until stopped, passing new lines to `handle`
+pub(super) async fn follow(
+    mut tailer: Tailer,
+    counter: Arc<VectorCounter>,
+    mut stop: watch::Receiver<bool>,
+    mut handle: impl FnMut(PathBuf, String) -> Option<Event>,
+    fanout: Fanout,
+) {
+    let mut ticker = time::interval(POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = stop.changed() => return,
+            _ = ticker.tick() => (),
+        }
+        let c = counter.clone();
+        let lines;
+        (tailer, lines) = match tokio::task::spawn_blocking(move || {
+            let lines = tailer.poll(&c);
+            (tailer, lines)
+        })
+        .await
+        {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        for (path, line) in lines {
+            if let Some(event) = handle(path, line) {
+                counter.events_in.fetch_add(1, Ordering::Relaxed);
+                fanout.send(event).await;
+            }
+        }
+    }
+}
+
+pub(super) async fn run(
+    config: FileConfig,
+    fanout: Fanout,
+    counter: Arc<VectorCounter>,
+    stop: watch::Receiver<bool>,
+) {
+    let tailer = Tailer::new(
+        config.include,
+        config.exclude,
+        config.read_from,
+        config.glob_minimum_cooldown_ms,
+        config.max_line_bytes,
+    );
+    let handle = |path: PathBuf, line: String| {
+        let mut event = Event::new();
+        event.insert("message".into(), Value::String(line));
+        event.insert("file".into(), Value::String(path.display().to_string()));
+        event.insert("source_type".into(), "file".into());
+        event.insert("timestamp".into(), Value::String(now()));
+        Some(event)
+    };
+    follow(tailer, counter, stop, handle, fanout).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use super::*;
+
+    fn poll(tailer: &mut Tailer) -> Vec<String> {
+        let mut lines: Vec<_> = tailer
+            .poll(&VectorCounter::default())
+            .into_iter()
+            .map(|(p, l)| format!("{}:{}", p.file_name().unwrap().to_str().unwrap(), l))
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.log");
+        fs::write(&path, "old\n").unwrap();
+        let pattern = Pattern::new(&format!("{}/*.log", dir.path().display())).unwrap();
+        let mut tailer = Tailer::new(vec![pattern], vec![], ReadFrom::End, 0, 8);
+        assert!(poll(&mut tailer).is_empty());
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"one\r\ntw").unwrap();
+        fs::write(dir.path().join("b.log"), "new file\n").unwrap();
+        assert_eq!(poll(&mut tailer), vec!["a.log:one", "b.log:new file"]);
+
+        file.write_all(b"o\nlonger than eight\n").unwrap();
+        assert_eq!(poll(&mut tailer), vec!["a.log:longer t", "a.log:two"]);
+
+        // truncated
+        fs::write(&path, "3\n").unwrap();
+        assert_eq!(poll(&mut tailer), vec!["a.log:3"]);
+
+        // rotated
+        fs::rename(&path, dir.path().join("a.log.1")).unwrap();
+        fs::write(&path, "4\n").unwrap();
+        assert_eq!(poll(&mut tailer), vec!["a.log:4"]);
+
+        let pattern = Pattern::new(&format!("{}/*.log", dir.path().display())).unwrap();
+        let exclude = Pattern::new(&format!("{}/b.*", dir.path().display())).unwrap();
+        let mut tailer = Tailer::new(vec![pattern], vec![exclude], ReadFrom::Beginning, 0, 100);
+        assert_eq!(poll(&mut tailer), vec!["a.log:4"]);
+    }
+}
@@ -0,0 +1,187 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Journald entries read with `journalctl --follow --output=json`
+
+use std::process::Stdio;
+use std::sync::{atomic::Ordering, Arc};
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat};
+use log::{debug, warn};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::watch;
+
+use crate::config::JournaldConfig;
+use crate::pipeline::Fanout;
+use crate::vector_component::VectorCounter;
+use crate::{now, Event};
+
+const RESTART_INTERVAL: Duration = Duration::from_secs(10);
+
+fn field_string(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        // journald exports non-utf8 fields as byte arrays
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .filter_map(|b| b.as_u64().map(|b| b as u8))
+                .collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        v => v.to_string(),
+    }
+}
+
+fn journald_event(line: &str, exclude_units: &[String]) -> Option<Event> {
+    let Ok(Value::Object(fields)) = serde_json::from_str(line) else {
+        return None;
+    };
+    if let Some(Value::String(unit)) = fields.get("_SYSTEMD_UNIT") {
+        if exclude_units.contains(unit) {
+            return None;
+        }
+    }
+    let mut event = Event::new();
+    let mut timestamp = None;
+    for (key, value) in fields {
+        match key.as_str() {
+            "MESSAGE" => {
+                event.insert("message".into(), Value::String(field_string(value)));
+            }
+            "_HOSTNAME" => {
+                event.insert("host".into(), Value::String(field_string(value)));
+            }
+            "__REALTIME_TIMESTAMP" => {
+                timestamp = field_string(value)
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|us| {
+                        DateTime::from_timestamp(
+                            us.div_euclid(1_000_000),
+                            us.rem_euclid(1_000_000) as u32 * 1000,
+                        )
+                    })
+                    .map(|t| t.to_rfc3339_opts(SecondsFormat::Micros, true));
+            }
+            // cursors and other internal fields
+            k if k.starts_with("__") => (),
+            _ => {
+                event.insert(key, Value::String(field_string(value)));
+            }
+        }
+    }
+    event.insert("source_type".into(), "journald".into());
+    event.insert(
+        "timestamp".into(),
+        Value::String(timestamp.unwrap_or_else(now)),
+    );
+    Some(event)
+}
+
+fn command(config: &JournaldConfig) -> Command {
+    let mut command = Command::new(&config.journalctl_path);
+    command
+        .args(["--follow", "--output=json", "--since=now", "--no-pager"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    if config.current_boot_only {
+        command.arg("--boot");
+    }
+    for unit in config.include_units.iter() {
+        command.arg(format!("--unit={}", unit));
+    }
+    command
+}
+
+pub(super) async fn run(
+    config: JournaldConfig,
+    fanout: Fanout,
+    counter: Arc<VectorCounter>,
+    mut stop: watch::Receiver<bool>,
+) {
+    loop {
+        match command(&config).spawn() {
+            Ok(mut child) => {
+                let stdout = child.stdout.take().unwrap();
+                let mut lines = BufReader::new(stdout).lines();
+                loop {
+                    let line = tokio::select! {
+                        _ = stop.changed() => return,
+                        line = lines.next_line() => line,
+                    };
+                    match line {
+                        Ok(Some(line)) => {
+                            if let Some(event) = journald_event(&line, &config.exclude_units) {
+                                counter.events_in.fetch_add(1, Ordering::Relaxed);
+                                fanout.send(event).await;
+                            }
+                        }
+                        Ok(None) => {
+                            debug!("{} exited", config.journalctl_path);
+                            break;
+                        }
+                        Err(e) => {
+                            counter.read_errors.fetch_add(1, Ordering::Relaxed);
+                            debug!("read from {} failed: {}", config.journalctl_path, e);
+                            break;
+                        }
+                    }
+                }
+                let _ = child.kill().await;
+            }
+            Err(e) => {
+                counter.read_errors.fetch_add(1, Ordering::Relaxed);
+                warn!("run {} failed: {}", config.journalctl_path, e);
+            }
+        }
+        tokio::select! {
+            _ = stop.changed() => return,
+            _ = tokio::time::sleep(RESTART_INTERVAL) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn event() {
+        let line = r#"{"__CURSOR":"s=1","__REALTIME_TIMESTAMP":"1700000000123456","_HOSTNAME":"node-1","_SYSTEMD_UNIT":"kubelet.service","PRIORITY":"6","MESSAGE":[104,105,255]}"#;
+        let event = journald_event(line, &[]).unwrap();
+        assert_eq!(
+            Value::Object(event),
+            json!({
+                "message": "hi\u{fffd}",
+                "host": "node-1",
+                "_SYSTEMD_UNIT": "kubelet.service",
+                "PRIORITY": "6",
+                "source_type": "journald",
+                "timestamp": "2023-11-14T22:13:20.123456Z",
+            })
+        );
+        assert!(journald_event(line, &["kubelet.service".to_string()]).is_none());
+        assert!(journald_event("not json", &[]).is_none());
+    }
+}
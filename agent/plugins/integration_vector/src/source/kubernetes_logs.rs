@@ -0,0 +1,240 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Pod logs written by the container runtime under /var/log/pods/<namespace>_<pod>_<uid>/<container>/,
+// in CRI format or in docker json-file format
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+
+use super::file::{follow, Tailer};
+use crate::config::KubernetesLogsConfig;
+use crate::pipeline::Fanout;
+use crate::vector_component::VectorCounter;
+use crate::{now, Event};
+
+#[derive(Debug, PartialEq)]
+struct PodInfo<'a> {
+    namespace: &'a str,
+    name: &'a str,
+    uid: &'a str,
+    container: &'a str,
+}
+
+fn parse_path(path: &Path) -> Option<PodInfo<'_>> {
+    let mut components = path.iter().rev().skip(1).filter_map(|c| c.to_str());
+    let container = components.next()?;
+    let mut pod = components.next()?.splitn(3, '_');
+    Some(PodInfo {
+        namespace: pod.next()?,
+        name: pod.next()?,
+        uid: pod.next()?,
+        container,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+struct LogLine {
+    timestamp: Option<String>,
+    stream: String,
+    // the runtime splits long lines into partial ones
+    partial: bool,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct DockerLine {
+    log: String,
+    #[serde(default)]
+    stream: String,
+    time: Option<String>,
+}
+
+fn normalize_time(s: &str) -> Option<String> {
+    DateTime::parse_from_rfc3339(s).ok().map(|t| {
+        t.with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Micros, true)
+    })
+}
+
+fn parse_line(line: &str) -> Option<LogLine> {
+    if line.starts_with('{') {
+        let d: DockerLine = serde_json::from_str(line).ok()?;
+        let partial = !d.log.ends_with('\n');
+        return Some(LogLine {
+            timestamp: d.time.as_deref().and_then(normalize_time),
+            stream: d.stream,
+            partial,
+            message: d.log.trim_end_matches('\n').to_string(),
+        });
+    }
+    // <time> <stream> <P|F> <message>
+    let mut parts = line.splitn(4, ' ');
+    let time = parts.next()?;
+    let stream = parts.next()?;
+    let tag = parts.next()?;
+    Some(LogLine {
+        timestamp: normalize_time(time),
+        stream: stream.to_string(),
+        partial: tag == "P",
+        message: parts.next().unwrap_or_default().to_string(),
+    })
+}
+
+#[derive(Default)]
+struct Parser {
+    partials: HashMap<PathBuf, String>,
+}
+
+impl Parser {
+    fn event(&mut self, path: PathBuf, line: String) -> Option<Event> {
+        let log = parse_line(&line)?;
+        if log.partial {
+            self.partials
+                .entry(path)
+                .or_default()
+                .push_str(&log.message);
+            return None;
+        }
+        let message = match self.partials.remove(&path) {
+            Some(mut partial) => {
+                partial.push_str(&log.message);
+                partial
+            }
+            None => log.message,
+        };
+
+        let mut event = Event::new();
+        event.insert("message".into(), Value::String(message));
+        event.insert("stream".into(), Value::String(log.stream));
+        event.insert("file".into(), Value::String(path.display().to_string()));
+        event.insert("source_type".into(), "kubernetes_logs".into());
+        event.insert(
+            "timestamp".into(),
+            Value::String(log.timestamp.unwrap_or_else(now)),
+        );
+        if let Some(pod) = parse_path(&path) {
+            let mut kubernetes = Map::new();
+            kubernetes.insert("pod_namespace".into(), pod.namespace.into());
+            kubernetes.insert("pod_name".into(), pod.name.into());
+            kubernetes.insert("pod_uid".into(), pod.uid.into());
+            kubernetes.insert("container_name".into(), pod.container.into());
+            event.insert("kubernetes".into(), Value::Object(kubernetes));
+        }
+        Some(event)
+    }
+}
+
+pub(super) async fn run(
+    config: KubernetesLogsConfig,
+    fanout: Fanout,
+    counter: Arc<VectorCounter>,
+    stop: watch::Receiver<bool>,
+) {
+    let tailer = Tailer::new(
+        config.include_paths_glob_patterns,
+        config.exclude_paths_glob_patterns,
+        config.read_from,
+        config.glob_minimum_cooldown_ms,
+        config.max_line_bytes,
+    );
+    let mut parser = Parser::default();
+    follow(
+        tailer,
+        counter,
+        stop,
+        move |path, line| parser.event(path, line),
+        fanout,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn path() {
+        assert_eq!(
+            parse_path(Path::new(
+                "/var/log/pods/deepflow_deepflow-agent-x2k_4b1f-99/deepflow-agent/0.log"
+            )),
+            Some(PodInfo {
+                namespace: "deepflow",
+                name: "deepflow-agent-x2k",
+                uid: "4b1f-99",
+                container: "deepflow-agent",
+            })
+        );
+        assert_eq!(parse_path(Path::new("/var/log/pods/0.log")), None);
+    }
+
+    #[test]
+    fn lines() {
+        let path = PathBuf::from("/var/log/pods/ns_pod_uid/app/0.log");
+        let mut parser = Parser::default();
+        assert_eq!(
+            parser.event(
+                path.clone(),
+                "2024-03-01T08:00:00.123456789+08:00 stdout P hello ".into()
+            ),
+            None
+        );
+        let event = parser
+            .event(
+                path.clone(),
+                "2024-03-01T08:00:00.2Z stdout F world [INFO]".into(),
+            )
+            .unwrap();
+        assert_eq!(
+            Value::Object(event),
+            json!({
+                "message": "hello world [INFO]",
+                "stream": "stdout",
+                "file": "/var/log/pods/ns_pod_uid/app/0.log",
+                "source_type": "kubernetes_logs",
+                "timestamp": "2024-03-01T08:00:00.200000Z",
+                "kubernetes": {
+                    "pod_namespace": "ns",
+                    "pod_name": "pod",
+                    "pod_uid": "uid",
+                    "container_name": "app",
+                },
+            })
+        );
+
+        let event = parser
+            .event(
+                path,
+                r#"{"log":"error\n","stream":"stderr","time":"2024-03-01T00:00:01.5Z"}"#.into(),
+            )
+            .unwrap();
+        assert_eq!(event["message"], "error");
+        assert_eq!(event["stream"], "stderr");
+        assert_eq!(event["timestamp"], "2024-03-01T00:00:01.500000Z");
+        assert!(parser
+            .event(PathBuf::from("/x"), "garbage".into())
+            .is_none());
+    }
+}
@@ -1,19 +1,189 @@
-use std::sync::Arc;
-use std::thread::JoinHandle;
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt::Debug;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::thread::{self, JoinHandle};
+
+use log::{info, warn};
 use tokio::runtime::Runtime;
+use tokio::sync::watch;
+
+use public::counter::{Counter, CounterType, CounterValue, RefCountable};
+use public::queue::DebugSender;
+
+use crate::config::PipelineConfig;
+use crate::pipeline::{self, Output};
+
+#[derive(Debug, Default)]
+pub struct VectorCounter {
+    pub(crate) events_in: AtomicU64,
+    pub(crate) events_out: AtomicU64,
+    pub(crate) dropped: AtomicU64,
+    pub(crate) remap_errors: AtomicU64,
+    pub(crate) read_errors: AtomicU64,
+}
 
-pub struct VectorComponent {}
+impl RefCountable for VectorCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "events-in",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.events_in.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "events-out",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.events_out.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "dropped",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.dropped.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "remap-errors",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.remap_errors.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "read-errors",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.read_errors.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+/// Runs the log pipeline defined in `inputs.vector.config` when the enterprise Vector binary is
+/// not available, its sinks send application logs to `sender`
+pub struct VectorComponent {
+    enabled: bool,
+    config: serde_yaml::Value,
+    runtime: Arc<Runtime>,
+    output: Output,
+    counter: Arc<VectorCounter>,
+
+    running: bool,
+    stop_sender: Option<watch::Sender<bool>>,
+    thread: Option<JoinHandle<()>>,
+}
 
 impl VectorComponent {
-    pub fn new(_: bool, _: serde_yaml::Value, _: Arc<Runtime>) -> Self {
-        Self {}
+    pub fn new<T>(
+        enabled: bool,
+        config: serde_yaml::Value,
+        runtime: Arc<Runtime>,
+        sender: DebugSender<T>,
+    ) -> Self
+    where
+        T: From<Vec<u8>> + Debug + Send + 'static,
+    {
+        Self {
+            enabled,
+            config,
+            runtime,
+            output: Arc::new(move |data| sender.send(T::from(data)).is_ok()),
+            counter: Default::default(),
+            running: false,
+            stop_sender: None,
+            thread: None,
+        }
     }
 
-    pub fn start(&mut self) {}
+    pub fn counter(&self) -> Arc<VectorCounter> {
+        self.counter.clone()
+    }
+
+    // `previous` is the thread of the stopped pipeline, it is joined in the new thread
+    fn run_pipeline(&mut self, previous: Option<JoinHandle<()>>) {
+        if !self.enabled {
+            return;
+        }
+        let config = match PipelineConfig::from_yaml(&self.config) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("vector component not started: {}", e);
+                return;
+            }
+        };
+        let (stop_sender, stop_receiver) = watch::channel(false);
+        let runtime = self.runtime.clone();
+        let output = self.output.clone();
+        let counter = self.counter.clone();
+        let thread = thread::Builder::new()
+            .name("vector".to_owned())
+            .spawn(move || {
+                // the old pipeline flushes its events and closes its files first
+                if let Some(previous) = previous {
+                    let _ = previous.join();
+                }
+                runtime.block_on(pipeline::run(config, output, counter, stop_receiver));
+            });
+        match thread {
+            Ok(thread) => {
+                self.stop_sender = Some(stop_sender);
+                self.thread = Some(thread);
+            }
+            Err(e) => warn!("vector component not started: {}", e),
+        }
+    }
+
+    fn stop_pipeline(&mut self) -> Option<JoinHandle<()>> {
+        if let Some(sender) = self.stop_sender.take() {
+            let _ = sender.send(true);
+        }
+        self.thread.take()
+    }
+
+    pub fn start(&mut self) {
+        if self.running {
+            return;
+        }
+        self.running = true;
+        self.run_pipeline(None);
+        info!("vector component started");
+    }
 
     pub fn notify_stop(&mut self) -> Option<JoinHandle<()>> {
-        None
+        if !self.running {
+            return None;
+        }
+        self.running = false;
+        info!("notified stopping vector component");
+        self.stop_pipeline()
     }
 
-    pub fn on_config_change(&mut self, _: bool, _: serde_yaml::Value) {}
+    /// Restarts the pipeline with the new config without blocking, the new pipeline starts
+    /// after the old one flushes its events
+    pub fn on_config_change(&mut self, enabled: bool, config: serde_yaml::Value) {
+        if self.enabled == enabled && self.config == config {
+            return;
+        }
+        self.enabled = enabled;
+        self.config = config;
+        if !self.running {
+            return;
+        }
+        let previous = self.stop_pipeline();
+        self.run_pipeline(previous);
+        info!("vector component restarted, enabled: {}", self.enabled);
+    }
 }
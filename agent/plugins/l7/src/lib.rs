@@ -14,5 +14,6 @@
  * limitations under the License.
  */
 
+pub mod oracle;
 pub mod some_ip;
 pub mod tls;
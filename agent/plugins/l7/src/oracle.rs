@@ -0,0 +1,1010 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+
+use bitflags::bitflags;
+use serde::Serialize;
+
+// TNS packet header, all fields are big endian
+//
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-------------------------------+-------------------------------+
+// |         Packet Length         |        Packet Checksum        |
+// +---------------+---------------+-------------------------------+
+// |  Packet Type  |     Flags     |        Header Checksum        |
+// +---------------+---------------+-------------------------------+
+//
+// Since TNS version 315 (Oracle 12c), packets after Connect/Accept use the first 4 bytes
+// as a u32 length, the checksum is never used in both formats.
+const TNS_HEADER_LEN: usize = 8;
+// max SDU of 12c and later
+const TNS_MAX_PACKET_LEN: usize = 2 * 1024 * 1024;
+
+// Data packet: flags (u16) + TTC message
+const DATA_FLAGS_LEN: usize = 2;
+
+// TTC length prefixed bytes longer than this are sent in chunks
+const TTC_MAX_SHORT_LENGTH: u8 = 252;
+const TTC_LONG_LENGTH_INDICATOR: u8 = 0xfe;
+const TTC_NULL_LENGTH_INDICATOR: u8 = 0xff;
+
+// return status is at the end of a response, only the tail is searched
+const RETURN_STATUS_SEARCH_LEN: usize = 512;
+// bytes allowed after a decoded return status, e.g. end of response message
+const RETURN_STATUS_MAX_TRAILER: usize = 16;
+
+const SQL_KEYWORDS: [&[u8]; 21] = [
+    b"SELECT",
+    b"INSERT",
+    b"UPDATE",
+    b"DELETE",
+    b"MERGE",
+    b"WITH",
+    b"BEGIN",
+    b"DECLARE",
+    b"CALL",
+    b"CREATE",
+    b"ALTER",
+    b"DROP",
+    b"TRUNCATE",
+    b"GRANT",
+    b"REVOKE",
+    b"COMMIT",
+    b"ROLLBACK",
+    b"SAVEPOINT",
+    b"LOCK",
+    b"EXPLAIN",
+    b"SET",
+];
+
+pub struct Request {
+    pub sql: String,
+    pub req_data_flags: DataFlags, // 仅 TnsPacketType 为 TnsTypeData 时有值
+    pub req_data_id: Option<DataId>, // 仅 TnsPacketType 为 TnsTypeData 时有值
+    pub req_call_id: Option<CallId>, // 仅 TnsPacketType 为 TnsTypeData 时有值
+}
+
+pub struct Response {
+    pub ret_code: u16,
+    pub affected_rows: Option<u32>,
+    pub error_message: String,
+    pub resp_data_flags: DataFlags, // 仅 TnsPacketType 为 TnsTypeData 时有值
+    pub resp_data_id: Option<DataId>, // 仅 TnsPacketType 为 TnsTypeData 时有值
+}
+
+pub enum Body {
+    Request(Request),
+    Response(Response),
+}
+
+// TTC function codes carried in a function call (0x03) message
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CallId {
+    Reexecute,
+    Fetch,
+    Logoff,
+    Commit,
+    Rollback,
+    Version,
+    All7,
+    ReexecuteAndFetch,
+    Execute,
+    LobOp,
+    TxnSwitch,
+    TxnChangeState,
+    CloseCursors,
+    AuthPhaseTwo,
+    AuthPhaseOne,
+    SetEndToEndAttr,
+    Ping,
+    SetSchema,
+    SessionGet,
+    SessionRelease,
+    Unknown(u8),
+}
+
+impl From<u8> for CallId {
+    fn from(v: u8) -> Self {
+        match v {
+            0x04 => Self::Reexecute,
+            0x05 => Self::Fetch,
+            0x09 => Self::Logoff,
+            0x0e => Self::Commit,
+            0x0f => Self::Rollback,
+            0x3b => Self::Version,
+            0x47 => Self::All7,
+            0x4e => Self::ReexecuteAndFetch,
+            0x5e => Self::Execute,
+            0x60 => Self::LobOp,
+            0x67 => Self::TxnSwitch,
+            0x68 => Self::TxnChangeState,
+            0x69 => Self::CloseCursors,
+            0x73 => Self::AuthPhaseTwo,
+            0x76 => Self::AuthPhaseOne,
+            0x87 => Self::SetEndToEndAttr,
+            0x93 => Self::Ping,
+            0x98 => Self::SetSchema,
+            0xa2 => Self::SessionGet,
+            0xa3 => Self::SessionRelease,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl CallId {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Reexecute => "REEXECUTE",
+            Self::Fetch => "FETCH",
+            Self::Logoff => "LOGOFF",
+            Self::Commit => "COMMIT",
+            Self::Rollback => "ROLLBACK",
+            Self::Version => "VERSION",
+            Self::All7 => "ALL7",
+            Self::ReexecuteAndFetch => "REEXECUTE_AND_FETCH",
+            Self::Execute => "EXECUTE",
+            Self::LobOp => "LOB_OP",
+            Self::TxnSwitch => "TXN_SWITCH",
+            Self::TxnChangeState => "TXN_CHANGE_STATE",
+            Self::CloseCursors => "CLOSE_CURSORS",
+            Self::AuthPhaseTwo => "AUTH_PHASE_TWO",
+            Self::AuthPhaseOne => "AUTH_PHASE_ONE",
+            Self::SetEndToEndAttr => "SET_END_TO_END_ATTR",
+            Self::Ping => "PING",
+            Self::SetSchema => "SET_SCHEMA",
+            Self::SessionGet => "SESSION_GET",
+            Self::SessionRelease => "SESSION_RELEASE",
+            Self::Unknown(_) => "UNKNOWN",
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        !matches!(self, Self::Unknown(_))
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    pub struct DataFlags: u16 {
+        const SEND_TOKEN = 0x0001;
+        const REQUEST_CONFIRMATION = 0x0002;
+        const CONFIRMATION = 0x0004;
+        const RESERVED = 0x0008;
+        const MORE_DATA = 0x0020;
+        const END_OF_FILE = 0x0040;
+        const IMMEDIATE_CONFIRMATION = 0x0080;
+        const REQUEST_TO_SEND = 0x0100;
+        const SEND_NT_TRAILER = 0x0200;
+    }
+}
+
+impl fmt::Display for DataFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NAMES: [(DataFlags, &str); 9] = [
+            (DataFlags::SEND_TOKEN, "SEND_TOKEN"),
+            (DataFlags::REQUEST_CONFIRMATION, "REQUEST_CONFIRMATION"),
+            (DataFlags::CONFIRMATION, "CONFIRMATION"),
+            (DataFlags::RESERVED, "RESERVED"),
+            (DataFlags::MORE_DATA, "MORE_DATA"),
+            (DataFlags::END_OF_FILE, "END_OF_FILE"),
+            (DataFlags::IMMEDIATE_CONFIRMATION, "IMMEDIATE_CONFIRMATION"),
+            (DataFlags::REQUEST_TO_SEND, "REQUEST_TO_SEND"),
+            (DataFlags::SEND_NT_TRAILER, "SEND_NT_TRAILER"),
+        ];
+        let mut first = true;
+        for (flag, name) in NAMES {
+            if !self.contains(flag) {
+                continue;
+            }
+            if !first {
+                write!(f, "|")?;
+            }
+            write!(f, "{}", name)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+// TTC message types, the first byte of the payload of data packets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataId {
+    ProtocolNegotiation,
+    DataTypes,
+    FunctionCall,
+    ReturnStatus,
+    RowHeader,
+    RowData,
+    ReturnParameter,
+    Status,
+    IoVector,
+    DescribeInfo,
+    Piggyback,
+    BitVector,
+    ServerSidePiggyback,
+    EndOfResponse,
+    SecureNetworkServices,
+    Unknown(u8),
+}
+
+impl From<u8> for DataId {
+    fn from(v: u8) -> Self {
+        match v {
+            0x01 => Self::ProtocolNegotiation,
+            0x02 => Self::DataTypes,
+            0x03 => Self::FunctionCall,
+            0x04 => Self::ReturnStatus,
+            0x06 => Self::RowHeader,
+            0x07 => Self::RowData,
+            0x08 => Self::ReturnParameter,
+            0x09 => Self::Status,
+            0x0b => Self::IoVector,
+            0x10 => Self::DescribeInfo,
+            0x11 => Self::Piggyback,
+            0x15 => Self::BitVector,
+            0x17 => Self::ServerSidePiggyback,
+            0x1d => Self::EndOfResponse,
+            0xde => Self::SecureNetworkServices,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl DataId {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::ProtocolNegotiation => "PROTOCOL_NEGOTIATION",
+            Self::DataTypes => "DATA_TYPES",
+            Self::FunctionCall => "FUNCTION_CALL",
+            Self::ReturnStatus => "RETURN_STATUS",
+            Self::RowHeader => "ROW_HEADER",
+            Self::RowData => "ROW_DATA",
+            Self::ReturnParameter => "RETURN_PARAMETER",
+            Self::Status => "STATUS",
+            Self::IoVector => "IO_VECTOR",
+            Self::DescribeInfo => "DESCRIBE_INFO",
+            Self::Piggyback => "PIGGYBACK",
+            Self::BitVector => "BIT_VECTOR",
+            Self::ServerSidePiggyback => "SERVER_SIDE_PIGGYBACK",
+            Self::EndOfResponse => "END_OF_RESPONSE",
+            Self::SecureNetworkServices => "SECURE_NETWORK_SERVICES",
+            Self::Unknown(_) => "UNKNOWN",
+        }
+    }
+}
+
+pub struct OracleParseConfig {
+    pub is_be: bool,
+    pub int_compress: bool,
+    // 0x04 结构有差异，个别结构有一个额外的 0x01 字节
+    pub resp_0x04_extra_byte: bool,
+}
+
+pub struct Frame {
+    pub packet_type: TnsPacketType,
+    pub length: usize,
+    pub body: Body,
+}
+
+#[derive(Default)]
+pub struct OracleParser {
+    pub frames: Vec<Frame>,
+}
+
+impl OracleParser {
+    // Only used to identify the protocol, accepts a Connect packet carrying a connect
+    // descriptor or a Data packet with a known TTC function call
+    pub fn check_payload(&mut self, payload: &[u8], config: &OracleParseConfig) -> bool {
+        let Some(header) = TnsHeader::parse(payload) else {
+            return false;
+        };
+        let end = payload.len().min(header.length);
+        let body = &payload[TNS_HEADER_LEN..end];
+        match header.packet_type {
+            TnsPacketType::Connect => {
+                find(body, b"(DESCRIPTION").is_some() || find(body, b"(CONNECT_DATA").is_some()
+            }
+            TnsPacketType::Data => {
+                if body.len() < DATA_FLAGS_LEN
+                    || DataFlags::from_bits(u16::from_be_bytes([body[0], body[1]])).is_none()
+                {
+                    return false;
+                }
+                let req = parse_data_request(body, config);
+                matches!(
+                    req.req_data_id,
+                    Some(DataId::FunctionCall | DataId::Piggyback)
+                ) && req.req_call_id.map(|c| c.is_known()).unwrap_or(false)
+            }
+            _ => false,
+        }
+    }
+
+    // Returns false if the payload does not start with a TNS header. Packets that carry no
+    // request or response (Marker, Ack, etc.) are framed but produce no frames.
+    pub fn parse_payload(
+        &mut self,
+        payload: &[u8],
+        is_req: bool,
+        config: &OracleParseConfig,
+    ) -> bool {
+        self.frames.clear();
+        let mut offset = 0;
+        while offset + TNS_HEADER_LEN <= payload.len() {
+            let Some(header) = TnsHeader::parse(&payload[offset..]) else {
+                break;
+            };
+            // the last packet may be truncated by the capture length
+            let end = payload.len().min(offset + header.length);
+            let body = &payload[offset + TNS_HEADER_LEN..end];
+            let body = match (header.packet_type, is_req) {
+                (TnsPacketType::Connect, true) => Some(Body::Request(Request {
+                    sql: String::new(),
+                    req_data_flags: DataFlags::empty(),
+                    req_data_id: None,
+                    req_call_id: None,
+                })),
+                (TnsPacketType::Accept | TnsPacketType::Redirect, false) => {
+                    Some(Body::Response(Response {
+                        ret_code: 0,
+                        affected_rows: None,
+                        error_message: String::new(),
+                        resp_data_flags: DataFlags::empty(),
+                        resp_data_id: None,
+                    }))
+                }
+                (TnsPacketType::Refuse, false) => Some(Body::Response(parse_refuse(body))),
+                (TnsPacketType::Data, true) if body.len() >= DATA_FLAGS_LEN => {
+                    Some(Body::Request(parse_data_request(body, config)))
+                }
+                (TnsPacketType::Data, false) if body.len() >= DATA_FLAGS_LEN => {
+                    Some(Body::Response(parse_data_response(body, config)))
+                }
+                _ => None,
+            };
+            if let Some(body) = body {
+                self.frames.push(Frame {
+                    packet_type: header.packet_type,
+                    length: header.length,
+                    body,
+                });
+            }
+            offset += header.length;
+        }
+        offset > 0
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum TnsPacketType {
+    Connect,
+    Accept,
+    Ack,
+    Refuse,
+    Redirect,
+    Data,
+    Null,
+    Abort,
+    Resend,
+    Marker,
+    Attention,
+    Control,
+    #[default]
+    Unknown,
+}
+
+impl From<u8> for TnsPacketType {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => Self::Connect,
+            2 => Self::Accept,
+            3 => Self::Ack,
+            4 => Self::Refuse,
+            5 => Self::Redirect,
+            6 => Self::Data,
+            7 => Self::Null,
+            9 => Self::Abort,
+            11 => Self::Resend,
+            12 => Self::Marker,
+            13 => Self::Attention,
+            14 => Self::Control,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl TnsPacketType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Connect => "CONNECT",
+            Self::Accept => "ACCEPT",
+            Self::Ack => "ACK",
+            Self::Refuse => "REFUSE",
+            Self::Redirect => "REDIRECT",
+            Self::Data => "DATA",
+            Self::Null => "NULL",
+            Self::Abort => "ABORT",
+            Self::Resend => "RESEND",
+            Self::Marker => "MARKER",
+            Self::Attention => "ATTENTION",
+            Self::Control => "CONTROL",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+struct TnsHeader {
+    length: usize,
+    packet_type: TnsPacketType,
+}
+
+impl TnsHeader {
+    fn parse(payload: &[u8]) -> Option<Self> {
+        if payload.len() < TNS_HEADER_LEN {
+            return None;
+        }
+        let length = if payload[0] == 0 && payload[1] == 0 {
+            u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize
+        } else if payload[2] == 0 && payload[3] == 0 {
+            u16::from_be_bytes([payload[0], payload[1]]) as usize
+        } else {
+            return None;
+        };
+        let packet_type = TnsPacketType::from(payload[4]);
+        if !(TNS_HEADER_LEN..=TNS_MAX_PACKET_LEN).contains(&length)
+            || packet_type == TnsPacketType::Unknown
+        {
+            return None;
+        }
+        Some(Self {
+            length,
+            packet_type,
+        })
+    }
+}
+
+// Reads TTC integers and length prefixed bytes. With int compression an integer is a
+// length byte (high bit set for negative values) followed by big endian bytes.
+struct TtcReader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    is_be: bool,
+    int_compress: bool,
+}
+
+impl<'a> TtcReader<'a> {
+    fn new(buf: &'a [u8], config: &OracleParseConfig) -> Self {
+        Self {
+            buf,
+            offset: 0,
+            is_be: config.is_be,
+            int_compress: config.int_compress,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.offset
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        if self.remaining() < n {
+            return None;
+        }
+        self.offset += n;
+        Some(())
+    }
+
+    fn ub1(&mut self) -> Option<u8> {
+        let v = *self.buf.get(self.offset)?;
+        self.offset += 1;
+        Some(v)
+    }
+
+    fn read(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.offset..self.offset + n)?;
+        self.offset += n;
+        Some(bytes)
+    }
+
+    fn ub(&mut self, size: usize) -> Option<u64> {
+        if self.int_compress {
+            let len = (self.ub1()? & 0x7f) as usize;
+            if len > size {
+                return None;
+            }
+            Some(self.read(len)?.iter().fold(0, |v, b| v << 8 | *b as u64))
+        } else {
+            let bytes = self.read(size)?;
+            if self.is_be {
+                Some(bytes.iter().fold(0, |v, b| v << 8 | *b as u64))
+            } else {
+                Some(bytes.iter().rev().fold(0, |v, b| v << 8 | *b as u64))
+            }
+        }
+    }
+
+    fn ub2(&mut self) -> Option<u64> {
+        self.ub(2)
+    }
+
+    fn ub4(&mut self) -> Option<u64> {
+        self.ub(4)
+    }
+
+    fn ub8(&mut self) -> Option<u64> {
+        self.ub(8)
+    }
+
+    fn bytes_with_len(&mut self) -> Option<Vec<u8>> {
+        match self.ub1()? {
+            0 | TTC_NULL_LENGTH_INDICATOR => Some(vec![]),
+            TTC_LONG_LENGTH_INDICATOR => {
+                let mut bytes = vec![];
+                loop {
+                    let len = self.ub4()? as usize;
+                    if len == 0 {
+                        return Some(bytes);
+                    }
+                    bytes.extend_from_slice(self.read(len)?);
+                }
+            }
+            len => Some(self.read(len as usize)?.to_vec()),
+        }
+    }
+}
+
+fn find(payload: &[u8], pattern: &[u8]) -> Option<usize> {
+    payload.windows(pattern.len()).position(|w| w == pattern)
+}
+
+fn is_sql(text: &[u8]) -> bool {
+    let text = match text
+        .iter()
+        .position(|b| !b.is_ascii_whitespace() && *b != b'(')
+    {
+        Some(start) => &text[start..],
+        None => return false,
+    };
+    SQL_KEYWORDS.iter().any(|k| {
+        text.len() >= k.len()
+            && text[..k.len()].eq_ignore_ascii_case(k)
+            && text
+                .get(k.len())
+                .map(|b| !b.is_ascii_alphanumeric() && *b != b'_')
+                .unwrap_or(true)
+    })
+}
+
+// The position of the statement in function call arguments differs between call ids and
+// client versions, so the arguments are scanned for length prefixed bytes starting with a
+// SQL keyword.
+fn find_sql(args: &[u8], config: &OracleParseConfig) -> Option<String> {
+    for i in 0..args.len() {
+        let len = args[i];
+        if len == 0 || (len > TTC_MAX_SHORT_LENGTH && len != TTC_LONG_LENGTH_INDICATOR) {
+            continue;
+        }
+        if len != TTC_LONG_LENGTH_INDICATOR
+            && !args
+                .get(i + 1)
+                .map(|b| b.is_ascii_alphabetic() || b.is_ascii_whitespace() || *b == b'(')
+                .unwrap_or(false)
+        {
+            continue;
+        }
+        let mut reader = TtcReader::new(&args[i..], config);
+        if let Some(text) = reader.bytes_with_len() {
+            if is_sql(&text) {
+                return Some(String::from_utf8_lossy(&text).trim().to_owned());
+            }
+        }
+    }
+    None
+}
+
+fn parse_data_request(body: &[u8], config: &OracleParseConfig) -> Request {
+    let mut req = Request {
+        sql: String::new(),
+        req_data_flags: DataFlags::from_bits_truncate(u16::from_be_bytes([body[0], body[1]])),
+        req_data_id: body.get(DATA_FLAGS_LEN).map(|id| DataId::from(*id)),
+        req_call_id: None,
+    };
+    let ttc = &body[DATA_FLAGS_LEN..];
+    // function call: message type, function code, sequence number, arguments
+    let call_offset = match req.req_data_id {
+        Some(DataId::FunctionCall) => Some(0),
+        // piggybacked calls (e.g. close cursors) come before the function call
+        Some(DataId::Piggyback) => ttc
+            .windows(2)
+            .skip(1)
+            .position(|w| w[0] == 0x03 && CallId::from(w[1]).is_known())
+            .map(|p| p + 1),
+        _ => None,
+    };
+    if let Some(call_offset) = call_offset {
+        if let Some(call_id) = ttc.get(call_offset + 1) {
+            req.req_call_id = Some(CallId::from(*call_id));
+        }
+        if let Some(args) = ttc.get(call_offset + 3..) {
+            req.sql = find_sql(args, config).unwrap_or_default();
+        }
+    }
+    req
+}
+
+struct ReturnStatus {
+    code: u32,
+    row_count: u64,
+    message: String,
+}
+
+// Decodes the return status (0x04) message, reader is positioned after the message type
+fn decode_return_status(
+    reader: &mut TtcReader,
+    config: &OracleParseConfig,
+) -> Option<ReturnStatus> {
+    if config.resp_0x04_extra_byte {
+        reader.ub1()?;
+    }
+    reader.ub4()?; // end of call status
+    reader.ub2()?; // end to end sequence number
+    let current_row = reader.ub4()?;
+    let short_code = reader.ub2()?;
+    reader.ub2()?; // array element error
+    reader.ub2()?; // array element error
+    reader.ub2()?; // cursor id
+    reader.ub2()?; // error position
+
+    // sql type, fatal, flags, user cursor options, UPI parameter, warning flag
+    reader.skip(6)?;
+    // rowid: rba, partition id, skipped byte, block number, slot number
+    reader.ub4()?;
+    reader.ub2()?;
+    reader.ub1()?;
+    reader.ub4()?;
+    reader.ub2()?;
+    reader.ub4()?; // os error
+    reader.skip(2)?; // statement number, call number
+    reader.ub2()?; // padding
+    reader.ub4()?; // success iterations
+    if reader.ub4()? > 0 {
+        reader.bytes_with_len()?; // logical rowid
+    }
+    let batch_errors = reader.ub2()?;
+    let batch_offsets = if batch_errors == 0 { reader.ub4()? } else { 0 };
+    let batch_messages = if batch_errors == 0 { reader.ub2()? } else { 0 };
+    if batch_errors > 0 || batch_offsets > 0 || batch_messages > 0 {
+        // batch errors of array DML are not decoded, message is left for ORA- searching
+        return Some(ReturnStatus {
+            code: short_code as u32,
+            row_count: current_row,
+            message: String::new(),
+        });
+    }
+    let code = reader.ub4()?;
+    let row_count = reader.ub8()?;
+    let code = if code == 0 {
+        short_code as u32
+    } else {
+        code as u32
+    };
+    let mut message = String::new();
+    if code != 0 {
+        let start = reader.offset;
+        message = read_ora_message(reader).or_else(|| {
+            // 20c and later have sql type and server checksum before the message
+            reader.offset = start;
+            reader.ub4()?;
+            reader.ub4()?;
+            read_ora_message(reader)
+        })?;
+    }
+    Some(ReturnStatus {
+        code,
+        row_count,
+        message,
+    })
+}
+
+fn read_ora_message(reader: &mut TtcReader) -> Option<String> {
+    let text = reader.bytes_with_len()?;
+    if !text.starts_with(b"ORA-") {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&text).trim_end().to_owned())
+}
+
+fn find_return_status(ttc: &[u8], config: &OracleParseConfig) -> Option<ReturnStatus> {
+    if ttc.first() == Some(&0x04) {
+        let mut reader = TtcReader::new(&ttc[1..], config);
+        if let Some(status) = decode_return_status(&mut reader, config) {
+            return Some(status);
+        }
+    }
+    // row data and return parameters come before the return status
+    let start = ttc.len().saturating_sub(RETURN_STATUS_SEARCH_LEN).max(1);
+    for i in (start..ttc.len()).rev() {
+        if ttc[i] != 0x04 {
+            continue;
+        }
+        let mut reader = TtcReader::new(&ttc[i + 1..], config);
+        if let Some(status) = decode_return_status(&mut reader, config) {
+            if status.code != 0 || reader.remaining() <= RETURN_STATUS_MAX_TRAILER {
+                return Some(status);
+            }
+        }
+    }
+    None
+}
+
+// ORA-NNNNN: message
+fn find_ora_error(payload: &[u8]) -> Option<(u32, String)> {
+    let start = find(payload, b"ORA-")?;
+    let digits = payload[start + 4..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    if digits == 0 {
+        return None;
+    }
+    let code = std::str::from_utf8(&payload[start + 4..start + 4 + digits])
+        .ok()?
+        .parse()
+        .ok()?;
+    let len = payload[start..]
+        .iter()
+        .take_while(|b| **b != b'\n' && (b.is_ascii_graphic() || **b == b' '))
+        .count();
+    Some((
+        code,
+        String::from_utf8_lossy(&payload[start..start + len])
+            .trim_end()
+            .to_owned(),
+    ))
+}
+
+fn to_ret_code(code: u32) -> u16 {
+    code.min(u16::MAX as u32) as u16
+}
+
+fn parse_data_response(body: &[u8], config: &OracleParseConfig) -> Response {
+    let mut resp = Response {
+        ret_code: 0,
+        affected_rows: None,
+        error_message: String::new(),
+        resp_data_flags: DataFlags::from_bits_truncate(u16::from_be_bytes([body[0], body[1]])),
+        resp_data_id: body.get(DATA_FLAGS_LEN).map(|id| DataId::from(*id)),
+    };
+    let ttc = &body[DATA_FLAGS_LEN..];
+    if let Some(status) = find_return_status(ttc, config) {
+        resp.ret_code = to_ret_code(status.code);
+        resp.affected_rows = Some(status.row_count.min(u32::MAX as u64) as u32);
+        resp.error_message = status.message;
+    }
+    if resp.error_message.is_empty() {
+        if let Some((code, message)) = find_ora_error(ttc) {
+            if resp.ret_code == 0 {
+                resp.ret_code = to_ret_code(code);
+            }
+            resp.error_message = message;
+        }
+    }
+    resp
+}
+
+// Refuse packet: user reason (u8), system reason (u8), data length (u16), data such as
+// (DESCRIPTION=(TMP=)(VSNNUM=0)(ERR=12514)(ERROR_STACK=...))
+fn parse_refuse(body: &[u8]) -> Response {
+    let system_reason = body.get(1).copied().unwrap_or_default();
+    let data = body.get(4..).unwrap_or_default();
+    let code = find(data, b"(ERR=").and_then(|start| {
+        let digits = data[start + 5..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        std::str::from_utf8(&data[start + 5..start + 5 + digits])
+            .ok()?
+            .parse::<u32>()
+            .ok()
+    });
+    Response {
+        ret_code: code
+            .map(to_ret_code)
+            .unwrap_or((system_reason as u16).max(1)),
+        affected_rows: None,
+        error_message: String::from_utf8_lossy(data).trim_end().to_owned(),
+        resp_data_flags: DataFlags::empty(),
+        resp_data_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: OracleParseConfig = OracleParseConfig {
+        is_be: true,
+        int_compress: true,
+        resp_0x04_extra_byte: false,
+    };
+
+    fn ub(v: u64) -> Vec<u8> {
+        let bytes = v.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        let mut encoded = vec![(8 - skip) as u8];
+        encoded.extend_from_slice(&bytes[skip..]);
+        encoded
+    }
+
+    fn packet(packet_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut p = ((body.len() + TNS_HEADER_LEN) as u32)
+            .to_be_bytes()
+            .to_vec();
+        p.extend_from_slice(&[packet_type, 0, 0, 0]);
+        p.extend_from_slice(body);
+        p
+    }
+
+    fn return_status(code: u64, rows: u64, message: &str) -> Vec<u8> {
+        let mut s = vec![0x04];
+        for v in [0, 0, rows, code, 0, 0, 0, 0] {
+            s.extend(ub(v));
+        }
+        s.extend_from_slice(&[0; 6]);
+        s.extend(ub(0));
+        s.extend(ub(0));
+        s.push(0);
+        for _ in 0..3 {
+            s.extend(ub(0));
+        }
+        s.extend_from_slice(&[0; 2]);
+        for _ in 0..6 {
+            s.extend(ub(0));
+        }
+        s.extend(ub(code));
+        s.extend(ub(rows));
+        if code != 0 {
+            s.push(message.len() as u8);
+            s.extend_from_slice(message.as_bytes());
+        }
+        s
+    }
+
+    #[test]
+    fn connect() {
+        let mut body = vec![0x01, 0x3d, 0x01, 0x2c, 0x0c, 0x41];
+        body.extend_from_slice(b"(DESCRIPTION=(CONNECT_DATA=(SERVICE_NAME=ORCLPDB1)))");
+        let mut p = packet(1, &body);
+        // Connect is always sent with a u16 length
+        let len = (p.len() as u16).to_be_bytes();
+        p[..4].copy_from_slice(&[len[0], len[1], 0, 0]);
+
+        let mut parser = OracleParser::default();
+        assert!(parser.check_payload(&p, &CONFIG));
+        assert!(parser.parse_payload(&p, true, &CONFIG));
+        assert_eq!(parser.frames.len(), 1);
+        assert_eq!(parser.frames[0].packet_type, TnsPacketType::Connect);
+
+        let mut refuse = vec![0x01, 0x00, 0x00, 0x40];
+        refuse
+            .extend_from_slice(b"(DESCRIPTION=(TMP=)(VSNNUM=0)(ERR=12514)(ERROR_STACK=(ERROR=)))");
+        assert!(parser.parse_payload(&packet(4, &refuse), false, &CONFIG));
+        let Body::Response(resp) = &parser.frames[0].body else {
+            panic!("refuse is not a response");
+        };
+        assert_eq!(resp.ret_code, 12514);
+    }
+
+    #[test]
+    fn function_call() {
+        let sql = "SELECT * FROM employees WHERE id = :1";
+        let mut body = vec![0x00, 0x00, 0x03, 0x5e, 0x02];
+        for v in [0x8061, 0, 1] {
+            body.extend(ub(v));
+        }
+        body.extend(ub(sql.len() as u64));
+        body.extend_from_slice(&[0x01, 0x01, 0x0d]);
+        body.push(sql.len() as u8);
+        body.extend_from_slice(sql.as_bytes());
+        body.extend_from_slice(&[0x01, 0x01, 0x00]);
+        let p = packet(6, &body);
+
+        let mut parser = OracleParser::default();
+        assert!(parser.check_payload(&p, &CONFIG));
+        assert!(parser.parse_payload(&p, true, &CONFIG));
+        let Body::Request(req) = &parser.frames[0].body else {
+            panic!("function call is not a request");
+        };
+        assert_eq!(req.sql, sql);
+        assert_eq!(req.req_data_id, Some(DataId::FunctionCall));
+        assert_eq!(req.req_call_id, Some(CallId::Execute));
+
+        // piggybacked close cursors and a chunked long statement
+        let sql = format!("UPDATE employees SET name = '{}'", "x".repeat(300));
+        let mut body = vec![0x00, 0x00, 0x11, 0x69, 0x01, 0x01, 0x01, 0x03, 0x5e, 0x03];
+        body.extend(ub(0x8021));
+        body.push(TTC_LONG_LENGTH_INDICATOR);
+        body.extend(ub(sql.len() as u64));
+        body.extend_from_slice(sql.as_bytes());
+        body.extend(ub(0));
+        parser.parse_payload(&packet(6, &body), true, &CONFIG);
+        let Body::Request(req) = &parser.frames[0].body else {
+            panic!("function call is not a request");
+        };
+        assert_eq!(req.sql, sql);
+        assert_eq!(req.req_data_id, Some(DataId::Piggyback));
+        assert_eq!(req.req_call_id, Some(CallId::Execute));
+    }
+
+    #[test]
+    fn return_status_and_errors() {
+        let mut parser = OracleParser::default();
+
+        let mut body = vec![0x00, 0x00, 0x08, 0x01, 0x06, 0x00];
+        body.extend(return_status(0, 3, ""));
+        assert!(parser.parse_payload(&packet(6, &body), false, &CONFIG));
+        let Body::Response(resp) = &parser.frames[0].body else {
+            panic!("data is not a response");
+        };
+        assert_eq!(resp.ret_code, 0);
+        assert_eq!(resp.affected_rows, Some(3));
+        assert_eq!(resp.resp_data_id, Some(DataId::ReturnParameter));
+
+        let message = "ORA-00942: table or view does not exist\n";
+        let mut body = vec![0x00, 0x40];
+        body.extend(return_status(942, 0, message));
+        assert!(parser.parse_payload(&packet(6, &body), false, &CONFIG));
+        let Body::Response(resp) = &parser.frames[0].body else {
+            panic!("data is not a response");
+        };
+        assert_eq!(resp.ret_code, 942);
+        assert_eq!(resp.error_message, message.trim_end());
+        assert_eq!(resp.resp_data_flags, DataFlags::END_OF_FILE);
+        assert_eq!(resp.resp_data_flags.to_string(), "END_OF_FILE");
+
+        // undecodable return status falls back to searching the error text
+        let mut body = vec![0x00, 0x00, 0x04, 0x01];
+        body.extend_from_slice(b"\x2fORA-01017: invalid username/password; logon denied\n");
+        parser.parse_payload(&packet(6, &body), false, &CONFIG);
+        let Body::Response(resp) = &parser.frames[0].body else {
+            panic!("data is not a response");
+        };
+        assert_eq!(resp.ret_code, 1017);
+        assert_eq!(
+            resp.error_message,
+            "ORA-01017: invalid username/password; logon denied"
+        );
+    }
+
+    #[test]
+    fn framing() {
+        let mut payload = packet(12, &[0x01, 0x00, 0x02]);
+        payload.extend(packet(6, &[0x00, 0x00, 0x03, 0x0e, 0x04]));
+        let mut parser = OracleParser::default();
+        assert!(parser.parse_payload(&payload, true, &CONFIG));
+        // marker is framed but carries no request
+        assert_eq!(parser.frames.len(), 1);
+        let Body::Request(req) = &parser.frames[0].body else {
+            panic!("data is not a request");
+        };
+        assert_eq!(req.req_call_id, Some(CallId::Commit));
+
+        assert!(!parser.parse_payload(b"GET / HTTP/1.1\r\n", true, &CONFIG));
+        assert!(!parser.check_payload(&packet(12, &[0x01, 0x00, 0x02]), &CONFIG));
+    }
+}
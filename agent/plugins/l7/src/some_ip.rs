@@ -14,9 +14,27 @@
  * limitations under the License.
  */
 
+use std::fmt::Display;
+
+// SOME/IP header, all fields are big endian
+//
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-------------------------------+-------------------------------+
+// |          Service ID           |           Method ID           |
+// +-------------------------------+-------------------------------+
+// |                            Length                             |
+// +-------------------------------+-------------------------------+
+// |           Client ID           |          Session ID           |
+// +---------------+---------------+---------------+---------------+
+// |Protocol Ver.  |Interface Ver. | Message Type  |  Return Code  |
+// +---------------+---------------+---------------+---------------+
+// |                            Payload                            |
+// +---------------------------------------------------------------+
 pub struct SomeIpHeader {
     pub service_id: u16,
     pub method_id: u16,
+    // length in bytes starting from Client ID until the end of the message
     pub length: u32,
     pub client_id: u16,
     pub session_id: u16,
@@ -24,6 +42,7 @@ pub struct SomeIpHeader {
     pub interface_version: u8,
     pub message_type: u8,
     pub return_code: u8,
+    // length of the data the header is decoded from
     pub packet_length: u32,
 }
 
@@ -44,32 +63,328 @@ pub const E_E2E: u8 = 0xd;
 pub const E_E2E_NOT_AVAILABLE: u8 = 0xe;
 pub const E_E2E_NO_NEW_DATA: u8 = 0xf;
 
+pub const MSG_REQUEST: u8 = 0x0;
+pub const MSG_REQUEST_NO_RETURN: u8 = 0x1;
+pub const MSG_NOTIFICATION: u8 = 0x2;
+pub const MSG_RESPONSE: u8 = 0x80;
+pub const MSG_ERROR: u8 = 0x81;
+// set on the message type of segments of SOME/IP-TP
+pub const MSG_TP_FLAG: u8 = 0x20;
+
+pub const SOME_IP_PROTOCOL_VERSION: u8 = 0x1;
+
+pub const SD_SERVICE_ID: u16 = 0xffff;
+pub const SD_METHOD_ID: u16 = 0x8100;
+
 impl TryFrom<&[u8]> for SomeIpHeader {
     type Error = &'static str;
 
-    fn try_from(_: &[u8]) -> std::result::Result<Self, Self::Error> {
-        Err("Not supported")
+    fn try_from(payload: &[u8]) -> std::result::Result<Self, Self::Error> {
+        if payload.len() < SomeIpHeader::HEADER_LEN {
+            return Err("Insufficient payload length");
+        }
+        let read_u16 = |offset: usize| u16::from_be_bytes([payload[offset], payload[offset + 1]]);
+        Ok(Self {
+            service_id: read_u16(0),
+            method_id: read_u16(2),
+            length: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+            client_id: read_u16(8),
+            session_id: read_u16(10),
+            protocol_version: payload[12],
+            interface_version: payload[13],
+            message_type: payload[14],
+            return_code: payload[15],
+            packet_length: payload.len() as u32,
+        })
     }
 }
 
 impl SomeIpHeader {
+    pub const HEADER_LEN: usize = 16;
+    // Message ID and Length are not covered by the length field
+    const UNCOVERED_HEADER_LEN: u32 = 8;
+
+    // Only used to identify the protocol, so only complete request
+    // messages are accepted
     pub fn check(&self) -> bool {
-        false
+        if !self.does_supported() || !self.is_request() || self.return_code != E_OK {
+            return false;
+        }
+        if self.length < Self::HEADER_LEN as u32 - Self::UNCOVERED_HEADER_LEN {
+            return false;
+        }
+        self.length as u64 + Self::UNCOVERED_HEADER_LEN as u64 <= self.packet_length as u64
     }
 
     pub fn does_supported(&self) -> bool {
-        false
+        if self.protocol_version != SOME_IP_PROTOCOL_VERSION {
+            return false;
+        }
+        matches!(
+            self.message_type & !MSG_TP_FLAG,
+            MSG_REQUEST | MSG_REQUEST_NO_RETURN | MSG_NOTIFICATION | MSG_RESPONSE | MSG_ERROR
+        )
+    }
+
+    pub fn is_tp(&self) -> bool {
+        self.message_type & MSG_TP_FLAG != 0
+    }
+
+    pub fn is_sd(&self) -> bool {
+        self.service_id == SD_SERVICE_ID && self.method_id == SD_METHOD_ID
+    }
+
+    pub fn is_request(&self) -> bool {
+        matches!(
+            self.message_type & !MSG_TP_FLAG,
+            MSG_REQUEST | MSG_REQUEST_NO_RETURN | MSG_NOTIFICATION
+        )
+    }
+
+    // REQUEST_NO_RETURN and NOTIFICATION are never answered
+    pub fn expects_response(&self) -> bool {
+        self.message_type & !MSG_TP_FLAG == MSG_REQUEST
+    }
+
+    pub fn is_response(&self) -> bool {
+        matches!(self.message_type & !MSG_TP_FLAG, MSG_RESPONSE | MSG_ERROR)
     }
 
     pub fn to_version(&self) -> String {
-        "Not supported".to_string()
+        format!(
+            "Protocol {} Interface {}",
+            self.protocol_version, self.interface_version
+        )
     }
 
     pub fn to_message_type(&self) -> String {
-        "Not supported".to_string()
+        let name = match self.message_type & !MSG_TP_FLAG {
+            MSG_REQUEST => "Request",
+            MSG_REQUEST_NO_RETURN => "Request No Return",
+            MSG_NOTIFICATION => "Notification",
+            MSG_RESPONSE => "Response",
+            MSG_ERROR => "Error",
+            _ => return format!("Unknown(0x{:x})", self.message_type),
+        };
+        if self.is_tp() {
+            format!("TP {}", name)
+        } else {
+            name.to_string()
+        }
     }
 
     pub fn to_exception(&self) -> String {
-        "Not supported".to_string()
+        match self.return_code {
+            E_OK => "No error occurred",
+            E_NOT_OK => "An unspecified error occurred",
+            E_UNKNOWN_SERVICE => "The requested Service ID is unknown",
+            E_UNKNOWN_METHOD => "The requested Method ID is unknown",
+            E_NOT_READY => "Service ID and Method ID are known. Application not running",
+            E_NOT_REACHABLE => "System running the service is not reachable",
+            E_TIMEOUT => "A timeout occurred",
+            E_WRONG_PROTOCOL_VERSION => "Version of SOME/IP protocol not supported",
+            E_WRONG_INTERFACE_VERSION => "Interface version mismatch",
+            E_MALFORMED_MESSAGE => "Deserialization error, so that payload cannot be deserialized",
+            E_WRONG_MESSAGE_TYPE => "An unexpected message type was received",
+            E_E2E_REPEATED => "Repeated E2E calculation error",
+            E_E2E_WRONG_SEQUENCE => "Wrong E2E sequence error",
+            E_E2E => "Not further specified E2E error",
+            E_E2E_NOT_AVAILABLE => "E2E not available",
+            E_E2E_NO_NEW_DATA => "No new data for E2E calculation present",
+            0x10..=0x1f => "Reserved for generic SOME/IP errors",
+            0x20..=0x5e => "Reserved for specific errors of services and methods",
+            _ => "Unknown error",
+        }
+        .to_string()
+    }
+}
+
+// SOME/IP-TP header following the SOME/IP header of a segment
+//
+// +-------------------------------------------------------+-+-+-+-+
+// |                  Offset [bits 31-4]                   |Res  |M|
+// +-------------------------------------------------------+-+-+-+-+
+pub struct SomeIpTpHeader {
+    // offset of the segment payload in bytes
+    pub offset: u32,
+    pub more_segments: bool,
+}
+
+impl SomeIpTpHeader {
+    pub const HEADER_LEN: usize = 4;
+}
+
+impl TryFrom<&[u8]> for SomeIpTpHeader {
+    type Error = &'static str;
+
+    // payload is the data after SOME/IP header
+    fn try_from(payload: &[u8]) -> std::result::Result<Self, Self::Error> {
+        if payload.len() < Self::HEADER_LEN {
+            return Err("Insufficient payload length");
+        }
+        let value = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        Ok(Self {
+            offset: value & 0xfffffff0,
+            more_segments: value & 0x1 != 0,
+        })
+    }
+}
+
+// SOME/IP-SD entries, options are ignored
+//
+// Service entry (FindService/OfferService/StopOfferService):
+// | Type | Index 1st | Index 2nd | # of opt 1|2 | Service ID | Instance ID |
+// | Major Version | TTL (24 bits) | Minor Version (32 bits) |
+//
+// Eventgroup entry (Subscribe/StopSubscribe/SubscribeAck/SubscribeNack):
+// | Type | Index 1st | Index 2nd | # of opt 1|2 | Service ID | Instance ID |
+// | Major Version | TTL (24 bits) | Reserved | Counter | Eventgroup ID |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SomeIpSdEntry {
+    pub entry_type: u8,
+    pub service_id: u16,
+    pub instance_id: u16,
+    pub major_version: u8,
+    pub ttl: u32,
+    // minor version for service entries, eventgroup id for eventgroup entries
+    pub minor_version_or_eventgroup: u32,
+}
+
+impl SomeIpSdEntry {
+    pub const ENTRY_LEN: usize = 16;
+    // flags(1) reserved(3) length of entries array(4)
+    const SD_HEADER_LEN: usize = 8;
+
+    pub const FIND_SERVICE: u8 = 0x0;
+    pub const OFFER_SERVICE: u8 = 0x1;
+    pub const SUBSCRIBE_EVENTGROUP: u8 = 0x6;
+    pub const SUBSCRIBE_EVENTGROUP_ACK: u8 = 0x7;
+
+    pub fn is_offer_service(&self) -> bool {
+        self.entry_type == Self::OFFER_SERVICE && self.ttl > 0
+    }
+
+    // payload is the data after SOME/IP header
+    pub fn parse_entries(payload: &[u8]) -> Vec<Self> {
+        if payload.len() < Self::SD_HEADER_LEN {
+            return vec![];
+        }
+        let length = u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]) as usize;
+        let entries = &payload[Self::SD_HEADER_LEN..];
+        let entries = &entries[..length.min(entries.len())];
+        entries
+            .chunks_exact(Self::ENTRY_LEN)
+            .map(|e| Self {
+                entry_type: e[0],
+                service_id: u16::from_be_bytes([e[4], e[5]]),
+                instance_id: u16::from_be_bytes([e[6], e[7]]),
+                major_version: e[8],
+                ttl: u32::from_be_bytes([0, e[9], e[10], e[11]]),
+                minor_version_or_eventgroup: u32::from_be_bytes([e[12], e[13], e[14], e[15]]),
+            })
+            .collect()
+    }
+}
+
+impl Display for SomeIpSdEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match (self.entry_type, self.ttl) {
+            (Self::FIND_SERVICE, _) => "FindService",
+            (Self::OFFER_SERVICE, 0) => "StopOfferService",
+            (Self::OFFER_SERVICE, _) => "OfferService",
+            (Self::SUBSCRIBE_EVENTGROUP, 0) => "StopSubscribeEventgroup",
+            (Self::SUBSCRIBE_EVENTGROUP, _) => "SubscribeEventgroup",
+            (Self::SUBSCRIBE_EVENTGROUP_ACK, 0) => "SubscribeEventgroupNack",
+            (Self::SUBSCRIBE_EVENTGROUP_ACK, _) => "SubscribeEventgroupAck",
+            (t, _) => return write!(f, "Unknown(0x{:x})", t),
+        };
+        match self.entry_type {
+            Self::FIND_SERVICE | Self::OFFER_SERVICE => write!(
+                f,
+                "{} 0x{:04x}.0x{:04x} v{}.{}",
+                name,
+                self.service_id,
+                self.instance_id,
+                self.major_version,
+                self.minor_version_or_eventgroup
+            ),
+            _ => write!(
+                f,
+                "{} 0x{:04x}.0x{:04x} eventgroup 0x{:04x}",
+                name,
+                self.service_id,
+                self.instance_id,
+                self.minor_version_or_eventgroup & 0xffff
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let request = [
+            0x00, 0x29, 0x50, 0x04, 0x00, 0x00, 0x00, 0x08, 0x00, 0x01, 0x00, 0xab, 0x01, 0x01,
+            0x00, 0x00,
+        ];
+        let header = SomeIpHeader::try_from(&request[..]).unwrap();
+        assert_eq!(header.service_id, 0x29);
+        assert_eq!(header.method_id, 0x5004);
+        assert_eq!(header.session_id, 0xab);
+        assert!(header.check());
+        assert!(header.expects_response());
+        assert_eq!(header.to_version(), "Protocol 1 Interface 1");
+        assert_eq!(header.to_message_type(), "Request");
+
+        let mut response = request;
+        response[14] = MSG_ERROR;
+        response[15] = E_UNKNOWN_METHOD;
+        let header = SomeIpHeader::try_from(&response[..]).unwrap();
+        assert!(!header.check());
+        assert!(header.does_supported());
+        assert!(header.is_response());
+        assert_eq!(header.to_exception(), "The requested Method ID is unknown");
+
+        assert!(SomeIpHeader::try_from(&request[..15]).is_err());
+    }
+
+    #[test]
+    fn tp_segment() {
+        let mut segment = vec![
+            0x00, 0x29, 0x50, 0x04, 0x00, 0x00, 0x00, 0x14, 0x00, 0x01, 0x00, 0xab, 0x01, 0x01,
+            0x20, 0x00, 0x00, 0x00, 0x05, 0xa1,
+        ];
+        segment.extend_from_slice(&[0; 8]);
+        let header = SomeIpHeader::try_from(&segment[..]).unwrap();
+        assert!(header.is_tp());
+        assert!(header.check());
+        assert_eq!(header.to_message_type(), "TP Request");
+        let tp = SomeIpTpHeader::try_from(&segment[SomeIpHeader::HEADER_LEN..]).unwrap();
+        assert_eq!(tp.offset, 0x5a0);
+        assert!(tp.more_segments);
+    }
+
+    #[test]
+    fn sd_entries() {
+        let payload = [
+            0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, // sd header
+            0x01, 0x00, 0x00, 0x10, 0x12, 0x34, 0x00, 0x01, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00,
+            0x00, 0x02, // offer
+            0x06, 0x00, 0x00, 0x10, 0x12, 0x34, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x05, // stop subscribe
+            0x00, 0x00, 0x00, 0x0c, // options
+        ];
+        let entries = SomeIpSdEntry::parse_entries(&payload);
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_offer_service());
+        assert_eq!(entries[0].to_string(), "OfferService 0x1234.0x0001 v1.2");
+        assert!(!entries[1].is_offer_service());
+        assert_eq!(
+            entries[1].to_string(),
+            "StopSubscribeEventgroup 0x1234.0x0001 eventgroup 0x0005"
+        );
     }
 }
@@ -17,81 +17,657 @@
 use std::fmt::Display;
 use std::time::Duration;
 
-pub struct HandshakeHeader {}
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u24(bytes: &[u8], offset: usize) -> Option<usize> {
+    bytes
+        .get(offset..offset + 3)
+        .map(|b| (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+}
+
+// A minimal DER reader which only understands what is needed to locate the
+// validity of an X.509 certificate. Certificates usually span several TCP
+// segments, so constructed values are entered without requiring their full
+// content to be present.
+struct Der<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Der<'a> {
+    const TAG_SEQUENCE: u8 = 0x30;
+    const TAG_UTC_TIME: u8 = 0x17;
+    const TAG_GENERALIZED_TIME: u8 = 0x18;
+    const TAG_EXPLICIT_VERSION: u8 = 0xa0;
+
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.bytes.get(self.offset).copied()
+    }
+
+    // returns (tag, content length) and moves offset to the start of the content
+    fn header(&mut self) -> Option<(u8, usize)> {
+        let tag = *self.bytes.get(self.offset)?;
+        let first = *self.bytes.get(self.offset + 1)? as usize;
+        self.offset += 2;
+        if first & 0x80 == 0 {
+            return Some((tag, first));
+        }
+        let n = first & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let mut length = 0;
+        for b in self.bytes.get(self.offset..self.offset + n)? {
+            length = length << 8 | *b as usize;
+        }
+        self.offset += n;
+        Some((tag, length))
+    }
+
+    fn enter(&mut self, expected_tag: u8) -> Option<()> {
+        match self.header()? {
+            (tag, _) if tag == expected_tag => Some(()),
+            _ => None,
+        }
+    }
+
+    fn skip(&mut self) -> Option<()> {
+        let (_, length) = self.header()?;
+        if self.offset + length > self.bytes.len() {
+            return None;
+        }
+        self.offset += length;
+        Some(())
+    }
+
+    fn time(&mut self) -> Option<Duration> {
+        let (tag, length) = self.header()?;
+        let value = self.bytes.get(self.offset..self.offset + length)?;
+        self.offset += length;
+        let value = std::str::from_utf8(value).ok()?;
+        let (year, rest) = match tag {
+            // YYMMDDHHMMSSZ, years 50-99 are 19xx
+            Self::TAG_UTC_TIME => {
+                let yy: i64 = value.get(..2)?.parse().ok()?;
+                (if yy >= 50 { 1900 + yy } else { 2000 + yy }, &value[2..])
+            }
+            // YYYYMMDDHHMMSSZ
+            Self::TAG_GENERALIZED_TIME => (value.get(..4)?.parse().ok()?, &value[4..]),
+            _ => return None,
+        };
+        if rest.len() < 10 || !rest.is_char_boundary(10) {
+            return None;
+        }
+        let field = |i: usize| -> Option<i64> { rest.get(i..i + 2)?.parse().ok() };
+        let (month, day) = (field(0)?, field(2)?);
+        let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+        let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+        if secs < 0 {
+            return None;
+        }
+        Some(Duration::from_secs(secs as u64))
+    }
+}
+
+// days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// Certificate ::= SEQUENCE {
+//     tbsCertificate       TBSCertificate ::= SEQUENCE {
+//         version         [0]  EXPLICIT Version DEFAULT v1,
+//         serialNumber         CertificateSerialNumber,
+//         signature            AlgorithmIdentifier,
+//         issuer               Name,
+//         validity             Validity ::= SEQUENCE { notBefore Time, notAfter Time },
+//         ...
+//     },
+//     ...
+// }
+fn parse_certificate_validity(cert: &[u8]) -> Option<(Duration, Duration)> {
+    let mut der = Der::new(cert);
+    der.enter(Der::TAG_SEQUENCE)?;
+    der.enter(Der::TAG_SEQUENCE)?;
+    if der.peek_tag()? == Der::TAG_EXPLICIT_VERSION {
+        der.skip()?;
+    }
+    der.skip()?; // serialNumber
+    der.skip()?; // signature
+    der.skip()?; // issuer
+    der.enter(Der::TAG_SEQUENCE)?;
+    let not_before = der.time()?;
+    let not_after = der.time()?;
+    Some((not_before, not_after))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeHeader {
+    pub handshake_type: u8,
+    pub length: u32,
+}
+
+impl HandshakeHeader {
+    pub const HEADER_LEN: usize = 4;
+
+    pub const HELLO_REQUEST: u8 = 0;
+    pub const CLIENT_HELLO: u8 = 1;
+    pub const SERVER_HELLO: u8 = 2;
+    pub const NEW_SESSION_TICKET: u8 = 4;
+    pub const END_OF_EARLY_DATA: u8 = 5;
+    pub const ENCRYPTED_EXTENSIONS: u8 = 8;
+    pub const CERTIFICATE: u8 = 11;
+    pub const SERVER_KEY_EXCHANGE: u8 = 12;
+    pub const CERTIFICATE_REQUEST: u8 = 13;
+    pub const SERVER_HELLO_DONE: u8 = 14;
+    pub const CERTIFICATE_VERIFY: u8 = 15;
+    pub const CLIENT_KEY_EXCHANGE: u8 = 16;
+    pub const FINISHED: u8 = 20;
+    pub const CERTIFICATE_STATUS: u8 = 22;
+    pub const KEY_UPDATE: u8 = 24;
+
+    fn is_known_type(handshake_type: u8) -> bool {
+        matches!(
+            handshake_type,
+            Self::HELLO_REQUEST
+                | Self::CLIENT_HELLO
+                | Self::SERVER_HELLO
+                | Self::NEW_SESSION_TICKET
+                | Self::END_OF_EARLY_DATA
+                | Self::ENCRYPTED_EXTENSIONS
+                | Self::CERTIFICATE
+                | Self::SERVER_KEY_EXCHANGE
+                | Self::CERTIFICATE_REQUEST
+                | Self::SERVER_HELLO_DONE
+                | Self::CERTIFICATE_VERIFY
+                | Self::CLIENT_KEY_EXCHANGE
+                | Self::FINISHED
+                | Self::CERTIFICATE_STATUS
+                | Self::KEY_UPDATE
+        )
+    }
+}
 
 impl Display for HandshakeHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Unsupport")
+        match self.handshake_type {
+            Self::HELLO_REQUEST => write!(f, "HelloRequest"),
+            Self::CLIENT_HELLO => write!(f, "ClientHello"),
+            Self::SERVER_HELLO => write!(f, "ServerHello"),
+            Self::NEW_SESSION_TICKET => write!(f, "NewSessionTicket"),
+            Self::END_OF_EARLY_DATA => write!(f, "EndOfEarlyData"),
+            Self::ENCRYPTED_EXTENSIONS => write!(f, "EncryptedExtensions"),
+            Self::CERTIFICATE => write!(f, "Certificate"),
+            Self::SERVER_KEY_EXCHANGE => write!(f, "ServerKeyExchange"),
+            Self::CERTIFICATE_REQUEST => write!(f, "CertificateRequest"),
+            Self::SERVER_HELLO_DONE => write!(f, "ServerHelloDone"),
+            Self::CERTIFICATE_VERIFY => write!(f, "CertificateVerify"),
+            Self::CLIENT_KEY_EXCHANGE => write!(f, "ClientKeyExchange"),
+            Self::FINISHED => write!(f, "Finished"),
+            Self::CERTIFICATE_STATUS => write!(f, "CertificateStatus"),
+            Self::KEY_UPDATE => write!(f, "KeyUpdate"),
+            t => write!(f, "Unknown({})", t),
+        }
     }
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct TlsHeader {
+    content_type: u8,
+    version: u16,
+    length: u16,
+    // record reaches or exceeds the end of the payload
+    last: bool,
+
     pub handshake_headers: Vec<HandshakeHeader>,
+
+    server_name: Option<String>,
+    supported_version: Option<u16>,
+    cipher_suite: Option<u16>,
+    validity: Option<(Duration, Duration)>,
+    alert: Option<(u8, u8)>,
 }
 
 impl Display for TlsHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Unsupport")
+        match self.content_type {
+            Self::CONTENT_TYPE_CHANGE_CIPHER_SPEC => write!(f, "ChangeCipherSpec"),
+            Self::CONTENT_TYPE_ALERT => match self.alert {
+                Some((_, description)) => write!(f, "Alert({})", description),
+                None => write!(f, "EncryptedAlert"),
+            },
+            Self::CONTENT_TYPE_HANDSHAKE if self.handshake_headers.is_empty() => {
+                write!(f, "EncryptedHandshakeMessage")
+            }
+            Self::CONTENT_TYPE_HANDSHAKE => {
+                for (i, h) in self.handshake_headers.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", h)?;
+                }
+                Ok(())
+            }
+            Self::CONTENT_TYPE_APPLICATION_DATA => write!(f, "ApplicationData"),
+            Self::CONTENT_TYPE_HEARTBEAT => write!(f, "Heartbeat"),
+            t => write!(f, "Unknown({})", t),
+        }
     }
 }
 
 impl TlsHeader {
     pub const HEADER_LEN: usize = 5;
-    pub fn new(_: &[u8]) -> Self {
-        Self {
-            handshake_headers: vec![],
+
+    pub const CONTENT_TYPE_CHANGE_CIPHER_SPEC: u8 = 20;
+    pub const CONTENT_TYPE_ALERT: u8 = 21;
+    pub const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+    pub const CONTENT_TYPE_APPLICATION_DATA: u8 = 23;
+    pub const CONTENT_TYPE_HEARTBEAT: u8 = 24;
+
+    const EXTENSION_SERVER_NAME: u16 = 0;
+    const EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+    const SERVER_NAME_TYPE_HOST_NAME: u8 = 0;
+
+    const RANDOM_LEN: usize = 32;
+
+    pub fn new(payload: &[u8]) -> Self {
+        if payload.len() < Self::HEADER_LEN {
+            return Self {
+                last: true,
+                ..Default::default()
+            };
+        }
+        let mut header = Self {
+            content_type: payload[0],
+            version: u16::from_be_bytes([payload[1], payload[2]]),
+            length: u16::from_be_bytes([payload[3], payload[4]]),
+            ..Default::default()
+        };
+        header.last = header.next() >= payload.len();
+
+        let end = header.next().min(payload.len());
+        let fragment = &payload[Self::HEADER_LEN..end];
+        match header.content_type {
+            Self::CONTENT_TYPE_HANDSHAKE => header.parse_handshakes(fragment),
+            // an encrypted alert is longer than 2 bytes
+            Self::CONTENT_TYPE_ALERT if header.length == 2 && fragment.len() == 2 => {
+                header.alert = Some((fragment[0], fragment[1]));
+            }
+            _ => {}
+        }
+        header
+    }
+
+    // QUIC carries the handshake messages in CRYPTO frames without the record layer
+    pub fn from_handshake_messages(messages: &[u8]) -> Self {
+        let mut header = Self {
+            content_type: Self::CONTENT_TYPE_HANDSHAKE,
+            length: messages.len().min(u16::MAX as usize) as u16,
+            last: true,
+            ..Default::default()
+        };
+        header.parse_handshakes(&messages[..header.length as usize]);
+        header
+    }
+
+    fn parse_handshakes(&mut self, fragment: &[u8]) {
+        let mut offset = 0;
+        while offset + HandshakeHeader::HEADER_LEN <= fragment.len() {
+            let handshake_type = fragment[offset];
+            let length = read_u24(fragment, offset + 1).unwrap();
+            // handshake messages encrypted after ChangeCipherSpec can not be
+            // distinguished by type alone, so check the length as well
+            if !HandshakeHeader::is_known_type(handshake_type)
+                || offset + HandshakeHeader::HEADER_LEN + length > self.length as usize
+            {
+                break;
+            }
+            self.handshake_headers.push(HandshakeHeader {
+                handshake_type,
+                length: length as u32,
+            });
+
+            let start = offset + HandshakeHeader::HEADER_LEN;
+            let end = (start + length).min(fragment.len());
+            let body = &fragment[start..end];
+            match handshake_type {
+                HandshakeHeader::CLIENT_HELLO => {
+                    let _ = self.parse_client_hello(body);
+                }
+                HandshakeHeader::SERVER_HELLO => {
+                    let _ = self.parse_server_hello(body);
+                }
+                HandshakeHeader::CERTIFICATE if self.validity.is_none() => {
+                    self.parse_certificate(body)
+                }
+                _ => {}
+            }
+            offset = start + length;
+        }
+    }
+
+    // client_version(2) random(32) session_id<0..32> cipher_suites<2..2^16-2>
+    // compression_methods<1..2^8-1> extensions<0..2^16-1>
+    fn parse_client_hello(&mut self, body: &[u8]) -> Option<()> {
+        let mut offset = 2 + Self::RANDOM_LEN;
+        offset += 1 + *body.get(offset)? as usize;
+        offset += 2 + read_u16(body, offset)? as usize;
+        offset += 1 + *body.get(offset)? as usize;
+        self.parse_extensions(body, offset, true)
+    }
+
+    // server_version(2) random(32) session_id<0..32> cipher_suite(2)
+    // compression_method(1) extensions<0..2^16-1>
+    fn parse_server_hello(&mut self, body: &[u8]) -> Option<()> {
+        let mut offset = 2 + Self::RANDOM_LEN;
+        offset += 1 + *body.get(offset)? as usize;
+        self.cipher_suite = Some(read_u16(body, offset)?);
+        offset += 2 + 1;
+        self.parse_extensions(body, offset, false)
+    }
+
+    fn parse_extensions(&mut self, body: &[u8], offset: usize, is_client: bool) -> Option<()> {
+        let total = read_u16(body, offset)? as usize;
+        let mut offset = offset + 2;
+        let end = (offset + total).min(body.len());
+        while offset + 4 <= end {
+            let ext_type = read_u16(body, offset)?;
+            let ext_len = read_u16(body, offset + 2)? as usize;
+            offset += 4;
+            let Some(data) = body.get(offset..offset + ext_len) else {
+                break;
+            };
+            match ext_type {
+                Self::EXTENSION_SERVER_NAME if is_client => self.parse_server_name(data),
+                // the client sends a list, the server answers with the selected one
+                Self::EXTENSION_SUPPORTED_VERSIONS if !is_client => {
+                    self.supported_version = read_u16(data, 0);
+                }
+                _ => {}
+            }
+            offset += ext_len;
+        }
+        Some(())
+    }
+
+    // server_name_list<1..2^16-1> of name_type(1) + host_name<1..2^16-1>
+    fn parse_server_name(&mut self, data: &[u8]) {
+        let mut offset = 2;
+        while offset + 3 <= data.len() {
+            let name_type = data[offset];
+            let Some(len) = read_u16(data, offset + 1) else {
+                return;
+            };
+            let Some(name) = data.get(offset + 3..offset + 3 + len as usize) else {
+                return;
+            };
+            if name_type == Self::SERVER_NAME_TYPE_HOST_NAME {
+                if let Ok(name) = std::str::from_utf8(name) {
+                    self.server_name = Some(name.to_owned());
+                }
+                return;
+            }
+            offset += 3 + len as usize;
+        }
+    }
+
+    // certificate_list<0..2^24-1> of ASN.1Cert<1..2^24-1>, only the first
+    // (leaf) certificate is used. TLS 1.3 certificates are always encrypted.
+    fn parse_certificate(&mut self, body: &[u8]) {
+        let Some(cert_len) = read_u24(body, 3) else {
+            return;
+        };
+        let end = (6 + cert_len).min(body.len());
+        if end > 6 {
+            self.validity = parse_certificate_validity(&body[6..end]);
         }
     }
 
     pub fn is_unsupport_content_type(&self) -> bool {
-        true
+        !(Self::CONTENT_TYPE_CHANGE_CIPHER_SPEC..=Self::CONTENT_TYPE_HEARTBEAT)
+            .contains(&self.content_type)
     }
 
     pub fn is_handshake(&self) -> bool {
-        false
+        self.content_type == Self::CONTENT_TYPE_HANDSHAKE
     }
 
     pub fn is_client_hello(&self) -> bool {
-        false
+        self.handshake_headers
+            .iter()
+            .any(|h| h.handshake_type == HandshakeHeader::CLIENT_HELLO)
     }
 
     pub fn is_last(&self) -> bool {
-        true
+        self.last
     }
 
     pub fn content_type(&self) -> u8 {
-        0
+        self.content_type
     }
 
     pub fn version(&self) -> u16 {
-        0
+        self.version
     }
 
     pub fn next(&self) -> usize {
-        0
+        Self::HEADER_LEN + self.length as usize
     }
 
     pub fn supported_version(&self) -> Option<u16> {
-        None
+        self.supported_version
     }
 
     pub fn is_change_cipher_spec(&self) -> bool {
-        false
+        self.content_type == Self::CONTENT_TYPE_CHANGE_CIPHER_SPEC
     }
 
     pub fn domain_name(&self) -> Option<String> {
-        None
+        self.server_name.clone()
     }
 
     pub fn validity(&self) -> Option<(Duration, Duration)> {
-        None
+        self.validity
     }
 
     pub fn is_alert(&self) -> bool {
-        false
+        self.content_type == Self::CONTENT_TYPE_ALERT
+    }
+
+    pub fn alert(&self) -> Option<(u8, u8)> {
+        self.alert
     }
 
     pub fn cipher_suite(&self) -> Option<u16> {
-        None
+        self.cipher_suite
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(content_type: u8, version: u16, fragment: &[u8]) -> Vec<u8> {
+        let mut r = vec![content_type];
+        r.extend_from_slice(&version.to_be_bytes());
+        r.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        r.extend_from_slice(fragment);
+        r
+    }
+
+    fn handshake(handshake_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut h = vec![handshake_type];
+        h.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        h.extend_from_slice(body);
+        h
+    }
+
+    fn extension(ext_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut e = ext_type.to_be_bytes().to_vec();
+        e.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        e.extend_from_slice(data);
+        e
+    }
+
+    fn client_hello(sni: &str) -> Vec<u8> {
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.push(0); // session id
+        body.extend_from_slice(&[0, 4, 0x13, 0x01, 0xc0, 0x2f]);
+        body.extend_from_slice(&[1, 0]);
+
+        let mut sni_data = ((sni.len() + 3) as u16).to_be_bytes().to_vec();
+        sni_data.push(0);
+        sni_data.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+        sni_data.extend_from_slice(sni.as_bytes());
+        let mut extensions = extension(0x000a, &[0, 2, 0, 0x1d]);
+        extensions.extend(extension(0, &sni_data));
+        extensions.extend(extension(43, &[4, 0x03, 0x04, 0x03, 0x03]));
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend(extensions);
+        handshake(HandshakeHeader::CLIENT_HELLO, &body)
+    }
+
+    fn server_hello(cipher_suite: u16, version: Option<u16>) -> Vec<u8> {
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.push(0);
+        body.extend_from_slice(&cipher_suite.to_be_bytes());
+        body.push(0);
+        let extensions = match version {
+            Some(v) => extension(43, &v.to_be_bytes()),
+            None => vec![],
+        };
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend(extensions);
+        handshake(HandshakeHeader::SERVER_HELLO, &body)
+    }
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut d = vec![tag];
+        if content.len() < 0x80 {
+            d.push(content.len() as u8);
+        } else {
+            d.push(0x82);
+            d.extend_from_slice(&(content.len() as u16).to_be_bytes());
+        }
+        d.extend_from_slice(content);
+        d
+    }
+
+    fn certificate(not_before: &[u8], not_after: &[u8]) -> Vec<u8> {
+        let mut tbs = der(0xa0, &der(0x02, &[2]));
+        tbs.extend(der(0x02, &[0x12, 0x34]));
+        tbs.extend(der(0x30, &der(0x06, &[0x2a, 0x86, 0x48])));
+        tbs.extend(der(0x30, &der(0x31, &[0; 100])));
+        let mut validity = der(Der::TAG_UTC_TIME, not_before);
+        validity.extend(der(Der::TAG_GENERALIZED_TIME, not_after));
+        tbs.extend(der(0x30, &validity));
+        tbs.extend(der(0x30, &[0; 200]));
+        let cert = der(0x30, &der(0x30, &tbs));
+
+        let mut body = ((cert.len() + 3) as u32).to_be_bytes()[1..].to_vec();
+        body.extend_from_slice(&(cert.len() as u32).to_be_bytes()[1..]);
+        body.extend(cert);
+        handshake(HandshakeHeader::CERTIFICATE, &body)
+    }
+
+    #[test]
+    fn client_hello_sni() {
+        let payload = record(22, 0x0301, &client_hello("www.example.com"));
+        let header = TlsHeader::new(&payload);
+        assert!(header.is_handshake());
+        assert!(header.is_client_hello());
+        assert!(header.is_last());
+        assert_eq!(header.version(), 0x0301);
+        assert_eq!(header.domain_name().as_deref(), Some("www.example.com"));
+        assert_eq!(header.supported_version(), None);
+        assert_eq!(header.to_string(), "ClientHello");
+    }
+
+    #[test]
+    fn quic_handshake_messages() {
+        let header = TlsHeader::from_handshake_messages(&client_hello("www.example.com"));
+        assert!(header.is_client_hello());
+        assert_eq!(header.domain_name().as_deref(), Some("www.example.com"));
+
+        let header = TlsHeader::from_handshake_messages(&server_hello(0x1301, Some(0x0304)));
+        assert_eq!(header.cipher_suite(), Some(0x1301));
+        assert_eq!(header.supported_version(), Some(0x0304));
+        assert_eq!(header.to_string(), "ServerHello");
+    }
+
+    #[test]
+    fn server_hello_certificate() {
+        let mut fragment = server_hello(0x1301, Some(0x0304));
+        fragment.extend(certificate(b"240101000000Z", b"20250601120000Z"));
+        fragment.extend(handshake(HandshakeHeader::SERVER_HELLO_DONE, &[]));
+        let mut payload = record(22, 0x0303, &fragment);
+        payload.extend(record(20, 0x0303, &[1]));
+
+        let header = TlsHeader::new(&payload);
+        assert!(!header.is_last());
+        assert!(!header.is_client_hello());
+        assert_eq!(header.cipher_suite(), Some(0x1301));
+        assert_eq!(header.supported_version(), Some(0x0304));
+        assert_eq!(
+            header.validity(),
+            Some((
+                Duration::from_secs(1704067200),
+                Duration::from_secs(1748779200)
+            ))
+        );
+        assert_eq!(
+            header.to_string(),
+            "ServerHello,Certificate,ServerHelloDone"
+        );
+
+        let next = TlsHeader::new(&payload[header.next()..]);
+        assert!(next.is_change_cipher_spec());
+        assert!(next.is_last());
+    }
+
+    #[test]
+    fn truncated_certificate() {
+        let fragment = certificate(b"240101000000Z", b"20250601120000Z");
+        let payload = record(22, 0x0303, &fragment);
+        // validity is located in the first segment
+        let header = TlsHeader::new(&payload[..200]);
+        assert!(header.is_last());
+        assert_eq!(
+            header.validity().map(|v| v.1),
+            Some(Duration::from_secs(1748779200))
+        );
+    }
+
+    #[test]
+    fn encrypted_and_alert() {
+        let header = TlsHeader::new(&record(22, 0x0303, &[0xa7; 40]));
+        assert!(header.handshake_headers.is_empty());
+        assert_eq!(header.to_string(), "EncryptedHandshakeMessage");
+
+        let header = TlsHeader::new(&record(21, 0x0303, &[2, 40]));
+        assert!(header.is_alert());
+        assert_eq!(header.alert(), Some((2, 40)));
+
+        let header = TlsHeader::new(&[0x47, 0x45, 0x54, 0x20, 0x2f, 0x20]);
+        assert!(header.is_unsupport_content_type());
     }
 }
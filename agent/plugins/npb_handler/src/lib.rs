@@ -14,19 +14,31 @@
  * limitations under the License.
  */
 
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 
-use npb_pcap_policy::{NpbTunnelType, PolicyData};
+use log::debug;
+
+use npb_pcap_policy::{NpbAction, NpbTunnelType, PolicyData};
 use public::{
+    consts::{
+        erspan, ipv4, ipv6, udp, vxlan, ETH_ADDR_SIZE, ETH_HEADER_SIZE, GRE_HEADER_SIZE,
+        IPV4_HEADER_SIZE, IPV6_HEADER_SIZE, TCP_HEADER_SIZE, UDP_HEADER_SIZE, VLAN_HEADER_SIZE,
+        VLAN_ID_MASK, VXLAN6_PACKET_SIZE,
+    },
     counter::{CounterType, CounterValue, OwnedCountable},
     leaky_bucket::LeakyBucket,
     proto::agent::VlanMode,
     queue::DebugSender,
 };
 
-pub const NOT_SUPPORT: bool = true;
+pub const NOT_SUPPORT: bool = false;
 
+const ETH_TYPE_DOT1Q: u16 = 0x8100;
+const ETH_TYPE_DOT1AD: u16 = 0x88a8;
+const VNI_MASK: u32 = 0xffffff;
+const ERSPAN_SESSION_ID_MASK: u32 = 0x3ff;
 #[derive(Debug)]
 pub enum NpbMode {
     L2,
@@ -42,6 +54,7 @@ pub struct NpbHandlerCounter {
     pub rx_bytes: AtomicUsize,
     pub tx: AtomicUsize,
     pub tx_bytes: AtomicUsize,
+    pub drop: AtomicUsize,
 }
 
 impl NpbHandlerCounter {
@@ -50,6 +63,7 @@ impl NpbHandlerCounter {
         self.rx_bytes.store(0, Ordering::Relaxed);
         self.tx.store(0, Ordering::Relaxed);
         self.tx_bytes.store(0, Ordering::Relaxed);
+        self.drop.store(0, Ordering::Relaxed);
     }
 }
 
@@ -63,11 +77,12 @@ impl OwnedCountable for StatsNpbHandlerCounter {
     fn get_counters(&self) -> Vec<public::counter::Counter> {
         match self.0.upgrade() {
             Some(x) => {
-                let (rx, rx_bytes, tx, tx_bytes) = (
+                let (rx, rx_bytes, tx, tx_bytes, drop) = (
                     x.rx.load(Ordering::Relaxed) as u64,
                     x.rx_bytes.load(Ordering::Relaxed) as u64,
                     x.tx.load(Ordering::Relaxed) as u64,
                     x.tx_bytes.load(Ordering::Relaxed) as u64,
+                    x.drop.load(Ordering::Relaxed) as u64,
                 );
                 x.reset();
 
@@ -84,6 +99,7 @@ impl OwnedCountable for StatsNpbHandlerCounter {
                         CounterType::Counted,
                         CounterValue::Unsigned(tx_bytes),
                     ),
+                    ("drop", CounterType::Counted, CounterValue::Unsigned(drop)),
                 ]
             }
             None => {
@@ -93,63 +109,451 @@ impl OwnedCountable for StatsNpbHandlerCounter {
     }
 }
 
-pub struct NpbHandler;
+// Encapsulates packets selected by NPB actions into VXLAN, ERSPAN or TCP tunnels.
+//
+// The tunnel header is copied from `pseudo_tunnel_header` (indexed by NpbTunnelType), only the
+// fields depending on the action and packet are filled here. Underlay MAC, source IP, checksum
+// and sequence are filled by NpbPacketSender according to the socket type.
+pub struct NpbHandler {
+    id: usize,
+    mtu: usize,
+    pseudo_tunnel_header: [Vec<u8>; NpbTunnelType::Max as usize],
+    underlay_vlan_header_size: usize,
+    underlay_is_ipv6: bool,
+    overlay_vlan_mode: VlanMode,
+    ignore_overlay_vlan: bool,
+
+    bps_limit: Arc<LeakyBucket>,
+    counter: Arc<NpbHandlerCounter>,
+    sender: DebugSender<(u64, usize, Vec<u8>)>,
+}
 
 impl NpbHandler {
     pub fn new(
-        _id: usize,
-        _mtu: usize,
-        _pseudo_tunnel_header: [Vec<u8>; NpbTunnelType::Max as usize],
-        _underlay_vlan_header_size: usize,
-        _overlay_vlan_mode: VlanMode,
-        _ignore_overlay_vlan: bool,
-        _bps_limit: Arc<LeakyBucket>,
-        _counter: Arc<NpbHandlerCounter>,
-        _sender: DebugSender<(u64, usize, Vec<u8>)>,
+        id: usize,
+        mtu: usize,
+        pseudo_tunnel_header: [Vec<u8>; NpbTunnelType::Max as usize],
+        underlay_vlan_header_size: usize,
+        overlay_vlan_mode: VlanMode,
+        ignore_overlay_vlan: bool,
+        bps_limit: Arc<LeakyBucket>,
+        counter: Arc<NpbHandlerCounter>,
+        sender: DebugSender<(u64, usize, Vec<u8>)>,
     ) -> Self {
-        NpbHandler {}
+        let underlay_is_ipv6 = pseudo_tunnel_header[NpbTunnelType::VxLan as usize].len()
+            == VXLAN6_PACKET_SIZE + underlay_vlan_header_size;
+        NpbHandler {
+            id,
+            mtu,
+            pseudo_tunnel_header,
+            underlay_vlan_header_size,
+            underlay_is_ipv6,
+            overlay_vlan_mode,
+            ignore_overlay_vlan,
+            bps_limit,
+            counter,
+            sender,
+        }
+    }
+
+    // size of headers not counted in payload slice
+    fn payload_offset(
+        npb_mode: &NpbMode,
+        l2_opt_size: usize,
+        l3_opt_size: usize,
+        l4_opt_size: usize,
+    ) -> usize {
+        let l2_size = ETH_HEADER_SIZE + l2_opt_size;
+        match npb_mode {
+            NpbMode::L2 => l2_size,
+            NpbMode::IPv4 => l2_size + IPV4_HEADER_SIZE + l3_opt_size,
+            NpbMode::IPv6 => l2_size + IPV6_HEADER_SIZE + l3_opt_size,
+            NpbMode::IPv4TCP => {
+                l2_size + IPV4_HEADER_SIZE + l3_opt_size + TCP_HEADER_SIZE + l4_opt_size
+            }
+            NpbMode::IPv6TCP => {
+                l2_size + IPV6_HEADER_SIZE + l3_opt_size + TCP_HEADER_SIZE + l4_opt_size
+            }
+        }
+    }
+
+    // Copy overlay packet into buffer, trimming its VLAN tags if configured and inserting
+    // VLAN tags carrying tunnel id according to overlay_vlan_mode.
+    fn append_overlay(&self, buffer: &mut Vec<u8>, overlay: &[u8], l2_opt_size: usize, id: u32) {
+        buffer.extend_from_slice(&overlay[..ETH_ADDR_SIZE]);
+        match self.overlay_vlan_mode {
+            VlanMode::Vlan => {
+                buffer.extend_from_slice(&ETH_TYPE_DOT1Q.to_be_bytes());
+                buffer.extend_from_slice(&(id as u16 & VLAN_ID_MASK).to_be_bytes());
+            }
+            VlanMode::Qinq => {
+                buffer.extend_from_slice(&ETH_TYPE_DOT1AD.to_be_bytes());
+                buffer.extend_from_slice(&((id >> 12) as u16 & VLAN_ID_MASK).to_be_bytes());
+                buffer.extend_from_slice(&ETH_TYPE_DOT1Q.to_be_bytes());
+                buffer.extend_from_slice(&(id as u16 & VLAN_ID_MASK).to_be_bytes());
+            }
+            VlanMode::None => (),
+        }
+        if self.ignore_overlay_vlan {
+            buffer.extend_from_slice(&overlay[ETH_ADDR_SIZE + l2_opt_size..]);
+        } else {
+            buffer.extend_from_slice(&overlay[ETH_ADDR_SIZE..]);
+        }
+    }
+
+    fn encapsulate(
+        &self,
+        action: &NpbAction,
+        timestamp: u64,
+        overlay: &[u8],
+        l2_opt_size: usize,
+        sliced: bool,
+    ) -> Option<Vec<u8>> {
+        let tunnel_type = action.tunnel_type();
+        let header = &self.pseudo_tunnel_header[tunnel_type as usize];
+        let l2_size = ETH_HEADER_SIZE + self.underlay_vlan_header_size;
+        let (l3_size, max_size) = match (action.tunnel_ip(), self.underlay_is_ipv6) {
+            (IpAddr::V4(_), false) => (IPV4_HEADER_SIZE, l2_size + self.mtu),
+            (IpAddr::V6(_), true) => (IPV6_HEADER_SIZE, l2_size + self.mtu),
+            _ => return None,
+        };
+
+        let mut buffer = Vec::with_capacity(header.len() + VLAN_HEADER_SIZE * 2 + overlay.len());
+        buffer.extend_from_slice(header);
+        self.append_overlay(&mut buffer, overlay, l2_opt_size, action.tunnel_id());
+
+        // cut packets exceeding MTU, TCP tunnel is limited by u16 total length in NpbHeader
+        let full_size = buffer.len();
+        match tunnel_type {
+            NpbTunnelType::VxLan | NpbTunnelType::GreErspan => buffer.truncate(max_size),
+            _ => {
+                let overlay_offset = header.len() - NpbHeader::SIZEOF;
+                buffer.truncate(overlay_offset + u16::MAX as usize);
+            }
+        }
+
+        let ip_offset = l2_size;
+        let ip_len = buffer.len() - ip_offset;
+        match action.tunnel_ip() {
+            IpAddr::V4(ip) => {
+                let offset = ip_offset + ipv4::TOTAL_LENGTH_OFFSET;
+                buffer[offset..offset + 2].copy_from_slice(&(ip_len as u16).to_be_bytes());
+                let offset = ip_offset + ipv4::DST_OFFSET;
+                buffer[offset..offset + 4].copy_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                let offset = ip_offset + ipv6::PAYLOAD_LENGTH_OFFSET;
+                buffer[offset..offset + 2]
+                    .copy_from_slice(&((ip_len - IPV6_HEADER_SIZE) as u16).to_be_bytes());
+                let offset = ip_offset + ipv6::DST_OFFSET;
+                buffer[offset..offset + 16].copy_from_slice(&ip.octets());
+            }
+        }
+
+        let l4_offset = ip_offset + l3_size;
+        match tunnel_type {
+            NpbTunnelType::VxLan => {
+                let offset = l4_offset + udp::LENGTH_OFFSET;
+                let udp_len = buffer.len() - l4_offset;
+                buffer[offset..offset + 2].copy_from_slice(&(udp_len as u16).to_be_bytes());
+                // VNI takes the higher 24 bits, the lowest 8 bits are reserved
+                let offset = l4_offset + UDP_HEADER_SIZE + vxlan::VNI_OFFSET;
+                buffer[offset..offset + 4]
+                    .copy_from_slice(&((action.tunnel_id() & VNI_MASK) << 8).to_be_bytes());
+            }
+            NpbTunnelType::GreErspan => {
+                let offset = l4_offset + erspan::GRE_KEY_OFFSET;
+                buffer[offset..offset + 4].copy_from_slice(&action.tunnel_id().to_be_bytes());
+                let erspan_offset = l4_offset + GRE_HEADER_SIZE;
+                let offset = erspan_offset + erspan::TYPE3_SESSION_ID_OFFSET;
+                buffer[offset..offset + 2].copy_from_slice(
+                    &((action.tunnel_id() & ERSPAN_SESSION_ID_MASK) as u16).to_be_bytes(),
+                );
+                // user defined granularity: seconds in high 32 bits and milliseconds in lower 16 bits
+                let offset = erspan_offset + erspan::TYPE3_TIMESTAMP_HIGH_OFFSET;
+                buffer[offset..offset + 4]
+                    .copy_from_slice(&((timestamp / 1_000_000_000) as u32).to_be_bytes());
+                let offset = erspan_offset + erspan::TYPE3_TIMESTAMP_LOWER_OFFSET;
+                buffer[offset..offset + 2].copy_from_slice(
+                    &((timestamp % 1_000_000_000 / 1_000_000) as u16).to_be_bytes(),
+                );
+            }
+            _ => {
+                let offset = header.len() - NpbHeader::SIZEOF;
+                let total_length = (buffer.len() - offset) as u16;
+                let sliced = sliced || buffer.len() < full_size;
+                NpbHeader::new(
+                    total_length,
+                    if sliced { NpbHeader::FLAG_SLICED } else { 0 },
+                    action.tunnel_id(),
+                    timestamp,
+                )
+                .encode(&mut buffer[offset..]);
+            }
+        }
+        Some(buffer)
     }
 
     pub fn handle(
         &mut self,
-        _policy: Option<&Arc<PolicyData>>,
-        _npb_mode: &NpbMode,
-        _timestamp: u64,
-        _packet: &[u8],
-        _packet_size: usize,
-        _l2_opt_size: usize,
-        _l3_opt_size: usize,
-        _l4_opt_size: usize,
+        policy: Option<&Arc<PolicyData>>,
+        npb_mode: &NpbMode,
+        timestamp: u64,
+        packet: &[u8],
+        packet_size: usize,
+        l2_opt_size: usize,
+        l3_opt_size: usize,
+        l4_opt_size: usize,
         _ipv6_last_option_offset: usize,
         _ipv6_fragment_option_offset: usize,
     ) {
+        let Some(policy) = policy else {
+            return;
+        };
+        if !policy.contain_npb() {
+            return;
+        }
+        let packet = &packet[..packet_size.min(packet.len())];
+        if packet.len() < ETH_HEADER_SIZE + l2_opt_size {
+            return;
+        }
+        self.counter.rx.fetch_add(1, Ordering::Relaxed);
+        self.counter
+            .rx_bytes
+            .fetch_add(packet.len(), Ordering::Relaxed);
+
+        let payload_offset = Self::payload_offset(npb_mode, l2_opt_size, l3_opt_size, l4_opt_size);
+        for action in policy.npb_actions.iter() {
+            match action.tunnel_type() {
+                NpbTunnelType::VxLan | NpbTunnelType::GreErspan | NpbTunnelType::TcpNpb => (),
+                _ => continue,
+            }
+            let overlay_size = packet
+                .len()
+                .min(payload_offset.saturating_add(action.payload_slice()));
+            let Some(buffer) = self.encapsulate(
+                action,
+                timestamp,
+                &packet[..overlay_size],
+                l2_opt_size,
+                overlay_size < packet.len(),
+            ) else {
+                self.counter.drop.fetch_add(1, Ordering::Relaxed);
+                continue;
+            };
+            let size = buffer.len();
+            if !self.bps_limit.acquire(size as u64 * 8) {
+                self.counter.drop.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            if let Err(e) = self
+                .sender
+                .send((timestamp, self.underlay_vlan_header_size, buffer))
+            {
+                debug!("npb handler {} send packet error: {:?}", self.id, e);
+                self.counter.drop.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            self.counter.tx.fetch_add(1, Ordering::Relaxed);
+            self.counter.tx_bytes.fetch_add(size, Ordering::Relaxed);
+        }
     }
 }
 
-#[derive(Default)]
+/*
+ NPB header used in TCP tunnel, all fields are in network byte order:
+ 0                   1                   2                   3
+ 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|         Total Length          |    Version    |     Flags     |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                           Tunnel ID                           |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+|                                                               |
++                     Timestamp (nanoseconds)                   +
+|                                                               |
++-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+ Total Length includes the header itself, followed by the overlay packet.
+*/
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct NpbHeader {
     pub total_length: u16,
+    pub version: u8,
+    pub flags: u8,
+    pub tunnel_id: u32,
+    pub timestamp: u64,
 }
 
 impl NpbHeader {
     pub const SIZEOF: usize = 16;
+    pub const VERSION: u8 = 1;
+    // overlay packet is cut by payload slice or MTU
+    pub const FLAG_SLICED: u8 = 0x1;
 
-    pub fn new(_: u16, _: u8, _: u32, _: u64) -> Self {
-        NpbHeader::default()
+    pub fn new(total_length: u16, flags: u8, tunnel_id: u32, timestamp: u64) -> Self {
+        NpbHeader {
+            total_length,
+            version: Self::VERSION,
+            flags,
+            tunnel_id,
+            timestamp,
+        }
     }
 
-    pub fn encode(&self, _buffer: &mut [u8]) -> usize {
+    pub fn encode(&self, buffer: &mut [u8]) -> usize {
+        if buffer.len() < Self::SIZEOF {
+            return 0;
+        }
+        buffer[0..2].copy_from_slice(&self.total_length.to_be_bytes());
+        buffer[2] = self.version;
+        buffer[3] = self.flags;
+        buffer[4..8].copy_from_slice(&self.tunnel_id.to_be_bytes());
+        buffer[8..16].copy_from_slice(&self.timestamp.to_be_bytes());
         Self::SIZEOF
     }
 
-    pub fn decode(&mut self, _buffer: &[u8]) -> usize {
+    pub fn decode(&mut self, buffer: &[u8]) -> usize {
+        if buffer.len() < Self::SIZEOF {
+            return 0;
+        }
+        self.total_length = u16::from_be_bytes(buffer[0..2].try_into().unwrap());
+        self.version = buffer[2];
+        self.flags = buffer[3];
+        self.tunnel_id = u32::from_be_bytes(buffer[4..8].try_into().unwrap());
+        self.timestamp = u64::from_be_bytes(buffer[8..16].try_into().unwrap());
         Self::SIZEOF
     }
 }
 
 impl TryFrom<&[u8]> for NpbHeader {
     type Error = bool;
-    fn try_from(_buffer: &[u8]) -> Result<Self, Self::Error> {
-        Ok(NpbHeader::default())
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        let mut header = NpbHeader::default();
+        if header.decode(buffer) == 0 || header.version != Self::VERSION {
+            return Err(false);
+        }
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use npb_pcap_policy::{DirectionType, TapSide};
+    use public::{consts::TCP_PACKET_SIZE, debug::QueueDebugger, queue};
+
+    const TUNNEL_IP: Ipv4Addr = Ipv4Addr::new(10, 1, 1, 1);
+
+    fn pseudo_headers() -> [Vec<u8>; NpbTunnelType::Max as usize] {
+        let mut vxlan = vec![0u8; 50];
+        vxlan[12..14].copy_from_slice(&[0x08, 0x00]);
+        vxlan[14] = 0x45;
+        vxlan[23] = 17;
+        vxlan[36..38].copy_from_slice(&4789u16.to_be_bytes());
+        vxlan[42] = 0x08;
+        let mut tcp = vec![6u8; TCP_PACKET_SIZE + NpbHeader::SIZEOF];
+        tcp[TCP_PACKET_SIZE..].fill(0);
+        [vxlan, vec![0u8; 54], vec![], vec![], tcp]
+    }
+
+    // ethernet + ipv4 + tcp headers followed by payload
+    fn tcp_packet(payload_size: usize) -> Vec<u8> {
+        let mut packet = vec![0u8; TCP_PACKET_SIZE + payload_size];
+        packet[12..14].copy_from_slice(&[0x08, 0x00]);
+        packet[14] = 0x45;
+        packet[23] = 6;
+        packet
+    }
+
+    fn handle(
+        tunnel_type: NpbTunnelType,
+        vlan_mode: VlanMode,
+        packet: &[u8],
+        slice: u16,
+    ) -> Vec<(u64, usize, Vec<u8>)> {
+        let (sender, receiver, _) = queue::bounded_with_debug(16, "test", &QueueDebugger::new());
+        let counter = Arc::new(NpbHandlerCounter::default());
+        let mut handler = NpbHandler::new(
+            0,
+            1500,
+            pseudo_headers(),
+            0,
+            vlan_mode,
+            false,
+            Arc::new(LeakyBucket::new(None)),
+            counter.clone(),
+            sender,
+        );
+        let action = NpbAction::new(
+            1,
+            0x123456,
+            IpAddr::from(TUNNEL_IP),
+            1,
+            tunnel_type,
+            TapSide::ALL,
+            DirectionType::ALL,
+            slice,
+        );
+        let policy = Arc::new(PolicyData::new(vec![action], 1));
+        handler.handle(
+            Some(&policy),
+            &NpbMode::IPv4TCP,
+            1_000_000_000,
+            packet,
+            packet.len(),
+            0,
+            0,
+            0,
+            0,
+            0,
+        );
+        assert_eq!(counter.rx.load(Ordering::Relaxed), 1);
+        let mut output = vec![];
+        while let Ok(item) = receiver.recv(Some(std::time::Duration::from_millis(10))) {
+            output.push(item);
+        }
+        output
+    }
+
+    #[test]
+    fn vxlan_with_slice_and_vlan() {
+        let packet = tcp_packet(100);
+        let output = handle(NpbTunnelType::VxLan, VlanMode::Vlan, &packet, 10);
+        assert_eq!(output.len(), 1);
+        let (timestamp, underlay_vlan_size, buffer) = &output[0];
+        assert_eq!((*timestamp, *underlay_vlan_size), (1_000_000_000, 0));
+        // 50 bytes vxlan header + headers with 10 bytes payload + 4 bytes vlan
+        assert_eq!(buffer.len(), 50 + TCP_PACKET_SIZE + 10 + VLAN_HEADER_SIZE);
+        assert_eq!(&buffer[16..18], &((buffer.len() - 14) as u16).to_be_bytes());
+        assert_eq!(&buffer[30..34], &TUNNEL_IP.octets());
+        assert_eq!(&buffer[38..40], &((buffer.len() - 34) as u16).to_be_bytes());
+        assert_eq!(&buffer[46..49], &[0x12, 0x34, 0x56]);
+        // vlan id is the lower 12 bits of tunnel id
+        assert_eq!(&buffer[62..66], &[0x81, 0x00, 0x04, 0x56]);
+        assert_eq!(&buffer[66..68], &[0x08, 0x00]);
+    }
+
+    #[test]
+    fn tcp_tunnel() {
+        let packet = tcp_packet(100);
+        let output = handle(NpbTunnelType::TcpNpb, VlanMode::None, &packet, 0);
+        assert_eq!(output.len(), 1);
+        let buffer = &output[0].2;
+        assert_eq!(buffer[23], 6);
+        assert_eq!(&buffer[30..34], &TUNNEL_IP.octets());
+        let header = NpbHeader::try_from(&buffer[TCP_PACKET_SIZE..]).unwrap();
+        assert_eq!(
+            header,
+            NpbHeader::new(
+                (NpbHeader::SIZEOF + packet.len()) as u16,
+                0,
+                0x123456,
+                1_000_000_000
+            )
+        );
+        assert_eq!(&buffer[TCP_PACKET_SIZE + NpbHeader::SIZEOF..], &packet[..]);
     }
 }
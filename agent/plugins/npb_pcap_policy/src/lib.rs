@@ -14,7 +14,6 @@
  * limitations under the License.
  */
 
-//! Enterprise Edition Feature: policy
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr};
 
@@ -22,7 +21,10 @@ use bitflags::bitflags;
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-pub const NOT_SUPPORT: bool = true;
+pub const NOT_SUPPORT: bool = false;
+
+// payload_slice 0 means the whole packet
+const PAYLOAD_SLICE_FULL: usize = u16::MAX as usize;
 
 bitflags! {
     #[derive(Default)]
@@ -36,14 +38,19 @@ bitflags! {
 }
 
 impl From<DirectionType> for TapSide {
-    fn from(_d: DirectionType) -> Self {
-        TapSide::NONE
+    fn from(d: DirectionType) -> Self {
+        match d {
+            DirectionType::FORWARD => TapSide::SRC,
+            DirectionType::BACKWARD => TapSide::DST,
+            _ => TapSide::ALL,
+        }
     }
 }
 
 impl TapSide {
-    pub fn new(_flags: u8) -> Self {
-        TapSide::NONE
+    // flags is PacketCaptureSide in agent.proto: SRC = 1, DST = 2, BOTH = 3
+    pub fn new(flags: u8) -> Self {
+        TapSide::from_bits_truncate(flags) & TapSide::MASK
     }
 }
 
@@ -66,8 +73,12 @@ bitflags! {
 }
 
 impl From<TapSide> for DirectionType {
-    fn from(_d: TapSide) -> Self {
-        DirectionType::ALL
+    fn from(d: TapSide) -> Self {
+        match d {
+            TapSide::SRC => DirectionType::FORWARD,
+            TapSide::DST => DirectionType::BACKWARD,
+            _ => DirectionType::ALL,
+        }
     }
 }
 
@@ -78,8 +89,13 @@ impl Default for DirectionType {
 }
 
 impl DirectionType {
-    pub fn new(_: u8) -> Self {
-        Self::ALL
+    // direction is Direction in agent.proto: ALL = 1, FORWARD = 2, BACKWARD = 3
+    pub fn new(direction: u8) -> Self {
+        match direction {
+            2 => Self::FORWARD,
+            3 => Self::BACKWARD,
+            _ => Self::ALL,
+        }
     }
 }
 
@@ -95,13 +111,23 @@ pub enum NpbTunnelType {
 }
 
 impl NpbTunnelType {
-    pub fn new(_flags: u8) -> Self {
-        Self::VxLan
+    // flags is TunnelType in agent.proto
+    pub fn new(flags: u8) -> Self {
+        match Self::try_from(flags) {
+            Ok(t) if t != Self::Max => t,
+            _ => Self::VxLan,
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct NpbAction {
+    tunnel_type: NpbTunnelType,
+    tunnel_id: u32,
+    tunnel_ip: IpAddr,
+    tap_side: TapSide,
+    direction: DirectionType,
+    payload_slice: u16,
     acl_gids: Vec<u16>,
     tunnel_ip_ids: Vec<u16>,
 }
@@ -109,6 +135,12 @@ pub struct NpbAction {
 impl Default for NpbAction {
     fn default() -> Self {
         Self {
+            tunnel_type: NpbTunnelType::VxLan,
+            tunnel_id: 0,
+            tunnel_ip: IpAddr::from(Ipv4Addr::UNSPECIFIED),
+            tap_side: TapSide::NONE,
+            direction: DirectionType::ALL,
+            payload_slice: 0,
             acl_gids: vec![],
             tunnel_ip_ids: vec![],
         }
@@ -118,41 +150,55 @@ impl Default for NpbAction {
 impl NpbAction {
     pub fn new(
         acl_gid: u32,
-        _id: u32,
-        _tunnel_ip: IpAddr,
+        id: u32,
+        tunnel_ip: IpAddr,
         tunnel_ip_id: u16,
-        _tunnel_type: NpbTunnelType,
-        _tap_side: TapSide,
-        _direction_capacity: DirectionType,
-        _slice: u16,
+        tunnel_type: NpbTunnelType,
+        tap_side: TapSide,
+        direction_capacity: DirectionType,
+        slice: u16,
     ) -> Self {
         Self {
+            tunnel_type,
+            tunnel_id: id,
+            tunnel_ip,
+            tap_side,
+            direction: direction_capacity,
+            payload_slice: slice,
             acl_gids: vec![acl_gid as u16],
             tunnel_ip_ids: vec![tunnel_ip_id],
         }
     }
 
     pub const fn tap_side(&self) -> TapSide {
-        TapSide::SRC
+        self.tap_side
     }
 
     pub const fn tunnel_id(&self) -> u32 {
-        100
+        self.tunnel_id
     }
 
     pub const fn payload_slice(&self) -> usize {
-        65535
+        if self.payload_slice == 0 {
+            PAYLOAD_SLICE_FULL
+        } else {
+            self.payload_slice as usize
+        }
     }
 
     pub fn tunnel_type(&self) -> NpbTunnelType {
-        NpbTunnelType::VxLan
+        self.tunnel_type
     }
 
     pub fn add_acl_gid(&mut self, acl_gids: &[u16], tunnel_ip_ids: &[u16]) {
-        acl_gids.into_iter().for_each(|x| self.acl_gids.push(*x));
-        tunnel_ip_ids
-            .into_iter()
-            .for_each(|x| self.tunnel_ip_ids.push(*x));
+        for (i, gid) in acl_gids.iter().enumerate() {
+            if self.acl_gids.contains(gid) {
+                continue;
+            }
+            self.acl_gids.push(*gid);
+            self.tunnel_ip_ids
+                .push(tunnel_ip_ids.get(i).copied().unwrap_or_default());
+        }
     }
 
     pub fn acl_gids(&self) -> &[u16] {
@@ -160,25 +206,69 @@ impl NpbAction {
     }
 
     pub fn tunnel_ip(&self) -> IpAddr {
-        IpAddr::from(Ipv4Addr::UNSPECIFIED)
+        self.tunnel_ip
     }
 
     pub fn tunnel_ip_ids(&self) -> &[u16] {
         &self.tunnel_ip_ids
     }
 
-    pub fn reverse_tap_side(&mut self) {}
+    pub fn reverse_tap_side(&mut self) {
+        if self.tap_side == TapSide::SRC || self.tap_side == TapSide::DST {
+            self.tap_side ^= TapSide::MASK;
+        }
+    }
 
-    pub fn set_payload_slice(&mut self, _payload_slice: u16) {}
+    pub fn set_payload_slice(&mut self, payload_slice: u16) {
+        self.payload_slice = payload_slice;
+    }
 
-    pub fn add_tap_side(&mut self, _tap_side: TapSide) {}
+    pub fn add_tap_side(&mut self, tap_side: TapSide) {
+        self.tap_side |= tap_side & TapSide::MASK;
+    }
 
-    pub fn set_tap_side(&mut self, _tap_side: TapSide) {}
+    pub fn set_tap_side(&mut self, tap_side: TapSide) {
+        self.tap_side = tap_side & TapSide::MASK;
+    }
+
+    fn action_flag(&self) -> ActionFlags {
+        match self.tunnel_type {
+            NpbTunnelType::Pcap => ActionFlags::PCAP,
+            NpbTunnelType::NpbDrop => ActionFlags::NPB_DROP,
+            _ => ActionFlags::NPB,
+        }
+    }
+
+    // actions sending to the same tunnel are merged into one
+    fn is_same_tunnel(&self, other: &NpbAction) -> bool {
+        self.tunnel_type == other.tunnel_type
+            && self.tunnel_id == other.tunnel_id
+            && self.tunnel_ip == other.tunnel_ip
+    }
+
+    fn merge(&mut self, other: &NpbAction) {
+        self.add_acl_gid(&other.acl_gids, &other.tunnel_ip_ids);
+        self.add_tap_side(other.tap_side);
+        self.direction |= other.direction;
+        if self.payload_slice() < other.payload_slice() {
+            self.payload_slice = other.payload_slice;
+        }
+    }
 }
 
 impl fmt::Display for NpbAction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Not support.")
+        write!(
+            f,
+            "{{{:?}@{}:{} gid: {:?} side: {:?} direction: {:?} slice: {}}}",
+            self.tunnel_type,
+            self.tunnel_ip,
+            self.tunnel_id,
+            self.acl_gids,
+            self.tap_side,
+            self.direction,
+            self.payload_slice
+        )
     }
 }
 
@@ -218,7 +308,38 @@ impl PolicyData {
         self.acl_id > 0 && self.action_flags.contains(ActionFlags::PCAP)
     }
 
-    pub fn format_npb_action(&mut self) {}
+    // sort actions for a stable output and recalculate action flags
+    pub fn format_npb_action(&mut self) {
+        self.npb_actions.sort_by(|a, b| {
+            a.tunnel_type
+                .cmp(&b.tunnel_type)
+                .then(a.tunnel_ip.cmp(&b.tunnel_ip))
+                .then(a.tunnel_id.cmp(&b.tunnel_id))
+        });
+        self.action_flags = self
+            .npb_actions
+            .iter()
+            .fold(ActionFlags::NONE, |flags, a| flags | a.action_flag());
+    }
+
+    fn add_npb_action(&mut self, action: NpbAction) {
+        self.set_action_flags(&action);
+        match self
+            .npb_actions
+            .iter_mut()
+            .find(|a| a.is_same_tunnel(&action))
+        {
+            Some(a) => a.merge(&action),
+            None => self.npb_actions.push(action),
+        }
+    }
+
+    fn set_acl_id(&mut self, acl_id: u32) {
+        // the first matched acl is reserved
+        if self.acl_id == 0 {
+            self.acl_id = acl_id;
+        }
+    }
 
     pub fn merge_and_dedup_npb_actions(
         &mut self,
@@ -226,49 +347,181 @@ impl PolicyData {
         acl_id: u32,
         reverse: bool,
     ) {
-        self.acl_id = acl_id;
-        actions.into_iter().for_each(|x| {
-            let mut action = x.clone();
+        self.set_acl_id(acl_id);
+        for action in actions {
+            let mut action = action.clone();
             if reverse {
                 action.reverse_tap_side();
             }
-            self.npb_actions.push(action)
-        })
+            self.add_npb_action(action);
+        }
     }
 
+    // merge actions of an acl matched in the direction, actions configured
+    // for the other direction only are ignored
     pub fn merge_npb_actions(
         &mut self,
         actions: &Vec<NpbAction>,
         acl_id: u32,
         direction: DirectionType,
     ) {
-        self.acl_id = acl_id;
-        let tap_side = TapSide::from(direction);
-        actions.into_iter().for_each(|x| {
-            let mut action = x.clone();
-            action.set_tap_side(tap_side);
-            self.npb_actions.push(action)
-        })
+        let mut merged = false;
+        for action in actions {
+            if !action.direction.intersects(direction) {
+                continue;
+            }
+            let mut action = action.clone();
+            if direction == DirectionType::BACKWARD {
+                action.reverse_tap_side();
+            }
+            self.add_npb_action(action);
+            merged = true;
+        }
+        if merged {
+            self.set_acl_id(acl_id);
+        }
     }
 
     pub fn merge_reverse_npb_action(&mut self, actions: &Vec<NpbAction>, acl_id: u32) {
-        self.acl_id = acl_id;
-        actions.into_iter().for_each(|x| {
-            let mut action = x.clone();
-            action.reverse_tap_side();
-            self.npb_actions.push(action)
-        })
+        self.merge_and_dedup_npb_actions(actions, acl_id, true);
     }
 
+    // When both ends are captured by the agent (e.g. traffic between local
+    // VMs), a single side action is only executed on the side it is
+    // configured for, so that the packet is not sent twice.
+    //
     // return true if policy have changes
-    pub fn dedup(&mut self, _: &dyn DedupOperator) -> bool {
-        false
+    pub fn dedup(&mut self, operator: &dyn DedupOperator) -> bool {
+        if !operator.is_tor() || self.npb_actions.is_empty() {
+            return false;
+        }
+        let (src_valid, dst_valid) = (
+            operator.is_valid(TapSide::SRC),
+            operator.is_valid(TapSide::DST),
+        );
+        let count = self.npb_actions.len();
+        self.npb_actions.retain(|a| match a.tap_side {
+            TapSide::SRC => src_valid || !dst_valid,
+            TapSide::DST => dst_valid || !src_valid,
+            _ => true,
+        });
+        if self.npb_actions.len() == count {
+            return false;
+        }
+        self.format_npb_action();
+        if self.npb_actions.is_empty() {
+            self.acl_id = 0;
+        }
+        true
     }
 
-    pub fn set_action_flags(&mut self, _actions: &NpbAction) {}
+    pub fn set_action_flags(&mut self, action: &NpbAction) {
+        self.action_flags |= action.action_flag();
+    }
 }
 
 pub trait DedupOperator: Send + Sync {
     fn is_tor(&self) -> bool;
     fn is_valid(&self, tap_side: TapSide) -> bool;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ends(bool, bool);
+
+    impl DedupOperator for Ends {
+        fn is_tor(&self) -> bool {
+            true
+        }
+
+        fn is_valid(&self, tap_side: TapSide) -> bool {
+            match tap_side {
+                TapSide::SRC => self.0,
+                TapSide::DST => self.1,
+                _ => false,
+            }
+        }
+    }
+
+    fn action(acl_gid: u32, tunnel_type: NpbTunnelType, tap_side: TapSide) -> NpbAction {
+        NpbAction::new(
+            acl_gid,
+            100,
+            "10.1.1.1".parse().unwrap(),
+            1,
+            tunnel_type,
+            tap_side,
+            DirectionType::ALL,
+            0,
+        )
+    }
+
+    #[test]
+    fn merge_actions() {
+        let mut policy = PolicyData::default();
+        policy.merge_npb_actions(
+            &vec![action(1, NpbTunnelType::Pcap, TapSide::SRC)],
+            10,
+            DirectionType::FORWARD,
+        );
+        policy.merge_npb_actions(
+            &vec![action(2, NpbTunnelType::Pcap, TapSide::SRC)],
+            11,
+            DirectionType::BACKWARD,
+        );
+        policy.merge_npb_actions(
+            &vec![action(3, NpbTunnelType::VxLan, TapSide::SRC)],
+            12,
+            DirectionType::FORWARD,
+        );
+        policy.format_npb_action();
+
+        assert_eq!(policy.acl_id, 10);
+        assert!(policy.contain_pcap());
+        assert!(policy.contain_npb());
+        assert_eq!(policy.npb_actions.len(), 2);
+        assert_eq!(policy.npb_actions[0].tunnel_type(), NpbTunnelType::VxLan);
+        let pcap = &policy.npb_actions[1];
+        assert_eq!(pcap.acl_gids(), &[1, 2]);
+        assert_eq!(pcap.tap_side(), TapSide::ALL);
+        assert_eq!(pcap.payload_slice(), PAYLOAD_SLICE_FULL);
+
+        let mut forward_only = action(4, NpbTunnelType::Pcap, TapSide::SRC);
+        forward_only.direction = DirectionType::FORWARD;
+        let mut policy = PolicyData::default();
+        policy.merge_npb_actions(&vec![forward_only], 13, DirectionType::BACKWARD);
+        assert_eq!(policy.acl_id, 0);
+        assert!(!policy.contain_pcap());
+    }
+
+    #[test]
+    fn dedup_actions() {
+        let mut policy = PolicyData::default();
+        policy.merge_and_dedup_npb_actions(
+            &vec![
+                action(1, NpbTunnelType::VxLan, TapSide::SRC),
+                action(2, NpbTunnelType::Pcap, TapSide::DST),
+            ],
+            10,
+            false,
+        );
+        policy.format_npb_action();
+
+        // neither end is local
+        assert!(!policy.clone().dedup(&Ends(false, false)));
+        assert!(!policy.clone().dedup(&Ends(true, true)));
+
+        let mut p = policy.clone();
+        assert!(p.dedup(&Ends(true, false)));
+        assert_eq!(p.npb_actions.len(), 1);
+        assert!(p.contain_npb() && !p.contain_pcap());
+
+        let mut p = policy.clone();
+        p.npb_actions.truncate(1);
+        assert!(p.dedup(&Ends(false, true)));
+        assert_eq!(p.acl_id, 0);
+        assert!(!p.contain_npb());
+    }
+}
@@ -14,23 +14,344 @@
  * limitations under the License.
  */
 
+mod zmtp;
+
+use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::io::{Error as IOError, ErrorKind, Result as IOResult};
-use std::net::IpAddr;
+use std::io::{Error as IOError, ErrorKind, Read, Result as IOResult, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
 
+// same as ZMQ_SNDHWM of libzmq
+pub const DEFAULT_SEND_HWM: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct ZmqSenderCounter {
+    pub hwm_dropped: AtomicUsize,
+    pub connect_failed: AtomicUsize,
+}
+
+/// A ZeroMQ PUSH socket connecting to a PULL socket at `dst_ip`, each packet is sent as a single
+/// frame message. Messages are queued while disconnected, new messages are dropped when the
+/// queue reaches the high-water mark. Connections and handshakes run in a separate thread so
+/// that the sender is never blocked.
 #[derive(Debug)]
 pub struct ZmqSender {
     pub dst_ip: IpAddr,
+    remote: SocketAddr,
+    stream: Option<TcpStream>,
+    connecting: Option<JoinHandle<IOResult<TcpStream>>>,
+
+    // encoded message frames not yet written
+    queue: VecDeque<Vec<u8>>,
+    // bytes of the first frame in queue already written
+    written: usize,
+    hwm: usize,
+
+    backoff: Duration,
+    next_connect: Instant,
+
+    counter: Arc<ZmqSenderCounter>,
 }
 
 impl ZmqSender {
-    pub fn new(dst_ip: &IpAddr, _: u16) -> Self {
+    const CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+    // starts from ZMQ_RECONNECT_IVL of libzmq, capped at the reconnect interval of the TCP sender
+    const MIN_BACKOFF: Duration = Duration::from_millis(100);
+    const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+    pub fn new(dst_ip: &IpAddr, port: u16, hwm: usize, counter: Arc<ZmqSenderCounter>) -> Self {
         Self {
-            dst_ip: dst_ip.clone(),
+            dst_ip: *dst_ip,
+            remote: SocketAddr::new(*dst_ip, port),
+            stream: None,
+            connecting: None,
+            queue: VecDeque::new(),
+            written: 0,
+            hwm: hwm.max(1),
+            backoff: Self::MIN_BACKOFF,
+            next_connect: Instant::now(),
+            counter,
+        }
+    }
+
+    fn connect(remote: SocketAddr) -> IOResult<TcpStream> {
+        let mut stream = TcpStream::connect_timeout(&remote, Self::CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(Self::HANDSHAKE_TIMEOUT))?;
+        stream.set_write_timeout(Some(Self::HANDSHAKE_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        zmtp::handshake(&mut stream, "PUSH", &["PULL"])?;
+        stream.set_nonblocking(true)?;
+        Ok(stream)
+    }
+
+    // Connects in a new thread, the result is returned by a later call once the thread finishes
+    fn poll_connect(&mut self) -> Option<IOResult<TcpStream>> {
+        match self.connecting.take() {
+            Some(thread) if thread.is_finished() => Some(
+                thread
+                    .join()
+                    .unwrap_or_else(|_| Err(IOError::other("connect panicked"))),
+            ),
+            Some(thread) => {
+                self.connecting = Some(thread);
+                None
+            }
+            None => {
+                if Instant::now() < self.next_connect {
+                    return None;
+                }
+                let remote = self.remote;
+                match thread::Builder::new()
+                    .name("npb-zmq-connect".to_owned())
+                    .spawn(move || Self::connect(remote))
+                {
+                    Ok(thread) => {
+                        self.connecting = Some(thread);
+                        None
+                    }
+                    Err(e) => Some(Err(e)),
+                }
+            }
+        }
+    }
+
+    fn reconnect(&mut self) -> bool {
+        match self.poll_connect() {
+            None => false,
+            Some(Ok(stream)) => {
+                info!("Npb ZmqSender connected to {}.", self.remote);
+                self.stream = Some(stream);
+                self.written = 0;
+                self.backoff = Self::MIN_BACKOFF;
+                true
+            }
+            Some(Err(e)) => {
+                debug!(
+                    "Npb ZmqSender connect to {} failed: {}, retry in {:?}.",
+                    self.remote, e, self.backoff
+                );
+                self.counter.connect_failed.fetch_add(1, Ordering::Relaxed);
+                self.next_connect = Instant::now() + self.backoff;
+                self.backoff = (self.backoff * 2).min(Self::MAX_BACKOFF);
+                false
+            }
+        }
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        if self.stream.take().is_some() {
+            warn!(
+                "Npb ZmqSender disconnected from {}: {}.",
+                self.remote, reason
+            );
+        }
+        // a partially written frame is sent again from the beginning on the next connection
+        self.written = 0;
+        self.next_connect = Instant::now() + self.backoff;
+    }
+
+    // PULL peers send nothing after the handshake, reading only notices a closed connection
+    fn peer_closed(stream: &mut TcpStream) -> Option<String> {
+        let mut buf = [0; 256];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) => return Some("closed by peer".to_string()),
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return None,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Some(e.to_string()),
+            }
+        }
+    }
+
+    /// Writes queued messages without blocking, connecting first if necessary
+    pub fn flush(&mut self) {
+        if self.stream.is_none() && !self.reconnect() {
+            return;
+        }
+        let stream = self.stream.as_mut().unwrap();
+        if let Some(reason) = Self::peer_closed(stream) {
+            self.disconnect(&reason);
+            return;
         }
+        while let Some(frame) = self.queue.front() {
+            match stream.write(&frame[self.written..]) {
+                Ok(0) => {
+                    self.disconnect("write zero");
+                    return;
+                }
+                Ok(n) => {
+                    self.written += n;
+                    if self.written == frame.len() {
+                        self.queue.pop_front();
+                        self.written = 0;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.disconnect(&e.to_string());
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn send(&mut self, payload: &[u8]) -> IOResult<usize> {
+        if self.queue.len() >= self.hwm {
+            self.flush();
+            if self.queue.len() >= self.hwm {
+                self.counter.hwm_dropped.fetch_add(1, Ordering::Relaxed);
+                return Err(IOError::new(
+                    ErrorKind::WouldBlock,
+                    format!("ZeroMQ queue to {} reached high-water mark", self.remote),
+                ));
+            }
+        }
+        self.queue.push_back(zmtp::message_frame(payload));
+        self.flush();
+        Ok(payload.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    // A PULL socket accepting `connections` connections, forwarding the messages received
+    fn pull_socket(connections: usize, close_after: usize) -> (u16, mpsc::Receiver<Vec<u8>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let mut stream = stream.unwrap();
+                zmtp::handshake(&mut stream, "PULL", &["PUSH"]).unwrap();
+                for _ in 0..close_after {
+                    match zmtp::read_frame(&mut stream, u64::MAX) {
+                        Ok(frame) => sender.send(frame.body).unwrap(),
+                        Err(_) => break,
+                    }
+                }
+            }
+        });
+        (port, receiver)
+    }
+
+    fn recv(receiver: &mpsc::Receiver<Vec<u8>>) -> Vec<u8> {
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    // connections complete in the background, flush until `done`
+    fn flush_until(sender: &mut ZmqSender, done: impl Fn(&ZmqSender) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(sender) {
+            assert!(Instant::now() < deadline, "timed out flushing");
+            sender.flush();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn connect_failed(sender: &ZmqSender) -> usize {
+        sender.counter.connect_failed.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn push() {
+        let (port, receiver) = pull_socket(1, usize::MAX);
+        let counter = Arc::new(ZmqSenderCounter::default());
+        let mut sender = ZmqSender::new(&Ipv4Addr::LOCALHOST.into(), port, 10, counter.clone());
+        let large = vec![0xab; 2000];
+        assert_eq!(sender.send(b"first").unwrap(), 5);
+        assert_eq!(sender.send(&large).unwrap(), 2000);
+        flush_until(&mut sender, |s| s.queue.is_empty());
+        assert_eq!(recv(&receiver), b"first");
+        assert_eq!(recv(&receiver), large);
+        assert_eq!(counter.connect_failed.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn high_water_mark() {
+        // nothing listens on the port after the listener is dropped
+        let port = {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let counter = Arc::new(ZmqSenderCounter::default());
+        let mut sender = ZmqSender::new(&Ipv4Addr::LOCALHOST.into(), port, 2, counter.clone());
+        assert!(sender.send(b"1").is_ok());
+        assert!(sender.send(b"2").is_ok());
+        let e = sender.send(b"3").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::WouldBlock);
+        assert_eq!(counter.hwm_dropped.load(Ordering::Relaxed), 1);
+        // the first attempt failed, the others wait for the backoff
+        flush_until(&mut sender, |s| connect_failed(s) == 1);
+        sender.flush();
+        assert_eq!(connect_failed(&sender), 1);
+        assert_eq!(sender.backoff, ZmqSender::MIN_BACKOFF * 2);
+    }
+
+    #[test]
+    fn reconnect() {
+        // the first connection is closed after one message
+        let (port, receiver) = pull_socket(2, 1);
+        let counter = Arc::new(ZmqSenderCounter::default());
+        let mut sender = ZmqSender::new(&Ipv4Addr::LOCALHOST.into(), port, 100, counter);
+        sender.send(b"before").unwrap();
+        flush_until(&mut sender, |s| s.queue.is_empty());
+        assert_eq!(recv(&receiver), b"before");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            assert!(Instant::now() < deadline, "no message after reconnection");
+            let _ = sender.send(b"after");
+            if let Ok(m) = receiver.recv_timeout(Duration::from_millis(50)) {
+                assert_eq!(m, b"after");
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn incompatible_peer() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = zmtp::handshake(&mut stream, "PUB", &["SUB"]);
+        });
+        let counter = Arc::new(ZmqSenderCounter::default());
+        let mut sender = ZmqSender::new(&Ipv4Addr::LOCALHOST.into(), port, 10, counter);
+        assert!(sender.send(b"x").is_ok());
+        flush_until(&mut sender, |s| connect_failed(s) == 1);
+        assert!(sender.stream.is_none());
     }
 
-    pub fn send(&mut self, _: usize, _: Vec<u8>) -> IOResult<usize> {
-        Err(IOError::new(ErrorKind::Other, "ZeroMQ not support."))
+    #[test]
+    fn connect_without_blocking() {
+        // connections are accepted by the kernel, but the handshake is never answered
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let counter = Arc::new(ZmqSenderCounter::default());
+        let mut sender = ZmqSender::new(&Ipv4Addr::LOCALHOST.into(), port, 10, counter);
+        let start = Instant::now();
+        assert!(sender.send(b"x").is_ok());
+        sender.flush();
+        assert!(start.elapsed() < ZmqSender::HANDSHAKE_TIMEOUT);
+        flush_until(&mut sender, |s| connect_failed(s) == 1);
+        assert!(sender.stream.is_none() && sender.queue.len() == 1);
     }
 }
@@ -0,0 +1,244 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// ZMTP 3.x framing with the NULL security mechanism, https://rfc.zeromq.org/spec/23/
+
+use std::io::{Error as IOError, ErrorKind, Read, Result as IOResult, Write};
+
+use public::zmtp::{
+    FLAG_COMMAND, FLAG_LONG, FLAG_MORE, GREETING_SIZE, MAJOR_VERSION, MECHANISM_NULL,
+    MECHANISM_OFFSET, MECHANISM_SIZE, MINOR_VERSION, PROPERTY_SOCKET_TYPE, SIGNATURE_END,
+    SIGNATURE_SIZE, SIGNATURE_START, VERSION_OFFSET,
+};
+
+// commands during the handshake are small, larger ones are rejected before allocating
+pub const MAX_COMMAND_SIZE: u64 = 64 << 10;
+
+fn invalid_data(message: String) -> IOError {
+    IOError::new(ErrorKind::InvalidData, message)
+}
+
+pub fn greeting() -> [u8; GREETING_SIZE] {
+    let mut greeting = [0; GREETING_SIZE];
+    greeting[0] = SIGNATURE_START;
+    greeting[SIGNATURE_SIZE - 1] = SIGNATURE_END;
+    greeting[VERSION_OFFSET] = MAJOR_VERSION;
+    greeting[VERSION_OFFSET + 1] = MINOR_VERSION;
+    greeting[MECHANISM_OFFSET..MECHANISM_OFFSET + MECHANISM_NULL.len()]
+        .copy_from_slice(MECHANISM_NULL);
+    // as-server and filler are left zero
+    greeting
+}
+
+pub fn check_greeting(greeting: &[u8; GREETING_SIZE]) -> IOResult<()> {
+    if greeting[0] != SIGNATURE_START || greeting[SIGNATURE_SIZE - 1] != SIGNATURE_END {
+        return Err(invalid_data("invalid ZMTP signature".to_string()));
+    }
+    if greeting[VERSION_OFFSET] < MAJOR_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported ZMTP version {}.{}",
+            greeting[VERSION_OFFSET],
+            greeting[VERSION_OFFSET + 1]
+        )));
+    }
+    let mechanism = &greeting[MECHANISM_OFFSET..MECHANISM_OFFSET + MECHANISM_SIZE];
+    let end = mechanism
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(MECHANISM_SIZE);
+    if &mechanism[..end] != MECHANISM_NULL {
+        return Err(invalid_data(format!(
+            "unsupported ZMTP mechanism {}",
+            String::from_utf8_lossy(&mechanism[..end])
+        )));
+    }
+    Ok(())
+}
+
+fn encode_frame(flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 9);
+    if body.len() > u8::MAX as usize {
+        frame.push(flags | FLAG_LONG);
+        frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        frame.push(flags);
+        frame.push(body.len() as u8);
+    }
+    frame.extend_from_slice(body);
+    frame
+}
+
+/// Encodes a single frame message
+pub fn message_frame(body: &[u8]) -> Vec<u8> {
+    encode_frame(0, body)
+}
+
+pub fn ready_command(socket_type: &str) -> Vec<u8> {
+    let mut body = vec![b"READY".len() as u8];
+    body.extend_from_slice(b"READY");
+    body.push(PROPERTY_SOCKET_TYPE.len() as u8);
+    body.extend_from_slice(PROPERTY_SOCKET_TYPE.as_bytes());
+    body.extend_from_slice(&(socket_type.len() as u32).to_be_bytes());
+    body.extend_from_slice(socket_type.as_bytes());
+    encode_frame(FLAG_COMMAND, &body)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub command: bool,
+    pub more: bool,
+    pub body: Vec<u8>,
+}
+
+pub fn read_frame<R: Read>(reader: &mut R, max_size: u64) -> IOResult<Frame> {
+    let mut flags = [0; 1];
+    reader.read_exact(&mut flags)?;
+    let flags = flags[0];
+    let size = if flags & FLAG_LONG != 0 {
+        let mut size = [0; 8];
+        reader.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    } else {
+        let mut size = [0; 1];
+        reader.read_exact(&mut size)?;
+        size[0] as u64
+    };
+    if size > max_size {
+        return Err(invalid_data(format!(
+            "ZMTP frame size {} exceeds {}",
+            size, max_size
+        )));
+    }
+    let mut body = vec![0; size as usize];
+    reader.read_exact(&mut body)?;
+    Ok(Frame {
+        command: flags & FLAG_COMMAND != 0,
+        more: flags & FLAG_MORE != 0,
+        body,
+    })
+}
+
+// Returns the command name and data
+fn parse_command(body: &[u8]) -> Option<(&[u8], &[u8])> {
+    let size = *body.first()? as usize;
+    if body.len() < size + 1 {
+        return None;
+    }
+    Some((&body[1..size + 1], &body[size + 1..]))
+}
+
+// Looks up a property in the metadata of a READY command, names are case insensitive
+fn property<'a>(mut data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    while !data.is_empty() {
+        let name_size = data[0] as usize;
+        let key = data.get(1..1 + name_size)?;
+        let value_size =
+            u32::from_be_bytes(data.get(1 + name_size..5 + name_size)?.try_into().unwrap())
+                as usize;
+        let value = data.get(5 + name_size..5 + name_size + value_size)?;
+        if key.eq_ignore_ascii_case(name.as_bytes()) {
+            return Some(value);
+        }
+        data = &data[5 + name_size + value_size..];
+    }
+    None
+}
+
+/// Exchanges greetings and READY commands, the peer socket type must be one of `peer_types`
+pub fn handshake<S: Read + Write>(
+    stream: &mut S,
+    socket_type: &str,
+    peer_types: &[&str],
+) -> IOResult<()> {
+    let mut out = greeting().to_vec();
+    out.extend(ready_command(socket_type));
+    stream.write_all(&out)?;
+
+    let mut peer_greeting = [0; GREETING_SIZE];
+    stream.read_exact(&mut peer_greeting)?;
+    check_greeting(&peer_greeting)?;
+
+    let frame = read_frame(stream, MAX_COMMAND_SIZE)?;
+    if !frame.command {
+        return Err(invalid_data("expected ZMTP READY command".to_string()));
+    }
+    match parse_command(&frame.body) {
+        Some((b"READY", data)) => match property(data, PROPERTY_SOCKET_TYPE) {
+            Some(t) if peer_types.iter().any(|p| p.as_bytes() == t) => Ok(()),
+            t => Err(invalid_data(format!(
+                "ZMTP peer socket type {} is not compatible with {}",
+                String::from_utf8_lossy(t.unwrap_or_default()),
+                socket_type
+            ))),
+        },
+        Some((b"ERROR", data)) => {
+            let reason = data.get(1..).unwrap_or_default();
+            Err(invalid_data(format!(
+                "ZMTP handshake rejected: {}",
+                String::from_utf8_lossy(reason)
+            )))
+        }
+        Some((name, _)) => Err(invalid_data(format!(
+            "unexpected ZMTP command {}",
+            String::from_utf8_lossy(name)
+        ))),
+        None => Err(invalid_data("invalid ZMTP command".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn frames() {
+        let short = message_frame(b"hello");
+        assert_eq!(short[..2], [0, 5]);
+        let long = message_frame(&[7; 300]);
+        assert_eq!(long[..9], [FLAG_LONG, 0, 0, 0, 0, 0, 0, 1, 44]);
+
+        let mut data = Cursor::new([short, long, ready_command("PULL")].concat());
+        let frame = read_frame(&mut data, u64::MAX).unwrap();
+        assert_eq!(frame.body, b"hello");
+        assert!(!frame.command && !frame.more);
+        assert_eq!(read_frame(&mut data, u64::MAX).unwrap().body, vec![7; 300]);
+        let frame = read_frame(&mut data, u64::MAX).unwrap();
+        assert!(frame.command);
+        let (name, data) = parse_command(&frame.body).unwrap();
+        assert_eq!(name, b"READY");
+        assert_eq!(property(data, "socket-type"), Some(&b"PULL"[..]));
+        assert_eq!(property(data, "Identity"), None);
+
+        let mut data = Cursor::new(message_frame(&[0; 300]));
+        assert!(read_frame(&mut data, 255).is_err());
+    }
+
+    #[test]
+    fn greetings() {
+        let mut g = greeting();
+        assert!(check_greeting(&g).is_ok());
+        // ZMTP 3.1 peers are compatible
+        g[VERSION_OFFSET + 1] = 1;
+        assert!(check_greeting(&g).is_ok());
+        g[VERSION_OFFSET] = 2;
+        assert!(check_greeting(&g).is_err());
+        let mut g = greeting();
+        g[MECHANISM_OFFSET..MECHANISM_OFFSET + 5].copy_from_slice(b"CURVE");
+        assert!(check_greeting(&g).is_err());
+    }
+}
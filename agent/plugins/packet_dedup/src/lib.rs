@@ -14,17 +14,317 @@
  * limitations under the License.
  */
 
-//! Enterprise Edition Feature: analyzer_mode
-use std::time::Duration;
+//! Packet de-duplication for analyzer and mirror mode
+//!
+//! The same packet may be mirrored more than once, e.g. from both uplinks of a ToR switch.
+//! Each packet is hashed on the invariant parts of its IP/L4 headers and a prefix of
+//! its payload, ignoring TTL/hop limit, TOS and checksums, which may be modified by
+//! routers between mirror points. A packet is duplicate if the same hash is seen
+//! within the time window.
 
-pub struct PacketDedupMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use public::{
+    bytes::read_u16_be,
+    consts::*,
+    counter::{Counter, CounterType, CounterValue, RefCountable},
+    enums::{EthernetType, IpProtocol},
+};
+
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_millis(10);
+pub const DEFAULT_DEDUP_CAPACITY: usize = 1 << 16;
+
+// L4 bytes including L4 header used for hashing
+const L4_HASH_PREFIX_LEN: usize = 64;
+
+const IPV4_TOTAL_LENGTH_OFFSET: usize = 2;
+const IPV4_PROTOCOL_OFFSET: usize = 9;
+const IPV4_SRC_OFFSET: usize = 12;
+const IPV4_FRAG_OFFSET_MASK: u16 = 0x1fff;
+const IPV6_FLOW_LABEL_OFFSET: usize = 1;
+const IPV6_NEXT_HEADER_OFFSET: usize = 6;
+const IPV6_SRC_OFFSET: usize = 8;
+
+const TCP_CHECKSUM_OFFSET: usize = 16;
+const UDP_CHECKSUM_OFFSET: usize = 6;
+const ICMP_CHECKSUM_OFFSET: usize = 2;
+const CHECKSUM_LEN: usize = 2;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(FNV_OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Default)]
+pub struct DedupCounter {
+    pub hit: AtomicU64,
+    pub miss: AtomicU64,
+    // entries removed before window expired because the map is full
+    pub evicted: AtomicU64,
+}
+
+impl RefCountable for DedupCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "hit",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.hit.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "miss",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.miss.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "evicted",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.evicted.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+pub struct PacketDedupMap {
+    window: Duration,
+    capacity: usize,
+
+    // packets in arrival order, used for expiring
+    queue: VecDeque<(Duration, u64)>,
+    // hash -> timestamp of the first packet
+    hashes: HashMap<u64, Duration>,
+
+    counter: Arc<DedupCounter>,
+}
+
+impl Default for PacketDedupMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl PacketDedupMap {
     pub fn new() -> Self {
-        PacketDedupMap
+        Self::with_window(DEFAULT_DEDUP_WINDOW, DEFAULT_DEDUP_CAPACITY)
+    }
+
+    pub fn with_window(window: Duration, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        PacketDedupMap {
+            window,
+            capacity,
+            queue: VecDeque::with_capacity(capacity),
+            hashes: HashMap::with_capacity(capacity),
+            counter: Arc::new(DedupCounter::default()),
+        }
     }
 
-    pub fn duplicate(&mut self, _: &mut [u8], _: Duration) -> bool {
+    pub fn counter(&self) -> &Arc<DedupCounter> {
+        &self.counter
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    // `packet` starts from L2 header, non-IP packets are never duplicate
+    pub fn duplicate(&mut self, packet: &mut [u8], timestamp: Duration) -> bool {
+        let Some(hash) = Self::hash(packet) else {
+            return false;
+        };
+
+        self.expire(timestamp);
+
+        if let Some(&last) = self.hashes.get(&hash) {
+            if timestamp.abs_diff(last) <= self.window {
+                self.counter.hit.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+        }
+        self.counter.miss.fetch_add(1, Ordering::Relaxed);
+
+        if self.queue.len() >= self.capacity {
+            if let Some((ts, h)) = self.queue.pop_front() {
+                self.remove(ts, h);
+                self.counter.evicted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.hashes.insert(hash, timestamp);
+        self.queue.push_back((timestamp, hash));
         false
     }
+
+    fn expire(&mut self, now: Duration) {
+        while let Some(&(ts, h)) = self.queue.front() {
+            if ts + self.window >= now {
+                break;
+            }
+            self.queue.pop_front();
+            self.remove(ts, h);
+        }
+    }
+
+    fn remove(&mut self, timestamp: Duration, hash: u64) {
+        // the entry may have been replaced by a later packet with the same hash
+        if self.hashes.get(&hash) == Some(&timestamp) {
+            self.hashes.remove(&hash);
+        }
+    }
+
+    fn hash(packet: &[u8]) -> Option<u64> {
+        let mut offset = FIELD_OFFSET_ETH_TYPE;
+        let mut eth_type = EthernetType::from(read_u16_be(packet.get(offset..offset + 2)?));
+        while eth_type == EthernetType::DOT1Q || eth_type == EthernetType::QINQ {
+            offset += VLAN_HEADER_SIZE;
+            eth_type = EthernetType::from(read_u16_be(packet.get(offset..offset + 2)?));
+        }
+        let l3 = packet.get(offset + 2..)?;
+
+        let mut hasher = Fnv1a::new();
+        let (protocol, l4) = match eth_type {
+            EthernetType::IPV4 => {
+                if l3.len() < IPV4_HEADER_SIZE {
+                    return None;
+                }
+                let ihl = ((l3[IP_IHL_OFFSET] & 0xf) as usize) << 2;
+                if ihl < IPV4_HEADER_SIZE || l3.len() < ihl {
+                    return None;
+                }
+                // total length, identification, flags and fragment offset
+                hasher.write(&l3[IPV4_TOTAL_LENGTH_OFFSET..IPV4_TOTAL_LENGTH_OFFSET + 6]);
+                hasher.write(&l3[IPV4_PROTOCOL_OFFSET..IPV4_PROTOCOL_OFFSET + 1]);
+                hasher.write(&l3[IPV4_SRC_OFFSET..IPV4_HEADER_SIZE]);
+                hasher.write(&l3[IPV4_HEADER_SIZE..ihl]);
+                let frag_offset =
+                    read_u16_be(&l3[IPV4_TOTAL_LENGTH_OFFSET + 4..]) & IPV4_FRAG_OFFSET_MASK;
+                let protocol = if frag_offset == 0 {
+                    IpProtocol::from(l3[IPV4_PROTOCOL_OFFSET])
+                } else {
+                    // no L4 header in non-first fragments
+                    IpProtocol::default()
+                };
+                (protocol, &l3[ihl..])
+            }
+            EthernetType::IPV6 => {
+                if l3.len() < IPV6_HEADER_SIZE {
+                    return None;
+                }
+                // flow label without traffic class bits, payload length and next header
+                hasher.write(&[l3[IPV6_FLOW_LABEL_OFFSET] & 0xf]);
+                hasher.write(&l3[IPV6_FLOW_LABEL_OFFSET + 1..IPV6_NEXT_HEADER_OFFSET + 1]);
+                hasher.write(&l3[IPV6_SRC_OFFSET..IPV6_HEADER_SIZE]);
+                (
+                    IpProtocol::from(l3[IPV6_NEXT_HEADER_OFFSET]),
+                    &l3[IPV6_HEADER_SIZE..],
+                )
+            }
+            _ => return None,
+        };
+
+        let l4 = &l4[..l4.len().min(L4_HASH_PREFIX_LEN)];
+        let checksum_offset = match protocol {
+            IpProtocol::TCP => Some(TCP_CHECKSUM_OFFSET),
+            IpProtocol::UDP => Some(UDP_CHECKSUM_OFFSET),
+            IpProtocol::ICMPV4 | IpProtocol::ICMPV6 => Some(ICMP_CHECKSUM_OFFSET),
+            _ => None,
+        };
+        match checksum_offset {
+            Some(o) if l4.len() >= o + CHECKSUM_LEN => {
+                hasher.write(&l4[..o]);
+                hasher.write(&l4[o + CHECKSUM_LEN..]);
+            }
+            _ => hasher.write(l4),
+        }
+        Some(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp_packet(ttl: u8, ip_checksum: u16, tcp_checksum: u16, seq: u32) -> Vec<u8> {
+        let mut packet = vec![0x02, 0, 0, 0, 0, 0x01, 0x02, 0, 0, 0, 0, 0x02, 0x08, 0x00];
+        packet.extend_from_slice(&[0x45, 0, 0, 44, 0x12, 0x34, 0x40, 0, ttl, 6]);
+        packet.extend_from_slice(&ip_checksum.to_be_bytes());
+        packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend_from_slice(&[0x9c, 0x40, 0, 80]);
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff]);
+        packet.extend_from_slice(&tcp_checksum.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, b'p', b'i', b'n', b'g']);
+        packet
+    }
+
+    #[test]
+    fn duplicate_in_window() {
+        let mut dedup = PacketDedupMap::new();
+        let mut a = tcp_packet(64, 0x1111, 0x2222, 1);
+        let mut b = tcp_packet(63, 0x1112, 0x2223, 1);
+        let mut c = tcp_packet(64, 0x1111, 0x2222, 2);
+
+        assert!(!dedup.duplicate(&mut a, Duration::from_micros(100)));
+        assert!(dedup.duplicate(&mut b, Duration::from_micros(150)));
+        assert!(!dedup.duplicate(&mut c, Duration::from_micros(200)));
+        // retransmission after window
+        assert!(!dedup.duplicate(&mut a, Duration::from_millis(300)));
+        assert_eq!(dedup.counter.hit.load(Ordering::Relaxed), 1);
+        assert_eq!(dedup.counter.miss.load(Ordering::Relaxed), 3);
+        // expired entries are removed
+        assert_eq!(dedup.len(), 1);
+    }
+
+    #[test]
+    fn non_ip_packet() {
+        let mut dedup = PacketDedupMap::new();
+        let mut arp = vec![0xff; 12];
+        arp.extend_from_slice(&[0x08, 0x06]);
+        arp.extend_from_slice(&[0; 28]);
+        assert!(!dedup.duplicate(&mut arp, Duration::ZERO));
+        assert!(!dedup.duplicate(&mut arp, Duration::ZERO));
+        assert!(dedup.is_empty());
+    }
+
+    #[test]
+    fn bounded_capacity() {
+        let mut dedup = PacketDedupMap::with_window(Duration::from_secs(1), 4);
+        for seq in 0..8 {
+            let mut p = tcp_packet(64, 0, 0, seq);
+            assert!(!dedup.duplicate(&mut p, Duration::from_micros(seq as u64)));
+        }
+        assert_eq!(dedup.len(), 4);
+        assert_eq!(dedup.counter.evicted.load(Ordering::Relaxed), 4);
+        let mut p = tcp_packet(64, 0, 0, 7);
+        assert!(dedup.duplicate(&mut p, Duration::from_micros(10)));
+        let mut p = tcp_packet(64, 0, 0, 0);
+        assert!(!dedup.duplicate(&mut p, Duration::from_micros(10)));
+    }
 }
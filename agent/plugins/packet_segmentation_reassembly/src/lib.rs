@@ -16,6 +16,16 @@
 
 use public::packet::Downcast;
 
+// Segments after a hole are buffered until the hole is filled, the hole is
+// skipped when the buffer is full
+pub const MAX_OUT_OF_ORDER_SEGMENTS: usize = 16;
+// Bytes buffered in one direction, including the pending message, bounded by
+// the u16 payload length of a merged segment
+pub const MAX_BUFFERED_BYTES: usize = u16::MAX as usize;
+// Segments without payload held behind buffered data, the pending messages are
+// output when more are held
+pub const MAX_HELD_SEGMENTS: usize = 64;
+
 pub trait Segment: Downcast {
     fn is_c2s(&self) -> bool;
     fn get_tcp_seq(&self) -> u32;
@@ -23,23 +33,450 @@ pub trait Segment: Downcast {
     fn merge_segments(&mut self, other: &[u8]);
     fn get_payload(&self) -> &[u8];
     fn get_payload_length(&self) -> u16;
+    // called when the direction of flow is reversed
+    fn reverse(&mut self);
+}
+
+fn seq_diff(a: u32, b: u32) -> i32 {
+    a.wrapping_sub(b) as i32
+}
+
+// Reassembly of one direction of a TCP connection
+//
+// Contiguous payload is accumulated into the first segment of a message, which
+// is output when data from the peer arrives, the flow is flushed or idle, or the
+// buffer is full. Every byte is output at most once.
+#[derive(Default)]
+struct Stream {
+    // sequence number of the next in order segment
+    next_seq: Option<u32>,
+    // the message being accumulated
+    pending: Option<Box<dyn Segment>>,
+    // segments after a hole, sorted by sequence number
+    out_of_order: Vec<Box<dyn Segment>>,
+    out_of_order_bytes: usize,
+    // bytes given up, either skipped holes or new data overlapping output data
+    dropped_bytes: u64,
+}
+
+impl Stream {
+    fn inject(&mut self, segment: Box<dyn Segment>, output: &mut Vec<Box<dyn Segment>>) {
+        self.process(segment, output);
+        self.drain_out_of_order(output);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.is_none() && self.out_of_order.is_empty()
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.pending
+            .as_ref()
+            .map(|p| p.get_payload_length() as usize)
+            .unwrap_or_default()
+    }
+
+    fn process(&mut self, segment: Box<dyn Segment>, output: &mut Vec<Box<dyn Segment>>) {
+        let Some(next_seq) = self.next_seq else {
+            self.push_in_order(segment, output);
+            return;
+        };
+
+        let diff = seq_diff(segment.get_tcp_seq(), next_seq);
+        if diff == 0 {
+            self.push_in_order(segment, output);
+        } else if diff > 0 {
+            self.push_out_of_order(segment, output);
+        } else if seq_diff(segment.next_tcp_seq(), next_seq) > 0 {
+            // partially overlapped, only the new data is appended
+            let payload = segment.get_payload();
+            let overlapped = (diff.unsigned_abs() as usize).min(payload.len());
+            let new_data = &payload[overlapped..];
+            match self.pending.as_mut() {
+                Some(pending)
+                    if pending.get_payload_length() as usize + new_data.len()
+                        <= MAX_BUFFERED_BYTES =>
+                {
+                    pending.merge_segments(new_data);
+                }
+                // the overlapped data has been output, the segment can not be
+                // output without repeating it
+                _ => self.dropped_bytes += new_data.len() as u64,
+            }
+            self.next_seq = Some(segment.next_tcp_seq());
+        }
+        // else retransmission of data already buffered or output, drop it
+    }
+
+    fn push_in_order(&mut self, segment: Box<dyn Segment>, output: &mut Vec<Box<dyn Segment>>) {
+        self.next_seq = Some(segment.next_tcp_seq());
+        match self.pending.as_mut() {
+            Some(pending)
+                if pending.get_payload_length() as usize
+                    + segment.get_payload_length() as usize
+                    <= MAX_BUFFERED_BYTES =>
+            {
+                pending.merge_segments(segment.get_payload());
+            }
+            _ => {
+                if let Some(pending) = self.pending.replace(segment) {
+                    output.push(pending);
+                }
+            }
+        }
+    }
+
+    fn push_out_of_order(&mut self, segment: Box<dyn Segment>, output: &mut Vec<Box<dyn Segment>>) {
+        let seq = segment.get_tcp_seq();
+        let index = match self
+            .out_of_order
+            .binary_search_by(|s| seq_diff(s.get_tcp_seq(), seq).cmp(&0))
+        {
+            // retransmission of buffered segment
+            Ok(_) => return,
+            Err(index) => index,
+        };
+        self.out_of_order_bytes += segment.get_payload_length() as usize;
+        self.out_of_order.insert(index, segment);
+
+        if self.out_of_order.len() > MAX_OUT_OF_ORDER_SEGMENTS
+            || self.pending_bytes() + self.out_of_order_bytes > MAX_BUFFERED_BYTES
+        {
+            self.skip_hole(output);
+        }
+    }
+
+    // outputs the pending message and gives up the hole before buffered segments
+    fn skip_hole(&mut self, output: &mut Vec<Box<dyn Segment>>) {
+        if let Some(pending) = self.pending.take() {
+            output.push(pending);
+        }
+        if let Some(first) = self.out_of_order.first() {
+            if let Some(next_seq) = self.next_seq {
+                self.dropped_bytes += seq_diff(first.get_tcp_seq(), next_seq).max(0) as u64;
+            }
+            self.next_seq = None;
+        }
+    }
+
+    fn drain_out_of_order(&mut self, output: &mut Vec<Box<dyn Segment>>) {
+        while let Some(first) = self.out_of_order.first() {
+            if let Some(next_seq) = self.next_seq {
+                if seq_diff(first.get_tcp_seq(), next_seq) > 0 {
+                    break;
+                }
+            }
+            let segment = self.out_of_order.remove(0);
+            self.out_of_order_bytes -= segment.get_payload_length() as usize;
+            self.process(segment, output);
+        }
+    }
+
+    fn take_pending(&mut self, output: &mut Vec<Box<dyn Segment>>) {
+        if let Some(pending) = self.pending.take() {
+            output.push(pending);
+        }
+    }
+
+    fn flush(&mut self, output: &mut Vec<Box<dyn Segment>>) {
+        loop {
+            self.skip_hole(output);
+            if self.out_of_order.is_empty() {
+                break;
+            }
+            self.drain_out_of_order(output);
+        }
+    }
+
+    fn reverse(&mut self) {
+        if let Some(pending) = self.pending.as_mut() {
+            pending.reverse();
+        }
+        for segment in self.out_of_order.iter_mut() {
+            segment.reverse();
+        }
+    }
 }
 
 #[derive(Default)]
-pub struct PacketSegmentationReassembly {}
+pub struct PacketSegmentationReassembly {
+    // indexed by is_c2s
+    streams: [Stream; 2],
+    // segments without payload arrived after buffered data, output behind it
+    held: Vec<Box<dyn Segment>>,
+}
 
 impl PacketSegmentationReassembly {
     pub fn does_support() -> bool {
-        false
+        true
+    }
+
+    pub fn reverse(&mut self) {
+        self.streams.swap(0, 1);
+        self.streams.iter_mut().for_each(|s| s.reverse());
+        self.held.iter_mut().for_each(|s| s.reverse());
+    }
+
+    // payload or segments held, to be output by flush if the flow stays idle
+    pub fn has_pending(&self) -> bool {
+        !self.held.is_empty() || self.streams.iter().any(|s| !s.is_empty())
     }
 
-    pub fn reverse(&mut self) {}
+    // bytes dropped since last call
+    pub fn take_dropped_bytes(&mut self) -> u64 {
+        self.streams
+            .iter_mut()
+            .map(|s| std::mem::take(&mut s.dropped_bytes))
+            .sum()
+    }
 
     pub fn flush(&mut self) -> Option<Vec<Box<dyn Segment>>> {
-        None
+        let mut output = vec![];
+        for stream in self.streams.iter_mut() {
+            stream.flush(&mut output);
+        }
+        output.append(&mut self.held);
+        if output.is_empty() {
+            None
+        } else {
+            Some(output)
+        }
+    }
+
+    pub fn inject(&mut self, segment: Box<dyn Segment>) -> Option<Vec<Box<dyn Segment>>> {
+        // segments without payload such as SYN or ACK need no reassembly, but
+        // are kept in order behind buffered data
+        if segment.get_payload_length() == 0 {
+            if !self.has_pending() {
+                return Some(vec![segment]);
+            }
+            self.held.push(segment);
+            if self.held.len() <= MAX_HELD_SEGMENTS {
+                return None;
+            }
+            let mut output = vec![];
+            for stream in self.streams.iter_mut() {
+                stream.take_pending(&mut output);
+            }
+            output.append(&mut self.held);
+            return Some(output);
+        }
+
+        let mut output = vec![];
+        let index = segment.is_c2s() as usize;
+        // data from the peer means the message in the other direction is complete
+        self.streams[1 - index].flush(&mut output);
+        self.streams[index].inject(segment, &mut output);
+        // held segments arrived after the output data
+        if !output.is_empty() {
+            output.append(&mut self.held);
+        }
+        if output.is_empty() {
+            None
+        } else {
+            Some(output)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+
+    use super::*;
+
+    struct TestSegment {
+        c2s: bool,
+        seq: u32,
+        payload: Vec<u8>,
+    }
+
+    impl TestSegment {
+        fn boxed(c2s: bool, seq: u32, payload: &[u8]) -> Box<dyn Segment> {
+            Box::new(Self {
+                c2s,
+                seq,
+                payload: payload.to_vec(),
+            })
+        }
+    }
+
+    impl Downcast for TestSegment {
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+
+        fn into_any(self: Box<Self>) -> Box<dyn Any> {
+            self
+        }
+    }
+
+    impl Segment for TestSegment {
+        fn is_c2s(&self) -> bool {
+            self.c2s
+        }
+
+        fn get_tcp_seq(&self) -> u32 {
+            self.seq
+        }
+
+        fn next_tcp_seq(&self) -> u32 {
+            self.seq.wrapping_add(self.payload.len() as u32)
+        }
+
+        fn merge_segments(&mut self, other: &[u8]) {
+            self.payload.extend_from_slice(other);
+        }
+
+        fn get_payload(&self) -> &[u8] {
+            &self.payload
+        }
+
+        fn get_payload_length(&self) -> u16 {
+            self.payload.len() as u16
+        }
+
+        fn reverse(&mut self) {
+            self.c2s = !self.c2s;
+        }
+    }
+
+    fn payloads(segments: Option<Vec<Box<dyn Segment>>>) -> Vec<Vec<u8>> {
+        segments
+            .unwrap_or_default()
+            .iter()
+            .map(|s| s.get_payload().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn out_of_order_and_retransmission() {
+        let mut r = PacketSegmentationReassembly::default();
+        assert!(r.inject(TestSegment::boxed(true, 100, b"ab")).is_none());
+        // hole at 102
+        assert!(r.inject(TestSegment::boxed(true, 104, b"ef")).is_none());
+        assert!(r.inject(TestSegment::boxed(true, 102, b"cd")).is_none());
+        // retransmission
+        assert!(r.inject(TestSegment::boxed(true, 100, b"abcd")).is_none());
+        // partial overlap
+        assert!(r.inject(TestSegment::boxed(true, 105, b"fgh")).is_none());
+        assert!(r.inject(TestSegment::boxed(true, 108, b"i")).is_none());
+        // response flushes the request
+        assert_eq!(
+            payloads(r.inject(TestSegment::boxed(false, 0, b"ok"))),
+            vec![b"abcdefghi".to_vec()]
+        );
+        assert_eq!(r.take_dropped_bytes(), 0);
+        // new data overlapping output data can not be output without repeating it
+        assert_eq!(
+            payloads(r.inject(TestSegment::boxed(true, 108, b"ij"))),
+            vec![b"ok".to_vec()]
+        );
+        assert_eq!(r.take_dropped_bytes(), 1);
+        assert!(r.flush().is_none());
+    }
+
+    #[test]
+    fn message_across_segments() {
+        let mut r = PacketSegmentationReassembly::default();
+        for (i, p) in [b"GET ", b"/ HT", b"TP/1", b".1\r\n"].iter().enumerate() {
+            assert!(r
+                .inject(TestSegment::boxed(true, 4 * i as u32, *p))
+                .is_none());
+        }
+        assert_eq!(payloads(r.flush()), vec![b"GET / HTTP/1.1\r\n".to_vec()]);
+        assert_eq!(r.take_dropped_bytes(), 0);
     }
 
-    pub fn inject(&mut self, _: Box<dyn Segment>) -> Option<Vec<Box<dyn Segment>>> {
-        None
+    #[test]
+    fn segments_without_payload() {
+        let mut r = PacketSegmentationReassembly::default();
+        // nothing buffered, output at once
+        assert_eq!(
+            payloads(r.inject(TestSegment::boxed(true, 0, b""))),
+            vec![b"".to_vec()]
+        );
+        assert!(!r.has_pending());
+
+        // acks are kept behind the buffered message
+        assert!(r.inject(TestSegment::boxed(false, 0, b"resp")).is_none());
+        assert!(r.inject(TestSegment::boxed(true, 0, b"")).is_none());
+        assert!(r.has_pending());
+        let output = r.inject(TestSegment::boxed(true, 0, b"req")).unwrap();
+        assert_eq!(
+            output
+                .iter()
+                .map(|s| (s.is_c2s(), s.get_payload().to_vec()))
+                .collect::<Vec<_>>(),
+            vec![(false, b"resp".to_vec()), (true, b"".to_vec())]
+        );
+
+        // flushed when idle
+        assert!(r.inject(TestSegment::boxed(false, 4, b"")).is_none());
+        assert_eq!(payloads(r.flush()), vec![b"req".to_vec(), b"".to_vec()]);
+        assert!(!r.has_pending());
+
+        // too many held segments output the pending message
+        assert!(r.inject(TestSegment::boxed(true, 3, b"more")).is_none());
+        for _ in 0..MAX_HELD_SEGMENTS {
+            assert!(r.inject(TestSegment::boxed(false, 4, b"")).is_none());
+        }
+        let output = payloads(r.inject(TestSegment::boxed(false, 4, b"")));
+        assert_eq!(output.len(), MAX_HELD_SEGMENTS + 2);
+        assert_eq!(output[0], b"more".to_vec());
+        assert!(!r.has_pending());
+        // the message continues after the hold is released
+        assert!(r.inject(TestSegment::boxed(true, 7, b"!")).is_none());
+        assert_eq!(payloads(r.flush()), vec![b"!".to_vec()]);
+        assert_eq!(r.take_dropped_bytes(), 0);
+    }
+
+    #[test]
+    fn bounded_out_of_order() {
+        let mut r = PacketSegmentationReassembly::default();
+        assert!(r.inject(TestSegment::boxed(true, u32::MAX, b"a")).is_none());
+        let mut output = vec![];
+        for i in 0..=MAX_OUT_OF_ORDER_SEGMENTS as u32 {
+            output.extend(payloads(r.inject(TestSegment::boxed(true, 1 + i, b"x"))));
+        }
+        // hole at 0 is skipped when buffer is full
+        assert_eq!(output, vec![b"a".to_vec()]);
+        assert_eq!(r.take_dropped_bytes(), 1);
+        assert_eq!(
+            payloads(r.flush()),
+            vec![vec![b'x'; MAX_OUT_OF_ORDER_SEGMENTS + 1]]
+        );
+    }
+
+    #[test]
+    fn bounded_bytes() {
+        let mut r = PacketSegmentationReassembly::default();
+        let segment = vec![b'x'; 1500];
+        let mut output = vec![];
+        let count = MAX_BUFFERED_BYTES / segment.len() + 1;
+        for i in 0..count as u32 {
+            output.extend(payloads(r.inject(TestSegment::boxed(
+                true,
+                i * 1500,
+                &segment,
+            ))));
+        }
+        // the pending message is output when it is full
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].len(), (count - 1) * segment.len());
+        assert_eq!(payloads(r.flush()), vec![segment.clone()]);
+
+        // a hole is skipped when out of order segments exceed the byte cap
+        let mut r = PacketSegmentationReassembly::default();
+        let large = vec![b'y'; MAX_BUFFERED_BYTES / 2 + 1];
+        assert!(r.inject(TestSegment::boxed(true, 0, b"a")).is_none());
+        assert!(r.inject(TestSegment::boxed(true, 2, &large)).is_none());
+        let next = 2 + large.len() as u32;
+        assert_eq!(
+            payloads(r.inject(TestSegment::boxed(true, next, &large))),
+            vec![b"a".to_vec(), large.clone()]
+        );
+        assert_eq!(r.take_dropped_bytes(), 1);
+        assert_eq!(payloads(r.flush()), vec![large]);
     }
 }
@@ -14,40 +14,185 @@
  * limitations under the License.
  */
 
-//! Enterprise Edition Feature: packet-sequence
+//! Packet Sequence Block
+//!
+//! TCP headers of a flow are encoded into a block, which is sent when it is
+//! full, when the flow enters a new minute or when the flow is flushed.
+//!
+//! Encoded block:
+//!
+//! ```text
+//! | flow_id: u64 | packet_count: u8, end_time(us): u56 | flag: u8 | records |
+//! ```
+//!
+//! Each record starts with the direction (highest bit) and the time delta
+//! in microseconds to the previous packet (the first packet to the block
+//! start time), followed by the fields selected by flag, all in little endian:
+//!
+//! ```text
+//! | direction: u1, delta: u31 | flags: u8 | seq: u32 | ack: u32 | payload_size: u16 |
+//! | window_size: u16 | mss: u16 | win_scale: u8 | sack_permitted: u1, sack_len: u7 | sack |
+//! ```
 use std::time::Duration;
 
 use public::sender::{SendMessageType, Sendable};
 
-#[derive(Debug, Default, PartialEq)]
-pub struct PacketData {}
+// header fields flag
+// | FLAG | SEQ | ACK | PAYLOAD_SIZE | WINDOW_SIZE | OPT_MSS | OPT_WS | OPT_SACK |
+//     7     6     5              4             3         2        1          0
+pub const FIELD_FLAG: u8 = 1 << 7;
+pub const FIELD_SEQ: u8 = 1 << 6;
+pub const FIELD_ACK: u8 = 1 << 5;
+pub const FIELD_PAYLOAD_SIZE: u8 = 1 << 4;
+pub const FIELD_WINDOW_SIZE: u8 = 1 << 3;
+pub const FIELD_OPT_MSS: u8 = 1 << 2;
+pub const FIELD_OPT_WS: u8 = 1 << 1;
+pub const FIELD_OPT_SACK: u8 = 1;
+
+pub const BLOCK_HEAD_SIZE: usize = 16;
+// at most 4 sack blocks in tcp options
+const MAX_SACK_LEN: usize = 32;
+const MAX_DELTA: u64 = 0x7fff_ffff;
+const DIRECTION_BIT: u32 = 1 << 31;
+// packet_count is encoded in 8 bits
+const MAX_PACKET_COUNT: u8 = u8::MAX;
+const END_TIME_MASK: u64 = (1 << 56) - 1;
+
+fn fixed_record_len(flag: u8) -> usize {
+    let mut len = 4;
+    for (field, size) in [
+        (FIELD_FLAG, 1),
+        (FIELD_SEQ, 4),
+        (FIELD_ACK, 4),
+        (FIELD_PAYLOAD_SIZE, 2),
+        (FIELD_WINDOW_SIZE, 2),
+        (FIELD_OPT_MSS, 2),
+        (FIELD_OPT_WS, 1),
+        (FIELD_OPT_SACK, 1),
+    ] {
+        if flag & field != 0 {
+            len += size;
+        }
+    }
+    len
+}
+
+fn max_record_len(flag: u8) -> usize {
+    if flag & FIELD_OPT_SACK != 0 {
+        fixed_record_len(flag) + MAX_SACK_LEN
+    } else {
+        fixed_record_len(flag)
+    }
+}
 
 #[derive(Debug, Default, PartialEq)]
-pub struct PacketSequenceBlock {}
+pub struct PacketSequenceBlock {
+    flow_id: u64,
+    // start time in minute of the flow, in seconds
+    start_time: u32,
+    // offset of the last packet to start_time, in microseconds
+    last_offset: u64,
+    end_time: Duration,
+    packet_count: u8,
+    flag: u8,
+    records: Vec<u8>,
+}
 
 impl PacketSequenceBlock {
-    pub fn new(_: u32) -> Self {
-        unimplemented!();
+    pub fn new(start_time: u32) -> Self {
+        Self {
+            start_time,
+            ..Default::default()
+        }
     }
 
-    pub fn with_capacity(_: usize, _: u32) -> Self {
-        unimplemented!();
+    pub fn with_capacity(block_size: usize, start_time: u32) -> Self {
+        Self {
+            start_time,
+            records: Vec::with_capacity(block_size),
+            ..Default::default()
+        }
     }
 
-    pub fn is_available(&self, _: usize, _: u32) -> bool {
-        unimplemented!();
+    // whether one more packet can be appended
+    pub fn is_available(&self, block_size: usize, start_time: u32) -> bool {
+        self.start_time == start_time
+            && self.packet_count < MAX_PACKET_COUNT
+            && self.records.len() + max_record_len(self.flag) <= block_size
     }
 
-    pub fn convert_duration_to_timestamp(_: usize, _: Duration) -> u64 {
-        unimplemented!();
+    pub fn convert_duration_to_timestamp(start_time: usize, timestamp: Duration) -> u64 {
+        (timestamp.as_micros() as u64).saturating_sub(start_time as u64 * 1_000_000)
     }
 
-    pub fn append_packet(&self, _: MiniMetaPacket, _: u8) {
-        unimplemented!();
+    pub fn packet_count(&self) -> u8 {
+        self.packet_count
     }
 
+    pub fn append_packet(&mut self, packet: MiniMetaPacket, flag: u8) {
+        if self.packet_count == 0 {
+            self.flow_id = packet.flow_id;
+            self.flag = flag;
+        }
+        let flag = self.flag;
+
+        let offset =
+            Self::convert_duration_to_timestamp(self.start_time as usize, packet.timestamp);
+        let delta = offset.saturating_sub(self.last_offset).min(MAX_DELTA) as u32;
+        self.last_offset = self.last_offset.max(offset);
+        self.end_time = self.end_time.max(packet.timestamp);
+
+        let head = if packet.direction != 0 {
+            delta | DIRECTION_BIT
+        } else {
+            delta
+        };
+        let records = &mut self.records;
+        records.extend_from_slice(&head.to_le_bytes());
+        if flag & FIELD_FLAG != 0 {
+            records.push(packet.flags);
+        }
+        if flag & FIELD_SEQ != 0 {
+            records.extend_from_slice(&packet.seq.to_le_bytes());
+        }
+        if flag & FIELD_ACK != 0 {
+            records.extend_from_slice(&packet.ack.to_le_bytes());
+        }
+        if flag & FIELD_PAYLOAD_SIZE != 0 {
+            records.extend_from_slice(&packet.payload_len.to_le_bytes());
+        }
+        if flag & FIELD_WINDOW_SIZE != 0 {
+            records.extend_from_slice(&packet.win_size.to_le_bytes());
+        }
+        if flag & FIELD_OPT_MSS != 0 {
+            records.extend_from_slice(&packet.mss.to_le_bytes());
+        }
+        if flag & FIELD_OPT_WS != 0 {
+            records.push(packet.win_scale);
+        }
+        if flag & FIELD_OPT_SACK != 0 {
+            let sack = match packet.sack {
+                Some(sack) => &sack[..sack.len().min(MAX_SACK_LEN)],
+                None => &[],
+            };
+            records.push((packet.sack_permitted as u8) << 7 | sack.len() as u8);
+            records.extend_from_slice(sack);
+        }
+        self.packet_count += 1;
+    }
+
+    // The flow is reversed, directions of appended packets are flipped so
+    // that they are consistent with packets appended later
     pub fn reverse_needed_for_new_packet(&mut self) {
-        unimplemented!();
+        let fixed_len = fixed_record_len(self.flag);
+        let mut offset = 0;
+        while offset + fixed_len <= self.records.len() {
+            self.records[offset + 3] ^= (DIRECTION_BIT >> 24) as u8;
+            offset += fixed_len;
+            if self.flag & FIELD_OPT_SACK != 0 {
+                offset += (self.records[offset - 1] & 0x7f) as usize;
+            }
+        }
     }
 }
 
@@ -55,8 +200,14 @@ impl PacketSequenceBlock {
 pub struct BoxedPacketSequenceBlock(pub Box<PacketSequenceBlock>);
 
 impl Sendable for BoxedPacketSequenceBlock {
-    fn encode(self, _: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
-        Ok(0)
+    fn encode(self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        let block = self.0;
+        let end_time = block.end_time.as_micros() as u64 & END_TIME_MASK;
+        buf.extend_from_slice(&block.flow_id.to_le_bytes());
+        buf.extend_from_slice(&((block.packet_count as u64) << 56 | end_time).to_le_bytes());
+        buf.push(block.flag);
+        buf.extend_from_slice(&block.records);
+        Ok(BLOCK_HEAD_SIZE + 1 + block.records.len())
     }
 
     fn message_type(&self) -> SendMessageType {
@@ -65,24 +216,140 @@ impl Sendable for BoxedPacketSequenceBlock {
 }
 
 pub struct MiniMetaPacket<'a> {
-    _foo: &'a Option<Vec<u8>>,
+    flow_id: u64,
+    direction: u8,
+    timestamp: Duration,
+    payload_len: u16,
+    seq: u32,
+    ack: u32,
+    win_size: u16,
+    mss: u16,
+    flags: u8,
+    win_scale: u8,
+    sack_permitted: bool,
+    sack: &'a Option<Vec<u8>>,
 }
 
 impl<'a> MiniMetaPacket<'a> {
     pub fn new(
-        _: u64,
-        _: u8,
-        _: Duration,
-        _: u16,
-        _: u32,
-        _: u32,
-        _: u16,
-        _: u16,
-        _: u8,
-        _: u8,
-        _: bool,
-        _: &'a Option<Vec<u8>>,
+        flow_id: u64,
+        direction: u8,
+        timestamp: Duration,
+        payload_len: u16,
+        seq: u32,
+        ack: u32,
+        win_size: u16,
+        mss: u16,
+        flags: u8,
+        win_scale: u8,
+        sack_permitted: bool,
+        sack: &'a Option<Vec<u8>>,
     ) -> Self {
-        MiniMetaPacket { _foo: &None }
+        MiniMetaPacket {
+            flow_id,
+            direction,
+            timestamp,
+            payload_len,
+            seq,
+            ack,
+            win_size,
+            mss,
+            flags,
+            win_scale,
+            sack_permitted,
+            sack,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START_TIME: u32 = 1_700_000_040;
+
+    fn packet<'a>(
+        direction: u8,
+        micros: u64,
+        seq: u32,
+        sack: &'a Option<Vec<u8>>,
+    ) -> MiniMetaPacket<'a> {
+        MiniMetaPacket::new(
+            10,
+            direction,
+            Duration::from_secs(START_TIME as u64) + Duration::from_micros(micros),
+            100,
+            seq,
+            1,
+            1024,
+            1460,
+            0x18,
+            7,
+            true,
+            sack,
+        )
+    }
+
+    #[test]
+    fn encode_block() {
+        let sack = Some(vec![0xaa; 8]);
+        let mut block = PacketSequenceBlock::with_capacity(256, START_TIME);
+        block.append_packet(packet(0, 1500, 1000, &None), 0xff);
+        block.append_packet(packet(1, 1700, 2000, &sack), 0xff);
+        assert_eq!(block.packet_count(), 2);
+
+        let mut buf = vec![];
+        let size = BoxedPacketSequenceBlock(Box::new(block)).encode(&mut buf);
+        assert_eq!(size.unwrap(), buf.len());
+
+        assert_eq!(&buf[..8], &10u64.to_le_bytes());
+        let end_time = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+        assert_eq!(end_time >> 56, 2);
+        assert_eq!(
+            end_time & END_TIME_MASK,
+            START_TIME as u64 * 1_000_000 + 1700
+        );
+        assert_eq!(buf[16], 0xff);
+
+        let records = &buf[17..];
+        let fixed_len = fixed_record_len(0xff);
+        assert_eq!(records.len(), fixed_len * 2 + 8);
+        assert_eq!(&records[..4], &1500u32.to_le_bytes());
+        assert_eq!(records[4], 0x18);
+        assert_eq!(&records[5..9], &1000u32.to_le_bytes());
+        assert_eq!(records[fixed_len - 1], 0x80);
+        let second = &records[fixed_len..];
+        assert_eq!(&second[..4], &(200 | DIRECTION_BIT).to_le_bytes());
+        assert_eq!(second[fixed_len - 1], 0x88);
+        assert_eq!(&second[fixed_len..], &[0xaa; 8]);
+    }
+
+    #[test]
+    fn block_bounds() {
+        let flag = FIELD_SEQ | FIELD_OPT_SACK;
+        let sack = Some(vec![0xbb; 16]);
+        let block_size = max_record_len(flag) * 2;
+        let mut block = PacketSequenceBlock::with_capacity(block_size, START_TIME);
+        assert!(block.is_available(block_size, START_TIME));
+        block.append_packet(packet(0, 0, 1, &sack), flag);
+        block.append_packet(packet(1, 10, 2, &None), flag);
+        assert!(block.is_available(block_size, START_TIME));
+        assert!(!block.is_available(block_size, START_TIME + 60));
+        block.append_packet(packet(0, 20, 3, &None), flag);
+        assert!(!block.is_available(block_size, START_TIME));
+
+        block.reverse_needed_for_new_packet();
+        let fixed_len = fixed_record_len(flag);
+        let directions = [0, fixed_len + 16, fixed_len * 2 + 16]
+            .iter()
+            .map(|o| block.records[o + 3] >> 7)
+            .collect::<Vec<_>>();
+        assert_eq!(directions, vec![1, 0, 1]);
+
+        let mut block = PacketSequenceBlock::new(START_TIME);
+        for i in 0..MAX_PACKET_COUNT {
+            block.append_packet(packet(0, i as u64, i as u32, &None), FIELD_SEQ);
+        }
+        assert!(!block.is_available(usize::MAX, START_TIME));
     }
 }
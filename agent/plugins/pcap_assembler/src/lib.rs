@@ -14,28 +14,92 @@
  * limitations under the License.
  */
 
-//! Enterprise Edition Feature: RawPcap Assembler
+//! RawPcap Assembler
+//!
+//! Packets hitting PCAP policies are buffered per flow in pcap record format
+//! (16 bytes record header followed by packet data). A flow buffer is flushed
+//! when it exceeds `buffer_size_per_flow`, when it has been buffered for
+//! `flush_interval`, when the flow enters a new minute, or when all buffers
+//! exceed `total_buffer_size`. Flushed buffers are sent in `PcapBatch`es
+//! tagged with the pcap magic number.
+//!
+//! `packet_records` carries records only, without the 24 bytes pcap global
+//! header. The ingester builds the global header from `magic` with version 2.4,
+//! snaplen 65535 and LINKTYPE_ETHERNET, and prepends it to the records of each
+//! `Pcap` (see server/ingester/pcap/decoder). Record headers carry the sliced
+//! length as `incl_len` and the length on the wire as `orig_len`.
 
-use std::sync::atomic::AtomicI64;
-use std::sync::Arc;
-use std::thread::JoinHandle;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
 
-use public::counter::{Counter, RefCountable};
-use public::packet::MiniPacket;
-use public::proto::agent::PcapBatch;
-use public::queue::{DebugSender, Receiver};
+use log::{info, warn};
+use prost::Message;
+
+use public::consts::PCAP_MAGIC;
+use public::counter::{Counter, CounterType, CounterValue, RefCountable};
+use public::packet::{write_record_header, MiniPacket};
+use public::proto::agent::{Pcap, PcapBatch};
+use public::queue::{DebugSender, Error, Receiver};
 use public::sender::{SendMessageType, Sendable};
 
-pub struct PcapAssembler {
-    pub counter: Arc<AssemblerCounter>,
-}
+const QUEUE_BATCH_SIZE: usize = 1024;
+const RCV_TIMEOUT: Duration = Duration::from_secs(1);
+// a batch is sent when its packet records exceed this size
+const MAX_BATCH_SIZE: usize = 1 << 20;
 
-pub struct AssemblerCounter;
+#[derive(Default)]
+pub struct AssemblerCounter {
+    rx_packets: AtomicU64,
+    tx_pcaps: AtomicU64,
+    tx_bytes: AtomicU64,
+    drop_pcaps: AtomicU64,
+    flush_by_total_size: AtomicU64,
+    buffered_flows: AtomicU64,
+    buffered_bytes: AtomicU64,
+}
 
 impl RefCountable for AssemblerCounter {
     fn get_counters(&self) -> Vec<Counter> {
-        vec![]
+        vec![
+            (
+                "rx_packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.rx_packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "tx_pcaps",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.tx_pcaps.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "tx_bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.tx_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "drop_pcaps",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.drop_pcaps.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "flush_by_total_size",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.flush_by_total_size.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "buffered_flows",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.buffered_flows.load(Ordering::Relaxed)),
+            ),
+            (
+                "buffered_bytes",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.buffered_bytes.load(Ordering::Relaxed)),
+            ),
+        ]
     }
 }
 
@@ -43,8 +107,8 @@ impl RefCountable for AssemblerCounter {
 pub struct BoxedPcapBatch(pub Box<PcapBatch>);
 
 impl Sendable for BoxedPcapBatch {
-    fn encode(self, _: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
-        Ok(0)
+    fn encode(self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        self.0.encode(buf).map(|_| self.0.encoded_len())
     }
 
     fn message_type(&self) -> SendMessageType {
@@ -52,27 +116,453 @@ impl Sendable for BoxedPcapBatch {
     }
 }
 
+struct FlowBuffer {
+    // wall clock time when the buffer is created
+    create_time: Duration,
+    start_time_in_minute: Duration,
+    start_time: Duration,
+    end_time: Duration,
+    packet_count: u32,
+    acl_gids: Vec<u16>,
+    records: Vec<u8>,
+}
+
+impl FlowBuffer {
+    fn new(packet: &MiniPacket, now: Duration, capacity: usize) -> Self {
+        Self {
+            create_time: now,
+            start_time_in_minute: packet.start_time_in_minute(),
+            start_time: packet.timestamp,
+            end_time: packet.timestamp,
+            packet_count: 0,
+            acl_gids: vec![],
+            records: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn append(&mut self, packet: MiniPacket) {
+        let len = packet.packet.len() as u32;
+        // writing to vec never fails
+        let _ = write_record_header(
+            &mut self.records,
+            packet.timestamp,
+            len,
+            packet.packet_len.max(len),
+        );
+        self.records.extend_from_slice(&packet.packet);
+        self.start_time = self.start_time.min(packet.timestamp);
+        self.end_time = self.end_time.max(packet.timestamp);
+        self.packet_count += 1;
+        for gid in packet.acl_gids {
+            if !self.acl_gids.contains(&gid) {
+                self.acl_gids.push(gid);
+            }
+        }
+    }
+
+    fn into_pcap(self, flow_id: u64) -> Pcap {
+        Pcap {
+            flow_id: Some(flow_id),
+            start_time: Some(self.start_time.as_nanos() as u64),
+            end_time: Some(self.end_time.as_nanos() as u64),
+            packet_count: Some(self.packet_count),
+            packet_records: Some(self.records),
+            acl_gids: self.acl_gids.into_iter().map(|g| g as u32).collect(),
+        }
+    }
+}
+
+struct Assembler {
+    total_buffer_size: usize,
+    buffer_size_per_flow: usize,
+    flush_interval: Duration,
+
+    flows: HashMap<u64, FlowBuffer>,
+    buffered_bytes: usize,
+
+    batch: Vec<Pcap>,
+    batch_bytes: usize,
+    output: Vec<BoxedPcapBatch>,
+
+    counter: Arc<AssemblerCounter>,
+}
+
+impl Assembler {
+    fn new(
+        total_buffer_size: u64,
+        buffer_size_per_flow: u32,
+        flush_interval: Duration,
+        counter: Arc<AssemblerCounter>,
+    ) -> Self {
+        Self {
+            total_buffer_size: total_buffer_size as usize,
+            buffer_size_per_flow: buffer_size_per_flow as usize,
+            flush_interval,
+            flows: HashMap::new(),
+            buffered_bytes: 0,
+            batch: vec![],
+            batch_bytes: 0,
+            output: vec![],
+            counter,
+        }
+    }
+
+    fn inject(&mut self, packet: MiniPacket, now: Duration) {
+        self.counter.rx_packets.fetch_add(1, Ordering::Relaxed);
+        let record_len = packet.record_len();
+        if self.buffered_bytes + record_len > self.total_buffer_size {
+            self.counter
+                .flush_by_total_size
+                .fetch_add(1, Ordering::Relaxed);
+            self.flush_all();
+        }
+
+        let flow_id = packet.flow_id;
+        // pcaps of a flow are aligned with its flow logs by minute
+        if self
+            .flows
+            .get(&flow_id)
+            .map(|b| b.start_time_in_minute != packet.start_time_in_minute())
+            .unwrap_or(false)
+        {
+            self.flush_flow(flow_id);
+        }
+
+        let capacity = self.buffer_size_per_flow.min(self.total_buffer_size);
+        let buffer = self
+            .flows
+            .entry(flow_id)
+            .or_insert_with(|| FlowBuffer::new(&packet, now, capacity));
+        buffer.append(packet);
+        self.buffered_bytes += record_len;
+        if buffer.records.len() >= self.buffer_size_per_flow {
+            self.flush_flow(flow_id);
+        }
+    }
+
+    fn flush_flow(&mut self, flow_id: u64) {
+        if let Some(buffer) = self.flows.remove(&flow_id) {
+            self.buffered_bytes -= buffer.records.len();
+            self.push_pcap(buffer.into_pcap(flow_id));
+        }
+    }
+
+    fn flush_all(&mut self) {
+        let flows = std::mem::take(&mut self.flows);
+        self.buffered_bytes = 0;
+        for (flow_id, buffer) in flows {
+            self.push_pcap(buffer.into_pcap(flow_id));
+        }
+    }
+
+    fn flush_timeout(&mut self, now: Duration) {
+        let flush_interval = self.flush_interval;
+        let timeout_flows = self
+            .flows
+            .iter()
+            .filter(|(_, b)| now.saturating_sub(b.create_time) >= flush_interval)
+            .map(|(flow_id, _)| *flow_id)
+            .collect::<Vec<_>>();
+        for flow_id in timeout_flows {
+            self.flush_flow(flow_id);
+        }
+    }
+
+    fn push_pcap(&mut self, pcap: Pcap) {
+        self.batch_bytes += pcap.packet_records.as_ref().map(|r| r.len()).unwrap_or(0);
+        self.batch.push(pcap);
+        if self.batch_bytes >= MAX_BATCH_SIZE {
+            self.pack_batch();
+        }
+    }
+
+    fn pack_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        self.counter
+            .tx_pcaps
+            .fetch_add(self.batch.len() as u64, Ordering::Relaxed);
+        self.counter
+            .tx_bytes
+            .fetch_add(self.batch_bytes as u64, Ordering::Relaxed);
+        self.output.push(BoxedPcapBatch(Box::new(PcapBatch {
+            magic: Some(PCAP_MAGIC),
+            batches: std::mem::take(&mut self.batch),
+        })));
+        self.batch_bytes = 0;
+    }
+
+    fn take_output(&mut self) -> &mut Vec<BoxedPcapBatch> {
+        self.pack_batch();
+        self.counter
+            .buffered_flows
+            .store(self.flows.len() as u64, Ordering::Relaxed);
+        self.counter
+            .buffered_bytes
+            .store(self.buffered_bytes as u64, Ordering::Relaxed);
+        &mut self.output
+    }
+}
+
+fn get_timestamp(ntp_diff: i64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i128
+        + ntp_diff as i128;
+    Duration::from_nanos(now as u64)
+}
+
+pub struct PcapAssembler {
+    id: u32,
+    total_buffer_size: u64,
+    buffer_size_per_flow: u32,
+    flush_interval: Duration,
+    output_queue: DebugSender<BoxedPcapBatch>,
+    input_queue: Arc<Receiver<MiniPacket>>,
+    ntp_diff: Arc<AtomicI64>,
+    running: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    pub counter: Arc<AssemblerCounter>,
+}
+
 impl PcapAssembler {
     pub fn new(
-        _: u32,
-        _: bool,
-        _: u64,
-        _: u32,
-        _: Duration,
-        _: DebugSender<BoxedPcapBatch>,
-        _: Receiver<MiniPacket>,
-        _: Arc<AtomicI64>,
+        id: u32,
+        total_buffer_size: u64,
+        buffer_size_per_flow: u32,
+        flush_interval: Duration,
+        output_queue: DebugSender<BoxedPcapBatch>,
+        input_queue: Receiver<MiniPacket>,
+        ntp_diff: Arc<AtomicI64>,
     ) -> Self {
         PcapAssembler {
-            counter: Arc::new(AssemblerCounter),
+            id,
+            total_buffer_size,
+            buffer_size_per_flow,
+            flush_interval,
+            output_queue,
+            input_queue: Arc::new(input_queue),
+            ntp_diff,
+            running: Default::default(),
+            thread: Mutex::new(None),
+            counter: Default::default(),
         }
     }
 
-    pub fn start(&self) {}
+    pub fn start(&self) {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let running = self.running.clone();
+        let input_queue = self.input_queue.clone();
+        let output_queue = self.output_queue.clone();
+        let ntp_diff = self.ntp_diff.clone();
+        let counter = self.counter.clone();
+        let mut assembler = Assembler::new(
+            self.total_buffer_size,
+            self.buffer_size_per_flow,
+            self.flush_interval,
+            self.counter.clone(),
+        );
+
+        let thread = thread::Builder::new()
+            .name("pcap-assembler".to_owned())
+            .spawn(move || {
+                let mut packets = Vec::with_capacity(QUEUE_BATCH_SIZE);
+                let mut last_check = Duration::ZERO;
+                while running.load(Ordering::Relaxed) {
+                    match input_queue.recv_all(&mut packets, Some(RCV_TIMEOUT)) {
+                        Ok(_) | Err(Error::Timeout) => {}
+                        Err(Error::Terminated(..)) => break,
+                        Err(Error::BatchTooLarge(_)) => unreachable!(),
+                    }
+                    let now = get_timestamp(ntp_diff.load(Ordering::Relaxed));
+                    for packet in packets.drain(..) {
+                        assembler.inject(packet, now);
+                    }
+                    if now.saturating_sub(last_check) >= RCV_TIMEOUT {
+                        assembler.flush_timeout(now);
+                        last_check = now;
+                    }
+                    let output = assembler.take_output();
+                    if output.is_empty() {
+                        continue;
+                    }
+                    let count = output.iter().map(|b| b.0.batches.len()).sum::<usize>();
+                    if let Err(e) = output_queue.send_all(output) {
+                        warn!("pcap batch to queue failed, because {:?}", e);
+                        counter
+                            .drop_pcaps
+                            .fetch_add(count as u64, Ordering::Relaxed);
+                        output.clear();
+                    }
+                }
+                assembler.flush_all();
+                let output = assembler.take_output();
+                if !output.is_empty() {
+                    let _ = output_queue.send_all(output);
+                }
+            })
+            .unwrap();
+        self.thread.lock().unwrap().replace(thread);
+        info!("pcap assembler id: {} started", self.id);
+    }
 
     pub fn notify_stop(&self) -> Option<JoinHandle<()>> {
-        None
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        info!("notified pcap assembler id: {} to stop", self.id);
+        self.thread.lock().unwrap().take()
     }
 
-    pub fn stop(&self) {}
+    pub fn stop(&self) {
+        if let Some(handler) = self.notify_stop() {
+            if let Err(e) = handler.join() {
+                warn!("pcap assembler id: {} join failed: {:?}", self.id, e);
+            }
+            info!("stopped pcap assembler id: {}", self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use public::consts::RECORD_HEADER_LEN;
+
+    fn packet(flow_id: u64, secs: u64, len: usize, acl_gid: u16) -> MiniPacket {
+        MiniPacket {
+            packet: vec![0xab; len],
+            packet_len: len as u32,
+            timestamp: Duration::from_secs(secs),
+            flow_id,
+            acl_gids: vec![acl_gid],
+            second_in_minute: 0,
+        }
+    }
+
+    fn pcaps(assembler: &mut Assembler) -> Vec<Pcap> {
+        assembler
+            .take_output()
+            .drain(..)
+            .flat_map(|b| {
+                assert_eq!(b.0.magic, Some(PCAP_MAGIC));
+                b.0.batches
+            })
+            .collect()
+    }
+
+    #[test]
+    fn flush_by_size() {
+        let record_len = 100 + RECORD_HEADER_LEN;
+        let mut assembler = Assembler::new(
+            (record_len * 5) as u64,
+            (record_len * 2) as u32,
+            Duration::from_secs(60),
+            Default::default(),
+        );
+        let now = Duration::from_secs(1000);
+
+        assembler.inject(packet(1, 10, 100, 1), now);
+        assembler.inject(packet(2, 10, 100, 1), now);
+        assert!(pcaps(&mut assembler).is_empty());
+
+        // flow 1 reaches buffer_size_per_flow
+        assembler.inject(packet(1, 11, 100, 2), now);
+        let output = pcaps(&mut assembler);
+        assert_eq!(output.len(), 1);
+        let pcap = &output[0];
+        assert_eq!(pcap.flow_id, Some(1));
+        assert_eq!(pcap.packet_count, Some(2));
+        assert_eq!(pcap.start_time, Some(10_000_000_000));
+        assert_eq!(pcap.end_time, Some(11_000_000_000));
+        assert_eq!(pcap.acl_gids, vec![1, 2]);
+        let records = pcap.packet_records.as_ref().unwrap();
+        assert_eq!(records.len(), record_len * 2);
+        assert_eq!(&records[..4], &10u32.to_le_bytes());
+        assert_eq!(&records[8..12], &100u32.to_le_bytes());
+
+        // total_buffer_size exceeded
+        for i in 3..8 {
+            assembler.inject(packet(i, 10, 100, 1), now);
+        }
+        let output = pcaps(&mut assembler);
+        assert_eq!(output.len(), 5);
+        assert_eq!(assembler.flows.len(), 1);
+        assert_eq!(assembler.buffered_bytes, record_len);
+    }
+
+    #[test]
+    fn flush_by_time() {
+        let mut assembler =
+            Assembler::new(1 << 20, 1 << 16, Duration::from_secs(5), Default::default());
+        let now = Duration::from_secs(1000);
+
+        assembler.inject(packet(1, 10, 100, 1), now);
+        assembler.inject(packet(2, 10, 100, 1), now + Duration::from_secs(3));
+        assembler.flush_timeout(now + Duration::from_secs(5));
+        let output = pcaps(&mut assembler);
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].flow_id, Some(1));
+
+        // next minute of flow 2
+        assembler.inject(packet(2, 60, 100, 1), now + Duration::from_secs(4));
+        let output = pcaps(&mut assembler);
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].end_time, Some(10_000_000_000));
+        assert_eq!(assembler.flows[&2].start_time, Duration::from_secs(60));
+    }
+
+    // global header as written by the ingester
+    fn global_header(magic: u32) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend_from_slice(&magic.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&65535u32.to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn pcap_file_contract() {
+        let mut assembler =
+            Assembler::new(1 << 20, 1 << 16, Duration::from_secs(5), Default::default());
+        let now = Duration::from_secs(1000);
+
+        let mut sliced = packet(1, 10, 64, 1);
+        sliced.packet_len = 1514;
+        assembler.inject(sliced, now);
+        assembler.inject(packet(1, 11, 100, 1), now);
+        assembler.flush_all();
+        let output = assembler.take_output().drain(..).collect::<Vec<_>>();
+        assert_eq!(output.len(), 1);
+        let batch = &output[0].0;
+        let records = batch.batches[0].packet_records.as_ref().unwrap();
+
+        let mut file = global_header(batch.magic.unwrap());
+        assert_eq!(file.len(), 24);
+        file.extend_from_slice(records);
+        assert_eq!(&file[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+
+        // walk records as a pcap reader does
+        let mut offset = 24;
+        let mut lens = vec![];
+        while offset < file.len() {
+            let field =
+                |i: usize| u32::from_le_bytes(file[offset + i..offset + i + 4].try_into().unwrap());
+            let (incl_len, orig_len) = (field(8), field(12));
+            lens.push((incl_len, orig_len));
+            offset += RECORD_HEADER_LEN + incl_len as usize;
+        }
+        assert_eq!(offset, file.len());
+        assert_eq!(lens, vec![(64, 1514), (100, 100)]);
+    }
 }
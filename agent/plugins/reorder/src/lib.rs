@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
 use std::sync::Arc;
 
@@ -88,21 +89,181 @@ impl counter::OwnedCountable for StatsReorderCounter {
     }
 }
 
+// Cached items are output when not reordered in time
+pub const REORDER_TIMEOUT: u64 = 100;
+// Reorder state of a flow is removed when no items received for a while
+const FLOW_IDLE_TIMEOUT: u64 = 60_000;
+
+struct FlowCache {
+    // sequence of the next item to output, None if no item output yet
+    next_seq: Option<u64>,
+    // sorted by sequence
+    items: VecDeque<Box<dyn CacheItem>>,
+    last_timestamp: u64,
+}
+
+impl FlowCache {
+    fn new(timestamp: u64) -> Self {
+        Self {
+            next_seq: None,
+            items: VecDeque::new(),
+            last_timestamp: timestamp,
+        }
+    }
+
+    fn pop_in_order(&mut self, output: &mut Vec<Box<dyn CacheItem>>) {
+        if self.next_seq.is_none() {
+            // the first item of flow is output when its successor arrives
+            match (self.items.front(), self.items.get(1)) {
+                (Some(first), Some(second)) if first.get_seq() + 1 == second.get_seq() => {
+                    self.next_seq = Some(first.get_seq());
+                }
+                _ => return,
+            }
+        }
+        let Some(mut next_seq) = self.next_seq else {
+            return;
+        };
+        while let Some(item) = self.items.front() {
+            if item.get_seq() != next_seq {
+                break;
+            }
+            next_seq += 1;
+            output.push(self.items.pop_front().unwrap());
+        }
+        self.next_seq = Some(next_seq);
+    }
+
+    fn skip_hole(&mut self, output: &mut Vec<Box<dyn CacheItem>>) {
+        if let Some(first) = self.items.front() {
+            self.next_seq = Some(first.get_seq());
+            self.pop_in_order(output);
+        }
+    }
+
+    fn oldest_timestamp(&self) -> Option<u64> {
+        self.items.iter().map(|i| i.get_timestmap()).min()
+    }
+}
+
+// Reorder eBPF socket data of the same flow by sequence
+//
+// Items of a flow are output immediately if they are in order, otherwise they
+// are cached until the missing items arrive, the flow cache is full or
+// `REORDER_TIMEOUT` expires.
 pub struct Reorder {
+    checker: Box<dyn L7ProtocolChecker>,
     counter: Arc<ReorderCounter>,
+    cache_size: usize,
+
+    flows: HashMap<u64, FlowCache>,
+    cached_items: usize,
+    last_flush: u64,
 }
 
 impl Reorder {
-    pub fn new(_: Box<dyn L7ProtocolChecker>, counter: Arc<ReorderCounter>, _: usize) -> Self {
-        Self { counter }
+    pub fn new(
+        checker: Box<dyn L7ProtocolChecker>,
+        counter: Arc<ReorderCounter>,
+        cache_size: usize,
+    ) -> Self {
+        Self {
+            checker,
+            counter,
+            cache_size: cache_size.max(1),
+            flows: HashMap::new(),
+            cached_items: 0,
+            last_flush: 0,
+        }
     }
 
-    pub fn flush(&mut self, _: u64) -> Vec<Box<dyn CacheItem>> {
-        vec![]
+    // Output cached items which are not reordered in `REORDER_TIMEOUT`
+    pub fn flush(&mut self, now: u64) -> Vec<Box<dyn CacheItem>> {
+        let mut output = vec![];
+        for flow in self.flows.values_mut() {
+            match flow.oldest_timestamp() {
+                Some(t) if t + REORDER_TIMEOUT <= now => {
+                    while !flow.items.is_empty() {
+                        flow.skip_hole(&mut output);
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.flows
+            .retain(|_, f| !f.items.is_empty() || f.last_timestamp + FLOW_IDLE_TIMEOUT > now);
+        self.last_flush = now;
+
+        self.cached_items -= output.len();
+        self.update_counter();
+        output
     }
 
     pub fn inject_item(&mut self, item: Box<dyn CacheItem>) -> Vec<Box<dyn CacheItem>> {
-        vec![item]
+        let now = item.get_timestmap();
+        let mut output = vec![];
+        if self.checker.is_disabled(item.get_l7_protocol()) {
+            output.push(item);
+        } else {
+            self.inject_ordered(item, &mut output);
+        }
+
+        if now >= self.last_flush + REORDER_TIMEOUT {
+            output.append(&mut self.flush(now));
+        } else {
+            self.update_counter();
+        }
+        output
+    }
+
+    fn inject_ordered(&mut self, item: Box<dyn CacheItem>, output: &mut Vec<Box<dyn CacheItem>>) {
+        let (seq, timestamp) = (item.get_seq(), item.get_timestmap());
+        let flow = self
+            .flows
+            .entry(item.get_id())
+            .or_insert_with(|| FlowCache::new(timestamp));
+        flow.last_timestamp = flow.last_timestamp.max(timestamp);
+
+        if let Some(next_seq) = flow.next_seq {
+            if seq < next_seq {
+                // items after it have been output
+                self.counter.drop_before_window.fetch_add(1, Relaxed);
+                return;
+            }
+            if seq == next_seq && flow.items.is_empty() {
+                flow.next_seq = Some(seq + 1);
+                output.push(item);
+                return;
+            }
+            self.counter.max_seq_gap.fetch_max(seq - next_seq, Relaxed);
+        }
+
+        let index = match flow.items.binary_search_by_key(&seq, |i| i.get_seq()) {
+            Ok(_) => {
+                // duplicated sequence
+                self.counter.drop_out_of_order.fetch_add(1, Relaxed);
+                return;
+            }
+            Err(index) => index,
+        };
+        flow.items.insert(index, item);
+        self.cached_items += 1;
+
+        let n = output.len();
+        flow.pop_in_order(output);
+        if flow.items.len() > self.cache_size {
+            flow.skip_hole(output);
+        }
+        self.cached_items -= output.len() - n;
+    }
+
+    fn update_counter(&self) {
+        self.counter
+            .flow_counter
+            .store(self.flows.len() as u64, Relaxed);
+        self.counter
+            .packet_counter
+            .store(self.cached_items as u64, Relaxed);
     }
 }
 
@@ -111,3 +272,120 @@ impl Drop for Reorder {
         self.counter.closed.store(true, Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+
+    use super::*;
+
+    struct Item {
+        id: u64,
+        seq: u64,
+        timestamp: u64,
+        protocol: L7Protocol,
+    }
+
+    impl Downcast for Item {
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+
+        fn into_any(self: Box<Self>) -> Box<dyn Any> {
+            self
+        }
+    }
+
+    impl CacheItem for Item {
+        fn get_id(&self) -> u64 {
+            self.id
+        }
+
+        fn get_seq(&self) -> u64 {
+            self.seq
+        }
+
+        fn get_timestmap(&self) -> u64 {
+            self.timestamp
+        }
+
+        fn get_l7_protocol(&self) -> L7Protocol {
+            self.protocol
+        }
+
+        fn is_segment_start(&self) -> bool {
+            false
+        }
+    }
+
+    struct Http1Only;
+
+    impl L7ProtocolChecker for Http1Only {
+        fn is_disabled(&self, p: L7Protocol) -> bool {
+            p != L7Protocol::Http1
+        }
+
+        fn is_enabled(&self, p: L7Protocol) -> bool {
+            p == L7Protocol::Http1
+        }
+    }
+
+    fn item(id: u64, seq: u64, timestamp: u64) -> Box<dyn CacheItem> {
+        Box::new(Item {
+            id,
+            seq,
+            timestamp,
+            protocol: L7Protocol::Http1,
+        })
+    }
+
+    fn seqs(items: Vec<Box<dyn CacheItem>>) -> Vec<u64> {
+        items.iter().map(|i| i.get_seq()).collect()
+    }
+
+    #[test]
+    fn reorder_by_seq() {
+        let counter = Arc::new(ReorderCounter::default());
+        let mut reorder = Reorder::new(Box::new(Http1Only), counter.clone(), 16);
+
+        // response before request
+        assert!(reorder.inject_item(item(1, 11, 1)).is_empty());
+        assert_eq!(seqs(reorder.inject_item(item(1, 10, 1))), vec![10, 11]);
+        assert_eq!(seqs(reorder.inject_item(item(1, 12, 2))), vec![12]);
+        assert!(reorder.inject_item(item(1, 14, 2)).is_empty());
+        assert!(reorder.inject_item(item(2, 1, 2)).is_empty());
+        assert_eq!(seqs(reorder.inject_item(item(1, 13, 3))), vec![13, 14]);
+        assert!(reorder.inject_item(item(1, 12, 3)).is_empty());
+        assert_eq!(counter.drop_before_window.load(Relaxed), 1);
+        assert_eq!(counter.max_seq_gap.load(Relaxed), 1);
+
+        let other = Box::new(Item {
+            id: 1,
+            seq: 0,
+            timestamp: 3,
+            protocol: L7Protocol::Kafka,
+        });
+        assert_eq!(seqs(reorder.inject_item(other)), vec![0]);
+
+        // flow 2 is flushed on timeout
+        assert!(reorder.flush(2 + REORDER_TIMEOUT - 1).is_empty());
+        assert_eq!(seqs(reorder.flush(2 + REORDER_TIMEOUT)), vec![1]);
+        assert_eq!(seqs(reorder.inject_item(item(2, 2, 200))), vec![2]);
+    }
+
+    #[test]
+    fn cache_full() {
+        let counter = Arc::new(ReorderCounter::default());
+        let mut reorder = Reorder::new(Box::new(Http1Only), counter.clone(), 2);
+
+        assert_eq!(seqs(reorder.inject_item(item(1, 0, 0))), vec![]);
+        assert_eq!(seqs(reorder.inject_item(item(1, 1, 0))), vec![0, 1]);
+        assert!(reorder.inject_item(item(1, 3, 0)).is_empty());
+        assert!(reorder.inject_item(item(1, 5, 0)).is_empty());
+        assert_eq!(counter.packet_counter.load(Relaxed), 2);
+        // hole at 2 is skipped
+        assert_eq!(seqs(reorder.inject_item(item(1, 4, 0))), vec![3, 4, 5]);
+        assert_eq!(counter.packet_counter.load(Relaxed), 0);
+        assert_eq!(counter.flow_counter.load(Relaxed), 1);
+    }
+}
@@ -14,46 +14,425 @@
  * limitations under the License.
  */
 
-//! Enterprise Edition Feature: windows-dispatcher
+//! Receive engines other than AF_PACKET.
+//!
+//! `Libpcap` captures packets from live interfaces or replays a pcap file.
+//!
+//! Enterprise Edition Feature: dpdk, `Dpdk`, `VhostUser` and `DpdkFromEbpf` are placeholders here.
 
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::info;
+use pcap::{Active, Capture, Linktype, Offline};
 
 use public::counter;
 use public::debug::QueueDebugger;
-use public::error::Result;
+use public::error::{Error, Result};
 use public::packet;
 use public::queue::Receiver;
 
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+const MIN_BUFFER_SIZE: usize = 1 << 20;
+const ETH_HEADER_SIZE: usize = 14;
+const SLL_HEADER_SIZE: usize = 16;
+const SLL2_HEADER_SIZE: usize = 20;
+
+/// How packets from a pcap file are handed to the dispatcher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplayPacing {
+    /// Read the file without waiting, timestamps keep their original spacing.
+    ///
+    /// Timestamps run ahead of the wall clock by up to the duration of the file,
+    /// downstream timers only catch up with them as time passes.
+    #[default]
+    AsFastAsPossible,
+    /// Deliver each packet when its offset from the first packet has elapsed.
+    WallClock,
+}
+
 #[derive(Default)]
-pub struct LibpcapCounter;
+pub struct LibpcapCounter {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    kernel_packets: AtomicU64,
+    kernel_drops: AtomicU64,
+    kernel_if_drops: AtomicU64,
+    unsupported_packets: AtomicU64,
+    replay_finished: AtomicBool,
+}
 
 impl counter::RefCountable for LibpcapCounter {
     fn get_counters(&self) -> Vec<counter::Counter> {
-        unimplemented!();
+        vec![
+            (
+                "rx_packets",
+                counter::CounterType::Counted,
+                counter::CounterValue::Unsigned(self.rx_packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "rx_bytes",
+                counter::CounterType::Counted,
+                counter::CounterValue::Unsigned(self.rx_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "kernel_packets",
+                counter::CounterType::Counted,
+                counter::CounterValue::Unsigned(self.kernel_packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "kernel_drops",
+                counter::CounterType::Counted,
+                counter::CounterValue::Unsigned(self.kernel_drops.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "kernel_if_drops",
+                counter::CounterType::Counted,
+                counter::CounterValue::Unsigned(self.kernel_if_drops.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "unsupported_packets",
+                counter::CounterType::Counted,
+                counter::CounterValue::Unsigned(
+                    self.unsupported_packets.swap(0, Ordering::Relaxed),
+                ),
+            ),
+            (
+                "replay_finished",
+                counter::CounterType::Gauged,
+                counter::CounterValue::Unsigned(self.replay_finished.load(Ordering::Relaxed) as u64),
+            ),
+        ]
     }
 }
 
-pub struct Libpcap;
+struct LiveSource {
+    cap: Capture<Active>,
+    if_index: isize,
+    link_type: Linktype,
+    // last pcap_stats() result as (received, dropped, if_dropped), libpcap reports accumulated values
+    last_stat: (u32, u32, u32),
+}
+
+struct ReplaySource {
+    cap: Capture<Offline>,
+    path: String,
+    link_type: Linktype,
+    pacing: ReplayPacing,
+    // (first packet timestamp in file, replay start instant, replay start wall time)
+    base: Option<(Duration, Instant, Duration)>,
+    // packet read from file but not yet due in wall clock mode, its data is in `Libpcap.buffer`
+    pending: Option<(Duration, isize)>,
+    finished: bool,
+}
+
+enum Source {
+    Live {
+        sources: Vec<LiveSource>,
+        next: usize,
+        last_stats: Instant,
+    },
+    Replay(ReplaySource),
+}
+
+/// Receive engine backed by libpcap.
+///
+/// Packets are either captured from live interfaces or replayed from a `.pcap`/`.pcapng` file.
+/// Data is copied into an internal buffer, so the returned packet is valid until the next `read`.
+pub struct Libpcap {
+    source: Source,
+    buffer: Vec<u8>,
+    counter: Arc<LibpcapCounter>,
+}
 
 impl Libpcap {
-    pub fn new(_: Vec<(&str, isize)>, _: usize, _: usize, _: &QueueDebugger) -> Result<Self> {
-        unimplemented!();
+    pub fn new(
+        src_ifaces: Vec<(&str, isize)>,
+        packet_blocks: usize,
+        snap_len: usize,
+        _: &QueueDebugger,
+    ) -> Result<Self> {
+        if src_ifaces.is_empty() {
+            return Err(Error::LibpcapError(
+                "libpcap capture must give interface to capture packet".into(),
+            ));
+        }
+        // packet_blocks is in unit of megabytes and shared by all interfaces
+        let buffer_size = (packet_blocks << 20) / src_ifaces.len();
+        let buffer_size = buffer_size.clamp(MIN_BUFFER_SIZE, i32::MAX as usize) as i32;
+        let mut sources = Vec::with_capacity(src_ifaces.len());
+        for (name, if_index) in src_ifaces {
+            let cap = Capture::from_device(name)
+                .and_then(|cap| {
+                    cap.promisc(true)
+                        .snaplen(snap_len as i32)
+                        .buffer_size(buffer_size)
+                        .timeout(POLL_TIMEOUT.as_millis() as i32)
+                        .open()
+                })
+                .and_then(|cap| cap.setnonblock())
+                .map_err(|e| Error::LibpcapError(format!("open {} failed: {}", name, e)))?;
+            let link_type = cap.get_datalink();
+            check_link_type(link_type)?;
+            sources.push(LiveSource {
+                cap,
+                if_index,
+                link_type,
+                last_stat: (0, 0, 0),
+            });
+        }
+        Ok(Self {
+            source: Source::Live {
+                sources,
+                next: 0,
+                last_stats: Instant::now(),
+            },
+            buffer: Vec::with_capacity(snap_len.max(ETH_HEADER_SIZE)),
+            counter: Default::default(),
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P, pacing: ReplayPacing) -> Result<Self> {
+        let path_str = path.as_ref().display().to_string();
+        let cap = Capture::from_file(path)
+            .map_err(|e| Error::LibpcapError(format!("open {} failed: {}", path_str, e)))?;
+        let link_type = cap.get_datalink();
+        check_link_type(link_type)?;
+        info!(
+            "libpcap replay {} with {:?} pacing, link type {:?}",
+            path_str, pacing, link_type
+        );
+        Ok(Self {
+            source: Source::Replay(ReplaySource {
+                cap,
+                path: path_str,
+                link_type,
+                pacing,
+                base: None,
+                pending: None,
+                finished: false,
+            }),
+            buffer: vec![],
+            counter: Default::default(),
+        })
     }
 
     pub unsafe fn read(&mut self) -> Result<packet::Packet> {
-        unimplemented!();
+        let (timestamp, if_index, capture_length) = match &mut self.source {
+            Source::Live {
+                sources,
+                next,
+                last_stats,
+            } => {
+                if last_stats.elapsed() >= STATS_INTERVAL {
+                    *last_stats = Instant::now();
+                    Self::update_stats(sources, &self.counter);
+                }
+                Self::read_live(sources, next, &mut self.buffer, &self.counter)?
+            }
+            Source::Replay(replay) => Self::read_replay(replay, &mut self.buffer, &self.counter)?,
+        };
+        self.counter.rx_packets.fetch_add(1, Ordering::Relaxed);
+        self.counter
+            .rx_bytes
+            .fetch_add(capture_length as u64, Ordering::Relaxed);
+        Ok(packet::Packet {
+            timestamp,
+            if_index,
+            capture_length,
+            data: &mut self.buffer[..],
+            raw: None,
+        })
     }
 
-    pub fn set_bpf(&mut self, _: &str) -> Result<()> {
-        unimplemented!();
+    fn read_live(
+        sources: &mut [LiveSource],
+        next: &mut usize,
+        buffer: &mut Vec<u8>,
+        counter: &LibpcapCounter,
+    ) -> Result<(Duration, isize, isize)> {
+        // poll interfaces in turn so that a busy interface does not starve the others
+        let len = sources.len();
+        for _ in 0..len {
+            let source = &mut sources[*next];
+            *next = (*next + 1) % len;
+            match source.cap.next() {
+                Ok(packet) => {
+                    if !copy_as_ethernet(source.link_type, packet.data, buffer) {
+                        counter.unsupported_packets.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    return Ok((
+                        timeval_to_duration(
+                            packet.header.ts.tv_sec as i64,
+                            packet.header.ts.tv_usec as i64,
+                        ),
+                        source.if_index,
+                        packet.header.len as isize,
+                    ));
+                }
+                Err(pcap::Error::TimeoutExpired) => (),
+                Err(e) => return Err(Error::LibpcapError(e.to_string())),
+            }
+        }
+        thread::sleep(IDLE_SLEEP);
+        Err(Error::Timeout)
+    }
+
+    fn read_replay(
+        replay: &mut ReplaySource,
+        buffer: &mut Vec<u8>,
+        counter: &LibpcapCounter,
+    ) -> Result<(Duration, isize, isize)> {
+        let (ts, len) = match replay.pending {
+            Some(pending) => pending,
+            None => loop {
+                if replay.finished {
+                    thread::sleep(POLL_TIMEOUT);
+                    return Err(Error::Timeout);
+                }
+                match replay.cap.next() {
+                    Ok(packet) => {
+                        if !copy_as_ethernet(replay.link_type, packet.data, buffer) {
+                            counter.unsupported_packets.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                        break (
+                            timeval_to_duration(
+                                packet.header.ts.tv_sec as i64,
+                                packet.header.ts.tv_usec as i64,
+                            ),
+                            packet.header.len as isize,
+                        );
+                    }
+                    Err(pcap::Error::NoMorePackets) => {
+                        info!("libpcap replay {} finished", replay.path);
+                        replay.finished = true;
+                        counter.replay_finished.store(true, Ordering::Relaxed);
+                    }
+                    Err(e) => return Err(Error::LibpcapError(e.to_string())),
+                }
+            },
+        };
+
+        let (first_ts, start, start_time) = *replay.base.get_or_insert_with(|| {
+            (
+                ts,
+                Instant::now(),
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default(),
+            )
+        });
+        // packets out of order in file are sent immediately
+        let offset = ts.saturating_sub(first_ts);
+        if replay.pacing == ReplayPacing::WallClock {
+            let elapsed = start.elapsed();
+            if offset > elapsed {
+                let wait = offset - elapsed;
+                if wait > POLL_TIMEOUT {
+                    // return periodically so that dispatcher can handle other events
+                    replay.pending = Some((ts, len));
+                    thread::sleep(POLL_TIMEOUT);
+                    return Err(Error::Timeout);
+                }
+                thread::sleep(wait);
+            }
+        }
+        replay.pending = None;
+        Ok((start_time + offset, 0, len))
+    }
+
+    fn update_stats(sources: &mut [LiveSource], counter: &LibpcapCounter) {
+        for source in sources.iter_mut() {
+            let Ok(stat) = source.cap.stats() else {
+                continue;
+            };
+            counter.kernel_packets.fetch_add(
+                stat.received.wrapping_sub(source.last_stat.0) as u64,
+                Ordering::Relaxed,
+            );
+            counter.kernel_drops.fetch_add(
+                stat.dropped.wrapping_sub(source.last_stat.1) as u64,
+                Ordering::Relaxed,
+            );
+            counter.kernel_if_drops.fetch_add(
+                stat.if_dropped.wrapping_sub(source.last_stat.2) as u64,
+                Ordering::Relaxed,
+            );
+            source.last_stat = (stat.received, stat.dropped, stat.if_dropped);
+        }
+    }
+
+    pub fn set_bpf(&mut self, syntax: &str) -> Result<()> {
+        match &mut self.source {
+            Source::Live { sources, .. } => {
+                for source in sources.iter_mut() {
+                    source
+                        .cap
+                        .filter(syntax, true)
+                        .map_err(|e| Error::LibpcapError(e.to_string()))?;
+                }
+            }
+            Source::Replay(replay) => replay
+                .cap
+                .filter(syntax, true)
+                .map_err(|e| Error::LibpcapError(e.to_string()))?,
+        }
+        info!("libpcap set bpf: {}", syntax);
+        Ok(())
     }
 
     pub fn get_counter_handle(&self) -> Arc<dyn counter::RefCountable> {
-        unimplemented!();
+        self.counter.clone()
+    }
+}
+
+fn check_link_type(link_type: Linktype) -> Result<()> {
+    match link_type {
+        Linktype::ETHERNET | Linktype::LINUX_SLL | Linktype::LINUX_SLL2 => Ok(()),
+        _ => Err(Error::LibpcapError(format!(
+            "unsupported link type {:?}",
+            link_type
+        ))),
     }
 }
 
+fn timeval_to_duration(sec: i64, usec: i64) -> Duration {
+    Duration::from_secs(sec.max(0) as u64) + Duration::from_micros(usec.max(0) as u64)
+}
+
+// Copy packet into buffer, converting cooked capture header to ethernet header.
+// The link layer source address is kept as source mac, destination mac is zero.
+fn copy_as_ethernet(link_type: Linktype, data: &[u8], buffer: &mut Vec<u8>) -> bool {
+    buffer.clear();
+    let (src_mac, eth_type, payload) = match link_type {
+        Linktype::ETHERNET => {
+            buffer.extend_from_slice(data);
+            return data.len() >= ETH_HEADER_SIZE;
+        }
+        Linktype::LINUX_SLL if data.len() >= SLL_HEADER_SIZE => {
+            (&data[6..12], &data[14..16], &data[SLL_HEADER_SIZE..])
+        }
+        Linktype::LINUX_SLL2 if data.len() >= SLL2_HEADER_SIZE => {
+            (&data[12..18], &data[0..2], &data[SLL2_HEADER_SIZE..])
+        }
+        _ => return false,
+    };
+    buffer.extend_from_slice(&[0; 6]);
+    buffer.extend_from_slice(src_mac);
+    buffer.extend_from_slice(eth_type);
+    buffer.extend_from_slice(payload);
+    true
+}
+
 pub struct Dpdk;
 
 impl Dpdk {
@@ -101,3 +480,66 @@ impl DpdkFromEbpf {
         unimplemented!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTTP_PCAP: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../resources/test/flow_generator/http.pcap"
+    );
+
+    fn replay_all(libpcap: &mut Libpcap) -> Vec<(Duration, usize)> {
+        let mut packets = vec![];
+        loop {
+            match unsafe { libpcap.read() } {
+                Ok(packet) => packets.push((packet.timestamp, packet.data.len())),
+                Err(Error::Timeout) => break,
+                Err(e) => panic!("replay failed: {}", e),
+            }
+        }
+        packets
+    }
+
+    #[test]
+    fn replay_file() {
+        for pacing in [ReplayPacing::AsFastAsPossible, ReplayPacing::WallClock] {
+            let mut libpcap = Libpcap::from_file(HTTP_PCAP, pacing).unwrap();
+            let packets = replay_all(&mut libpcap);
+            assert_eq!(packets.len(), 13);
+            // spacing between packets in file is kept
+            assert_eq!(packets[12].0 - packets[0].0, Duration::from_micros(1011));
+            assert!(libpcap.counter.replay_finished.load(Ordering::Relaxed));
+            assert_eq!(libpcap.counter.rx_packets.load(Ordering::Relaxed), 13);
+        }
+    }
+
+    #[test]
+    fn replay_with_bpf() {
+        let mut libpcap = Libpcap::from_file(HTTP_PCAP, ReplayPacing::AsFastAsPossible).unwrap();
+        libpcap.set_bpf("tcp port 8080").unwrap();
+        assert!(replay_all(&mut libpcap).is_empty());
+
+        let mut libpcap = Libpcap::from_file(HTTP_PCAP, ReplayPacing::AsFastAsPossible).unwrap();
+        libpcap.set_bpf("tcp port 80").unwrap();
+        assert_eq!(replay_all(&mut libpcap).len(), 13);
+    }
+
+    #[test]
+    fn sll_to_ethernet() {
+        let mut sll = vec![0, 0, 0, 1, 0, 6, 1, 2, 3, 4, 5, 6, 0, 0, 0x08, 0x00];
+        sll.extend_from_slice(&[0x45, 0x00]);
+        let mut buffer = vec![];
+        assert!(copy_as_ethernet(Linktype::LINUX_SLL, &sll, &mut buffer));
+        assert_eq!(
+            buffer,
+            vec![0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 0x08, 0x00, 0x45, 0x00]
+        );
+        assert!(!copy_as_ethernet(
+            Linktype::LINUX_SLL,
+            &sll[..10],
+            &mut buffer
+        ));
+    }
+}
@@ -14,35 +14,278 @@
  * limitations under the License.
  */
 
-pub fn decapsulate_gpe_vxlan(_packet: &mut [u8], _l2_len: usize) -> Option<(usize, u32)> {
-    None
+// All functions return the offset of the overlay L2 header relative to the
+// underlay L3 header and the tunnel id. When the overlay packet has no L2
+// header, a pseudo one is written right before the overlay L3 header by
+// copying the underlay L2 header, so the returned offset is always relative
+// to the L3 header and the caller can parse the packet from the L2 header.
+
+use public::{bytes, consts::*, enums::EthernetType};
+
+const ERSPAN_ID_MASK: u32 = 0x3ff;
+const ERSPAN_III_FLAGS_OPTIONAL_SUBHEADER_MASK: u8 = 0x1;
+
+fn gre_option_size(flags: u16) -> usize {
+    let mut size = 0;
+    if flags & GRE_FLAGS_KEY_MASK != 0 {
+        size += GRE_KEY_LEN;
+    }
+    if flags & GRE_FLAGS_SEQ_MASK != 0 {
+        size += GRE_SEQ_LEN;
+    }
+    if flags & GRE_FLAGS_CSUM_MASK != 0 {
+        size += GRE_CSUM_LEN;
+    }
+    size
+}
+
+// GRE key is located after checksum and reserved fields if present
+fn gre_key_offset(flags: u16) -> usize {
+    if flags & GRE_FLAGS_CSUM_MASK != 0 {
+        GRE_KEY_OFFSET + GRE_CSUM_LEN
+    } else {
+        GRE_KEY_OFFSET
+    }
+}
+
+// Write the underlay L2 header right before the overlay L3 header at
+// `overlay_l3_offset` (relative to L3) and return the offset of it
+fn write_pseudo_l2_header(
+    packet: &mut [u8],
+    l2_len: usize,
+    overlay_l3_offset: usize,
+    eth_type: EthernetType,
+) -> Option<usize> {
+    // the pseudo header must not overlap the underlay l2 header
+    if overlay_l3_offset < l2_len || l2_len + overlay_l3_offset > packet.len() {
+        return None;
+    }
+    let start = overlay_l3_offset;
+    packet.copy_within(0..l2_len, start);
+    bytes::write_u16_be(
+        &mut packet[start + l2_len - ETH_TYPE_LEN..],
+        eth_type.into(),
+    );
+    Some(overlay_l3_offset - l2_len)
+}
+
+// VXLAN-GPE (UDP 4790) carrying NSH, IPv4, IPv6 or Ethernet
+pub fn decapsulate_gpe_vxlan(packet: &mut [u8], l2_len: usize) -> Option<(usize, u32)> {
+    let l3_packet = packet.get(l2_len..)?;
+    let ip_header_size = (*l3_packet.first()? as usize & 0xf) << 2;
+    let gpe_offset = ip_header_size + UDP_HEADER_SIZE;
+    let gpe = l3_packet.get(gpe_offset..gpe_offset + GPE_VXLAN_HEADER_SIZE)?;
+
+    let flags = gpe[GPE_VXLAN_FLAGS_OFFSET];
+    if flags & GPE_VXLAN_FLAGS_VERSION_MASK != 0 || flags & GPE_VXLAN_FLAGS_PROTOCOL_MASK == 0 {
+        return None;
+    }
+    let id = if flags & GPE_VXLAN_FLAGS_INSTANCE_MASK != 0 {
+        bytes::read_u32_be(&gpe[GPE_VXLAN_VNI_OFFSET..]) >> 8
+    } else {
+        0
+    };
+
+    let mut offset = gpe_offset + GPE_VXLAN_HEADER_SIZE;
+    let next_protocol = match gpe[GPE_VXLAN_NEXT_PROTOCOL_OFFSET] {
+        GPE_VXLAN_NEXT_PROTOCOL_IPV4 => NSH_NEXT_PROTOCOL_IPV4,
+        GPE_VXLAN_NEXT_PROTOCOL_IPV6 => NSH_NEXT_PROTOCOL_IPV6,
+        GPE_VXLAN_NEXT_PROTOCOL_ETHERNET => NSH_NEXT_PROTOCOL_ETHERNET,
+        GPE_VXLAN_NEXT_PROTOCOL_NSH => {
+            let nsh = l3_packet.get(offset..offset + NSH_HEADER_SIZE)?;
+            let nsh_flags = bytes::read_u16_be(&nsh[NSH_FLAGS_OFFSET..]);
+            if nsh_flags & NSH_FLAGS_BITS_MASK != 0 {
+                return None;
+            }
+            // length in 4-byte words including context headers
+            let nsh_size = ((nsh_flags & NSH_FLAGS_LENGTH_MASK) as usize) << 2;
+            if nsh_size < NSH_HEADER_SIZE {
+                return None;
+            }
+            offset += nsh_size;
+            nsh[NSH_NEXT_PROTOCOL_OFFSET]
+        }
+        _ => return None,
+    };
+
+    let eth_type = match next_protocol {
+        NSH_NEXT_PROTOCOL_IPV4 => EthernetType::IPV4,
+        NSH_NEXT_PROTOCOL_IPV6 => EthernetType::IPV6,
+        NSH_NEXT_PROTOCOL_ETHERNET => {
+            if l3_packet.len() < offset + ETH_HEADER_SIZE {
+                return None;
+            }
+            return Some((offset, id));
+        }
+        _ => return None,
+    };
+    write_pseudo_l2_header(packet, l2_len, offset, eth_type).map(|o| (o, id))
 }
 
+// ERSPAN type I (GRE without sequence number), II and III, the session id is
+// used as tunnel id
 pub fn decapsulate_erspan(
-    _packet: &[u8],
-    _l2_len: usize,
-    _flags: u16,
-    _gre_protocol_type: u16,
-    _ip_header_size: usize,
+    packet: &[u8],
+    l2_len: usize,
+    flags: u16,
+    gre_protocol_type: u16,
+    ip_header_size: usize,
 ) -> Option<(usize, u32)> {
-    None
+    let l3_packet = packet.get(l2_len..)?;
+    let erspan_offset = ip_header_size + GRE_HEADER_SIZE_DECAP + gre_option_size(flags);
+    let (erspan_header_size, id) = match gre_protocol_type {
+        LE_ERSPAN_PROTO_TYPE_II if flags & GRE_FLAGS_SEQ_MASK == 0 => (ERSPAN_I_HEADER_SIZE, 0),
+        LE_ERSPAN_PROTO_TYPE_II => {
+            let header = l3_packet.get(erspan_offset..erspan_offset + ERSPAN_II_HEADER_SIZE)?;
+            (
+                ERSPAN_II_HEADER_SIZE,
+                bytes::read_u32_be(&header[ERSPAN_ID_OFFSET..]) & ERSPAN_ID_MASK,
+            )
+        }
+        LE_ERSPAN_PROTO_TYPE_III => {
+            let header = l3_packet.get(erspan_offset..erspan_offset + ERSPAN_III_HEADER_SIZE)?;
+            let id = bytes::read_u32_be(&header[ERSPAN_ID_OFFSET..]) & ERSPAN_ID_MASK;
+            if header[ERSPAN_III_FLAGS_OFFSET] & ERSPAN_III_FLAGS_OPTIONAL_SUBHEADER_MASK != 0 {
+                (ERSPAN_III_HEADER_SIZE + ERSPAN_III_SUBHEADER_SIZE, id)
+            } else {
+                (ERSPAN_III_HEADER_SIZE, id)
+            }
+        }
+        _ => return None,
+    };
+
+    let offset = erspan_offset + erspan_header_size;
+    if l3_packet.len() < offset + ETH_HEADER_SIZE {
+        return None;
+    }
+    Some((offset, id))
 }
 
+// GRE carrying Ethernet (0x6558), the key is used as tunnel id if present
 pub fn decapsulate_teb(
-    _packet: &[u8],
-    _l2_len: usize,
-    _flags: u16,
-    _ip_header_size: usize,
+    packet: &[u8],
+    l2_len: usize,
+    flags: u16,
+    ip_header_size: usize,
 ) -> Option<(usize, u32)> {
-    None
+    let l3_packet = packet.get(l2_len..)?;
+    if flags & GRE_FLAGS_VER_MASK != 0 {
+        return None;
+    }
+    let offset = ip_header_size + GRE_HEADER_SIZE_DECAP + gre_option_size(flags);
+    if l3_packet.len() < offset + ETH_HEADER_SIZE {
+        return None;
+    }
+    let id = if flags & GRE_FLAGS_KEY_MASK != 0 {
+        bytes::read_u32_be(&l3_packet[ip_header_size + gre_key_offset(flags)..])
+    } else {
+        0
+    };
+    Some((offset, id))
 }
 
+// Tencent TCE uses GRE with key carrying IPv4 or IPv6 directly. IP addresses
+// of different VPCs may conflict, so the pseudo L2 header uses 00:00 followed
+// by the key as MAC addresses to tell VPCs apart.
 pub fn decapsulate_tencent_gre(
-    _packet: &mut [u8],
-    _l2_len: usize,
-    _flags: u16,
-    _gre_protocol_type: u16,
-    _ip_header_size: usize,
+    packet: &mut [u8],
+    l2_len: usize,
+    flags: u16,
+    gre_protocol_type: u16,
+    ip_header_size: usize,
 ) -> Option<(usize, u32)> {
-    None
+    if flags & GRE_FLAGS_VER_MASK != 0 || flags & GRE_FLAGS_KEY_MASK == 0 {
+        return None;
+    }
+    let eth_type = match gre_protocol_type {
+        LE_IPV4_PROTO_TYPE_I => EthernetType::IPV4,
+        LE_IPV6_PROTO_TYPE_I => EthernetType::IPV6,
+        _ => return None,
+    };
+    let l3_packet = packet.get(l2_len..)?;
+    let key_offset = ip_header_size + gre_key_offset(flags);
+    let id = bytes::read_u32_be(l3_packet.get(key_offset..key_offset + GRE_KEY_LEN)?);
+
+    let overlay_l3_offset = ip_header_size + GRE_HEADER_SIZE_DECAP + gre_option_size(flags);
+    let offset = write_pseudo_l2_header(packet, l2_len, overlay_l3_offset, eth_type)?;
+    let start = l2_len + offset;
+    for mac_offset in [FIELD_OFFSET_DA, FIELD_OFFSET_SA] {
+        let mac = &mut packet[start + mac_offset..start + mac_offset + MAC_ADDR_LEN];
+        mac[..2].fill(0);
+        bytes::write_u32_be(&mut mac[2..], id);
+    }
+    Some((offset, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4_header(protocol: u8) -> Vec<u8> {
+        let mut h = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, protocol, 0, 0];
+        h.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        h
+    }
+
+    fn underlay(eth_type: u16) -> Vec<u8> {
+        let mut p = vec![0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01];
+        p.extend_from_slice(&eth_type.to_be_bytes());
+        p
+    }
+
+    #[test]
+    fn gpe_vxlan_ipv4() {
+        let mut packet = underlay(0x0800);
+        packet.extend(ipv4_header(17));
+        packet.extend_from_slice(&[0x12, 0xb6, 0x12, 0xb6, 0, 0, 0, 0]);
+        packet.extend_from_slice(&[0x0c, 0, 0, GPE_VXLAN_NEXT_PROTOCOL_IPV4, 0, 0, 0x7b, 0]);
+        packet.extend(ipv4_header(6));
+
+        let (offset, id) = decapsulate_gpe_vxlan(&mut packet, 14).unwrap();
+        assert_eq!(id, 123);
+        assert_eq!(offset, 20 + 8 + 8 - 14);
+        let inner = &packet[14 + offset..];
+        assert_eq!(&inner[..12], &underlay(0)[..12]);
+        assert_eq!(&inner[12..14], &[0x08, 0x00]);
+        assert_eq!(inner[14], 0x45);
+    }
+
+    #[test]
+    fn erspan_iii_subheader() {
+        let mut packet = underlay(0x0800);
+        packet.extend(ipv4_header(47));
+        packet.extend_from_slice(&[0x10, 0x00, 0x22, 0xeb, 0, 0, 0, 1]);
+        packet.extend_from_slice(&[0x20, 0, 0x03, 0xff, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+        packet.extend_from_slice(&[0; 8]);
+        packet.extend(underlay(0x0800));
+
+        let flags = bytes::read_u16_be(&packet[34..]);
+        assert_eq!(
+            decapsulate_erspan(&packet, 14, flags, LE_ERSPAN_PROTO_TYPE_III, 20),
+            Some((20 + 8 + 12 + 8, 0x3ff))
+        );
+        assert_eq!(
+            decapsulate_erspan(&packet[..60], 14, flags, LE_ERSPAN_PROTO_TYPE_III, 20),
+            None
+        );
+    }
+
+    #[test]
+    fn tencent_gre() {
+        let mut packet = underlay(0x0800);
+        packet.extend(ipv4_header(47));
+        packet.extend_from_slice(&[0x20, 0x00, 0x08, 0x00, 0x00, 0x01, 0x02, 0x03]);
+        packet.extend(ipv4_header(6));
+
+        let flags = bytes::read_u16_be(&packet[34..]);
+        let (offset, id) =
+            decapsulate_tencent_gre(&mut packet, 14, flags, LE_IPV4_PROTO_TYPE_I, 20).unwrap();
+        assert_eq!(id, 0x010203);
+        assert_eq!(offset, 20 + 8 - 14);
+        let inner = &packet[14 + offset..];
+        assert_eq!(&inner[..6], &[0, 0, 0, 0x01, 0x02, 0x03]);
+        assert_eq!(&inner[6..12], &[0, 0, 0, 0x01, 0x02, 0x03]);
+        assert_eq!(&inner[12..14], &[0x08, 0x00]);
+        assert_eq!(inner[14], 0x45);
+    }
 }
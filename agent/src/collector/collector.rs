@@ -523,7 +523,7 @@ impl Stash {
                 let flow_meter = if ep == FLOW_METRICS_PEER_DST {
                     acc_flow.flow_meter.to_reversed()
                 } else {
-                    acc_flow.flow_meter
+                    acc_flow.flow_meter.clone()
                 };
                 let tagger = get_single_tagger(
                     self.global_thread_id,
@@ -555,7 +555,7 @@ impl Stash {
             );
             // edge_stats: If the direction of a certain end is known, the statistical data
             // will be recorded with the direction (corresponding tap-side), up to two times
-            self.fill_edge_l4_stats(tagger, acc_flow.flow_meter);
+            self.fill_edge_l4_stats(tagger, acc_flow.flow_meter.clone());
         }
         // edge_stats: If both ends of direction are None, record the
         // statistical data with direction=0 (corresponding tap-side=rest)
@@ -579,7 +579,7 @@ impl Stash {
                 L7Protocol::Unknown,
                 self.context.agent_mode,
             );
-            self.fill_edge_l4_stats(tagger, acc_flow.flow_meter);
+            self.fill_edge_l4_stats(tagger, acc_flow.flow_meter.clone());
         }
     }
 
@@ -724,7 +724,7 @@ impl Stash {
                     self.context.agent_mode,
                 );
                 tagger.code |= Code::L7_PROTOCOL;
-                self.fill_single_l7_stats(tagger, meter.endpoint_hash, meter.app_meter);
+                self.fill_single_l7_stats(tagger, meter.endpoint_hash, meter.app_meter.clone());
             }
             let mut tagger = get_edge_tagger(
                 self.global_thread_id,
@@ -742,7 +742,7 @@ impl Stash {
             tagger.code |= Code::L7_PROTOCOL;
             // edge_stats: If the direction of a certain end is known, the statistical data
             // will be recorded with the direction (corresponding tap-side), up to two times
-            self.fill_edge_l7_stats(tagger, meter.endpoint_hash, meter.app_meter);
+            self.fill_edge_l7_stats(tagger, meter.endpoint_hash, meter.app_meter.clone());
         }
         // edge_stats: If both ends of direction are None, record the
         // statistical data with direction=0 (corresponding tap-side=rest)
@@ -767,7 +767,7 @@ impl Stash {
                 self.context.agent_mode,
             );
             tagger.code |= Code::L7_PROTOCOL;
-            self.fill_edge_l7_stats(tagger, meter.endpoint_hash, meter.app_meter);
+            self.fill_edge_l7_stats(tagger, meter.endpoint_hash, meter.app_meter.clone());
         }
     }
 
@@ -287,11 +287,11 @@ impl SubQuadGen {
                     meter.app_meter.sequential_merge(app_meter);
                 } else if meter.l7_protocol == L7Protocol::Unknown {
                     meter.l7_protocol = l7_stats.l7_protocol;
-                    meter.app_meter = *app_meter;
+                    meter.app_meter = app_meter.clone();
                 }
             } else {
                 let meter = AppMeterWithL7Protocol {
-                    app_meter: *app_meter,
+                    app_meter: app_meter.clone(),
                     l7_protocol: l7_stats.l7_protocol,
                     endpoint: l7_stats.endpoint.clone(),
                     endpoint_hash,
@@ -349,7 +349,7 @@ impl SubQuadGen {
                 let (is_active_host0, is_active_host1) =
                     check_active(time_in_second.as_secs(), possible_host, &flow);
                 let boxed_app_meter = Box::new(AppMeterWithFlow {
-                    app_meter: *app_meter,
+                    app_meter: app_meter.clone(),
                     flow,
                     l7_protocol: l7_stats.l7_protocol,
                     endpoint_hash,
@@ -371,7 +371,7 @@ impl SubQuadGen {
                 }
             } else {
                 let meter = AppMeterWithL7Protocol {
-                    app_meter: *app_meter,
+                    app_meter: app_meter.clone(),
                     l7_protocol: l7_stats.l7_protocol,
                     endpoint: l7_stats.endpoint.clone(),
                     endpoint_hash,
@@ -707,6 +707,7 @@ impl L7QuadrupleGenerator {
                     rrt_max: stats.rrt_max,
                     rrt_sum: stats.rrt_sum as u64,
                     rrt_count: stats.rrt_count,
+                    rrt_histogram: stats.rrt_histogram.clone(),
                 },
                 anomaly: AppAnomaly {
                     client_error: stats.err_client_count,
@@ -42,7 +42,7 @@ use crate::common::{
     tagged_flow::TaggedFlow,
 };
 use crate::config::handler::{CollectorAccess, CollectorConfig};
-use crate::metric::meter::{FlowMeter, Latency, Performance, Traffic};
+use crate::metric::meter::{FlowMeter, Latency, LatencyHistogram, Performance, Traffic};
 use crate::platform::process_info_enabled;
 use crate::rpc::get_timestamp;
 use crate::utils::{
@@ -591,7 +591,7 @@ impl SubQuadGen {
                 is_active_host0: true,
                 is_active_host1: true,
                 id_maps: id_maps.clone(),
-                flow_meter: *flow_meter,
+                flow_meter: flow_meter.clone(),
                 time_in_second: time_in_second.into(),
                 key: key.clone(),
             };
@@ -1034,6 +1034,10 @@ impl QuadrupleGenerator {
                 || tagged_flow.flow.flow_key.proto == IpProtocol::ICMPV4
                 || tagged_flow.flow.flow_key.proto == IpProtocol::ICMPV6
             {
+                let mut rtt_histogram = LatencyHistogram::default();
+                if stats.tcp.rtt > 0 {
+                    rtt_histogram.record(stats.tcp.rtt);
+                }
                 flow_meter.latency = Latency {
                     rtt_max: stats.tcp.rtt,
                     rtt_client_max: stats.tcp.rtt_client_max,
@@ -1058,6 +1062,11 @@ impl QuadrupleGenerator {
                     art_count: stats.tcp.art_count,
                     rrt_count: 0,
                     cit_count: stats.tcp.cit_count,
+
+                    rtt_histogram,
+                    srt_histogram: stats.tcp_histograms.srt.clone(),
+                    art_histogram: stats.tcp_histograms.art.clone(),
+                    cit_histogram: stats.tcp_histograms.cit.clone(),
                     ..Default::default()
                 };
 
@@ -1070,11 +1079,26 @@ impl QuadrupleGenerator {
                     zero_win_rx: dst_perf.zero_win_count as u64,
                     retrans_syn: stats.tcp.retrans_syn_count,
                     retrans_synack: stats.tcp.retrans_synack_count,
+                    sack_tx: src_perf.sack_count as u64,
+                    sack_rx: dst_perf.sack_count as u64,
+                    dsack_tx: src_perf.dsack_count as u64,
+                    dsack_rx: dst_perf.dsack_count as u64,
+                    out_of_order_tx: src_perf.out_of_order_count as u64,
+                    out_of_order_rx: dst_perf.out_of_order_count as u64,
+                    lost_tx: src_perf.lost_count as u64,
+                    lost_rx: dst_perf.lost_count as u64,
+                    fast_retrans_tx: src_perf.fast_retrans_count as u64,
+                    fast_retrans_rx: dst_perf.fast_retrans_count as u64,
+                    rto_tx: src_perf.rto_count as u64,
+                    rto_rx: dst_perf.rto_count as u64,
+                    bytes_in_flight_max_tx: src_perf.bytes_in_flight_max,
+                    bytes_in_flight_max_rx: dst_perf.bytes_in_flight_max,
                 };
             } else {
                 flow_meter.latency.art_max = stats.tcp.art_max;
                 flow_meter.latency.art_sum = stats.tcp.art_sum as u64;
                 flow_meter.latency.art_count = stats.tcp.art_max;
+                flow_meter.latency.art_histogram = stats.tcp_histograms.art.clone();
             }
 
             if !config.l7_metrics_enabled {
@@ -1099,6 +1123,8 @@ impl QuadrupleGenerator {
                 flow_meter.latency.rrt_max = l7_perf_stats.rrt_max;
                 flow_meter.latency.rrt_sum = l7_perf_stats.rrt_sum;
                 flow_meter.latency.rrt_count = l7_perf_stats.rrt_count;
+                flow_meter.latency.rrt_histogram = l7_perf_stats.rrt_histogram.clone();
+                flow_meter.latency.tls_rtt_histogram = l7_perf_stats.tls_rtt_histogram.clone();
                 flow_meter.anomaly.l7_client_error = l7_perf_stats.err_client_count;
                 flow_meter.anomaly.l7_server_error = l7_perf_stats.err_server_count;
                 flow_meter.anomaly.l7_timeout = l7_perf_stats.err_timeout;
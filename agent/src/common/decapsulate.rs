@@ -385,6 +385,9 @@ impl TunnelInfo {
         gre_protocol_type: u16,
         ip_header_size: usize,
     ) -> usize {
+        // the pseudo L2 header of overlay packet overwrites underlay IP addresses
+        let mut underlay = *self;
+        underlay.decapsulate_addr(&packet[l2_len..]);
         let Some((offset, id)) =
             decapsulate_tencent_gre(packet, l2_len, flags, gre_protocol_type, ip_header_size)
         else {
@@ -392,7 +395,8 @@ impl TunnelInfo {
         };
 
         if self.tier == 0 {
-            self.decapsulate_addr(&packet[l2_len..]);
+            self.src = underlay.src;
+            self.dst = underlay.dst;
             self.decapsulate_mac(packet);
             self.tunnel_type = TunnelType::TencentGre;
             self.id = id;
@@ -949,6 +953,44 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_decapsulate_tencent_gre() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::TencentGre]);
+        let expected = TunnelInfo {
+            src: Ipv4Addr::new(10, 1, 1, 1),
+            dst: Ipv4Addr::new(10, 1, 1, 2),
+            mac_src: 0x00d4e5f6,
+            mac_dst: 0x00a1b2c3,
+            id: 0x10203,
+            tunnel_type: TunnelType::TencentGre,
+            tier: 1,
+            is_ipv6: false,
+            from: 0,
+        };
+        let mut packets: Vec<Vec<u8>> =
+            Capture::load_pcap(Path::new(PCAP_PATH_PREFIX).join("tencent-gre.pcap")).into();
+        let packet = packets[0].as_mut_slice();
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let offset = actual.decapsulate(packet, l2_len, &bitmap);
+        let expected_offset = IPV4_HEADER_SIZE + GRE_HEADER_SIZE_DECAP + GRE_KEY_LEN - l2_len;
+
+        assert_eq!(offset, expected_offset);
+        assert_eq!(actual, expected);
+
+        let overlay = &packet[l2_len + offset..];
+        assert_eq!(
+            &overlay[FIELD_OFFSET_DA..][..MAC_ADDR_LEN],
+            &[0, 0, 0, 1, 2, 3]
+        );
+        assert_eq!(
+            &overlay[FIELD_OFFSET_SA..][..MAC_ADDR_LEN],
+            &[0, 0, 0, 1, 2, 3]
+        );
+        assert_eq!(&overlay[FIELD_OFFSET_SIP..][..4], &[192, 168, 0, 1]);
+    }
+
     #[test]
     fn test_decapsulate_ipv6_vxlan() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Vxlan]);
@@ -36,7 +36,7 @@ use super::{
 
 use crate::{
     common::{endpoint::EPC_INTERNET, timestamp_to_micros, Timestamp},
-    metric::document::Direction,
+    metric::{document::Direction, meter::LatencyHistogram},
 };
 use crate::{
     flow_generator::protocol_logs::to_string_format,
@@ -321,12 +321,35 @@ impl From<TunnelField> for flow_log::TunnelField {
 pub struct TcpPerfCountsPeer {
     pub retrans_count: u32,
     pub zero_win_count: u32,
+    pub sack_count: u32,          // SACK blocks sent
+    pub dsack_count: u32,         // D-SACK blocks sent, i.e. spurious retransmissions of the peer
+    pub out_of_order_count: u32,  // sequence gaps filled before any loss signal, i.e. reordering
+    pub lost_count: u32,          // sequence gaps filled after a loss signal or never filled
+    pub rto_count: u32,           // retransmissions without loss signals, at least RTO_MIN later
+    pub fast_retrans_count: u32,  // retransmissions after 3 duplicate ACKs or SACK of later data
+    pub bytes_in_flight_max: u32, // bytes sent and not acknowledged yet
+
+    // advertised in the SYN or SYN/ACK, reported in every period of the flow
+    pub win_size: u16,
+    pub mss: u16,
+    pub win_scale: u8,
 }
 
 impl TcpPerfCountsPeer {
     pub fn sequential_merge(&mut self, other: &TcpPerfCountsPeer) {
         self.retrans_count += other.retrans_count;
         self.zero_win_count += other.zero_win_count;
+        self.sack_count += other.sack_count;
+        self.dsack_count += other.dsack_count;
+        self.out_of_order_count += other.out_of_order_count;
+        self.lost_count += other.lost_count;
+        self.rto_count += other.rto_count;
+        self.fast_retrans_count += other.fast_retrans_count;
+        self.bytes_in_flight_max = self.bytes_in_flight_max.max(other.bytes_in_flight_max);
+
+        self.win_size = self.win_size.max(other.win_size);
+        self.mss = self.mss.max(other.mss);
+        self.win_scale = self.win_scale.max(other.win_scale);
     }
 }
 
@@ -335,6 +358,16 @@ impl From<TcpPerfCountsPeer> for flow_log::TcpPerfCountsPeer {
         flow_log::TcpPerfCountsPeer {
             retrans_count: p.retrans_count,
             zero_win_count: p.zero_win_count,
+            sack_count: p.sack_count,
+            dsack_count: p.dsack_count,
+            out_of_order_count: p.out_of_order_count,
+            lost_count: p.lost_count,
+            rto_count: p.rto_count,
+            fast_retrans_count: p.fast_retrans_count,
+            bytes_in_flight_max: p.bytes_in_flight_max,
+            win_size: p.win_size as u32,
+            mss: p.mss as u32,
+            win_scale: p.win_scale as u32,
         }
     }
 }
@@ -389,12 +422,52 @@ where
         pub retrans_rx: u32,
         pub zero_win_tx: u32,
         pub zero_win_rx: u32,
+        pub sack_tx: u32,
+        pub sack_rx: u32,
+        pub dsack_tx: u32,
+        pub dsack_rx: u32,
+        pub out_of_order_tx: u32,
+        pub out_of_order_rx: u32,
+        pub lost_tx: u32,
+        pub lost_rx: u32,
+        pub rto_tx: u32,
+        pub rto_rx: u32,
+        pub fast_retrans_tx: u32,
+        pub fast_retrans_rx: u32,
+        pub bytes_in_flight_max_tx: u32,
+        pub bytes_in_flight_max_rx: u32,
+        pub win_size_tx: u16,
+        pub win_size_rx: u16,
+        pub mss_tx: u16,
+        pub mss_rx: u16,
+        pub win_scale_tx: u8,
+        pub win_scale_rx: u8,
     }
     let s = Ser {
         retrans_tx: v[0].retrans_count,
         retrans_rx: v[1].retrans_count,
         zero_win_tx: v[0].zero_win_count,
         zero_win_rx: v[1].zero_win_count,
+        sack_tx: v[0].sack_count,
+        sack_rx: v[1].sack_count,
+        dsack_tx: v[0].dsack_count,
+        dsack_rx: v[1].dsack_count,
+        out_of_order_tx: v[0].out_of_order_count,
+        out_of_order_rx: v[1].out_of_order_count,
+        lost_tx: v[0].lost_count,
+        lost_rx: v[1].lost_count,
+        rto_tx: v[0].rto_count,
+        rto_rx: v[1].rto_count,
+        fast_retrans_tx: v[0].fast_retrans_count,
+        fast_retrans_rx: v[1].fast_retrans_count,
+        bytes_in_flight_max_tx: v[0].bytes_in_flight_max,
+        bytes_in_flight_max_rx: v[1].bytes_in_flight_max,
+        win_size_tx: v[0].win_size,
+        win_size_rx: v[1].win_size,
+        mss_tx: v[0].mss,
+        mss_rx: v[1].mss,
+        win_scale_tx: v[0].win_scale,
+        win_scale_rx: v[1].win_scale,
     };
     serializer.serialize_newtype_struct("tcp_perf_counts", &s)
 }
@@ -472,17 +545,134 @@ impl From<TcpPerfStats> for flow_log::TcpPerfStats {
     }
 }
 
-#[derive(Serialize, Debug, Default, Clone)]
+// Latency distributions of `TcpPerfStats`, only aggregated into metrics and not written to flow logs
+#[derive(Debug, Default, Clone)]
+pub struct TcpLatencyHistograms {
+    pub srt: LatencyHistogram,
+    pub art: LatencyHistogram, // UDP复用
+    pub cit: LatencyHistogram,
+}
+
+impl TcpLatencyHistograms {
+    pub fn sequential_merge(&mut self, other: &TcpLatencyHistograms) {
+        self.srt.merge(&other.srt);
+        self.art.merge(&other.art);
+        self.cit.merge(&other.cit);
+    }
+}
+
+fn serialize_connection_id<S>(v: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&hex::encode(v))
+}
+
+// Parsed from the long header packets of a QUIC connection. The connection IDs chosen by the
+// client and the server identify the connection across NAT rebinding and connection migration.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct QuicPerfStats {
+    #[serde(rename = "quic_version")]
+    pub version: u32,
+    #[serde(rename = "quic_client_cid", serialize_with = "serialize_connection_id")]
+    pub client_cid: Vec<u8>,
+    #[serde(rename = "quic_server_cid", serialize_with = "serialize_connection_id")]
+    pub server_cid: Vec<u8>,
+    // us, from the first Initial of the client to the first Handshake of the server
+    #[serde(rename = "quic_handshake_rtt")]
+    pub handshake_rtt: u32,
+    // the last CONNECTION_CLOSE frame in the period, 0x1c for transport and 0x1d for
+    // application errors, 0 if there is none
+    #[serde(rename = "quic_close_frame_type")]
+    pub close_frame_type: u8,
+    #[serde(rename = "quic_close_error_code")]
+    pub close_error_code: u64,
+}
+
+impl QuicPerfStats {
+    pub fn sequential_merge(&mut self, other: &QuicPerfStats) {
+        if other.version != 0 {
+            self.version = other.version;
+        }
+        if !other.client_cid.is_empty() {
+            self.client_cid = other.client_cid.clone();
+        }
+        if !other.server_cid.is_empty() {
+            self.server_cid = other.server_cid.clone();
+        }
+        self.handshake_rtt = self.handshake_rtt.max(other.handshake_rtt);
+        if other.close_frame_type != 0 {
+            self.close_frame_type = other.close_frame_type;
+            self.close_error_code = other.close_error_code;
+        }
+    }
+
+    // version and connection IDs are kept and reported in every period of the connection
+    pub fn reset(&mut self) {
+        self.handshake_rtt = 0;
+        self.close_frame_type = 0;
+        self.close_error_code = 0;
+    }
+}
+
+impl From<QuicPerfStats> for flow_log::QuicPerfStats {
+    fn from(p: QuicPerfStats) -> Self {
+        flow_log::QuicPerfStats {
+            version: p.version,
+            client_cid: p.client_cid,
+            server_cid: p.server_cid,
+            handshake_rtt: p.handshake_rtt,
+            close_frame_type: p.close_frame_type as u32,
+            close_error_code: p.close_error_code,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct FlowPerfStats {
-    #[serde(flatten)]
     pub tcp: TcpPerfStats,
-    #[serde(flatten)]
+    pub tcp_histograms: TcpLatencyHistograms,
     pub l7: L7PerfStats,
+    pub quic: QuicPerfStats,
     pub l4_protocol: L4Protocol,
     pub l7_protocol: L7Protocol,
     pub l7_failed_count: u32,
 }
 
+// quic_* fields are only serialized for QUIC flows, same as the protobuf message
+impl Serialize for FlowPerfStats {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Ser<'a> {
+            #[serde(flatten)]
+            tcp: &'a TcpPerfStats,
+            #[serde(flatten)]
+            l7: &'a L7PerfStats,
+            #[serde(flatten)]
+            quic: Option<&'a QuicPerfStats>,
+            l4_protocol: L4Protocol,
+            l7_protocol: L7Protocol,
+            l7_failed_count: u32,
+        }
+        Ser {
+            tcp: &self.tcp,
+            l7: &self.l7,
+            quic: if self.l7_protocol == L7Protocol::QUIC {
+                Some(&self.quic)
+            } else {
+                None
+            },
+            l4_protocol: self.l4_protocol,
+            l7_protocol: self.l7_protocol,
+            l7_failed_count: self.l7_failed_count,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl FlowPerfStats {
     pub fn sequential_merge(&mut self, other: &FlowPerfStats) {
         if self.l4_protocol == L4Protocol::Unknown {
@@ -496,7 +686,9 @@ impl FlowPerfStats {
         self.l7_failed_count = self.l7_failed_count.max(other.l7_failed_count);
 
         self.tcp.sequential_merge(&other.tcp);
+        self.tcp_histograms.sequential_merge(&other.tcp_histograms);
         self.l7.sequential_merge(&other.l7);
+        self.quic.sequential_merge(&other.quic);
     }
 
     pub fn reverse(&mut self) {
@@ -527,6 +719,11 @@ impl From<FlowPerfStats> for flow_log::FlowPerfStats {
         flow_log::FlowPerfStats {
             tcp: Some(p.tcp.into()),
             l7: Some(p.l7.into()),
+            quic: if p.l7_protocol == L7Protocol::QUIC {
+                Some(p.quic.into())
+            } else {
+                None
+            },
             l4_protocol: p.l4_protocol as u32,
             l7_protocol: p.l7_protocol as u32,
             l7_failed_count: p.l7_failed_count,
@@ -548,7 +745,7 @@ pub struct L7Stats {
     pub biz_type: u8,
 }
 
-#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[derive(Serialize, Debug, Default, Clone)]
 pub struct L7PerfStats {
     #[serde(rename = "l7_request")]
     pub request_count: u32,
@@ -564,8 +761,29 @@ pub struct L7PerfStats {
     pub rrt_sum: u64,   // us RRT(Request Response Time)
     pub rrt_max: u32,   // us agent保证在3600s以内
     pub tls_rtt: u32,
+    #[serde(skip)]
+    pub rrt_histogram: LatencyHistogram,
+    #[serde(skip)]
+    pub tls_rtt_histogram: LatencyHistogram,
 }
 
+// Histograms are left out, they only break down the samples already summarized by the other fields
+impl PartialEq for L7PerfStats {
+    fn eq(&self, other: &Self) -> bool {
+        self.request_count == other.request_count
+            && self.response_count == other.response_count
+            && self.err_client_count == other.err_client_count
+            && self.err_server_count == other.err_server_count
+            && self.err_timeout == other.err_timeout
+            && self.rrt_count == other.rrt_count
+            && self.rrt_sum == other.rrt_sum
+            && self.rrt_max == other.rrt_max
+            && self.tls_rtt == other.tls_rtt
+    }
+}
+
+impl Eq for L7PerfStats {}
+
 impl L7PerfStats {
     pub fn sequential_merge(&mut self, other: &L7PerfStats) {
         self.request_count += other.request_count;
@@ -579,6 +797,8 @@ impl L7PerfStats {
             self.rrt_max = other.rrt_max
         }
         self.tls_rtt += other.tls_rtt;
+        self.rrt_histogram.merge(&other.rrt_histogram);
+        self.tls_rtt_histogram.merge(&other.tls_rtt_histogram);
     }
 
     pub fn merge_perf(
@@ -599,9 +819,11 @@ impl L7PerfStats {
             self.rrt_max = self.rrt_max.max(rrt as u32);
             self.rrt_sum += rrt;
             self.rrt_count += 1;
+            self.rrt_histogram.record(rrt as u32);
         }
         if tls_rtt != 0 {
             self.tls_rtt += tls_rtt as u32;
+            self.tls_rtt_histogram.record(tls_rtt as u32);
         }
     }
 
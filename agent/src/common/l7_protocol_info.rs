@@ -83,8 +83,12 @@ cfg_if::cfg_if! {
             RocketmqInfo(RocketmqInfo),
             PostgreInfo(PostgreInfo),
             OpenWireInfo(OpenWireInfo),
+            OracleInfo(crate::flow_generator::protocol_logs::OracleInfo),
             SofaRpcInfo(SofaRpcInfo),
+            TlsInfo(crate::flow_generator::protocol_logs::TlsInfo),
+            SomeIpInfo(crate::flow_generator::protocol_logs::SomeIpInfo),
             PingInfo(PingInfo),
+            QuicInfo(crate::flow_generator::protocol_logs::QuicInfo),
             CustomInfo(CustomInfo),
             // add new protocol info below
         );
@@ -114,6 +118,7 @@ cfg_if::cfg_if! {
             TlsInfo(crate::flow_generator::protocol_logs::TlsInfo),
             SomeIpInfo(crate::flow_generator::protocol_logs::SomeIpInfo),
             PingInfo(PingInfo),
+            QuicInfo(crate::flow_generator::protocol_logs::QuicInfo),
             CustomInfo(CustomInfo),
             // add new protocol info below
         );
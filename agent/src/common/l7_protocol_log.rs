@@ -26,7 +26,7 @@ use log::debug;
 use lru::LruCache;
 
 use super::ebpf::EbpfType;
-use super::flow::{L7PerfStats, PacketDirection};
+use super::flow::{L7PerfStats, PacketDirection, QuicPerfStats};
 use super::l7_protocol_info::L7ProtocolInfo;
 use super::MetaPacket;
 
@@ -179,6 +179,7 @@ cfg_if::cfg_if! {
                 FastCGI(FastCGILog),
                 Brpc(BrpcLog),
                 Tars(TarsLog),
+                Oracle(crate::flow_generator::protocol_logs::OracleLog),
                 MQTT(MqttLog),
                 AMQP(AmqpLog),
                 NATS(NatsLog),
@@ -186,7 +187,10 @@ cfg_if::cfg_if! {
                 ZMTP(ZmtpLog),
                 RocketMQ(RocketmqLog),
                 OpenWire(OpenWireLog),
+                TLS(crate::flow_generator::protocol_logs::TlsLog),
+                SomeIp(crate::flow_generator::protocol_logs::SomeIpLog),
                 Ping(PingLog),
+                QUIC(crate::flow_generator::protocol_logs::QuicLog),
                 // add protocol below
             }
         }
@@ -218,6 +222,7 @@ cfg_if::cfg_if! {
                 TLS(crate::flow_generator::protocol_logs::TlsLog),
                 SomeIp(crate::flow_generator::protocol_logs::SomeIpLog),
                 Ping(PingLog),
+                QUIC(crate::flow_generator::protocol_logs::QuicLog),
                 // add protocol below
             }
         }
@@ -310,6 +315,11 @@ pub trait L7ProtocolParserInterface {
     // return perf data
     fn perf_stats(&mut self) -> Option<L7PerfStats>;
 
+    // connection details of QUIC flows, reported in FlowPerfStats
+    fn quic_perf_stats(&mut self) -> Option<QuicPerfStats> {
+        None
+    }
+
     fn set_obfuscate_cache(&mut self, _: Option<ObfuscateCache>) {}
 }
 
@@ -1414,12 +1414,16 @@ impl Segment for MetaPacket<'static> {
     }
 
     fn next_tcp_seq(&self) -> u32 {
-        self.get_tcp_seq() + self.l4_payload_len as u32
+        self.get_tcp_seq().wrapping_add(self.l4_payload_len as u32)
     }
 
     fn get_payload_length(&self) -> u16 {
         self.l4_payload_len
     }
+
+    fn reverse(&mut self) {
+        self.lookup_key.direction = self.lookup_key.direction.reversed();
+    }
 }
 
 #[derive(Clone, Debug, Default)]
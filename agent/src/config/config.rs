@@ -39,7 +39,10 @@ use tokio::runtime::Runtime;
 
 use crate::common::l7_protocol_log::L7ProtocolParser;
 use crate::dispatcher::recv_engine::DEFAULT_BLOCK_SIZE;
-use crate::flow_generator::{DnsLog, MemcachedLog};
+use crate::flow_generator::{
+    protocol_logs::plugin::custom_field_policy::{ExtraCustomFieldPolicy, ExtraField, FieldRegex},
+    DnsLog, MemcachedLog,
+};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::platform::{OsAppTag, ProcessData};
 use crate::{
@@ -48,24 +51,18 @@ use crate::{
 
 use public::{
     bitmap::Bitmap,
-    enums::{Charset, FieldType, TrafficDirection},
-    l7_protocol::L7Protocol,
+    enums::{Charset, FieldType, MatchType, TrafficDirection},
+    l7_protocol::{L7Protocol, L7ProtocolEnum},
     proto::agent,
+    segment_map::{parse_u16_range_list_to_port_pairs, SegmentBuilder, SegmentMap},
     utils::bitmap::parse_u16_range_list_to_bitmap,
 };
+use special_recv_engine::ReplayPacing;
 
-cfg_if::cfg_if! {
-if #[cfg(feature = "enterprise")] {
-        use public::{
-            enums::MatchType,
-            l7_protocol::L7ProtocolEnum,
-            segment_map::{parse_u16_range_list_to_port_pairs, SegmentBuilder, SegmentMap},
-        };
-        use enterprise_utils::l7::plugin::custom_field_policy::{
-            ExtraCustomFieldPolicy, ExtraProtocolCharacters, ExtraCustomProtocolConfig, ExtraField, KeywordMatcher,
-        };
-    }
-}
+#[cfg(feature = "enterprise")]
+use enterprise_utils::l7::plugin::custom_field_policy::{
+    ExtraCustomProtocolConfig, ExtraProtocolCharacters, KeywordMatcher,
+};
 
 pub const K8S_CA_CRT_PATH: &str = "/run/secrets/kubernetes.io/serviceaccount/ca.crt";
 const MINUTE: Duration = Duration::from_secs(60);
@@ -793,10 +790,27 @@ impl Default for Dpdk {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+fn to_replay_pacing<'de, D>(deserializer: D) -> Result<ReplayPacing, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match String::deserialize(deserializer)?.to_uppercase().as_str() {
+        "AS_FAST_AS_POSSIBLE" => Ok(ReplayPacing::AsFastAsPossible),
+        "WALL_CLOCK" => Ok(ReplayPacing::WallClock),
+        other => Err(de::Error::invalid_value(
+            Unexpected::Str(other),
+            &"as_fast_as_possible|wall_clock",
+        )),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default)]
 pub struct Libpcap {
     pub enabled: bool,
+    pub replay_file: String,
+    #[serde(deserialize_with = "to_replay_pacing")]
+    pub replay_pacing: ReplayPacing,
 }
 
 impl Default for Libpcap {
@@ -806,6 +820,8 @@ impl Default for Libpcap {
             enabled: false,
             #[cfg(target_os = "windows")]
             enabled: true,
+            replay_file: "".to_string(),
+            replay_pacing: ReplayPacing::default(),
         }
     }
 }
@@ -1365,6 +1381,34 @@ impl Default for PrometheusExtraLabels {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct PrometheusScrape {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    pub static_targets: Vec<String>,
+    pub kubernetes_pods: bool,
+    pub ca_cert_file: String,
+    pub insecure_skip_verify: bool,
+}
+
+impl Default for PrometheusScrape {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            static_targets: vec![],
+            kubernetes_pods: false,
+            ca_cert_file: "".to_string(),
+            insecure_skip_verify: false,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default)]
 pub struct FeatureControl {
@@ -1406,8 +1450,10 @@ impl Default for Compression {
 pub struct Integration {
     pub enabled: bool,
     pub listen_port: u16,
+    pub otlp_grpc_port: u16,
     pub compression: Compression,
     pub prometheus_extra_labels: PrometheusExtraLabels,
+    pub prometheus_scrape: PrometheusScrape,
     pub feature_control: FeatureControl,
 }
 
@@ -1416,8 +1462,10 @@ impl Default for Integration {
         Self {
             enabled: true,
             listen_port: 38086,
+            otlp_grpc_port: 0,
             compression: Compression::default(),
             prometheus_extra_labels: PrometheusExtraLabels::default(),
+            prometheus_scrape: PrometheusScrape::default(),
             feature_control: FeatureControl::default(),
         }
     }
@@ -1503,6 +1551,7 @@ pub struct TcpHeader {
     pub sender_queue_size: usize,
     #[serde(deserialize_with = "parse_maybe_binary_u8")]
     pub header_fields_flag: u8,
+    pub max_flows: usize,
 }
 
 impl Default for TcpHeader {
@@ -1511,6 +1560,7 @@ impl Default for TcpHeader {
             block_size: 256,
             sender_queue_size: 65536,
             header_fields_flag: 0b0000_0000,
+            max_flows: 65536,
         }
     }
 }
@@ -1692,6 +1742,7 @@ impl Default for Filters {
                 ("DNS".to_string(), "53,5353".to_string()),
                 ("TLS".to_string(), "443,6443".to_string()),
                 ("PING".to_string(), "1-65535".to_string()),
+                ("QUIC".to_string(), "443".to_string()),
                 ("Custom".to_string(), "1-65535".to_string()),
             ]),
             tag_filters: HashMap::from([
@@ -1721,6 +1772,7 @@ impl Default for Filters {
                 ("DNS".to_string(), vec![]),
                 ("TLS".to_string(), vec![]),
                 ("PING".to_string(), vec![]),
+                ("QUIC".to_string(), vec![]),
                 ("Custom".to_string(), vec![]),
             ]),
             unconcerned_dns_nxdomain_response_suffixes: Default::default(),
@@ -1965,13 +2017,33 @@ where
         "payload_json_value" => Ok(FieldType::PayloadJson),
         "payload_xml_value" => Ok(FieldType::PayloadXml),
         "payload_hessian2_value" => Ok(FieldType::PayloadHessian2),
+        "payload_regex_value" => Ok(FieldType::PayloadRegex),
         other => Err(de::Error::invalid_value(
             Unexpected::Str(other),
-            &"[header_field|http_url_field|payload_json_value|payload_xml_value|payload_hessian2_value]"
+            &"[header_field|http_url_field|payload_json_value|payload_xml_value|payload_hessian2_value|payload_regex_value]"
         )),
     }
 }
 
+fn to_fields<'de, D>(deserializer: D) -> Result<Vec<Field>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let fields = Vec::<Field>::deserialize(deserializer)?;
+    for field in fields.iter() {
+        if field.field_type != FieldType::PayloadRegex {
+            continue;
+        }
+        if let Err(e) = FieldRegex::new(&field.field_match_keyword) {
+            return Err(de::Error::custom(format!(
+                "field {} has malformed payload_regex_value {:?}: {}",
+                field.field_name, field.field_match_keyword, e
+            )));
+        }
+    }
+    Ok(fields)
+}
+
 #[derive(Clone, Default, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default)]
 pub struct RewriteResponseStatus {
@@ -2012,17 +2084,16 @@ pub struct CustomFieldPolicy {
     // only for custom protocol
     pub custom_protocol_name: Option<String>,
     pub port_list: String,
+    #[serde(deserialize_with = "to_fields")]
     pub fields: Vec<Field>,
 }
 
-#[cfg(feature = "enterprise")]
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub struct ExtraCustomFieldPolicyMap {
     pub indices: SegmentMap<usize>,
     pub policies: Vec<ExtraCustomFieldPolicy>,
 }
 
-#[cfg(feature = "enterprise")]
 impl From<&Field> for ExtraField {
     fn from(v: &Field) -> ExtraField {
         ExtraField {
@@ -2032,6 +2103,12 @@ impl From<&Field> for ExtraField {
                 _ => MatchType::String(false),
             },
             field_match_keyword: v.field_match_keyword.clone(),
+            // checked by to_fields when the config is parsed
+            field_regex: if v.field_type == FieldType::PayloadRegex {
+                FieldRegex::new(&v.field_match_keyword).ok()
+            } else {
+                None
+            },
             subfield_match_keyword: v.subfield_match_keyword.clone(),
             separator_between_subfield_kv_pair: v.separator_between_subfield_kv_pair.clone(),
             separator_between_subfield_key_and_value: v
@@ -2105,7 +2182,6 @@ impl From<&CustomProtocolConfig> for ExtraProtocolCharacters {
     }
 }
 
-#[cfg(feature = "enterprise")]
 impl From<&CustomFieldPolicy> for ExtraCustomFieldPolicy {
     fn from(v: &CustomFieldPolicy) -> ExtraCustomFieldPolicy {
         let mut extra_field_policy = ExtraCustomFieldPolicy::default();
@@ -2140,7 +2216,7 @@ impl From<&CustomFieldPolicy> for ExtraCustomFieldPolicy {
                 match field.field_type {
                     FieldType::Header | FieldType::HttpUrl | FieldType::PayloadHessian2 => {
                         extra_field_policy
-                            .from_req_key
+                            .from_resp_key
                             .entry(field.field_type)
                             .and_modify(|v: &mut HashMap<String, Vec<ExtraField>>| {
                                 v.entry(field.field_match_keyword.to_lowercase())
@@ -2778,7 +2854,7 @@ where
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default)]
 pub struct Socket {
     #[serde(deserialize_with = "to_agent_socket_type")]
@@ -2787,6 +2863,8 @@ pub struct Socket {
     pub npb_socket_type: agent::SocketType,
     pub raw_udp_qos_bypass: bool,
     pub multiple_sockets_to_ingester: bool,
+    pub ingester_disk_buffer: IngesterDiskBuffer,
+    pub ingester_tls: IngesterTls,
 }
 
 impl Default for Socket {
@@ -2796,10 +2874,48 @@ impl Default for Socket {
             npb_socket_type: agent::SocketType::RawUdp,
             raw_udp_qos_bypass: false,
             multiple_sockets_to_ingester: false,
+            ingester_disk_buffer: IngesterDiskBuffer::default(),
+            ingester_tls: IngesterTls::default(),
         }
     }
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct IngesterDiskBuffer {
+    pub enabled: bool,
+    pub data_dir: String,
+    // limits of each sender and message type
+    #[serde(deserialize_with = "deser_u64_with_mega_unit")]
+    pub max_size: u64,
+    #[serde(deserialize_with = "deser_u64_with_mega_unit")]
+    pub segment_size: u64,
+    pub max_replay_throughput: u64, // unit: Mbps
+}
+
+impl Default for IngesterDiskBuffer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            data_dir: "/var/lib/deepflow-agent/disk-buffer".to_string(),
+            max_size: 256 << 20,
+            segment_size: 16 << 20,
+            max_replay_throughput: 20,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct IngesterTls {
+    pub enabled: bool,
+    pub ca_cert_file: String,
+    pub client_cert_file: String,
+    pub client_key_file: String,
+    // verify ingester ip if empty
+    pub server_name: String,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default)]
 pub struct FlowLogFilters {
@@ -3098,38 +3214,33 @@ impl UserConfig {
         {
             new.insert(dns_str.to_string(), Self::DEFAULT_DNS_PORTS.to_string());
         }
-        #[cfg(feature = "enterprise")]
+        let tls_str =
+            L7ProtocolParser::TLS(crate::flow_generator::protocol_logs::TlsLog::default()).as_str();
+        // tls default only parse 443,6443 port. when l7_protocol_ports config without TLS, need to reserve the tls default config.
+        if !self
+            .processors
+            .request_log
+            .filters
+            .port_number_prefilters
+            .contains_key(tls_str)
         {
-            let tls_str =
-                L7ProtocolParser::TLS(crate::flow_generator::protocol_logs::TlsLog::default())
-                    .as_str();
-            // tls default only parse 443,6443 port. when l7_protocol_ports config without TLS, need to reserve the tls default config.
-            if !self
-                .processors
-                .request_log
-                .filters
-                .port_number_prefilters
-                .contains_key(tls_str)
-            {
-                new.insert(tls_str.to_string(), Self::DEFAULT_TLS_PORTS.to_string());
-            }
-            let oracle_str = L7ProtocolParser::Oracle(
-                crate::flow_generator::protocol_logs::OracleLog::default(),
-            )
-            .as_str();
-            // oracle default only parse 1521 port. when l7_protocol_ports config without ORACLE, need to reserve the oracle default config.
-            if !self
-                .processors
-                .request_log
-                .filters
-                .port_number_prefilters
-                .contains_key(oracle_str)
-            {
-                new.insert(
-                    oracle_str.to_string(),
-                    Self::DEFAULT_ORACLE_PORTS.to_string(),
-                );
-            }
+            new.insert(tls_str.to_string(), Self::DEFAULT_TLS_PORTS.to_string());
+        }
+        let oracle_str =
+            L7ProtocolParser::Oracle(crate::flow_generator::protocol_logs::OracleLog::default())
+                .as_str();
+        // oracle default only parse 1521 port. when l7_protocol_ports config without ORACLE, need to reserve the oracle default config.
+        if !self
+            .processors
+            .request_log
+            .filters
+            .port_number_prefilters
+            .contains_key(oracle_str)
+        {
+            new.insert(
+                oracle_str.to_string(),
+                Self::DEFAULT_ORACLE_PORTS.to_string(),
+            );
         }
         let memcached_str = L7ProtocolParser::Memcached(MemcachedLog::default()).as_str();
         // memcached default only parse 11211 port. when l7_protocol_ports config without MEMCACHED, need to reserve the memcached default config.
@@ -3229,7 +3340,6 @@ impl UserConfig {
         }
     }
 
-    #[cfg(feature = "enterprise")]
     pub fn get_extra_field_policies(&self) -> HashMap<L7ProtocolEnum, ExtraCustomFieldPolicyMap> {
         let mut extra_policy_map: HashMap<
             L7ProtocolEnum,
@@ -3352,6 +3462,41 @@ impl UserConfig {
                 self.global.self_monitoring.interval
             )));
         }
+        let disk_buffer = &self.outputs.socket.ingester_disk_buffer;
+        if disk_buffer.segment_size < 1 << 20 || disk_buffer.segment_size > disk_buffer.max_size {
+            return Err(ConfigError::RuntimeConfigInvalid(format!(
+                "ingester_disk_buffer segment_size {}MB not in [1MB, max_size {}MB]",
+                disk_buffer.segment_size >> 20,
+                disk_buffer.max_size >> 20
+            )));
+        }
+        let tls = &self.outputs.socket.ingester_tls;
+        if tls.enabled && tls.ca_cert_file.is_empty() {
+            return Err(ConfigError::RuntimeConfigInvalid(
+                "ingester_tls ca_cert_file not set".to_string(),
+            ));
+        }
+        if tls.client_cert_file.is_empty() != tls.client_key_file.is_empty() {
+            return Err(ConfigError::RuntimeConfigInvalid(format!(
+                "ingester_tls client_cert_file {:?} and client_key_file {:?} should be set together",
+                tls.client_cert_file, tls.client_key_file
+            )));
+        }
+        let scrape = &self.inputs.integration.prometheus_scrape;
+        if scrape.interval < Duration::from_secs(1)
+            || scrape.interval > Duration::from_secs(60 * 60)
+        {
+            return Err(ConfigError::RuntimeConfigInvalid(format!(
+                "prometheus_scrape interval {:?} not in [1s, 1h]",
+                scrape.interval
+            )));
+        }
+        if scrape.timeout.is_zero() || scrape.timeout > scrape.interval {
+            return Err(ConfigError::RuntimeConfigInvalid(format!(
+                "prometheus_scrape timeout {:?} not in (0, {:?}]",
+                scrape.timeout, scrape.interval
+            )));
+        }
 
         // 虽然RFC 791里最低MTU是68，但是此时compressor会崩溃，
         // 所以MTU最低限定到200以确保deepflow-agent能够成功运行
@@ -4063,4 +4208,30 @@ processors:
         assert_eq!(apps[1].protocol, L7Protocol::Grpc);
         assert_eq!(apps[1].timeout, Duration::from_secs(130));
     }
+
+    #[test]
+    fn parse_payload_regex_value() {
+        let yaml = r#"
+processors:
+  request_log:
+    tag_extraction:
+      custom_field_policies:
+      - policy_name: order
+        protocol_name: HTTP
+        fields:
+        - field_name: order_no
+          field_match_keyword: "order_no=([\\w-]+)"
+          field_type: payload_regex_value
+"#;
+        let cfg: UserConfig = serde_yaml::from_str(yaml).unwrap();
+        let policies = &cfg
+            .processors
+            .request_log
+            .tag_extraction
+            .custom_field_policies;
+        assert_eq!(policies[0].fields[0].field_type, FieldType::PayloadRegex);
+
+        let yaml = yaml.replace(r"([\\w-]+)", r"([\\w-]+");
+        assert!(serde_yaml::from_str::<UserConfig>(&yaml).is_err());
+    }
 }
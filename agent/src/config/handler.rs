@@ -49,8 +49,9 @@ use tokio::runtime::Runtime;
 use super::config::{Ebpf, EbpfFileIoEvent, ProcessMatcher, SymbolTable};
 use super::{
     config::{
-        ApiResources, Config, DpdkSource, ExtraLogFields, ExtraLogFieldsInfo, HttpEndpoint,
-        HttpEndpointMatchRule, OracleConfig, PcapStream, PortConfig, ProcessorsFlowLogTunning,
+        ApiResources, Config, DpdkSource, ExtraCustomFieldPolicyMap, ExtraLogFields,
+        ExtraLogFieldsInfo, HttpEndpoint, HttpEndpointMatchRule, IngesterDiskBuffer, IngesterTls,
+        OracleConfig, PcapStream, PortConfig, ProcessorsFlowLogTunning, PrometheusScrape,
         RequestLogTunning, SessionTimeout, TagFilterOperator, Timeouts, UserConfig,
     },
     ConfigError, KubernetesPollerType, TrafficOverflowAction,
@@ -89,20 +90,13 @@ use crate::{
 use crate::{trident::AgentId, utils::cgroups::is_kernel_available_for_cgroups};
 
 use public::bitmap::Bitmap;
-use public::l7_protocol::L7Protocol;
+use public::enums::FieldType;
+use public::l7_protocol::{L7Protocol, L7ProtocolEnum};
 use public::proto::agent::{self, AgentType, PacketCaptureType};
 use public::utils::net::MacAddr;
 
-cfg_if::cfg_if! {
-if #[cfg(feature = "enterprise")] {
-        use public::{
-            enums::FieldType,
-            l7_protocol::L7ProtocolEnum,
-        };
-        use super::config::ExtraCustomFieldPolicyMap;
-        use enterprise_utils::l7::plugin::custom_field_policy::ExtraCustomProtocolConfig;
-    }
-}
+#[cfg(feature = "enterprise")]
+use enterprise_utils::l7::plugin::custom_field_policy::ExtraCustomProtocolConfig;
 
 const MB: u64 = 1048576;
 
@@ -256,6 +250,8 @@ pub struct SenderConfig {
     pub collector_socket_type: agent::SocketType,
     pub standalone_data_file_size: u64,
     pub standalone_data_file_dir: String,
+    pub ingester_disk_buffer: IngesterDiskBuffer,
+    pub ingester_tls: IngesterTls,
     pub server_tx_bandwidth_threshold: u64,
     pub bandwidth_probe_interval: Duration,
     pub enabled: bool,
@@ -500,6 +496,7 @@ pub struct FlowConfig {
     // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_flag: u8,
     pub packet_sequence_block_size: usize,
+    pub packet_sequence_max_flows: usize,
 
     pub l7_protocol_enabled_bitmap: L7ProtocolBitmap,
 
@@ -611,6 +608,7 @@ impl From<&UserConfig> for FlowConfig {
                 .as_secs() as usize,
             packet_sequence_flag: conf.processors.packet.tcp_header.header_fields_flag, // Enterprise Edition Feature: packet-sequence
             packet_sequence_block_size: conf.processors.packet.tcp_header.block_size, // Enterprise Edition Feature: packet-sequence
+            packet_sequence_max_flows: conf.processors.packet.tcp_header.max_flows, // Enterprise Edition Feature: packet-sequence
             l7_protocol_enabled_bitmap: L7ProtocolBitmap::from(
                 conf.processors
                     .request_log
@@ -764,6 +762,7 @@ impl fmt::Debug for FlowConfig {
                 "packet_sequence_block_size",
                 &self.packet_sequence_block_size,
             )
+            .field("packet_sequence_max_flows", &self.packet_sequence_max_flows)
             .field(
                 "l7_protocol_enabled_bitmap",
                 &self.l7_protocol_enabled_bitmap,
@@ -1402,12 +1401,32 @@ impl TraceType {
         }
     }
 
-    fn decode_tingyun<'a, 'b>(value: &'a str, sub_tag: &'b str) -> Option<Cow<'a, str>> {
-        cloud_platform::tingyun::decode_trace_id(value, sub_tag)
+    // X-Tingyun: c=B|ACCOUNT;x=TRANSACTIONID;e=...
+    // the sub tag configured as `x-tingyun.SUBTAG` selects the key of trace_id,
+    // the header carries no span id
+    fn decode_tingyun<'a, 'b>(
+        value: &'a str,
+        sub_tag: &'b str,
+        id_type: u8,
+    ) -> Option<Cow<'a, str>> {
+        if id_type == Self::TRACE_ID {
+            cloud_platform::tingyun::decode_trace_id(value, sub_tag)
+        } else if id_type == Self::SPAN_ID {
+            None
+        } else {
+            unreachable!()
+        }
     }
 
-    fn decode_cloud_wise(value: &str) -> Option<&str> {
-        cloud_platform::cloudwise::decode_trace_id(value)
+    // CLOUDWISE: JAVA:0:0:0:0:TRACEID:SPANID:0:0
+    fn decode_cloud_wise(value: &str, id_type: u8) -> Option<&str> {
+        if id_type == Self::TRACE_ID {
+            cloud_platform::cloudwise::decode_trace_id(value)
+        } else if id_type == Self::SPAN_ID {
+            cloud_platform::cloudwise::decode_span_id(value)
+        } else {
+            unreachable!()
+        }
     }
 
     fn decode_id<'a, 'b>(&'b self, value: &'a str, id_type: u8) -> Option<Cow<'a, str>> {
@@ -1426,8 +1445,8 @@ impl TraceType {
             TraceType::NewRpcTraceContext => {
                 decode_new_rpc_trace_context_with_type(value.as_bytes(), id_type)
             }
-            TraceType::XTingyun(sub_tag) => Self::decode_tingyun(value, sub_tag),
-            TraceType::CloudWise => Self::decode_cloud_wise(value).map(|s| s.into()),
+            TraceType::XTingyun(sub_tag) => Self::decode_tingyun(value, sub_tag, id_type),
+            TraceType::CloudWise => Self::decode_cloud_wise(value, id_type).map(|s| s.into()),
         }
     }
 
@@ -1463,7 +1482,6 @@ pub struct L7LogDynamicConfig {
 
     pub grpc_streaming_data_enabled: bool,
 
-    #[cfg(feature = "enterprise")]
     pub extra_field_policies: HashMap<L7ProtocolEnum, ExtraCustomFieldPolicyMap>,
 }
 
@@ -1495,18 +1513,13 @@ impl fmt::Debug for L7LogDynamicConfig {
 
 impl PartialEq for L7LogDynamicConfig {
     fn eq(&self, other: &Self) -> bool {
-        #[allow(unused_mut)]
-        let mut eq = self.proxy_client == other.proxy_client
+        self.proxy_client == other.proxy_client
             && self.x_request_id == other.x_request_id
             && self.trace_types == other.trace_types
             && self.span_types == other.span_types
             && self.extra_log_fields == other.extra_log_fields
-            && self.grpc_streaming_data_enabled == other.grpc_streaming_data_enabled;
-        #[cfg(feature = "enterprise")]
-        {
-            eq &= self.extra_field_policies == other.extra_field_policies;
-        }
-        eq
+            && self.grpc_streaming_data_enabled == other.grpc_streaming_data_enabled
+            && self.extra_field_policies == other.extra_field_policies
     }
 }
 
@@ -1520,10 +1533,7 @@ impl L7LogDynamicConfig {
         span_types: Vec<TraceType>,
         mut extra_log_fields: ExtraLogFields,
         grpc_streaming_data_enabled: bool,
-        #[cfg(feature = "enterprise")] extra_field_policies: HashMap<
-            L7ProtocolEnum,
-            ExtraCustomFieldPolicyMap,
-        >,
+        extra_field_policies: HashMap<L7ProtocolEnum, ExtraCustomFieldPolicyMap>,
     ) -> Self {
         let mut expected_headers_set = get_expected_headers();
         let mut dup_checker = HashSet::new();
@@ -1571,7 +1581,6 @@ impl L7LogDynamicConfig {
             expected_headers_set.insert(f.field_name.as_bytes().to_vec());
         }
 
-        #[cfg(feature = "enterprise")]
         if let Some(policy_map) =
             extra_field_policies.get(&L7ProtocolEnum::L7Protocol(L7Protocol::Http2))
         {
@@ -1609,7 +1618,6 @@ impl L7LogDynamicConfig {
             expected_headers_set: Arc::new(expected_headers_set),
             extra_log_fields,
             grpc_streaming_data_enabled,
-            #[cfg(feature = "enterprise")]
             extra_field_policies,
         }
     }
@@ -1627,11 +1635,13 @@ impl L7LogDynamicConfig {
 pub struct MetricServerConfig {
     pub enabled: bool,
     pub port: u16,
+    pub otlp_grpc_port: u16,
     pub compressed: bool,
     pub profile_compressed: bool,
     pub application_log_compressed: bool,
     pub l7_flow_log_compressed: bool,
     pub l4_flow_log_compressed: bool,
+    pub prometheus_scrape: PrometheusScrape,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -1859,6 +1869,8 @@ impl TryFrom<(Config, UserConfig)> for ModuleConfig {
                 collector_socket_type: conf.outputs.socket.data_socket_type,
                 standalone_data_file_size: conf.global.standalone_mode.max_data_file_size,
                 standalone_data_file_dir: conf.global.standalone_mode.data_file_dir.clone(),
+                ingester_disk_buffer: conf.outputs.socket.ingester_disk_buffer.clone(),
+                ingester_tls: conf.outputs.socket.ingester_tls.clone(),
                 enabled: conf.outputs.flow_metrics.enabled,
             },
             npb: NpbConfig {
@@ -2079,7 +2091,6 @@ impl TryFrom<(Config, UserConfig)> for ModuleConfig {
                         .protocol_special_config
                         .grpc
                         .streaming_data_enabled,
-                    #[cfg(feature = "enterprise")]
                     conf.get_extra_field_policies(),
                 ),
                 l7_log_ignore_tap_sides: {
@@ -2253,11 +2264,13 @@ impl TryFrom<(Config, UserConfig)> for ModuleConfig {
             metric_server: MetricServerConfig {
                 enabled: conf.inputs.integration.enabled,
                 port: conf.inputs.integration.listen_port,
+                otlp_grpc_port: conf.inputs.integration.otlp_grpc_port,
                 compressed: conf.inputs.integration.compression.trace,
                 profile_compressed: conf.inputs.integration.compression.profile,
                 application_log_compressed: conf.outputs.compression.application_log,
                 l7_flow_log_compressed: conf.outputs.compression.l7_flow_log,
                 l4_flow_log_compressed: conf.outputs.compression.l4_flow_log,
+                prometheus_scrape: conf.inputs.integration.prometheus_scrape.clone(),
             },
             agent_type: conf.global.common.agent_type,
             port_config: PortConfig {
@@ -2700,6 +2713,14 @@ impl ConfigHandler {
             .metrics_server_component
             .external_metrics_server
             .set_port(handler.candidate_config.metric_server.port);
+        components
+            .metrics_server_component
+            .external_metrics_server
+            .set_otlp_grpc_port(handler.candidate_config.metric_server.otlp_grpc_port);
+        components
+            .metrics_server_component
+            .external_metrics_server
+            .set_prometheus_scrape(&handler.candidate_config.metric_server.prometheus_scrape);
     }
 
     fn set_npb(handler: &ConfigHandler, components: &mut AgentComponents) {
@@ -3101,6 +3122,22 @@ impl ConfigHandler {
             special_network.libpcap.enabled = new_special_network.libpcap.enabled;
             restart_agent = !first_run;
         }
+        if special_network.libpcap.replay_file != new_special_network.libpcap.replay_file {
+            info!(
+                "Update inputs.cbpf.special_network.libpcap.replay_file from {:?} to {:?}.",
+                special_network.libpcap.replay_file, new_special_network.libpcap.replay_file
+            );
+            special_network.libpcap.replay_file = new_special_network.libpcap.replay_file.clone();
+            restart_agent = !first_run;
+        }
+        if special_network.libpcap.replay_pacing != new_special_network.libpcap.replay_pacing {
+            info!(
+                "Update inputs.cbpf.special_network.libpcap.replay_pacing from {:?} to {:?}.",
+                special_network.libpcap.replay_pacing, new_special_network.libpcap.replay_pacing
+            );
+            special_network.libpcap.replay_pacing = new_special_network.libpcap.replay_pacing;
+            restart_agent = !first_run;
+        }
 
         let physical_switch = &mut special_network.physical_switch;
         let new_physical_switch = &mut new_special_network.physical_switch;
@@ -4412,6 +4449,20 @@ impl ConfigHandler {
             );
             socket.npb_socket_type = new_socket.npb_socket_type;
         }
+        if socket.ingester_disk_buffer != new_socket.ingester_disk_buffer {
+            info!(
+                "Update outputs.socket.ingester_disk_buffer from {:?} to {:?}.",
+                socket.ingester_disk_buffer, new_socket.ingester_disk_buffer
+            );
+            socket.ingester_disk_buffer = new_socket.ingester_disk_buffer.clone();
+        }
+        if socket.ingester_tls != new_socket.ingester_tls {
+            info!(
+                "Update outputs.socket.ingester_tls from {:?} to {:?}.",
+                socket.ingester_tls, new_socket.ingester_tls
+            );
+            socket.ingester_tls = new_socket.ingester_tls.clone();
+        }
 
         let flow_log = &mut outputs.flow_log;
         let new_flow_log = &mut new_outputs.flow_log;
@@ -4758,6 +4809,14 @@ impl ConfigHandler {
             tcp_header.sender_queue_size = new_tcp_header.sender_queue_size;
             restart_agent = !first_run;
         }
+        if tcp_header.max_flows != new_tcp_header.max_flows {
+            info!(
+                "Update processors.packet.tcp_header.max_flows from {:?} to {:?}.",
+                tcp_header.max_flows, new_tcp_header.max_flows
+            );
+            tcp_header.max_flows = new_tcp_header.max_flows;
+            restart_agent = !first_run;
+        }
 
         let toa = &mut packet.toa;
         let new_toa = &mut new_packet.toa;
@@ -5607,6 +5666,18 @@ mod tests {
                 Some("trace_id"),
                 Some("span_id"),
             ),
+            (
+                TraceType::XTingyun("x".to_owned()),
+                "c=B|account;x=trace_id;e=x;s=x",
+                Some("trace_id"),
+                None,
+            ),
+            (
+                TraceType::CloudWise,
+                "JAVA:0:x:x:x:trace_id:span_id:x:x",
+                Some("trace_id"),
+                Some("span_id"),
+            ),
         ];
         for (tt, value, tid, sid) in testcases {
             assert_eq!(tt.decode_trace_id(value).as_ref().map(|s| s.as_ref()), tid);
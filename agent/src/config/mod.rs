@@ -18,8 +18,9 @@ pub mod config;
 pub mod handler;
 
 pub use config::{
-    AgentIdType, Config, ConfigError, DpdkSource, KubernetesPollerType, OracleConfig, PcapStream,
-    PrometheusExtraLabels, TrafficOverflowAction, UserConfig, K8S_CA_CRT_PATH,
+    AgentIdType, Config, ConfigError, DpdkSource, IngesterDiskBuffer, IngesterTls,
+    KubernetesPollerType, OracleConfig, PcapStream, PrometheusExtraLabels, PrometheusScrape,
+    TrafficOverflowAction, UserConfig, K8S_CA_CRT_PATH,
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use config::{ApiResources, ProcessMatcher};
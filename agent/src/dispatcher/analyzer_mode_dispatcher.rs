@@ -18,7 +18,7 @@ use std::{
     collections::HashMap,
     mem::drop,
     ops::Add,
-    sync::{atomic::Ordering, Arc, RwLock},
+    sync::{atomic::Ordering, Arc, RwLock, Weak},
     thread::{self, JoinHandle},
     time::Duration,
 };
@@ -257,6 +257,10 @@ impl AnalyzerModeDispatcher {
         let vm_mac_addrs = self.vm_mac_addrs.clone();
         let mut dedup = PacketDedupMap::new();
         let id = base.id;
+        base.stats.register_countable(
+            &stats::SingleTagModule("packet_dedup", "id", id),
+            Countable::Ref(Arc::downgrade(dedup.counter()) as Weak<dyn stats::RefCountable>),
+        );
         let pool_raw_size = self.pool_raw_size;
 
         let npb_dedup_enabled = base.npb_dedup_enabled.clone();
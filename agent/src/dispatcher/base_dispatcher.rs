@@ -200,6 +200,17 @@ impl BaseDispatcher {
     }
 
     pub(super) fn switch_recv_engine(&mut self, config: &DispatcherConfig) -> Result<()> {
+        // replaying pcap file does not depend on tap interfaces
+        if !self
+            .is
+            .options
+            .lock()
+            .unwrap()
+            .libpcap_replay_file
+            .is_empty()
+        {
+            return Ok(());
+        }
         #[cfg(target_os = "linux")]
         let pcap_interfaces = match public::netns::links_by_name_regex_in_netns(
             &config.tap_interface_regex,
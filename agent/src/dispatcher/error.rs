@@ -34,7 +34,7 @@ pub enum Error {
     #[error("recv engine failure: {0}")]
     RecvEngineFailure(String),
     #[error("dispatcher winpcap: {0}")]
-    Libpcap(String), // Enterprise Edition Feature: windows-dispatcher
+    Libpcap(String),
     #[error("flavor dispatcher is empty")]
     DispatcherFlavorEmpty, // Enterprise Edition Feature: windows-dispatcher
     #[cfg(target_os = "linux")]
@@ -19,7 +19,7 @@ use std::{
     mem::drop,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, RwLock,
+        Arc, RwLock, Weak,
     },
     time::Duration,
 };
@@ -54,7 +54,10 @@ use crate::{
     handler::PacketHandlerBuilder,
     handler::{MiniPacket, PacketHandler},
     rpc::get_timestamp,
-    utils::environment::is_tt_hyper_v_compute,
+    utils::{
+        environment::is_tt_hyper_v_compute,
+        stats::{self, Countable},
+    },
 };
 use packet_dedup::PacketDedupMap;
 use public::packet::Packet;
@@ -545,6 +548,10 @@ impl MirrorModeDispatcher {
     pub(super) fn run(&mut self) {
         let base = &mut self.base.is;
         info!("Start mirror dispatcher {}", base.log_id);
+        base.stats.register_countable(
+            &stats::SingleTagModule("packet_dedup", "id", base.id),
+            Countable::Ref(Arc::downgrade(self.dedup.counter()) as Weak<dyn stats::RefCountable>),
+        );
         let time_diff = base.ntp_diff.load(Ordering::Relaxed);
         let mut prev_timestamp = get_timestamp(time_diff);
 
@@ -644,7 +651,7 @@ impl MirrorModeDispatcher {
 
             // Only virtual network traffic goes to remove duplicates
             #[cfg(any(target_os = "linux", target_os = "android"))]
-            if self.dedup.duplicate(overlay_packet, timestamp) {
+            if !base.analyzer_dedup_disabled && self.dedup.duplicate(overlay_packet, timestamp) {
                 debug!("Packet is duplicate");
                 continue;
             }
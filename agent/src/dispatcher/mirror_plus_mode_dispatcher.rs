@@ -293,6 +293,15 @@ impl MirrorPlusModeDispatcher {
         let cpu_set = base.options.lock().unwrap().cpu_set;
         #[cfg(any(target_os = "linux", target_os = "android"))]
         let mut dedup = packet_dedup::PacketDedupMap::new();
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        base.stats.register_countable(
+            &stats::SingleTagModule("packet_dedup", "id", id),
+            Countable::Ref(
+                Arc::downgrade(dedup.counter()) as std::sync::Weak<dyn stats::RefCountable>
+            ),
+        );
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let packet_dedup_disabled = base.analyzer_dedup_disabled;
 
         self.flow_generator_thread_handler.replace(
             thread::Builder::new()
@@ -378,7 +387,8 @@ impl MirrorPlusModeDispatcher {
 
                             // Only virtual network traffic goes to remove duplicates
                             #[cfg(any(target_os = "linux", target_os = "android"))]
-                            if dedup.duplicate(overlay_packet, timestamp) {
+                            if !packet_dedup_disabled && dedup.duplicate(overlay_packet, timestamp)
+                            {
                                 debug!("Packet is duplicate");
                                 continue;
                             }
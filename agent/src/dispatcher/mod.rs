@@ -48,9 +48,9 @@ use log::{debug, info, warn};
 use nix::sched::CpuSet;
 use packet_dedup::*;
 use public::debug::QueueDebugger;
-use special_recv_engine::Libpcap;
 #[cfg(target_os = "linux")]
 use special_recv_engine::{Dpdk, VhostUser};
+use special_recv_engine::{Libpcap, ReplayPacing};
 
 use analyzer_mode_dispatcher::{AnalyzerModeDispatcher, AnalyzerModeDispatcherListener}; // Enterprise Edition Feature: analyzer_mode
 use base_dispatcher::{BaseDispatcher, CaptureNetworkTypeHandler, InternalState};
@@ -604,6 +604,8 @@ pub struct Options {
     pub capture_mode: PacketCaptureType,
     pub dpdk_source: DpdkSource,
     pub libpcap_enabled: bool,
+    pub libpcap_replay_file: String,
+    pub libpcap_replay_pacing: ReplayPacing,
     pub dispatcher_queue: bool,
     pub packet_fanout_mode: u32,
     pub tap_mac_script: String,
@@ -1288,6 +1290,18 @@ impl DispatcherBuilder {
                     options.vhost_queue_size(),
                 )))
             }
+            PacketCaptureType::Mirror | PacketCaptureType::Local
+                if options.libpcap_enabled && !options.libpcap_replay_file.is_empty() =>
+            {
+                info!(
+                    "Libpcap replay init with: {} {:?}",
+                    options.libpcap_replay_file, options.libpcap_replay_pacing
+                );
+                let libpcap =
+                    Libpcap::from_file(&options.libpcap_replay_file, options.libpcap_replay_pacing)
+                        .map_err(|e| error::Error::Libpcap(e.to_string()))?;
+                Ok(RecvEngine::Libpcap(Some(libpcap)))
+            }
             PacketCaptureType::Mirror | PacketCaptureType::Local if options.libpcap_enabled => {
                 #[cfg(target_os = "windows")]
                 let src_ifaces = pcap_interfaces
@@ -99,6 +99,8 @@ use packet_segmentation_reassembly::PacketSegmentationReassembly;
 use packet_sequence_block::PacketSequenceBlock;
 
 const DEFAULT_SOCKET_CLOSE_TIMEOUT: Timestamp = Timestamp::from_secs(1);
+// payload held by tcp reassembly is output after the flow is idle for this long
+const REASSEMBLY_IDLE_TIMEOUT: Timestamp = Timestamp::from_secs(1);
 
 pub struct Config<'a> {
     pub flow: &'a FlowConfig,
@@ -219,6 +221,8 @@ pub struct FlowMap {
     l7_stats_output: BufferedSender<BatchedBox<L7Stats>>,
     l7_log_output: BufferedSender<AppProto>,
     pseq_output: Option<BufferedSender<Box<PacketSequenceBlock>>>, // Enterprise Edition Feature: packet-sequence
+    // the number of flows holding a packet sequence block
+    pseq_flows: usize,
 
     last_queue_flush: Duration,
 
@@ -339,6 +343,7 @@ impl FlowMap {
                 }
                 _ => None,
             },
+            pseq_flows: 0,
             last_queue_flush: Duration::ZERO,
             perf_cache: Rc::new(RefCell::new(L7PerfCache::new(config.capacity as usize))),
             flow_perf_counter,
@@ -652,6 +657,18 @@ impl FlowMap {
                     if node.timestamp_key > time_in_unit {
                         continue;
                     }
+                    // do not wait for the peer or flow timeout to output the last message
+                    let mut reassembly_pending = flow_config.collector_enabled
+                        && node
+                            .tcp_segments
+                            .as_ref()
+                            .map(|s| s.has_pending())
+                            .unwrap_or_default();
+                    if reassembly_pending && timestamp >= node.recent_time + REASSEMBLY_IDLE_TIMEOUT
+                    {
+                        self.flush_tcp_segments(config, node);
+                        reassembly_pending = false;
+                    }
                     // 未超时Flow的统计信息发送到队列下游
                     self.node_updated_aftercare(&flow_config, node, timestamp, None);
                     // Enterprise Edition Feature: packet-sequence
@@ -659,13 +676,19 @@ impl FlowMap {
                         if let Some(block) = node.packet_sequence_block.take() {
                             // flush the packet_sequence_block at the regular time
                             q.send(block);
+                            self.pseq_flows = self.pseq_flows.saturating_sub(1);
                         }
                     }
 
                     // 若流统计信息已输出，将节点移动至最终超时的时间
-                    let timeout = node.recent_time + node.timeout;
-                    if node.timestamp_key != timeout.as_secs() {
-                        node.timestamp_key = timeout.as_secs();
+                    // nodes with payload held by reassembly are checked again in the next time unit
+                    let timestamp_key = if reassembly_pending {
+                        time_in_unit + 1
+                    } else {
+                        (node.recent_time + node.timeout).as_secs()
+                    };
+                    if node.timestamp_key != timestamp_key {
+                        node.timestamp_key = timestamp_key;
                         moved_key.push((node.timestamp_key, flow_key));
                     }
                 }
@@ -847,6 +870,8 @@ impl FlowMap {
     fn append_to_block(
         config: &FlowConfig,
         output: &mut BufferedSender<Box<PacketSequenceBlock>>,
+        pseq_flows: &mut usize,
+        stats_counter: &FlowMapCounter,
         node: &mut FlowNode,
         meta_packet: &MetaPacket,
     ) {
@@ -860,10 +885,12 @@ impl FlowMap {
             {
                 // if the packet_sequence_block is no enough to push one more packet, then send it to the queue
                 output.send(node.packet_sequence_block.take().unwrap());
+                *pseq_flows = pseq_flows.saturating_sub(1);
             }
             _ => (),
         }
-        if node.packet_sequence_block.is_none() {
+        let new_block = node.packet_sequence_block.is_none();
+        if new_block {
             node.packet_sequence_block
                 .replace(Box::new(PacketSequenceBlock::with_capacity(
                     config.packet_sequence_block_size,
@@ -891,6 +918,18 @@ impl FlowMap {
                 .unwrap()
                 .append_packet(mini_meta_packet, config.packet_sequence_flag);
         }
+
+        if new_block {
+            if *pseq_flows < config.packet_sequence_max_flows {
+                *pseq_flows += 1;
+            } else {
+                // too many flows holding blocks, send the block without waiting for more packets
+                output.send(node.packet_sequence_block.take().unwrap());
+                stats_counter
+                    .pseq_flush_by_max_flows
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
     }
 
     fn update_tcp_node(
@@ -912,7 +951,11 @@ impl FlowMap {
 
         if flow_config.collector_enabled {
             if let Some(tcp_segments) = node.tcp_segments.as_mut() {
-                if let Some(mut packets) = tcp_segments.inject(meta_packet.to_owned_segment()) {
+                let packets = tcp_segments.inject(meta_packet.to_owned_segment());
+                self.stats_counter
+                    .drop_by_reassembly
+                    .fetch_add(tcp_segments.take_dropped_bytes(), Ordering::Relaxed);
+                if let Some(mut packets) = packets {
                     let mut packets = packets
                         .drain(..)
                         .map(|x| x.into_any().downcast::<MetaPacket>().unwrap())
@@ -947,7 +990,14 @@ impl FlowMap {
 
         // Enterprise Edition Feature: packet-sequence
         if let Some(output) = self.pseq_output.as_mut() {
-            Self::append_to_block(flow_config, output, node, meta_packet);
+            Self::append_to_block(
+                flow_config,
+                output,
+                &mut self.pseq_flows,
+                &self.stats_counter,
+                node,
+                meta_packet,
+            );
         }
 
         flow_closed
@@ -1699,6 +1749,9 @@ impl FlowMap {
 
         let l7_perf_stats_all = &mut perf_stats.l7;
         l7_perf_stats_all.sequential_merge(&l7_perf_stats);
+        if let Some(quic_perf_stats) = meta_flow_log.copy_and_reset_quic_perf_data() {
+            perf_stats.quic.sequential_merge(&quic_perf_stats);
+        }
         node.tagged_flow.flow.flow_perf_stats = Some(perf_stats);
     }
 
@@ -1864,7 +1917,11 @@ impl FlowMap {
 
         if flow_config.collector_enabled {
             if let Some(tcp_segments) = node.tcp_segments.as_mut() {
-                if let Some(mut packets) = tcp_segments.inject(meta_packet.to_owned_segment()) {
+                let packets = tcp_segments.inject(meta_packet.to_owned_segment());
+                self.stats_counter
+                    .drop_by_reassembly
+                    .fetch_add(tcp_segments.take_dropped_bytes(), Ordering::Relaxed);
+                if let Some(mut packets) = packets {
                     let mut packets = packets
                         .drain(..)
                         .map(|x| x.into_any().downcast::<MetaPacket>().unwrap())
@@ -1894,7 +1951,14 @@ impl FlowMap {
 
         // Enterprise Edition Feature: packet-sequence
         if let Some(output) = self.pseq_output.as_mut() {
-            Self::append_to_block(flow_config, output, &mut node, meta_packet);
+            Self::append_to_block(
+                flow_config,
+                output,
+                &mut self.pseq_flows,
+                &self.stats_counter,
+                &mut node,
+                meta_packet,
+            );
         }
         node
     }
@@ -2030,6 +2094,26 @@ impl FlowMap {
         }
     }
 
+    fn flush_tcp_segments(&mut self, config: &Config, node: &mut FlowNode) {
+        let Some(tcp_segments) = node.tcp_segments.as_mut() else {
+            return;
+        };
+        let packets = tcp_segments.flush();
+        self.stats_counter
+            .drop_by_reassembly
+            .fetch_add(tcp_segments.take_dropped_bytes(), Ordering::Relaxed);
+        if let Some(mut packets) = packets {
+            let mut packets = packets
+                .drain(..)
+                .map(|x| x.into_any().downcast::<MetaPacket>().unwrap())
+                .collect::<Vec<Box<MetaPacket>>>();
+            for packet in &mut packets {
+                let direction = packet.lookup_key.direction == PacketDirection::ClientToServer;
+                self.collect_metric(config, node, packet, direction, false);
+            }
+        }
+    }
+
     // go 版本的removeAndOutput
     fn node_removed_aftercare(
         &mut self,
@@ -2039,19 +2123,7 @@ impl FlowMap {
         meta_packet: Option<&mut MetaPacket>,
     ) {
         if config.flow.collector_enabled {
-            if let Some(tcp_segments) = node.tcp_segments.as_mut() {
-                if let Some(mut packets) = tcp_segments.flush() {
-                    let mut packets = packets
-                        .drain(..)
-                        .map(|x| x.into_any().downcast::<MetaPacket>().unwrap())
-                        .collect::<Vec<Box<MetaPacket>>>();
-                    for packet in &mut packets {
-                        let direction =
-                            packet.lookup_key.direction == PacketDirection::ClientToServer;
-                        self.collect_metric(config, &mut node, packet, direction, false);
-                    }
-                }
-            }
+            self.flush_tcp_segments(config, &mut node);
         }
 
         // 统计数据输出前矫正流方向
@@ -2075,6 +2147,7 @@ impl FlowMap {
             if let Some(output) = self.pseq_output.as_mut() {
                 if let Some(block) = node.packet_sequence_block.take() {
                     output.send(block);
+                    self.pseq_flows = self.pseq_flows.saturating_sub(1);
                 }
             }
         }
@@ -2503,6 +2576,8 @@ pub struct FlowMapCounter {
     closed: AtomicU64,                   // the number of closed flow
     drop_by_window: AtomicU64,           // times of flush which drop by window
     drop_by_capacity: AtomicU64,         // packet counter which drop by capacity
+    drop_by_reassembly: AtomicU64,       // bytes dropped by tcp segment reassembly
+    pseq_flush_by_max_flows: AtomicU64,  // packet sequence blocks sent early when too many flows hold blocks
     packet_delay: AtomicI64,             // inject_meta_packet delay compared to ntp corrected system time
     flush_delay: AtomicI64,              // inject_flush_ticker delay compared to ntp corrected system time
     flow_delay: AtomicI64,               // output flow `flow_stat_time` delay compared to ntp corrected system time
@@ -2541,6 +2616,16 @@ impl RefCountable for FlowMapCounter {
                 CounterType::Gauged,
                 CounterValue::Unsigned(self.drop_by_capacity.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "drop_by_reassembly",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.drop_by_reassembly.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "pseq_flush_by_max_flows",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.pseq_flush_by_max_flows.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "packet_delay",
                 CounterType::Gauged,
@@ -3542,4 +3627,68 @@ mod tests {
         assert_eq!(perf_stats.rtt_server_count, 2);
         assert_eq!(perf_stats.rtt, 2510);
     }
+
+    #[test]
+    fn packet_sequence_max_flows() {
+        let (_, mut policy_getter) = Policy::new(1, 0, 1 << 10, 1 << 14, false, false);
+        policy_getter.disable();
+        let queue_debugger = QueueDebugger::new();
+        let (output_queue_sender, _, _) = queue::bounded_with_debug(256, "", &queue_debugger);
+        let (l7_stats_output_queue_sender, _, _) =
+            queue::bounded_with_debug(256, "", &queue_debugger);
+        let (app_proto_log_queue, _, _) = queue::bounded_with_debug(256, "", &queue_debugger);
+        let (packet_sequence_queue, packet_sequence_receiver, _) =
+            queue::bounded_with_debug(256, "", &queue_debugger);
+        let module_config = ModuleConfig {
+            flow: FlowConfig {
+                agent_type: AgentType::TtProcess,
+                collector_enabled: true,
+                packet_sequence_flag: 0xff,
+                packet_sequence_max_flows: 2,
+                ..(&UserConfig::standalone_default()).into()
+            },
+            ..Default::default()
+        };
+        let mut flow_map = FlowMap::new(
+            0,
+            Some(output_queue_sender),
+            l7_stats_output_queue_sender,
+            policy_getter,
+            app_proto_log_queue,
+            Arc::new(AtomicI64::new(0)),
+            &module_config.flow,
+            Some(packet_sequence_queue),
+            Arc::new(stats::Collector::new("", Arc::new(AtomicI64::new(0)))),
+            false,
+        );
+        let config = Config {
+            flow: &module_config.flow,
+            log_parser: &module_config.log_parser,
+            collector: &module_config.collector,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ebpf: None,
+        };
+
+        for i in 0..3 {
+            let mut packet = _new_meta_packet();
+            packet.lookup_key.src_port += i;
+            flow_map.inject_meta_packet(&config, &mut packet);
+        }
+
+        // the third flow does not hold a block, its packet is sent at once
+        assert_eq!(flow_map.pseq_flows, 2);
+        assert_eq!(
+            flow_map
+                .stats_counter
+                .pseq_flush_by_max_flows
+                .load(Ordering::Relaxed),
+            1
+        );
+        flow_map.pseq_output.as_mut().unwrap().flush();
+        let block = packet_sequence_receiver.recv(Some(TIME_UNIT)).unwrap();
+        assert_eq!(block.packet_count(), 1);
+        assert!(packet_sequence_receiver
+            .recv(Some(DEFAULT_DURATION))
+            .is_err());
+    }
 }
@@ -172,7 +172,9 @@ impl FlowNode {
 
         if let Some(ref mut flow_perf_stats) = &mut flow.flow_perf_stats {
             flow_perf_stats.tcp = TcpPerfStats::default();
+            flow_perf_stats.tcp_histograms = Default::default();
             flow_perf_stats.l7 = L7PerfStats::default();
+            flow_perf_stats.quic.reset();
         }
     }
 
@@ -420,19 +422,20 @@ mod tests {
                 outputs.append(&mut packets);
             }
         }
+        if let Some(mut packets) = tcp_segments.flush() {
+            outputs.append(&mut packets);
+        }
 
         let outputs = outputs
             .drain(..)
             .map(|x| x.into_any().downcast::<MetaPacket>().unwrap())
             .collect::<Vec<Box<MetaPacket>>>();
 
-        assert_eq!(outputs.len(), 2);
-        assert_eq!(outputs[0].payload_len, 2896);
-        assert_eq!(outputs[0].packet_len, 2962);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].payload_len, 4344);
+        assert_eq!(outputs[0].packet_len, 4410);
         assert_eq!(outputs[0].get_l4_payload().as_ref().unwrap()[1447], 2);
         assert_eq!(outputs[0].get_l4_payload().as_ref().unwrap()[1448], 0x2c);
-        assert_eq!(outputs[1].payload_len, 2896);
-        assert_eq!(outputs[1].packet_len, 2962);
     }
 
     #[test]
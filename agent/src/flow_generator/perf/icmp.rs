@@ -14,7 +14,7 @@
  * limitations under the License.
  */
 
-use std::{cmp::max, collections::VecDeque};
+use std::{cmp::max, collections::VecDeque, mem};
 
 use pnet::packet::{
     icmp::{IcmpType, IcmpTypes},
@@ -28,6 +28,7 @@ use crate::{
         Timestamp,
     },
     flow_generator::error::{Error, Result},
+    metric::meter::LatencyHistogram,
 };
 
 use super::{L4FlowPerf, ART_MAX};
@@ -45,6 +46,7 @@ pub struct IcmpPerf {
     srt_max: Timestamp,
     srt_sum: Timestamp,
     srt_count: u32,
+    srt_histogram: LatencyHistogram,
     last_requests: VecDeque<LastIcmp>,
     last_replies: VecDeque<LastIcmp>,
     data_update_flag: bool,
@@ -60,6 +62,7 @@ impl IcmpPerf {
             self.srt_max = max(self.srt_max, srt);
             self.srt_sum += srt;
             self.srt_count += 1;
+            self.srt_histogram.record(srt.as_micros() as u32);
             self.data_update_flag = true;
         }
     }
@@ -68,6 +71,7 @@ impl IcmpPerf {
         self.srt_max = Timestamp::default();
         self.srt_sum = Timestamp::default();
         self.srt_count = 0;
+        self.srt_histogram = LatencyHistogram::default();
         self.data_update_flag = false;
     }
 }
@@ -160,6 +164,7 @@ impl L4FlowPerf for IcmpPerf {
                         self.srt_max = max(self.srt_max, srt);
                         self.srt_sum += srt;
                         self.srt_count += 1;
+                        self.srt_histogram.record(srt.as_micros() as u32);
                         self.data_update_flag = true;
                     }
                 }
@@ -174,6 +179,7 @@ impl L4FlowPerf for IcmpPerf {
         stats.tcp.srt_max = (self.srt_max.as_nanos() / Timestamp::from_micros(1).as_nanos()) as u32;
         stats.tcp.srt_sum = (self.srt_sum.as_nanos() / Timestamp::from_micros(1).as_nanos()) as u32;
         stats.tcp.srt_count = self.srt_count;
+        stats.tcp_histograms.srt = mem::take(&mut self.srt_histogram);
         self.reset();
 
         stats
@@ -41,7 +41,7 @@ use super::{
 
 use crate::common::l7_protocol_log::L7PerfCache;
 use crate::common::{
-    flow::{Flow, L7PerfStats},
+    flow::{Flow, L7PerfStats, QuicPerfStats},
     l7_protocol_log::L7ParseResult,
 };
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -644,8 +644,10 @@ impl FlowLog {
         if let Some(l4) = self.l4.as_mut() {
             if l4.data_updated() {
                 let flow_perf_stats = l4.copy_and_reset_data(flow_reversed);
-                flow.flow_perf_stats.as_mut().unwrap().l4_protocol = flow_perf_stats.l4_protocol;
-                flow.flow_perf_stats.as_mut().unwrap().tcp = flow_perf_stats.tcp;
+                let stats = flow.flow_perf_stats.as_mut().unwrap();
+                stats.l4_protocol = flow_perf_stats.l4_protocol;
+                stats.tcp = flow_perf_stats.tcp;
+                stats.tcp_histograms = flow_perf_stats.tcp_histograms;
             }
         }
     }
@@ -672,6 +674,12 @@ impl FlowLog {
         (l7_perf, self.l7_protocol_enum.get_l7_protocol())
     }
 
+    pub fn copy_and_reset_quic_perf_data(&mut self) -> Option<QuicPerfStats> {
+        self.l7_protocol_log_parser
+            .as_mut()
+            .and_then(|l| l.quic_perf_stats())
+    }
+
     pub fn reset_on_plugin_reload(&mut self) {
         if matches!(self.l7_protocol_enum, L7ProtocolEnum::Custom(_)) {
             self.l7_protocol_enum = Default::default();
@@ -15,22 +15,25 @@
  */
 
 use std::fmt;
+use std::mem;
 use std::sync::{atomic::Ordering, Arc};
 
 use bitflags::bitflags;
 use log::debug;
+use public::bytes::read_u32_be;
 
 use super::{FlowPerfCounter, L4FlowPerf, ART_MAX};
 
 use crate::{
     common::{
         enums::TcpFlags,
-        flow::{FlowPerfStats, L4Protocol},
+        flow::{FlowPerfStats, L4Protocol, TcpLatencyHistograms, TcpPerfCountsPeer},
         lookup_key::LookupKey,
         meta_packet::{MetaPacket, MetaPacketTcpHeader, ProtocolData},
         Timestamp,
     },
     flow_generator::error::{Error, Result},
+    metric::meter::LatencyHistogram,
 };
 
 const SRT_MAX: Timestamp = Timestamp::from_secs(10);
@@ -45,6 +48,16 @@ fn adjust_rtt(d: Timestamp, max: Timestamp) -> Timestamp {
     }
 }
 
+// Serial number comparison of sequence numbers, true if `a` is after `b`
+fn seq_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+const DUP_ACK_THRESHOLD: u32 = 3;
+// minimum retransmission timeout of Linux, retransmissions sent sooner without loss signals are
+// tail loss probes or spurious
+const RTO_MIN: Timestamp = Timestamp::from_millis(200);
+
 const WIN_SCALE_MAX: u8 = 14;
 const WIN_SCALE_MASK: u8 = 0x0f;
 const WIN_SCALE_FLAG: u8 = 0x80;
@@ -79,6 +92,16 @@ struct SeqSegment {
 
 const SEQ_LIST_MAX_LEN: usize = 16;
 
+// A sequence gap observed, counted as reordering or loss when filled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct SeqHole {
+    start: u32,
+    end: u32,
+    timestamp: Timestamp,
+}
+
+const SEQ_HOLE_MAX_LEN: usize = 4;
+
 #[derive(Default)]
 pub(crate) struct SessionPeer {
     seq_list: [SeqSegment; SEQ_LIST_MAX_LEN],
@@ -102,12 +125,64 @@ pub(crate) struct SessionPeer {
 
     rtt_full_precondition: bool, // rtt计算前置条件，SYN包在SYN/ACK包之前到达
     rtt_full_calculable: bool,   // rtt计算触发标志，完成计算后需reset
+
+    // options of the SYN or SYN/ACK
+    syn_win_size: u16,
+    syn_mss: u16,
+    syn_win_scale: u8,
+
+    snd_nxt: u32, // end of the highest segment sent, 0 if unknown
+    acked: u32,   // highest ACK sent, 0 if unknown
+    dup_ack_count: u32,
+    sacked: u32, // highest right edge of the SACK blocks sent, 0 if unknown
+    holes: [SeqHole; SEQ_HOLE_MAX_LEN],
+    holes_len: usize,
 }
 
 impl SessionPeer {
     const SEQ_NUMBER_LOW_THRESHOLD: u32 = 0x40000000;
     const SEQ_NUMBER_HIGH_THRESHOLD: u32 = 0xc0000000;
 
+    // records the gap [start, end), returns the oldest gap if it is evicted
+    fn add_hole(&mut self, start: u32, end: u32, timestamp: Timestamp) -> Option<SeqHole> {
+        let mut evicted = None;
+        if self.holes_len == SEQ_HOLE_MAX_LEN {
+            evicted = Some(self.holes[0]);
+            self.holes.copy_within(1.., 0);
+            self.holes_len -= 1;
+        }
+        self.holes[self.holes_len] = SeqHole {
+            start,
+            end,
+            timestamp,
+        };
+        self.holes_len += 1;
+        evicted
+    }
+
+    // removes and returns the gap containing `seq`
+    fn fill_hole(&mut self, seq: u32) -> Option<SeqHole> {
+        let i = self.holes[..self.holes_len]
+            .iter()
+            .position(|h| !seq_after(h.start, seq) && seq_after(h.end, seq))?;
+        let hole = self.holes[i];
+        self.holes.copy_within(i + 1..self.holes_len, i);
+        self.holes_len -= 1;
+        Some(hole)
+    }
+
+    // drops the gaps acknowledged by the peer, their segments were not captured
+    fn ack_holes(&mut self, ack: u32) {
+        let mut len = 0;
+        for i in 0..self.holes_len {
+            if seq_after(self.holes[i].end, ack) {
+                self.holes[len] = self.holes[i];
+                len += 1;
+            }
+        }
+        self.holes_len = len;
+    }
+
     fn is_sync_ack_ack_packet(&self, p: &MetaPacket) -> bool {
         if let ProtocolData::TcpHeader(tcp_data) = &p.protocol_data {
             return p.is_ack() && tcp_data.ack == self.seq_threshold;
@@ -371,12 +446,26 @@ impl fmt::Display for SessionPeer {
 #[derive(Default)]
 pub(crate) struct PerfControl(SessionPeer, SessionPeer);
 
-#[derive(Default, Debug, PartialEq, Eq)]
+#[derive(Default, PartialEq, Eq)]
 struct TimeStats {
     pub count: u32,
     pub sum: Timestamp,
     pub max: Timestamp,
     pub updated: bool,
+    // distribution in microseconds, only for latencies reported with histograms
+    pub histogram: LatencyHistogram,
+}
+
+// The histogram is left out, it only breaks down the samples summarized by the other fields
+impl fmt::Debug for TimeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeStats")
+            .field("count", &self.count)
+            .field("sum", &self.sum)
+            .field("max", &self.max)
+            .field("updated", &self.updated)
+            .finish()
+    }
 }
 
 impl TimeStats {
@@ -388,6 +477,11 @@ impl TimeStats {
         }
         self.updated = true;
     }
+
+    fn update_with_histogram(&mut self, d: Timestamp) {
+        self.update(d);
+        self.histogram.record(d.as_micros() as u32);
+    }
 }
 
 // art---Application Response Time
@@ -438,9 +532,9 @@ impl PerfData {
     // 计算art值
     fn calc_art(&mut self, d: Timestamp, fpd: bool) {
         if fpd {
-            self.art_0.update(d);
+            self.art_0.update_with_histogram(d);
         } else {
-            self.art_1.update(d);
+            self.art_1.update_with_histogram(d);
         }
         self.updated = true;
     }
@@ -448,9 +542,9 @@ impl PerfData {
     // 计算srt值
     fn calc_srt(&mut self, d: Timestamp, fpd: bool) {
         if fpd {
-            self.srt_0.update(d);
+            self.srt_0.update_with_histogram(d);
         } else {
-            self.srt_1.update(d);
+            self.srt_1.update_with_histogram(d);
         }
         self.updated = true;
     }
@@ -528,7 +622,7 @@ impl PerfData {
     }
 
     fn calc_cit(&mut self, d: Timestamp) {
-        self.cit.update(d);
+        self.cit.update_with_histogram(d);
         self.updated = true;
     }
 
@@ -538,6 +632,17 @@ impl PerfData {
         }
         self.updated = false;
 
+        let (srt, art) = if flow_reversed {
+            (&mut self.srt_0, &mut self.art_0)
+        } else {
+            (&mut self.srt_1, &mut self.art_1)
+        };
+        stats.tcp_histograms = TcpLatencyHistograms {
+            srt: mem::take(&mut srt.histogram),
+            art: mem::take(&mut art.histogram),
+            cit: mem::take(&mut self.cit.histogram),
+        };
+
         let stats = &mut stats.tcp;
         stats.counts_peers[0].retrans_count = self.retrans_0;
         stats.counts_peers[1].retrans_count = self.retrans_1;
@@ -597,9 +702,39 @@ impl PerfData {
     }
 }
 
+// Loss and reordering counters indexed by direction as `PerfControl`, reset on each report
+#[derive(Default)]
+struct PeerCounts {
+    sack: u32,
+    dsack: u32,
+    out_of_order: u32,
+    lost: u32,
+    rto: u32,
+    fast_retrans: u32,
+    bytes_in_flight_max: u32,
+    updated: bool,
+}
+
+impl PeerCounts {
+    fn update_perf_stats(&self, peer: &SessionPeer, stats: &mut TcpPerfCountsPeer) {
+        stats.sack_count = self.sack;
+        stats.dsack_count = self.dsack;
+        stats.out_of_order_count = self.out_of_order;
+        stats.lost_count = self.lost;
+        stats.rto_count = self.rto;
+        stats.fast_retrans_count = self.fast_retrans;
+        stats.bytes_in_flight_max = self.bytes_in_flight_max;
+
+        stats.win_size = peer.syn_win_size;
+        stats.mss = peer.syn_mss;
+        stats.win_scale = peer.syn_win_scale;
+    }
+}
+
 pub struct TcpPerf {
     ctrl_info: PerfControl,
     perf_data: PerfData,
+    peer_counts: [PeerCounts; 2],
     counter: Arc<FlowPerfCounter>,
     handshaking: bool,
 }
@@ -609,6 +744,7 @@ impl TcpPerf {
         Self {
             ctrl_info: Default::default(),
             perf_data: Default::default(),
+            peer_counts: Default::default(),
             counter,
             handshaking: false,
         }
@@ -617,6 +753,7 @@ impl TcpPerf {
     pub fn reset(&mut self) {
         self.ctrl_info = Default::default();
         self.perf_data = Default::default();
+        self.peer_counts = Default::default();
         self.handshaking = false;
     }
 
@@ -926,6 +1063,114 @@ impl TcpPerf {
         }
     }
 
+    // sack, dsack, out of order, lost, rto and fast retransmission, bytes in flight
+    fn calc_peer_counts(&mut self, p: &MetaPacket, fpd: bool, is_retrans: bool) {
+        let tcp_data = if let ProtocolData::TcpHeader(tcp_data) = &p.protocol_data {
+            tcp_data
+        } else {
+            unreachable!();
+        };
+        let (same_dir, oppo_dir) = if fpd {
+            (&mut self.ctrl_info.0, &mut self.ctrl_info.1)
+        } else {
+            (&mut self.ctrl_info.1, &mut self.ctrl_info.0)
+        };
+        let counts = &mut self.peer_counts[!fpd as usize];
+
+        if tcp_data.flags.contains(TcpFlags::SYN) {
+            same_dir.syn_win_size = tcp_data.win_size;
+            same_dir.syn_mss = tcp_data.mss;
+            same_dir.syn_win_scale = tcp_data.win_scale.min(WIN_SCALE_MAX);
+            same_dir.snd_nxt = tcp_data.seq.wrapping_add(1);
+        }
+
+        if tcp_data.flags.contains(TcpFlags::ACK) {
+            if same_dir.acked == 0 || seq_after(tcp_data.ack, same_dir.acked) {
+                same_dir.acked = tcp_data.ack;
+                same_dir.dup_ack_count = 0;
+                oppo_dir.ack_holes(tcp_data.ack);
+            } else if p.is_ack()
+                && tcp_data.ack == same_dir.acked
+                && tcp_data.win_size == same_dir.win_size
+                && oppo_dir.snd_nxt != tcp_data.ack
+            {
+                same_dir.dup_ack_count += 1;
+            }
+
+            if let Some(sack) = tcp_data.sack.as_ref() {
+                let mut blocks = sack
+                    .chunks_exact(8)
+                    .map(|b| (read_u32_be(b), read_u32_be(&b[4..])));
+                if let Some((left, right)) = blocks.next() {
+                    let second = blocks.next();
+                    // RFC 2883, only the first block reports duplicate data, which is either
+                    // below the ACK or within the second block
+                    let is_dsack = !seq_after(right, tcp_data.ack)
+                        || second.is_some_and(|(l, r)| !seq_after(l, left) && !seq_after(right, r));
+                    if is_dsack {
+                        counts.dsack += 1;
+                    }
+                    counts.sack += 1 + second.is_some() as u32 + blocks.count() as u32;
+                    counts.updated = true;
+                }
+                for right in sack.chunks_exact(8).map(|b| read_u32_be(&b[4..])) {
+                    if same_dir.sacked == 0 || seq_after(right, same_dir.sacked) {
+                        same_dir.sacked = right;
+                    }
+                }
+            }
+        }
+
+        if !p.has_valid_payload() || tcp_data.flags.contains(TcpFlags::SYN) {
+            return;
+        }
+        let dup_acked = oppo_dir.dup_ack_count >= DUP_ACK_THRESHOLD;
+        if is_retrans {
+            // RFC 6675, later data selectively acknowledged also triggers fast retransmission
+            let sacked_after = oppo_dir.sacked != 0
+                && seq_after(oppo_dir.sacked, oppo_dir.acked)
+                && seq_after(oppo_dir.sacked, tcp_data.seq);
+            if dup_acked || sacked_after {
+                counts.fast_retrans += 1;
+                counts.updated = true;
+            } else if p.lookup_key.timestamp >= same_dir.timestamp + RTO_MIN {
+                counts.rto += 1;
+                counts.updated = true;
+            }
+        } else if same_dir.snd_nxt != 0 && tcp_data.seq != same_dir.snd_nxt {
+            // Each gap is counted once when filled: as reordering if filled before the receiver
+            // could have reported it lost, and as loss before the capture point otherwise. Gaps
+            // evicted without being filled are lost as well.
+            let lost = if seq_after(same_dir.snd_nxt, tcp_data.seq) {
+                same_dir
+                    .fill_hole(tcp_data.seq)
+                    .map(|h| dup_acked || p.lookup_key.timestamp >= h.timestamp + RTO_MIN)
+            } else {
+                same_dir
+                    .add_hole(same_dir.snd_nxt, tcp_data.seq, p.lookup_key.timestamp)
+                    .map(|_| true)
+            };
+            match lost {
+                Some(true) => counts.lost += 1,
+                Some(false) => counts.out_of_order += 1,
+                None => (),
+            }
+            counts.updated |= lost.is_some();
+        }
+
+        let end = tcp_data.seq.wrapping_add(p.payload_len as u32);
+        if same_dir.snd_nxt == 0 || seq_after(end, same_dir.snd_nxt) {
+            same_dir.snd_nxt = end;
+        }
+        if oppo_dir.acked != 0 && seq_after(same_dir.snd_nxt, oppo_dir.acked) {
+            let in_flight = same_dir.snd_nxt.wrapping_sub(oppo_dir.acked);
+            if counts.bytes_in_flight_max < in_flight {
+                counts.bytes_in_flight_max = in_flight;
+                counts.updated = true;
+            }
+        }
+    }
+
     // 根据flag, direction, payload_len或PSH, SEQ, ACK重建状态机
     // assume: 包已经过预处理，无异常flag包，也没有与功能无关包（不关心报文）
     fn calculate(&mut self, p: &MetaPacket, fpd: bool) -> bool {
@@ -938,6 +1183,8 @@ impl TcpPerf {
             return is_retrans;
         }
 
+        self.calc_peer_counts(p, fpd, is_retrans);
+
         // 计算RTT
         if self.handshaking {
             self.handshaking = self.flow_opening(p, fpd);
@@ -1043,7 +1290,7 @@ impl L4FlowPerf for TcpPerf {
 
     fn data_updated(&self) -> bool {
         let d = &self.perf_data;
-        d.updated
+        d.updated || self.peer_counts.iter().any(|c| c.updated)
     }
 
     fn copy_and_reset_data(&mut self, flow_reversed: bool) -> FlowPerfStats {
@@ -1051,6 +1298,12 @@ impl L4FlowPerf for TcpPerf {
         stats.l4_protocol = L4Protocol::Tcp;
         self.perf_data.update_perf_stats(&mut stats, flow_reversed);
         self.perf_data = Default::default();
+        let (client, server) = if flow_reversed { (1, 0) } else { (0, 1) };
+        self.peer_counts[0]
+            .update_perf_stats(&self.ctrl_info.0, &mut stats.tcp.counts_peers[client]);
+        self.peer_counts[1]
+            .update_perf_stats(&self.ctrl_info.1, &mut stats.tcp.counts_peers[server]);
+        self.peer_counts = Default::default();
         stats
     }
 }
@@ -1892,6 +2145,35 @@ mod tests {
         assert_eq!(perf.perf_data, perf_data);
     }
 
+    #[test]
+    fn latency_histograms() {
+        for flow_reversed in [false, true] {
+            let mut perf = TcpPerf::new(Arc::new(FlowPerfCounter::default()));
+            // latencies of the first packet direction are 1ms, the other direction 2ms
+            let (first, second) = (Timestamp::from_millis(1), Timestamp::from_millis(2));
+            perf.perf_data.calc_srt(first, true);
+            perf.perf_data.calc_srt(second, false);
+            perf.perf_data.calc_art(first, true);
+            perf.perf_data.calc_art(second, false);
+            perf.perf_data.calc_cit(first);
+
+            let stats = perf.copy_and_reset_data(flow_reversed);
+            // srt and art are reported from the server side
+            let server = if flow_reversed { first } else { second };
+            let mut expected = LatencyHistogram::default();
+            expected.record(server.as_micros() as u32);
+            assert_eq!(stats.tcp.srt_max, server.as_micros() as u32);
+            assert_eq!(stats.tcp_histograms.srt, expected);
+            assert_eq!(stats.tcp.art_max, server.as_micros() as u32);
+            assert_eq!(stats.tcp_histograms.art, expected);
+            let mut expected = LatencyHistogram::default();
+            expected.record(first.as_micros() as u32);
+            assert_eq!(stats.tcp_histograms.cit, expected);
+
+            assert_eq!(perf.perf_data, PerfData::default());
+        }
+    }
+
     #[test]
     fn report() {
         let pcap_file = Path::new(FILE_DIR).join("art-continues-payload-len-larger-than-1.pcap");
@@ -2100,6 +2382,112 @@ mod tests {
         );
     }
 
+    #[test]
+    fn peer_counts() {
+        fn packet<'a>(
+            flags: TcpFlags,
+            seq: u32,
+            ack: u32,
+            payload_len: u16,
+            timestamp: u64,
+            sack: &[u32],
+        ) -> MetaPacket<'a> {
+            let mut packet = MetaPacket::empty();
+            packet.protocol_data = ProtocolData::TcpHeader(MetaPacketTcpHeader {
+                data_offset: 20,
+                flags,
+                seq,
+                ack,
+                win_size: 500,
+                sack: if sack.is_empty() {
+                    None
+                } else {
+                    Some(sack.iter().flat_map(|s| s.to_be_bytes()).collect())
+                },
+                ..Default::default()
+            });
+            packet.lookup_key.timestamp = Timestamp::from_millis(timestamp);
+            packet.payload_len = payload_len;
+            packet
+        }
+
+        let mut perf = TcpPerf::new(Arc::new(FlowPerfCounter::default()));
+
+        let mut syn = packet(TcpFlags::SYN, 100, 0, 0, 1, &[]);
+        if let ProtocolData::TcpHeader(h) = &mut syn.protocol_data {
+            h.win_size = 64240;
+            h.mss = 1460;
+            h.win_scale = 7;
+        }
+        let _ = perf.parse(&syn, true);
+        let mut syn_ack = packet(TcpFlags::SYN_ACK, 1000, 101, 0, 2, &[]);
+        if let ProtocolData::TcpHeader(h) = &mut syn_ack.protocol_data {
+            h.win_size = 65160;
+            h.mss = 1400;
+            h.win_scale = 9;
+        }
+        let _ = perf.parse(&syn_ack, false);
+
+        let packets = [
+            (true, packet(TcpFlags::ACK, 101, 1001, 0, 3, &[])),
+            (true, packet(TcpFlags::PSH_ACK, 101, 1001, 100, 100, &[])),
+            (true, packet(TcpFlags::PSH_ACK, 201, 1001, 100, 101, &[])),
+            (false, packet(TcpFlags::ACK, 1001, 201, 0, 110, &[])),
+            // duplicate ACKs
+            (false, packet(TcpFlags::ACK, 1001, 201, 0, 111, &[])),
+            (false, packet(TcpFlags::ACK, 1001, 201, 0, 112, &[])),
+            (false, packet(TcpFlags::ACK, 1001, 201, 0, 113, &[])),
+            // fast retransmission
+            (true, packet(TcpFlags::PSH_ACK, 201, 1001, 100, 114, &[])),
+            // D-SACK
+            (false, packet(TcpFlags::ACK, 1001, 301, 0, 120, &[201, 301])),
+            // 301..401 reordered, filled right after the gap
+            (true, packet(TcpFlags::PSH_ACK, 401, 1001, 100, 130, &[])),
+            (false, packet(TcpFlags::ACK, 1001, 301, 0, 130, &[401, 501])),
+            (true, packet(TcpFlags::PSH_ACK, 301, 1001, 100, 131, &[])),
+            // 501..601 lost before the capture point, filled after duplicate ACKs
+            (true, packet(TcpFlags::PSH_ACK, 601, 1001, 100, 140, &[])),
+            (false, packet(TcpFlags::ACK, 1001, 501, 0, 141, &[601, 701])),
+            (false, packet(TcpFlags::ACK, 1001, 501, 0, 142, &[601, 701])),
+            (false, packet(TcpFlags::ACK, 1001, 501, 0, 143, &[601, 701])),
+            (false, packet(TcpFlags::ACK, 1001, 501, 0, 144, &[601, 701])),
+            (true, packet(TcpFlags::PSH_ACK, 501, 1001, 100, 145, &[])),
+            (false, packet(TcpFlags::ACK, 1001, 701, 0, 150, &[])),
+            (true, packet(TcpFlags::PSH_ACK, 701, 1001, 100, 160, &[])),
+            // tail loss probe, neither fast retransmission nor timeout
+            (true, packet(TcpFlags::PSH_ACK, 701, 1001, 100, 200, &[])),
+            // retransmission by timeout
+            (true, packet(TcpFlags::PSH_ACK, 701, 1001, 100, 500, &[])),
+        ];
+        for (fpd, p) in packets.iter() {
+            let _ = perf.parse(p, *fpd);
+        }
+        assert!(perf.data_updated());
+
+        let stats = perf.copy_and_reset_data(false);
+        let client = &stats.tcp.counts_peers[0];
+        assert_eq!(client.retrans_count, 3);
+        assert_eq!(client.fast_retrans_count, 1);
+        assert_eq!(client.rto_count, 1);
+        assert_eq!(client.lost_count, 1);
+        assert_eq!(client.out_of_order_count, 1);
+        assert_eq!(client.bytes_in_flight_max, 400);
+        assert_eq!(
+            (client.win_size, client.mss, client.win_scale),
+            (64240, 1460, 7)
+        );
+        let server = &stats.tcp.counts_peers[1];
+        assert_eq!((server.sack_count, server.dsack_count), (6, 1));
+        assert_eq!(
+            (server.win_size, server.mss, server.win_scale),
+            (65160, 1400, 9)
+        );
+
+        let stats = perf.copy_and_reset_data(true);
+        assert_eq!(stats.tcp.counts_peers[1].fast_retrans_count, 0);
+        assert_eq!(stats.tcp.counts_peers[1].mss, 1460);
+    }
+
     fn update_test_helper<P: AsRef<Path>>(file: P, check_seq_list: bool) -> String {
         let mut output = String::new();
 
@@ -14,7 +14,7 @@
  * limitations under the License.
  */
 
-use std::cmp::max;
+use std::{cmp::max, fmt, mem};
 
 use crate::common::{
     flow::{FlowPerfStats, L4Protocol, PacketDirection},
@@ -22,19 +22,35 @@ use crate::common::{
     Timestamp,
 };
 use crate::flow_generator::error::{Error, Result};
+use crate::metric::meter::LatencyHistogram;
 
 use super::{L4FlowPerf, ART_MAX};
 
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct UdpPerf {
     req_timestamp: Timestamp,
     art_max: Timestamp,
     art_sum: Timestamp,
     art_count: u32,
+    art_histogram: LatencyHistogram,
     last_pkt_direction: PacketDirection,
     data_update_flag: bool,
 }
 
+// The histogram is left out, it only breaks down the samples summarized by the other fields
+impl fmt::Debug for UdpPerf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpPerf")
+            .field("req_timestamp", &self.req_timestamp)
+            .field("art_max", &self.art_max)
+            .field("art_sum", &self.art_sum)
+            .field("art_count", &self.art_count)
+            .field("last_pkt_direction", &self.last_pkt_direction)
+            .field("data_update_flag", &self.data_update_flag)
+            .finish()
+    }
+}
+
 impl UdpPerf {
     pub fn new() -> Self {
         UdpPerf::default()
@@ -59,6 +75,7 @@ impl L4FlowPerf for UdpPerf {
                 self.art_max = max(self.art_max, art);
                 self.art_sum += art;
                 self.art_count += 1;
+                self.art_histogram.record(art.as_micros() as u32);
                 self.data_update_flag = true;
             }
         }
@@ -78,6 +95,7 @@ impl L4FlowPerf for UdpPerf {
         stats.tcp.art_max = (self.art_max.as_nanos() / Timestamp::from_micros(1).as_nanos()) as u32;
         stats.tcp.art_sum = (self.art_sum.as_nanos() / Timestamp::from_micros(1).as_nanos()) as u32;
         stats.tcp.art_count = self.art_count;
+        stats.tcp_histograms.art = mem::take(&mut self.art_histogram);
         *self = UdpPerf::default();
 
         stats
@@ -23,8 +23,10 @@ mod parser;
 pub mod pb_adapter;
 pub(crate) mod ping;
 pub(crate) mod plugin;
+pub(crate) mod quic;
 pub(crate) mod rpc;
 pub(crate) mod sql;
+pub mod tls;
 pub use self::http::{check_http_method, parse_v1_headers, HttpInfo, HttpLog};
 use self::pb_adapter::L7ProtocolSendLog;
 
@@ -36,24 +38,16 @@ pub use mq::{
 use num_enum::TryFromPrimitive;
 pub use parser::{AppProto, MetaAppProto, SessionAggregator};
 pub use ping::{PingInfo, PingLog};
+pub use quic::{QuicInfo, QuicLog};
 pub use rpc::{
     decode_new_rpc_trace_context_with_type, BrpcInfo, BrpcLog, DubboInfo, DubboLog, SofaRpcInfo,
-    SofaRpcLog, TarsInfo, TarsLog, SOFA_NEW_RPC_TRACE_CTX_KEY,
+    SofaRpcLog, SomeIpInfo, SomeIpLog, TarsInfo, TarsLog, SOFA_NEW_RPC_TRACE_CTX_KEY,
 };
 pub use sql::{
-    MemcachedInfo, MemcachedLog, MongoDBInfo, MongoDBLog, MysqlInfo, MysqlLog, PostgreInfo,
-    PostgresqlLog, RedisInfo, RedisLog,
+    MemcachedInfo, MemcachedLog, MongoDBInfo, MongoDBLog, MysqlInfo, MysqlLog, OracleInfo,
+    OracleLog, PostgreInfo, PostgresqlLog, RedisInfo, RedisLog,
 };
-
-cfg_if::cfg_if! {
-    if #[cfg(feature = "enterprise")] {
-        pub mod tls;
-
-        pub use sql::{OracleInfo, OracleLog};
-        pub use rpc::{SomeIpInfo, SomeIpLog};
-        pub use tls::{TlsInfo, TlsLog};
-    }
-}
+pub use tls::{TlsInfo, TlsLog};
 
 #[cfg(test)]
 pub use self::plugin::wasm::{get_wasm_parser, WasmLog};
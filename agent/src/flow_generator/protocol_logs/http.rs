@@ -35,29 +35,25 @@ use super::{
 use crate::{
     common::{
         ebpf::EbpfType,
-        enums::IpProtocol,
-        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        enums::{FieldType, IpProtocol},
+        flow::{L7PerfStats, L7Protocol, L7ProtocolEnum, PacketDirection},
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
         l7_protocol_log::{L7ParseResult, L7ProtocolParserInterface, ParseParam},
         meta_packet::EbpfFlags,
     },
     config::handler::{L7LogDynamicConfig, LogParserConfig},
     flow_generator::error::{Error, Result},
-    flow_generator::protocol_logs::{set_captured_byte, L7ProtoRawDataType},
+    flow_generator::protocol_logs::{
+        plugin::custom_field_policy::{
+            field_type_support_protocol, format_payload, set_from_tag, ExtraCustomFieldPolicy,
+            ExtraField,
+        },
+        set_captured_byte, L7ProtoRawDataType,
+    },
     plugin::CustomInfo,
     utils::bytes::{read_u32_be, read_u32_le},
 };
 
-cfg_if::cfg_if! {
-if #[cfg(feature = "enterprise")] {
-        use crate::common::enums::FieldType;
-        use crate::common::flow::L7ProtocolEnum;
-        use enterprise_utils::l7::plugin::custom_field_policy::{
-            field_type_support_protocol, format_payload, set_from_tag, ExtraCustomFieldPolicy, ExtraField,
-        };
-    }
-}
-
 // priority: base field < custom policy < plugin
 const PLUGIN_FIELD_PRIORITY: u8 = 0;
 const CUSTOM_FIELD_POLICY_PRIORITY: u8 = PLUGIN_FIELD_PRIORITY + 1;
@@ -382,7 +378,6 @@ impl HttpInfo {
         }
     }
 
-    #[cfg(feature = "enterprise")]
     fn merge_policy_tags_to_http(&mut self, tags: &mut HashMap<&'static str, String>) {
         log::debug!("http merge custom poilcy tags: {:?}", tags);
         if tags.is_empty() {
@@ -760,7 +755,6 @@ impl L7ProtocolParserInterface for HttpLog {
         let mut info = HttpInfo::default();
 
         // check_payload would not init policies, so we won't handle any tags here
-        #[cfg(feature = "enterprise")]
         let mut tags = HashMap::new();
 
         if self.perf_stats.is_none() && param.parse_perf {
@@ -788,27 +782,14 @@ impl L7ProtocolParserInterface for HttpLog {
                             payload,
                             param,
                             &mut info,
-                            #[cfg(feature = "enterprise")]
                             &mut tags,
-                            #[cfg(feature = "enterprise")]
                             None,
-                            #[cfg(feature = "enterprise")]
                             None,
                         )
                         .is_ok()
                     }
                     _ => self
-                        .check_http_v2(
-                            payload,
-                            param,
-                            &mut info,
-                            #[cfg(feature = "enterprise")]
-                            &mut tags,
-                            #[cfg(feature = "enterprise")]
-                            None,
-                            #[cfg(feature = "enterprise")]
-                            None,
-                        )
+                        .check_http_v2(payload, param, &mut info, &mut tags, None, None)
                         .is_ok(),
                 }
             }
@@ -825,13 +806,11 @@ impl L7ProtocolParserInterface for HttpLog {
             self.perf_stats = Some(L7PerfStats::default())
         };
 
-        #[cfg(feature = "enterprise")]
         let port = match param.direction {
             PacketDirection::ClientToServer => param.port_dst,
             PacketDirection::ServerToClient => param.port_src,
         };
 
-        #[cfg(feature = "enterprise")]
         let (policy, policy_indices) = match self.proto {
             L7Protocol::Http1 => {
                 match config
@@ -863,25 +842,13 @@ impl L7ProtocolParserInterface for HttpLog {
                 info.proto = self.proto;
                 info.is_tls = param.is_tls();
 
-                #[cfg(feature = "enterprise")]
                 let mut tags = HashMap::new();
 
-                self.parse_http_v1(
-                    payload,
-                    param,
-                    &mut info,
-                    #[cfg(feature = "enterprise")]
-                    &mut tags,
-                    #[cfg(feature = "enterprise")]
-                    policy,
-                    #[cfg(feature = "enterprise")]
-                    policy_indices,
-                )?;
+                self.parse_http_v1(payload, param, &mut info, &mut tags, policy, policy_indices)?;
                 self.set_info_by_config(param, config, payload, &mut info);
 
                 // only handle once for a packet, it also means priority is payload > header > url (if get the same key)
                 // 对一个流量只处理一次，同时意味着优先级为 payload > header > url (如果有相同的 key)
-                #[cfg(feature = "enterprise")]
                 info.merge_policy_tags_to_http(&mut tags);
 
                 if param.parse_log {
@@ -907,7 +874,6 @@ impl L7ProtocolParserInterface for HttpLog {
                     let mut info = HttpInfo::default();
                     info.proto = self.proto;
                     info.is_tls = param.is_tls();
-                    #[cfg(feature = "enterprise")]
                     let mut tags = HashMap::new();
 
                     let ret = match param.ebpf_type {
@@ -916,22 +882,16 @@ impl L7ProtocolParserInterface for HttpLog {
                             &payload[offset..],
                             param,
                             &mut info,
-                            #[cfg(feature = "enterprise")]
                             &mut tags,
-                            #[cfg(feature = "enterprise")]
                             policy,
-                            #[cfg(feature = "enterprise")]
                             policy_indices,
                         ),
                         _ => self.parse_http_v2(
                             &payload[offset..],
                             param,
                             &mut info,
-                            #[cfg(feature = "enterprise")]
                             &mut tags,
-                            #[cfg(feature = "enterprise")]
                             policy,
-                            #[cfg(feature = "enterprise")]
                             policy_indices,
                         ),
                     };
@@ -944,7 +904,6 @@ impl L7ProtocolParserInterface for HttpLog {
                     };
                     self.set_info_by_config(param, config, &payload[offset..], &mut info);
 
-                    #[cfg(feature = "enterprise")]
                     info.merge_policy_tags_to_http(&mut tags);
 
                     if !info.is_invalid() || info.proto == L7Protocol::Grpc {
@@ -1223,9 +1182,9 @@ impl HttpLog {
         payload: &[u8],
         param: &ParseParam,
         info: &mut HttpInfo,
-        #[cfg(feature = "enterprise")] tags: &mut HashMap<&'static str, String>,
-        #[cfg(feature = "enterprise")] policy: Option<&Vec<ExtraCustomFieldPolicy>>,
-        #[cfg(feature = "enterprise")] policy_indices: Option<&Vec<usize>>,
+        tags: &mut HashMap<&'static str, String>,
+        policy: Option<&Vec<ExtraCustomFieldPolicy>>,
+        policy_indices: Option<&Vec<usize>>,
     ) -> Result<usize> {
         if payload.len() < HTTPV2_CUSTOM_DATA_MIN_LENGTH {
             return Err(Error::HttpHeaderParseFailed);
@@ -1253,7 +1212,6 @@ impl HttpLog {
         let key = &payload[HTTPV2_CUSTOM_DATA_MIN_LENGTH..val_offset];
         let val = &payload[val_offset..val_offset + val_len];
         self.on_header(config, key, val, direction, info)?;
-        #[cfg(feature = "enterprise")]
         {
             self.on_header_extra(info, direction, tags, policy, policy_indices, key, val);
             if key == b":path" {
@@ -1294,22 +1252,12 @@ impl HttpLog {
         payload: &[u8],
         param: &ParseParam,
         info: &mut HttpInfo,
-        #[cfg(feature = "enterprise")] tags: &mut HashMap<&'static str, String>,
-        #[cfg(feature = "enterprise")] policy: Option<&Vec<ExtraCustomFieldPolicy>>,
-        #[cfg(feature = "enterprise")] policy_indices: Option<&Vec<usize>>,
+        tags: &mut HashMap<&'static str, String>,
+        policy: Option<&Vec<ExtraCustomFieldPolicy>>,
+        policy_indices: Option<&Vec<usize>>,
     ) -> Result<usize> {
-        let n = self.check_http2_go_uprobe(
-            config,
-            payload,
-            param,
-            info,
-            #[cfg(feature = "enterprise")]
-            tags,
-            #[cfg(feature = "enterprise")]
-            policy,
-            #[cfg(feature = "enterprise")]
-            policy_indices,
-        )?;
+        let n =
+            self.check_http2_go_uprobe(config, payload, param, info, tags, policy, policy_indices)?;
         set_captured_byte!(info, param);
         Ok(n)
     }
@@ -1319,9 +1267,9 @@ impl HttpLog {
         payload: &[u8],
         param: &ParseParam,
         info: &mut HttpInfo,
-        #[cfg(feature = "enterprise")] tags: &mut HashMap<&'static str, String>,
-        #[cfg(feature = "enterprise")] policy: Option<&Vec<ExtraCustomFieldPolicy>>,
-        #[cfg(feature = "enterprise")] policy_indices: Option<&Vec<usize>>,
+        tags: &mut HashMap<&'static str, String>,
+        policy: Option<&Vec<ExtraCustomFieldPolicy>>,
+        policy_indices: Option<&Vec<usize>>,
     ) -> Result<()> {
         let (direction, config) = (
             param.direction,
@@ -1365,7 +1313,6 @@ impl HttpLog {
             info.version = get_http_request_version(version)?;
             info.msg_type = LogMessageType::Request;
 
-            #[cfg(feature = "enterprise")]
             self.on_http_url(info, direction, tags, policy, policy_indices);
         }
 
@@ -1397,7 +1344,6 @@ impl HttpLog {
                 content_length = Some(value.trim_start().parse::<u32>().unwrap_or_default());
             }
 
-            #[cfg(feature = "enterprise")]
             self.on_header_extra(
                 info,
                 direction,
@@ -1417,7 +1363,6 @@ impl HttpLog {
             info.req_content_length = content_length;
         }
 
-        #[cfg(feature = "enterprise")]
         self.on_payload(
             info,
             direction,
@@ -1515,9 +1460,9 @@ impl HttpLog {
         payload: &[u8],
         param: &ParseParam,
         info: &mut HttpInfo,
-        #[cfg(feature = "enterprise")] tags: &mut HashMap<&'static str, String>,
-        #[cfg(feature = "enterprise")] policy: Option<&Vec<ExtraCustomFieldPolicy>>,
-        #[cfg(feature = "enterprise")] policy_indices: Option<&Vec<usize>>,
+        tags: &mut HashMap<&'static str, String>,
+        policy: Option<&Vec<ExtraCustomFieldPolicy>>,
+        policy_indices: Option<&Vec<usize>>,
     ) -> Result<usize> {
         let (direction, config) = (
             param.direction,
@@ -1607,7 +1552,6 @@ impl HttpLog {
                     if key == b"content-length" {
                         content_length = Some(val.parse_to().unwrap_or_default())
                     }
-                    #[cfg(feature = "enterprise")]
                     {
                         self.on_header_extra(
                             info,
@@ -1719,26 +1663,15 @@ impl HttpLog {
         payload: &[u8],
         param: &ParseParam,
         info: &mut HttpInfo,
-        #[cfg(feature = "enterprise")] tags: &mut HashMap<&'static str, String>,
-        #[cfg(feature = "enterprise")] policy: Option<&Vec<ExtraCustomFieldPolicy>>,
-        #[cfg(feature = "enterprise")] policy_indices: Option<&Vec<usize>>,
+        tags: &mut HashMap<&'static str, String>,
+        policy: Option<&Vec<ExtraCustomFieldPolicy>>,
+        policy_indices: Option<&Vec<usize>>,
     ) -> Result<usize> {
-        let n = self.check_http_v2(
-            payload,
-            param,
-            info,
-            #[cfg(feature = "enterprise")]
-            tags,
-            #[cfg(feature = "enterprise")]
-            policy,
-            #[cfg(feature = "enterprise")]
-            policy_indices,
-        )?;
+        let n = self.check_http_v2(payload, param, info, tags, policy, policy_indices)?;
         set_captured_byte!(info, param);
         Ok(n)
     }
 
-    #[cfg(feature = "enterprise")]
     fn on_http_url(
         &mut self,
         info: &mut HttpInfo,
@@ -1792,7 +1725,6 @@ impl HttpLog {
         }
     }
 
-    #[cfg(feature = "enterprise")]
     fn on_header_extra(
         &mut self,
         info: &mut HttpInfo,
@@ -1849,7 +1781,6 @@ impl HttpLog {
         }
     }
 
-    #[cfg(feature = "enterprise")]
     fn on_payload(
         &mut self,
         info: &mut HttpInfo,
@@ -2360,14 +2291,10 @@ mod tests {
     use std::slice::from_raw_parts;
     use std::time::Duration;
 
-    cfg_if::cfg_if! {
-    if #[cfg(feature = "enterprise")] {
-            use enterprise_utils::l7::plugin::custom_field_policy::{ExtraCustomFieldPolicy, ExtraField};
-            use public::enums::{FieldType, MatchType};
-            use public::segment_map::{SegmentMap, SegmentBuilder};
-            use crate::config::config::ExtraCustomFieldPolicyMap;
-        }
-    }
+    use public::enums::MatchType;
+    use public::segment_map::{SegmentBuilder, SegmentMap};
+
+    use crate::config::config::ExtraCustomFieldPolicyMap;
 
     use super::*;
 
@@ -2390,7 +2317,6 @@ mod tests {
             vec![TraceType::Sw8],
             ExtraLogFields::default(),
             grpc_streaming_data_enabled,
-            #[cfg(feature = "enterprise")]
             HashMap::new(),
         );
         let parse_config = &LogParserConfig {
@@ -2653,11 +2579,8 @@ mod tests {
                     &payload,
                     param,
                     &mut info,
-                    #[cfg(feature = "enterprise")]
                     &mut HashMap::new(),
-                    #[cfg(feature = "enterprise")]
                     None,
-                    #[cfg(feature = "enterprise")]
                     None,
                 );
                 assert_eq!(res.is_ok(), false);
@@ -2694,11 +2617,8 @@ mod tests {
                 &payload,
                 param,
                 &mut info,
-                #[cfg(feature = "enterprise")]
                 &mut HashMap::new(),
-                #[cfg(feature = "enterprise")]
                 None,
-                #[cfg(feature = "enterprise")]
                 None,
             );
             assert_eq!(res.is_ok(), true);
@@ -2786,11 +2706,8 @@ mod tests {
             &payload,
             &param,
             &mut HttpInfo::default(),
-            #[cfg(feature = "enterprise")]
             &mut HashMap::new(),
-            #[cfg(feature = "enterprise")]
             None,
-            #[cfg(feature = "enterprise")]
             None,
         );
     }
@@ -2989,7 +2906,6 @@ mod tests {
             vec!["x-b3-spanid".into(), "traceparent".into(), "sw8".into()],
             ExtraLogFields::default(),
             false,
-            #[cfg(feature = "enterprise")]
             HashMap::new(),
         );
 
@@ -3092,7 +3008,6 @@ mod tests {
         assert!(parser.check_payload("GET / HTTP/1.1\r\n\r\n".as_bytes(), &param));
     }
 
-    #[cfg(feature = "enterprise")]
     #[test]
     fn test_extra_field_policy() {
         let mut parser = HttpLog::new_v1();
@@ -3246,7 +3161,6 @@ mod tests {
         assert_eq!(info.span_id.field, "test_parse_span_id");
     }
 
-    #[cfg(feature = "enterprise")]
     #[test]
     fn test_extra_field_policy_in_payload() {
         let mut parser = HttpLog::new_v1();
@@ -2323,7 +2323,6 @@ mod tests {
                 vec![TraceType::Sw8, TraceType::TraceParent],
                 ExtraLogFields::default(),
                 false,
-                #[cfg(feature = "enterprise")]
                 std::collections::HashMap::new(),
             );
             let parse_config = &LogParserConfig {
@@ -2405,7 +2404,6 @@ mod tests {
             vec![TraceType::Sw8, TraceType::TraceParent],
             ExtraLogFields::default(),
             false,
-            #[cfg(feature = "enterprise")]
             std::collections::HashMap::new(),
         );
         let (trace_id, span_id) = parse_trace_and_span(payload, &config).unwrap();
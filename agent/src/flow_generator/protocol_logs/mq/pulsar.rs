@@ -1005,7 +1005,6 @@ mod tests {
                 vec![TraceType::Sw8, TraceType::TraceParent],
                 ExtraLogFields::default(),
                 false,
-                #[cfg(feature = "enterprise")]
                 std::collections::HashMap::new(),
             );
             let parse_config = &LogParserConfig {
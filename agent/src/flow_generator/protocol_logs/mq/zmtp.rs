@@ -18,6 +18,10 @@ use crate::{
         WasmData,
     },
 };
+use public::zmtp::{
+    FILLER_SIZE, FLAG_COMMAND, FLAG_LONG, FLAG_MORE, GREETING_SIZE, MAJOR_VERSION, MECHANISM_SIZE,
+    PROPERTY_SOCKET_TYPE, SIGNATURE_END, SIGNATURE_SIZE, SIGNATURE_START,
+};
 use serde::Serialize;
 use std::fmt;
 
@@ -334,14 +338,14 @@ fn parse_long(payload: &[u8]) -> Option<(&[u8], u64)> {
 }
 
 fn check_major_version(major_version: u8) -> Result<()> {
-    if major_version != 3 {
+    if major_version != MAJOR_VERSION {
         return Err(Error::ZmtpLogParseFailed);
     }
     Ok(())
 }
 fn check_minor_version(major_version: u8, minor_version: u8) -> Result<()> {
     // 3.0, 3.1
-    if major_version == 3 && minor_version <= 1 {
+    if major_version == MAJOR_VERSION && minor_version <= 1 {
         return Ok(());
     }
     Err(Error::ZmtpLogParseFailed)
@@ -350,11 +354,12 @@ fn check_minor_version(major_version: u8, minor_version: u8) -> Result<()> {
 impl ZmtpLog {
     fn parse_greeting<'a>(payload: &'a [u8], info: &mut ZmtpInfo) -> Result<&'a [u8]> {
         info.frame_type = FrameType::Greeting;
-        let payload = if let Some(0xff) = payload.get(0) {
+        let payload = if let Some(&SIGNATURE_START) = payload.get(0) {
             // full greeting header
             // signature: 0xff 8OCTET 0x7f
-            let (payload, signature) = parse_bytes(payload, 10).ok_or(Error::ZmtpLogParseFailed)?;
-            if signature[9] != 0x7f {
+            let (payload, signature) =
+                parse_bytes(payload, SIGNATURE_SIZE).ok_or(Error::ZmtpLogParseFailed)?;
+            if signature[SIGNATURE_SIZE - 1] != SIGNATURE_END {
                 return Err(Error::ZmtpLogParseFailed);
             }
             // major version
@@ -366,15 +371,15 @@ impl ZmtpLog {
             check_minor_version(major_version, minor_version)?;
             info.minor_version = Some(minor_version);
             payload
-        } else if payload.len() == 53 {
+        } else if payload.len() == GREETING_SIZE - SIGNATURE_SIZE - 1 {
             // partial greeting header
             // minor version
             let (payload, minor_version) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
-            check_minor_version(3, minor_version)?;
-            info.major_version = Some(3);
+            check_minor_version(MAJOR_VERSION, minor_version)?;
+            info.major_version = Some(MAJOR_VERSION);
             info.minor_version = Some(minor_version);
             payload
-        } else if payload.len() == 54 {
+        } else if payload.len() == GREETING_SIZE - SIGNATURE_SIZE {
             // partial greeting header
             // major version
             let (payload, major_version) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
@@ -389,7 +394,8 @@ impl ZmtpLog {
             return Err(Error::ZmtpLogParseFailed);
         };
         // mechanism
-        let (payload, mechanism) = parse_bytes(payload, 20).ok_or(Error::ZmtpLogParseFailed)?;
+        let (payload, mechanism) =
+            parse_bytes(payload, MECHANISM_SIZE).ok_or(Error::ZmtpLogParseFailed)?;
         let mechanism = match &mechanism[..5] {
             b"NULL\0" => Mechanism::NULL,
             b"PLAIN" => Mechanism::PLAIN,
@@ -403,7 +409,8 @@ impl ZmtpLog {
             return Err(Error::ZmtpLogParseFailed);
         }
         // filler
-        let (payload, filler) = parse_bytes(payload, 31).ok_or(Error::ZmtpLogParseFailed)?;
+        let (payload, filler) =
+            parse_bytes(payload, FILLER_SIZE).ok_or(Error::ZmtpLogParseFailed)?;
         if filler.iter().any(|&x| x != 0) {
             return Err(Error::ZmtpLogParseFailed);
         }
@@ -418,22 +425,21 @@ impl ZmtpLog {
         // command size
         let (payload, size_type) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
         info.more_frames = Some(false);
-        let (payload, size) = match size_type {
-            0x04 => {
-                // short-size
-                let (payload, size) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
-                (payload, size as u64)
-            }
-            0x06 => {
-                // long-size
-                let (payload, size) = parse_long(payload).ok_or(Error::ZmtpLogParseFailed)?;
-                // size are unlikely to surpass 2^31
-                if size < u8::MAX as u64 || size > i32::MAX as u64 {
-                    return Err(Error::ZmtpLogParseFailed);
-                }
-                (payload, size as u64)
+        if size_type & !FLAG_LONG != FLAG_COMMAND {
+            return Err(Error::ZmtpLogParseFailed);
+        }
+        let (payload, size) = if size_type & FLAG_LONG == 0 {
+            // short-size
+            let (payload, size) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
+            (payload, size as u64)
+        } else {
+            // long-size
+            let (payload, size) = parse_long(payload).ok_or(Error::ZmtpLogParseFailed)?;
+            // size are unlikely to surpass 2^31
+            if size < u8::MAX as u64 || size > i32::MAX as u64 {
+                return Err(Error::ZmtpLogParseFailed);
             }
-            _ => return Err(Error::ZmtpLogParseFailed),
+            (payload, size as u64)
         };
         info.req_msg_size = Some(size);
         // command body
@@ -482,10 +488,11 @@ impl ZmtpLog {
                 match mechanism {
                     Some(Mechanism::NULL) | Some(Mechanism::PLAIN) | None => {
                         // socket type
+                        let property = PROPERTY_SOCKET_TYPE.as_bytes();
                         if let Some(data) = data
-                            .windows(11)
-                            .position(|w| w == b"Socket-Type")
-                            .map(|i| &data[i + 11..])
+                            .windows(property.len())
+                            .position(|w| w == property)
+                            .map(|i| &data[i + property.len()..])
                         {
                             if let Some((data, length)) = parse_integer(data) {
                                 if let Some((_, socket_type)) = parse_bytes(data, length as usize) {
@@ -517,27 +524,22 @@ impl ZmtpLog {
         info.frame_type = FrameType::Message;
         // message size
         let (payload, size_type) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
-        info.more_frames = match size_type {
-            0x00 | 0x02 => Some(false),
-            0x01 | 0x03 => Some(true),
-            _ => return Err(Error::ZmtpLogParseFailed),
-        };
-        let (payload, size) = match size_type {
-            0x00 | 0x01 => {
-                // short-size
-                let (payload, size) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
-                (payload, size as u64)
-            }
-            0x02 | 0x03 => {
-                // long-size
-                let (payload, size) = parse_long(payload).ok_or(Error::ZmtpLogParseFailed)?;
-                // size are unlikely to surpass 2^31
-                if size < u8::MAX as u64 || size > i32::MAX as u64 {
-                    return Err(Error::ZmtpLogParseFailed);
-                }
-                (payload, size as u64)
+        if size_type & !(FLAG_MORE | FLAG_LONG) != 0 {
+            return Err(Error::ZmtpLogParseFailed);
+        }
+        info.more_frames = Some(size_type & FLAG_MORE != 0);
+        let (payload, size) = if size_type & FLAG_LONG == 0 {
+            // short-size
+            let (payload, size) = parse_byte(payload).ok_or(Error::ZmtpLogParseFailed)?;
+            (payload, size as u64)
+        } else {
+            // long-size
+            let (payload, size) = parse_long(payload).ok_or(Error::ZmtpLogParseFailed)?;
+            // size are unlikely to surpass 2^31
+            if size < u8::MAX as u64 || size > i32::MAX as u64 {
+                return Err(Error::ZmtpLogParseFailed);
             }
-            _ => return Err(Error::ZmtpLogParseFailed),
+            (payload, size as u64)
         };
         info.req_msg_size = Some(size);
         // message body
@@ -0,0 +1,429 @@
+/*
+ * Copyright (c) 2025 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::str;
+
+use regex::Regex;
+use serde_json::Value;
+
+use public::{
+    enums::{Charset, FieldType, MatchType},
+    l7_protocol::L7Protocol,
+};
+
+const DEFAULT_SUBFIELD_KV_PAIR_SEPARATOR: &str = ";";
+const DEFAULT_SUBFIELD_KEY_VALUE_SEPARATOR: &str = "=";
+
+const RESPONSE_STATUS_OK: &str = "ok";
+const RESPONSE_STATUS_SERVER_ERROR: &str = "server_error";
+
+// overwrite `$field` if the policy has extracted a value for native tag `$key`
+macro_rules! set_from_tag {
+    ($field:expr, $tags:expr, $key:expr) => {
+        if let Some(v) = $tags.remove($key) {
+            $field = v;
+        }
+    };
+}
+
+pub(crate) use set_from_tag;
+
+// regex used by `payload_regex_value`, compared by its pattern so that policies stay comparable
+#[derive(Clone, Debug)]
+pub struct FieldRegex(Regex);
+
+impl FieldRegex {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self)
+    }
+
+    // the first capture group is preferred, otherwise the whole match is used
+    fn extract(&self, text: &str) -> Option<String> {
+        let captures = self.0.captures(text)?;
+        captures
+            .get(1)
+            .or_else(|| captures.get(0))
+            .map(|m| m.as_str().to_owned())
+    }
+}
+
+impl PartialEq for FieldRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for FieldRegex {}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtraField {
+    pub field_match_type: MatchType,
+    pub field_match_keyword: String,
+    // only for payload_regex_value, compiled from field_match_keyword
+    pub field_regex: Option<FieldRegex>,
+    pub subfield_match_keyword: Option<String>,
+    pub separator_between_subfield_kv_pair: Option<String>,
+    pub separator_between_subfield_key_and_value: Option<String>,
+    pub check_value_charset: bool,
+    pub value_primary_charset: Vec<Charset>,
+    pub value_special_charset: String,
+    pub attribute_name: Option<String>,
+    pub rewrite_native_tag: String,
+    pub response_success_values: Vec<String>,
+    pub metric_name: Option<String>,
+}
+
+impl ExtraField {
+    pub const VERSION: &'static str = "version";
+    pub const REQUEST_TYPE: &'static str = "request_type";
+    pub const REQUEST_DOMAIN: &'static str = "request_domain";
+    pub const REQUEST_RESOURCE: &'static str = "request_resource";
+    pub const REQUEST_ID: &'static str = "request_id";
+    pub const ENDPOINT: &'static str = "endpoint";
+    pub const RESPONSE_CODE: &'static str = "response_code";
+    pub const RESPONSE_STATUS: &'static str = "response_status";
+    pub const RESPONSE_EXCEPTION: &'static str = "response_exception";
+    pub const RESPONSE_RESULT: &'static str = "response_result";
+    pub const TRACE_ID: &'static str = "trace_id";
+    pub const SPAN_ID: &'static str = "span_id";
+    pub const X_REQUEST_ID: &'static str = "x_request_id";
+    pub const HTTP_PROXY_CLIENT: &'static str = "http_proxy_client";
+
+    fn native_tag(tag: &str) -> Option<&'static str> {
+        let tag = match tag {
+            Self::VERSION => Self::VERSION,
+            Self::REQUEST_TYPE => Self::REQUEST_TYPE,
+            Self::REQUEST_DOMAIN => Self::REQUEST_DOMAIN,
+            Self::REQUEST_RESOURCE => Self::REQUEST_RESOURCE,
+            Self::REQUEST_ID => Self::REQUEST_ID,
+            Self::ENDPOINT => Self::ENDPOINT,
+            Self::RESPONSE_CODE => Self::RESPONSE_CODE,
+            Self::RESPONSE_EXCEPTION => Self::RESPONSE_EXCEPTION,
+            Self::RESPONSE_RESULT => Self::RESPONSE_RESULT,
+            Self::TRACE_ID => Self::TRACE_ID,
+            Self::SPAN_ID => Self::SPAN_ID,
+            Self::X_REQUEST_ID => Self::X_REQUEST_ID,
+            Self::HTTP_PROXY_CLIENT => Self::HTTP_PROXY_CLIENT,
+            _ => return None,
+        };
+        Some(tag)
+    }
+
+    fn key_equals(&self, key: &str, keyword: &str) -> bool {
+        match self.field_match_type {
+            MatchType::String(true) => key.eq_ignore_ascii_case(keyword),
+            _ => key == keyword,
+        }
+    }
+
+    pub fn match_key(&self, key: &str) -> bool {
+        self.key_equals(key, &self.field_match_keyword)
+    }
+
+    fn extract_subfield<'a>(&self, value: &'a str) -> Option<&'a str> {
+        let Some(subfield) = self.subfield_match_keyword.as_ref() else {
+            return Some(value);
+        };
+        let kv_pair_separator = match self.separator_between_subfield_kv_pair.as_deref() {
+            Some(s) if !s.is_empty() => s,
+            _ => DEFAULT_SUBFIELD_KV_PAIR_SEPARATOR,
+        };
+        let key_value_separator = match self.separator_between_subfield_key_and_value.as_deref() {
+            Some(s) if !s.is_empty() => s,
+            _ => DEFAULT_SUBFIELD_KEY_VALUE_SEPARATOR,
+        };
+        value.split(kv_pair_separator).find_map(|pair| {
+            let (key, value) = pair.split_once(key_value_separator)?;
+            self.key_equals(key.trim(), subfield).then(|| value.trim())
+        })
+    }
+
+    fn check_charset(&self, value: &str) -> bool {
+        value.chars().all(|c| {
+            self.value_primary_charset.iter().any(|cs| cs.check(&c))
+                || self.value_special_charset.contains(c)
+        })
+    }
+
+    // returns the extracted value, and records it in `tags` if a native tag should be rewritten
+    pub fn set_value(&self, val: &str, tags: &mut HashMap<&'static str, String>) -> Option<String> {
+        let value = self.extract_subfield(val)?;
+        if value.is_empty() || (self.check_value_charset && !self.check_charset(value)) {
+            return None;
+        }
+        if let Some(tag) = Self::native_tag(&self.rewrite_native_tag) {
+            tags.insert(tag, value.to_owned());
+            if tag == Self::RESPONSE_CODE && !self.response_success_values.is_empty() {
+                let status = if self.response_success_values.iter().any(|v| v == value) {
+                    RESPONSE_STATUS_OK
+                } else {
+                    RESPONSE_STATUS_SERVER_ERROR
+                };
+                tags.insert(Self::RESPONSE_STATUS, status.to_owned());
+            }
+        }
+        Some(value.to_owned())
+    }
+
+    pub fn get_value_from_payload(&self, payload: &PayloadEntry) -> Option<String> {
+        match payload {
+            PayloadEntry::Json(root) => {
+                let value = match self.field_match_keyword.strip_prefix('$') {
+                    Some(path) => json_path_lookup(root, path),
+                    None => self.json_key_lookup(root),
+                }?;
+                json_value_to_string(value)
+            }
+            PayloadEntry::Xml(elements) => elements
+                .iter()
+                .find(|(name, _)| self.match_key(name))
+                .map(|(_, text)| text.clone()),
+            PayloadEntry::Text(text) => match self.field_regex.as_ref() {
+                Some(re) => re.extract(text),
+                None => self.json_key_scan(text),
+            },
+        }
+    }
+
+    // depth first search for the first object key matching this field
+    fn json_key_lookup<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        match value {
+            Value::Object(map) => {
+                if let Some((_, v)) = map.iter().find(|(k, _)| self.match_key(k)) {
+                    return Some(v);
+                }
+                map.values().find_map(|v| self.json_key_lookup(v))
+            }
+            Value::Array(values) => values.iter().find_map(|v| self.json_key_lookup(v)),
+            _ => None,
+        }
+    }
+
+    // fallback for json payloads that cannot be parsed, usually truncated by capture length
+    fn json_key_scan(&self, text: &str) -> Option<String> {
+        let keyword = &self.field_match_keyword;
+        if keyword.is_empty() || keyword.starts_with('$') {
+            return None;
+        }
+        let mut offset = 0;
+        while let Some(pos) = text[offset..].find('"') {
+            let key_start = offset + pos + 1;
+            let key_end = key_start + text[key_start..].find('"')?;
+            offset = key_end + 1;
+            if !self.match_key(&text[key_start..key_end]) {
+                continue;
+            }
+            let rest = text[offset..].trim_start();
+            let Some(rest) = rest.strip_prefix(':') else {
+                continue;
+            };
+            let rest = rest.trim_start();
+            let value = match rest.strip_prefix('"') {
+                Some(s) => &s[..s.find('"')?],
+                None => rest
+                    .split(|c: char| c == ',' || c == '}' || c == ']' || c.is_whitespace())
+                    .next()?,
+            };
+            if value.is_empty() || value == "null" {
+                return None;
+            }
+            return Some(value.to_owned());
+        }
+        None
+    }
+}
+
+pub enum PayloadEntry {
+    Json(Value),
+    // (element name, element text) in document order
+    Xml(Vec<(String, String)>),
+    Text(String),
+}
+
+// `path` is the part after `$`, e.g. `.data.users[0].id`
+fn json_path_lookup<'a>(root: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = root;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let (name, mut indices) = match segment.find('[') {
+            Some(i) => (&segment[..i], &segment[i..]),
+            None => (segment, ""),
+        };
+        if !name.is_empty() {
+            current = current.get(name)?;
+        }
+        while let Some(s) = indices.strip_prefix('[') {
+            let end = s.find(']')?;
+            current = current.get(s[..end].trim().parse::<usize>().ok()?)?;
+            indices = &s[end + 1..];
+        }
+    }
+    Some(current)
+}
+
+fn json_value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+pub fn field_type_support_protocol(field_type: &FieldType, protocol: L7Protocol) -> bool {
+    match field_type {
+        FieldType::Header => matches!(
+            protocol,
+            L7Protocol::Http1 | L7Protocol::Http2 | L7Protocol::Grpc | L7Protocol::Dubbo
+        ),
+        FieldType::HttpUrl => matches!(
+            protocol,
+            L7Protocol::Http1 | L7Protocol::Http2 | L7Protocol::Grpc
+        ),
+        FieldType::PayloadJson | FieldType::PayloadXml | FieldType::PayloadRegex => {
+            matches!(protocol, L7Protocol::Http1 | L7Protocol::Http2)
+        }
+        FieldType::PayloadHessian2 => protocol == L7Protocol::Dubbo,
+    }
+}
+
+pub fn format_payload(field_type: &FieldType, payload: &[u8]) -> Option<PayloadEntry> {
+    match field_type {
+        FieldType::PayloadJson => match serde_json::from_slice::<Value>(payload) {
+            Ok(v @ (Value::Object(_) | Value::Array(_))) => Some(PayloadEntry::Json(v)),
+            Ok(_) => None,
+            Err(_) => {
+                let text = String::from_utf8_lossy(payload);
+                let trimmed = text.trim_start();
+                (trimmed.starts_with('{') || trimmed.starts_with('['))
+                    .then(|| PayloadEntry::Text(text.into_owned()))
+            }
+        },
+        FieldType::PayloadXml => {
+            let text = str::from_utf8(payload).ok()?;
+            let doc = roxmltree::Document::parse(text).ok()?;
+            let elements = doc
+                .descendants()
+                .filter(|n| n.is_element())
+                .filter_map(|n| {
+                    let text = n.text()?.trim();
+                    (!text.is_empty()).then(|| (n.tag_name().name().to_owned(), text.to_owned()))
+                })
+                .collect::<Vec<_>>();
+            (!elements.is_empty()).then_some(PayloadEntry::Xml(elements))
+        }
+        FieldType::PayloadRegex => Some(PayloadEntry::Text(
+            String::from_utf8_lossy(payload).into_owned(),
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtraCustomFieldPolicy {
+    // key is lowercase field_match_keyword
+    pub from_req_key: HashMap<FieldType, HashMap<String, Vec<ExtraField>>>,
+    pub from_resp_key: HashMap<FieldType, HashMap<String, Vec<ExtraField>>>,
+    pub from_req_body: HashMap<FieldType, Vec<ExtraField>>,
+    pub from_resp_body: HashMap<FieldType, Vec<ExtraField>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(keyword: &str, ignore_case: bool) -> ExtraField {
+        ExtraField {
+            field_match_type: MatchType::String(ignore_case),
+            field_match_keyword: keyword.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_set_value() {
+        let mut tags = HashMap::new();
+        let mut f = field("cookie", true);
+        f.subfield_match_keyword = Some("uid".into());
+        f.separator_between_subfield_kv_pair = Some(",".into());
+        f.rewrite_native_tag = ExtraField::REQUEST_ID.into();
+        assert_eq!(
+            f.set_value("a=1, uid=42 ,c=3", &mut tags),
+            Some("42".into())
+        );
+        assert_eq!(tags.get(ExtraField::REQUEST_ID), Some(&"42".to_owned()));
+        assert_eq!(f.set_value("a=1", &mut tags), None);
+
+        let mut f = field("code", false);
+        f.rewrite_native_tag = ExtraField::RESPONSE_CODE.into();
+        f.response_success_values = vec!["0".into()];
+        f.check_value_charset = true;
+        f.value_primary_charset = vec![Charset::Digits];
+        f.value_special_charset = "-".into();
+        assert_eq!(f.set_value("-1", &mut tags), Some("-1".into()));
+        assert_eq!(
+            tags.get(ExtraField::RESPONSE_STATUS),
+            Some(&"server_error".to_owned())
+        );
+        assert_eq!(f.set_value("0", &mut tags), Some("0".into()));
+        assert_eq!(
+            tags.get(ExtraField::RESPONSE_STATUS),
+            Some(&"ok".to_owned())
+        );
+        assert_eq!(f.set_value("x1", &mut tags), None);
+    }
+
+    #[test]
+    fn test_json_payload() {
+        let payload = br#"{"data": {"users": [{"ID": 7, "name": "a"}], "token": null}}"#;
+        let entry = format_payload(&FieldType::PayloadJson, payload).unwrap();
+        assert_eq!(
+            field("$.data.users[0].ID", false).get_value_from_payload(&entry),
+            Some("7".into())
+        );
+        assert_eq!(
+            field("id", true).get_value_from_payload(&entry),
+            Some("7".into())
+        );
+        assert_eq!(field("id", false).get_value_from_payload(&entry), None);
+        assert_eq!(field("token", false).get_value_from_payload(&entry), None);
+
+        // truncated payload falls back to scanning
+        let entry = format_payload(&FieldType::PayloadJson, &payload[..46]).unwrap();
+        assert_eq!(
+            field("name", false).get_value_from_payload(&entry),
+            Some("a".into())
+        );
+        assert_eq!(
+            field("ID", false).get_value_from_payload(&entry),
+            Some("7".into())
+        );
+    }
+
+    #[test]
+    fn test_xml_and_regex_payload() {
+        let payload = br#"<req><head><traceId> abc </traceId></head><body a="1">x</body></req>"#;
+        let entry = format_payload(&FieldType::PayloadXml, payload).unwrap();
+        assert_eq!(
+            field("traceid", true).get_value_from_payload(&entry),
+            Some("abc".into())
+        );
+
+        let entry = format_payload(&FieldType::PayloadRegex, b"order_no=A-1024&x=1").unwrap();
+        let mut f = field(r"order_no=([\w-]+)", false);
+        f.field_regex = FieldRegex::new(&f.field_match_keyword).ok();
+        assert_eq!(f.get_value_from_payload(&entry), Some("A-1024".into()));
+    }
+}
@@ -20,7 +20,7 @@ use wasm::WasmLog;
 
 use crate::{
     common::{
-        flow::L7PerfStats,
+        flow::{L7PerfStats, QuicPerfStats},
         l7_protocol_log::{L7ParseResult, L7ProtocolParser, L7ProtocolParserInterface, ParseParam},
     },
     flow_generator::{protocol_logs::sql::ObfuscateCache, Result},
@@ -30,6 +30,7 @@ use crate::{
 use self::shared_obj::{get_so_parser, SoLog};
 use self::{custom_wrap::CustomWrapLog, wasm::get_wasm_parser};
 
+pub mod custom_field_policy;
 pub mod custom_wrap;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub mod shared_obj;
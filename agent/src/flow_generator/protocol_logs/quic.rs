@@ -0,0 +1,1066 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// QUIC (RFC 9000) and QUIC version 2 (RFC 9369)
+//
+// Only the long header packets are readable on the wire. Initial packets are protected with keys
+// derived from the first destination connection ID of the client (RFC 9001 section 5.2), so they
+// are decrypted to extract the SNI of the ClientHello, the cipher suite of the ServerHello and
+// CONNECTION_CLOSE frames of failed handshakes. Handshake and 1-RTT packets are only counted.
+
+use std::fmt;
+
+use ring::aead::{self, quic, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::hkdf;
+use serde::Serialize;
+
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
+use super::tls::CipherSuite;
+use super::{set_captured_byte, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+use crate::config::handler::LogParserConfig;
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{L7PerfStats, PacketDirection, QuicPerfStats},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ParseResult, L7ProtocolParserInterface, ParseParam},
+    },
+    flow_generator::error::{Error, Result},
+};
+use l7::tls::TlsHeader;
+use public::l7_protocol::L7Protocol;
+
+const VERSION_1: u32 = 0x00000001;
+const VERSION_2: u32 = 0x6b3343cf;
+
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+const INITIAL_SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+
+const MAX_CID_LEN: usize = 20;
+// the first Initial of a client is at least 1200 bytes with a destination connection ID of at
+// least 8 bytes
+const MIN_CLIENT_INITIAL_LEN: usize = 1200;
+const MIN_CLIENT_INITIAL_DCID_LEN: usize = 8;
+
+const FRAME_PADDING: u64 = 0x00;
+const FRAME_PING: u64 = 0x01;
+const FRAME_ACK: u64 = 0x02;
+const FRAME_ACK_ECN: u64 = 0x03;
+const FRAME_CRYPTO: u64 = 0x06;
+const FRAME_CONNECTION_CLOSE: u64 = 0x1c;
+const FRAME_CONNECTION_CLOSE_APP: u64 = 0x1d;
+
+const NO_ERROR: u64 = 0x00;
+
+fn read_varint(buf: &[u8], offset: &mut usize) -> Option<u64> {
+    let first = *buf.get(*offset)?;
+    let len = 1 << (first >> 6);
+    let bytes = buf.get(*offset..*offset + len)?;
+    let mut value = (first & 0x3f) as u64;
+    for b in &bytes[1..] {
+        value = value << 8 | *b as u64;
+    }
+    *offset += len;
+    Some(value)
+}
+
+fn version_name(version: u32) -> String {
+    match version {
+        VERSION_1 => "1".to_string(),
+        VERSION_2 => "2".to_string(),
+        v => format!("0x{:08x}", v),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PacketType {
+    VersionNegotiation,
+    Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
+    OneRtt,
+}
+
+impl PacketType {
+    // QUIC version 2 shifts the long header packet types by one
+    fn from_long_header(version: u32, bits: u8) -> Self {
+        let bits = if version == VERSION_2 {
+            bits.wrapping_sub(1) & 0x03
+        } else {
+            bits
+        };
+        match bits {
+            0 => Self::Initial,
+            1 => Self::ZeroRtt,
+            2 => Self::Handshake,
+            _ => Self::Retry,
+        }
+    }
+}
+
+impl fmt::Display for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VersionNegotiation => write!(f, "VersionNegotiation"),
+            Self::Initial => write!(f, "Initial"),
+            Self::ZeroRtt => write!(f, "0-RTT"),
+            Self::Handshake => write!(f, "Handshake"),
+            Self::Retry => write!(f, "Retry"),
+            Self::OneRtt => write!(f, "1-RTT"),
+        }
+    }
+}
+
+struct LongHeader<'a> {
+    packet_type: PacketType,
+    version: u32,
+    dcid: &'a [u8],
+    scid: &'a [u8],
+    // offset of the packet number, 0 for Version Negotiation and Retry packets
+    pn_offset: usize,
+    // Initial, 0-RTT and Handshake packets can be coalesced into one datagram,
+    // the others take up the rest of it
+    len: usize,
+}
+
+impl<'a> LongHeader<'a> {
+    fn parse(packet: &'a [u8]) -> Option<Self> {
+        let first = *packet.first()?;
+        if first & 0x80 == 0 {
+            return None;
+        }
+        let version = u32::from_be_bytes(packet.get(1..5)?.try_into().unwrap());
+        let mut offset = 5;
+        let dcid_len = *packet.get(offset)? as usize;
+        let dcid = packet.get(offset + 1..offset + 1 + dcid_len)?;
+        offset += 1 + dcid_len;
+        let scid_len = *packet.get(offset)? as usize;
+        let scid = packet.get(offset + 1..offset + 1 + scid_len)?;
+        offset += 1 + scid_len;
+
+        let mut header = Self {
+            packet_type: PacketType::VersionNegotiation,
+            version,
+            dcid,
+            scid,
+            pn_offset: 0,
+            len: packet.len(),
+        };
+        if version == 0 {
+            // supported versions follow
+            return if (packet.len() - offset) % 4 == 0 {
+                Some(header)
+            } else {
+                None
+            };
+        }
+        // connection IDs of the versions after version negotiation are limited to 20 bytes
+        if first & 0x40 == 0 || dcid_len > MAX_CID_LEN || scid_len > MAX_CID_LEN {
+            return None;
+        }
+        header.packet_type = PacketType::from_long_header(version, (first >> 4) & 0x03);
+        if header.packet_type == PacketType::Retry {
+            return Some(header);
+        }
+        if header.packet_type == PacketType::Initial {
+            let token_len = read_varint(packet, &mut offset)? as usize;
+            offset += token_len;
+        }
+        let length = read_varint(packet, &mut offset)? as usize;
+        if offset + length > packet.len() {
+            return None;
+        }
+        header.pn_offset = offset;
+        header.len = offset + length;
+        Some(header)
+    }
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+// HKDF-Expand-Label of TLS 1.3 with an empty context
+fn hkdf_expand_label(prk: &hkdf::Prk, label: &[u8], out: &mut [u8]) -> Option<()> {
+    const PREFIX: &[u8] = b"tls13 ";
+    let len = (out.len() as u16).to_be_bytes();
+    let label_len = [(PREFIX.len() + label.len()) as u8];
+    let info = [&len[..], &label_len[..], PREFIX, label, &[0]];
+    prk.expand(&info, Len(out.len())).ok()?.fill(out).ok()
+}
+
+// Initial packet protection of one direction, AEAD_AES_128_GCM with AES header protection
+struct InitialKeys {
+    key: LessSafeKey,
+    iv: [u8; aead::NONCE_LEN],
+    hp: quic::HeaderProtectionKey,
+}
+
+impl InitialKeys {
+    const SAMPLE_OFFSET: usize = 4;
+    const SAMPLE_LEN: usize = 16;
+
+    fn new(version: u32, client_dcid: &[u8], is_server: bool) -> Option<Self> {
+        let (salt, key_label, iv_label, hp_label): (_, &[u8], &[u8], &[u8]) = match version {
+            VERSION_1 => (&INITIAL_SALT_V1, b"quic key", b"quic iv", b"quic hp"),
+            VERSION_2 => (&INITIAL_SALT_V2, b"quicv2 key", b"quicv2 iv", b"quicv2 hp"),
+            _ => return None,
+        };
+        let initial_secret = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(client_dcid);
+        let mut secret = [0; 32];
+        let label: &[u8] = if is_server {
+            b"server in"
+        } else {
+            b"client in"
+        };
+        hkdf_expand_label(&initial_secret, label, &mut secret)?;
+        let secret = hkdf::Prk::new_less_safe(hkdf::HKDF_SHA256, &secret);
+
+        let mut key = [0; 16];
+        hkdf_expand_label(&secret, key_label, &mut key)?;
+        let mut iv = [0; aead::NONCE_LEN];
+        hkdf_expand_label(&secret, iv_label, &mut iv)?;
+        let mut hp = [0; 16];
+        hkdf_expand_label(&secret, hp_label, &mut hp)?;
+        Some(Self {
+            key: LessSafeKey::new(UnboundKey::new(&aead::AES_128_GCM, &key).ok()?),
+            iv,
+            hp: quic::HeaderProtectionKey::new(&quic::AES_128, &hp).ok()?,
+        })
+    }
+
+    // Returns the frames of a protected Initial packet. The truncated packet number is used as
+    // is, which holds for the first 256 Initial packets of a connection.
+    fn decrypt(&self, packet: &[u8], pn_offset: usize) -> Option<Vec<u8>> {
+        let sample_offset = pn_offset + Self::SAMPLE_OFFSET;
+        let sample = packet.get(sample_offset..sample_offset + Self::SAMPLE_LEN)?;
+        let mask = self.hp.new_mask(sample).ok()?;
+        let mut packet = packet.to_vec();
+        packet[0] ^= mask[0] & 0x0f;
+        let pn_len = (packet[0] & 0x03) as usize + 1;
+        let mut pn = 0u64;
+        for i in 0..pn_len {
+            packet[pn_offset + i] ^= mask[1 + i];
+            pn = pn << 8 | packet[pn_offset + i] as u64;
+        }
+        let mut nonce = self.iv;
+        for (n, p) in nonce[aead::NONCE_LEN - 8..]
+            .iter_mut()
+            .zip(pn.to_be_bytes())
+        {
+            *n ^= p;
+        }
+
+        let header_len = pn_offset + pn_len;
+        let (header, payload) = packet.split_at_mut(header_len);
+        let plaintext_len = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&*header),
+                payload,
+            )
+            .ok()?
+            .len();
+        packet.truncate(header_len + plaintext_len);
+        packet.drain(..header_len);
+        Some(packet)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Frame<'a> {
+    Crypto {
+        offset: u64,
+        data: &'a [u8],
+    },
+    ConnectionClose {
+        frame_type: u8,
+        error_code: u64,
+        reason: &'a [u8],
+    },
+}
+
+// Frames allowed in Initial packets, parsing stops at the first unexpected one
+fn parse_frames(payload: &[u8]) -> Vec<Frame<'_>> {
+    fn next<'a>(payload: &'a [u8], offset: &mut usize) -> Option<Option<Frame<'a>>> {
+        let frame_type = read_varint(payload, offset)?;
+        match frame_type {
+            FRAME_PADDING | FRAME_PING => Some(None),
+            FRAME_ACK | FRAME_ACK_ECN => {
+                // largest acknowledged, ack delay, ack range count and first ack range
+                let _ = read_varint(payload, offset)?;
+                let _ = read_varint(payload, offset)?;
+                let range_count = read_varint(payload, offset)?;
+                let _ = read_varint(payload, offset)?;
+                for _ in 0..range_count {
+                    let _ = read_varint(payload, offset)?;
+                    let _ = read_varint(payload, offset)?;
+                }
+                if frame_type == FRAME_ACK_ECN {
+                    for _ in 0..3 {
+                        let _ = read_varint(payload, offset)?;
+                    }
+                }
+                Some(None)
+            }
+            FRAME_CRYPTO => {
+                let crypto_offset = read_varint(payload, offset)?;
+                let len = read_varint(payload, offset)? as usize;
+                let data = payload.get(*offset..*offset + len)?;
+                *offset += len;
+                Some(Some(Frame::Crypto {
+                    offset: crypto_offset,
+                    data,
+                }))
+            }
+            FRAME_CONNECTION_CLOSE | FRAME_CONNECTION_CLOSE_APP => {
+                let error_code = read_varint(payload, offset)?;
+                if frame_type == FRAME_CONNECTION_CLOSE {
+                    // type of the frame that triggered the error
+                    let _ = read_varint(payload, offset)?;
+                }
+                let len = read_varint(payload, offset)? as usize;
+                let reason = payload.get(*offset..*offset + len)?;
+                *offset += len;
+                Some(Some(Frame::ConnectionClose {
+                    frame_type: frame_type as u8,
+                    error_code,
+                    reason,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    let mut frames = vec![];
+    let mut offset = 0;
+    while offset < payload.len() {
+        match next(payload, &mut offset) {
+            Some(Some(frame)) => frames.push(frame),
+            Some(None) => (),
+            None => break,
+        }
+    }
+    frames
+}
+
+// Reassembles the beginning of the CRYPTO stream of the Initial packets. Clients may split the
+// ClientHello into several frames and packets in any order.
+#[derive(Default)]
+struct CryptoStream {
+    data: Vec<u8>,
+    pending: Vec<(usize, Vec<u8>)>,
+    finished: bool,
+}
+
+impl CryptoStream {
+    const MAX_LEN: usize = 16384;
+    const MAX_PENDING: usize = 32;
+
+    fn push(&mut self, offset: u64, data: &[u8]) {
+        if self.finished || offset >= Self::MAX_LEN as u64 {
+            return;
+        }
+        let offset = offset as usize;
+        let data = &data[..data.len().min(Self::MAX_LEN - offset)];
+        if offset > self.data.len() {
+            if self.pending.len() < Self::MAX_PENDING {
+                self.pending.push((offset, data.to_vec()));
+            }
+            return;
+        }
+        self.append(offset, data);
+        while let Some(i) = self
+            .pending
+            .iter()
+            .position(|(offset, _)| *offset <= self.data.len())
+        {
+            let (offset, data) = self.pending.swap_remove(i);
+            self.append(offset, &data);
+        }
+    }
+
+    fn append(&mut self, offset: usize, data: &[u8]) {
+        if offset + data.len() > self.data.len() {
+            self.data
+                .extend_from_slice(&data[self.data.len() - offset..]);
+        }
+    }
+
+    // the first handshake message once all of it is received, returned only once
+    fn take_first_message(&mut self) -> Option<TlsHeader> {
+        if self.finished || self.data.len() < 4 {
+            return None;
+        }
+        let len = u32::from_be_bytes([0, self.data[1], self.data[2], self.data[3]]) as usize;
+        if self.data.len() < 4 + len && self.data.len() < Self::MAX_LEN {
+            return None;
+        }
+        self.finished = true;
+        let header = TlsHeader::from_handshake_messages(&self.data);
+        self.data = vec![];
+        self.pending = vec![];
+        Some(header)
+    }
+}
+
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct QuicInfo {
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: u32,
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub request_type: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub request_domain: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub request_resource: String,
+
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<u64>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub reason: String,
+    #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
+    pub response_result: String,
+
+    // Attribute
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub client_cid: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub server_cid: String,
+    #[serde(skip)]
+    pub cipher_suite: Option<CipherSuite>,
+
+    captured_request_byte: u32,
+    captured_response_byte: u32,
+
+    msg_type: LogMessageType,
+    rrt: u64,
+    handshake_rtt: u64,
+
+    #[serde(skip)]
+    is_on_blacklist: bool,
+}
+
+impl L7ProtocolInfoInterface for QuicInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: &mut L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::QuicInfo(other) = other {
+            self.merge(other);
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::QUIC,
+            msg_type: self.msg_type,
+            rrt: self.rrt,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        false
+    }
+
+    fn get_request_domain(&self) -> String {
+        self.request_domain.clone()
+    }
+
+    fn get_request_resource_length(&self) -> usize {
+        self.request_resource.len()
+    }
+
+    fn is_on_blacklist(&self) -> bool {
+        self.is_on_blacklist
+    }
+}
+
+impl QuicInfo {
+    pub fn merge(&mut self, other: &mut Self) {
+        if other.is_on_blacklist {
+            self.is_on_blacklist = other.is_on_blacklist;
+        }
+        match other.msg_type {
+            LogMessageType::Request => {
+                std::mem::swap(&mut self.request_type, &mut other.request_type);
+                std::mem::swap(&mut self.request_domain, &mut other.request_domain);
+                std::mem::swap(&mut self.request_resource, &mut other.request_resource);
+                std::mem::swap(&mut self.client_cid, &mut other.client_cid);
+                self.captured_request_byte = other.captured_request_byte;
+            }
+            LogMessageType::Response => {
+                self.status = other.status;
+                self.error_code = other.error_code;
+                std::mem::swap(&mut self.reason, &mut other.reason);
+                std::mem::swap(&mut self.response_result, &mut other.response_result);
+                std::mem::swap(&mut self.server_cid, &mut other.server_cid);
+                std::mem::swap(&mut self.cipher_suite, &mut other.cipher_suite);
+                self.rrt = other.rrt;
+                self.handshake_rtt = other.handshake_rtt;
+                if self.version == 0 {
+                    self.version = other.version;
+                }
+                self.captured_response_byte = other.captured_response_byte;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_is_on_blacklist(&mut self, config: &LogParserConfig) {
+        if let Some(t) = config.l7_log_blacklist_trie.get(&L7Protocol::QUIC) {
+            self.is_on_blacklist = t.request_resource.is_on_blacklist(&self.request_resource)
+                || t.request_type.is_on_blacklist(&self.request_type)
+                || t.request_domain.is_on_blacklist(&self.request_domain);
+        }
+    }
+}
+
+impl From<QuicInfo> for L7ProtocolSendLog {
+    fn from(f: QuicInfo) -> Self {
+        let mut attributes = vec![];
+        if !f.client_cid.is_empty() {
+            attributes.push(KeyVal {
+                key: "client_cid".to_string(),
+                val: f.client_cid,
+            });
+        }
+        if !f.server_cid.is_empty() {
+            attributes.push(KeyVal {
+                key: "server_cid".to_string(),
+                val: f.server_cid,
+            });
+        }
+        if let Some(cipher_suite) = f.cipher_suite {
+            attributes.push(KeyVal {
+                key: "cipher_suite".to_string(),
+                val: cipher_suite.to_string(),
+            });
+        }
+        L7ProtocolSendLog {
+            captured_request_byte: f.captured_request_byte,
+            captured_response_byte: f.captured_response_byte,
+            req: L7Request {
+                req_type: f.request_type,
+                domain: f.request_domain,
+                resource: f.request_resource,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_code.and_then(|c| i32::try_from(c).ok()),
+                exception: f.reason,
+                result: f.response_result,
+            },
+            version: if f.version != 0 {
+                Some(version_name(f.version))
+            } else {
+                None
+            },
+            ext_info: if !attributes.is_empty() {
+                Some(ExtendedInfo {
+                    attributes: Some(attributes),
+                    ..Default::default()
+                })
+            } else {
+                None
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct QuicLog {
+    perf_stats: Option<L7PerfStats>,
+    quic_stats: QuicPerfStats,
+    quic_stats_updated: bool,
+
+    // client and server keys, derived from the first Initial of the client
+    initial_keys: Option<Box<[InitialKeys; 2]>>,
+    crypto: [CryptoStream; 2],
+    client_initial_time: u64,
+    server_handshake_seen: bool,
+    last_is_on_blacklist: bool,
+}
+
+impl L7ProtocolParserInterface for QuicLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol()
+            || param.l4_protocol != IpProtocol::UDP
+            || param.direction != PacketDirection::ClientToServer
+            || payload.len() < MIN_CLIENT_INITIAL_LEN
+        {
+            return false;
+        }
+        let Some(header) = LongHeader::parse(payload) else {
+            return false;
+        };
+        if header.packet_type != PacketType::Initial
+            || header.dcid.len() < MIN_CLIENT_INITIAL_DCID_LEN
+        {
+            return false;
+        }
+        let Some(keys) = InitialKeys::new(header.version, header.dcid, false) else {
+            return false;
+        };
+        keys.decrypt(&payload[..header.len], header.pn_offset)
+            .is_some_and(|frames| {
+                parse_frames(&frames)
+                    .iter()
+                    .any(|f| matches!(f, Frame::Crypto { .. }))
+            })
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<L7ParseResult> {
+        let mut info = QuicInfo::default();
+        if !self.parse(payload, &mut info, param)? {
+            return Ok(L7ParseResult::None);
+        }
+
+        if let Some(config) = param.parse_config {
+            info.set_is_on_blacklist(config);
+        }
+        if !info.is_on_blacklist && !self.last_is_on_blacklist {
+            match param.direction {
+                PacketDirection::ClientToServer => {
+                    self.perf_stats.as_mut().map(|p| p.inc_req());
+                }
+                PacketDirection::ServerToClient => {
+                    self.perf_stats.as_mut().map(|p| p.inc_resp());
+                }
+            }
+            match info.status {
+                L7ResponseStatus::ClientError => {
+                    self.perf_stats.as_mut().map(|p| p.inc_req_err());
+                }
+                L7ResponseStatus::ServerError => {
+                    self.perf_stats.as_mut().map(|p| p.inc_resp_err());
+                }
+                _ => {}
+            }
+            if info.msg_type != LogMessageType::Session {
+                info.cal_rrt(param, &None).map(|(rrt, _)| {
+                    info.rrt = rrt;
+                    self.perf_stats.as_mut().map(|p| p.update_rrt(rrt));
+                });
+            }
+            if info.handshake_rtt != 0 {
+                self.perf_stats
+                    .as_mut()
+                    .map(|p| p.update_tls_rtt(info.handshake_rtt));
+            }
+        }
+        self.last_is_on_blacklist = info.is_on_blacklist;
+        if param.parse_log {
+            Ok(L7ParseResult::Single(L7ProtocolInfo::QuicInfo(info)))
+        } else {
+            Ok(L7ParseResult::None)
+        }
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::QUIC
+    }
+
+    fn parsable_on_tcp(&self) -> bool {
+        false
+    }
+
+    fn perf_stats(&mut self) -> Option<L7PerfStats> {
+        self.perf_stats.take()
+    }
+
+    fn quic_perf_stats(&mut self) -> Option<QuicPerfStats> {
+        if !self.quic_stats_updated {
+            return None;
+        }
+        self.quic_stats_updated = false;
+        let stats = self.quic_stats.clone();
+        self.quic_stats.reset();
+        Some(stats)
+    }
+}
+
+impl QuicLog {
+    // Returns false if there is nothing to report in the datagram, which holds for most of them
+    fn parse(&mut self, payload: &[u8], info: &mut QuicInfo, param: &ParseParam) -> Result<bool> {
+        if self.perf_stats.is_none() && param.parse_perf {
+            self.perf_stats = Some(L7PerfStats::default())
+        };
+        if payload.first().is_some_and(|b| b & 0x80 == 0) {
+            return Ok(false);
+        }
+
+        let from_client = param.direction == PacketDirection::ClientToServer;
+        let mut packet_types = vec![];
+        let mut hello = None;
+        let mut close = None;
+        let mut first_server_handshake = false;
+        let mut offset = 0;
+        while offset < payload.len() {
+            if payload[offset] & 0x80 == 0 {
+                // a short header packet can only be the last one in a datagram
+                packet_types.push(PacketType::OneRtt);
+                break;
+            }
+            let Some(header) = LongHeader::parse(&payload[offset..]) else {
+                break;
+            };
+            packet_types.push(header.packet_type);
+            if offset == 0 {
+                self.update_header(&header, info, from_client);
+            }
+            match header.packet_type {
+                PacketType::Initial => {
+                    let packet = &payload[offset..offset + header.len];
+                    if let Some((h, c)) = self.parse_initial(&header, packet, from_client, param) {
+                        hello = hello.or(h);
+                        close = close.or(c);
+                    }
+                }
+                PacketType::Handshake if !from_client && !self.server_handshake_seen => {
+                    self.server_handshake_seen = true;
+                    first_server_handshake = true;
+                    if self.client_initial_time != 0 && param.time > self.client_initial_time {
+                        info.handshake_rtt = param.time - self.client_initial_time;
+                        self.quic_stats.handshake_rtt = info.handshake_rtt as u32;
+                        self.quic_stats_updated = true;
+                    }
+                }
+                PacketType::Retry => {
+                    // the client starts over with the connection ID chosen by the server
+                    self.initial_keys = None;
+                    self.crypto = Default::default();
+                }
+                _ => (),
+            }
+            offset += header.len;
+        }
+        if packet_types.is_empty() {
+            return Err(Error::L7LogParseFailed {
+                proto: L7Protocol::QUIC,
+                reason: "invalid long header".into(),
+            });
+        }
+        let packet_types = packet_types
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join("|");
+
+        if let Some((frame_type, error_code, reason)) = close {
+            info.msg_type = LogMessageType::Session;
+            info.status = match (error_code, from_client) {
+                (NO_ERROR, _) => L7ResponseStatus::Ok,
+                (_, true) => L7ResponseStatus::ClientError,
+                (_, false) => L7ResponseStatus::ServerError,
+            };
+            info.error_code = Some(error_code);
+            info.reason = reason;
+            self.quic_stats.close_frame_type = frame_type;
+            self.quic_stats.close_error_code = error_code;
+            self.quic_stats_updated = true;
+        } else if from_client {
+            let Some(hello) = hello else {
+                return Ok(false);
+            };
+            info.msg_type = LogMessageType::Request;
+            if let Some(server_name) = hello.domain_name() {
+                info.request_domain = server_name.clone();
+                info.request_resource = server_name;
+            }
+        } else if first_server_handshake || hello.is_some() || info.version == 0 {
+            info.msg_type = LogMessageType::Response;
+            info.status = L7ResponseStatus::Ok;
+            if info.version == 0 {
+                info.status = L7ResponseStatus::ServerError;
+                info.reason = "version not supported".to_string();
+            }
+            if let Some(cipher_suite) = hello.and_then(|h| h.cipher_suite()) {
+                info.cipher_suite = Some(CipherSuite::from(cipher_suite));
+            }
+        } else {
+            return Ok(false);
+        }
+        if from_client {
+            info.request_type = packet_types;
+        } else {
+            info.response_result = packet_types;
+        }
+        set_captured_byte!(info, param);
+        Ok(true)
+    }
+
+    fn update_header(&mut self, header: &LongHeader, info: &mut QuicInfo, from_client: bool) {
+        info.version = header.version;
+        let (client_cid, server_cid) = if from_client {
+            (header.scid, header.dcid)
+        } else {
+            (header.dcid, header.scid)
+        };
+        info.client_cid = hex::encode(client_cid);
+        info.server_cid = hex::encode(server_cid);
+        if header.packet_type == PacketType::VersionNegotiation {
+            return;
+        }
+        let stats = &mut self.quic_stats;
+        if stats.version != header.version {
+            stats.version = header.version;
+            self.quic_stats_updated = true;
+        }
+        // the destination connection ID of the first Initial is chosen by the client
+        // and replaced by the server with its source connection ID
+        if !from_client && stats.server_cid != server_cid {
+            stats.server_cid = server_cid.to_vec();
+            self.quic_stats_updated = true;
+        }
+        if from_client && stats.client_cid != client_cid {
+            stats.client_cid = client_cid.to_vec();
+            self.quic_stats_updated = true;
+        }
+    }
+
+    // Returns the first complete handshake message and the CONNECTION_CLOSE frame if any
+    fn parse_initial(
+        &mut self,
+        header: &LongHeader,
+        packet: &[u8],
+        from_client: bool,
+        param: &ParseParam,
+    ) -> Option<(Option<TlsHeader>, Option<(u8, u64, String)>)> {
+        if from_client && self.client_initial_time == 0 {
+            self.client_initial_time = param.time;
+        }
+        if self.initial_keys.is_none() {
+            // keys can only be derived from an Initial of the client
+            if !from_client {
+                return None;
+            }
+            self.initial_keys = Some(Box::new([
+                InitialKeys::new(header.version, header.dcid, false)?,
+                InitialKeys::new(header.version, header.dcid, true)?,
+            ]));
+        }
+        let keys = &self.initial_keys.as_ref().unwrap()[!from_client as usize];
+        let frames = keys.decrypt(packet, header.pn_offset)?;
+
+        let stream = &mut self.crypto[!from_client as usize];
+        let mut close = None;
+        for frame in parse_frames(&frames) {
+            match frame {
+                Frame::Crypto { offset, data } => stream.push(offset, data),
+                Frame::ConnectionClose {
+                    frame_type,
+                    error_code,
+                    reason,
+                } => {
+                    close = Some((
+                        frame_type,
+                        error_code,
+                        String::from_utf8_lossy(reason).into_owned(),
+                    ))
+                }
+            }
+        }
+        Some((stream.take_first_message(), close))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::{cell::RefCell, fmt::Write, fs};
+
+    use super::*;
+
+    use crate::{
+        common::{l7_protocol_log::L7PerfCache, MetaPacket},
+        flow_generator::L7_RRT_CACHE_CAPACITY,
+        utils::test::Capture,
+    };
+
+    const FILE_DIR: &str = "resources/test/flow_generator/quic";
+
+    fn run(name: &str) -> String {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name));
+        let log_cache = Rc::new(RefCell::new(L7PerfCache::new(L7_RRT_CACHE_CAPACITY)));
+        let mut packets = capture.collect::<Vec<_>>();
+        if packets.is_empty() {
+            return "".to_string();
+        }
+
+        let mut output = String::new();
+        let mut quic = QuicLog::default();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let payload = match packet.get_l4_payload() {
+                Some(p) => p,
+                None => continue,
+            };
+
+            let param = &mut ParseParam::new(
+                packet as &MetaPacket,
+                log_cache.clone(),
+                Default::default(),
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                Default::default(),
+                true,
+                true,
+            );
+            param.set_captured_byte(payload.len());
+            let is_quic = QuicLog::default().check_payload(payload, param);
+            match quic.parse_payload(payload, param) {
+                Ok(L7ParseResult::Single(L7ProtocolInfo::QuicInfo(info))) => {
+                    let _ = writeln!(
+                        output,
+                        "{} is_quic: {}",
+                        serde_json::to_string(&info).unwrap(),
+                        is_quic
+                    );
+                }
+                Ok(_) => {
+                    let _ = writeln!(output, "is_quic: {}", is_quic);
+                }
+                Err(e) => {
+                    let _ = writeln!(output, "{} is_quic: {}", e, is_quic);
+                }
+            }
+        }
+        let perf = quic.perf_stats().unwrap();
+        let _ = writeln!(
+            output,
+            "request: {} response: {} server_error: {} rrt_sum: {} tls_rtt: {}",
+            perf.request_count,
+            perf.response_count,
+            perf.err_server_count,
+            perf.rrt_sum,
+            perf.tls_rtt
+        );
+        let _ = writeln!(output, "{:?}", quic.quic_perf_stats());
+        output
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![
+            ("quic_handshake.pcap", "quic_handshake.result"),
+            ("quic_connection_close.pcap", "quic_connection_close.result"),
+        ];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output = run(item.0);
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    // RFC 9001 appendix A.1
+    #[test]
+    fn initial_keys() {
+        let dcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+        let client = InitialKeys::new(VERSION_1, &dcid, false).unwrap();
+        assert_eq!(hex::encode(client.iv), "fa044b2f42a3fd3b46fb255c");
+        assert_eq!(
+            hex::encode(client.hp.new_mask(&[0; 16]).unwrap()),
+            hex::encode(aes_128_mask("9f50449e04a0e810283a1e9933adedd2"))
+        );
+        let server = InitialKeys::new(VERSION_1, &dcid, true).unwrap();
+        assert_eq!(hex::encode(server.iv), "0ac1493ca1905853b0bba03e");
+        assert!(InitialKeys::new(0xff00001d, &dcid, false).is_none());
+    }
+
+    fn aes_128_mask(hp: &str) -> [u8; 5] {
+        let hp = hex::decode(hp).unwrap();
+        quic::HeaderProtectionKey::new(&quic::AES_128, &hp)
+            .unwrap()
+            .new_mask(&[0; 16])
+            .unwrap()
+    }
+
+    #[test]
+    fn frames() {
+        let payload = [
+            0x02, 0x00, 0x00, 0x00, 0x00, // ACK
+            0x00, 0x00, // PADDING
+            0x06, 0x40, 0x05, 0x03, 0x61, 0x62, 0x63, // CRYPTO offset 5, "abc"
+            0x1c, 0x41, 0x78, 0x06, 0x02, 0x6e, 0x6f, // CONNECTION_CLOSE 0x178, "no"
+            0x1e, 0x00, // HANDSHAKE_DONE is not allowed in Initial packets
+            0x06, 0x00, 0x01, 0x00,
+        ];
+        assert_eq!(
+            parse_frames(&payload),
+            vec![
+                Frame::Crypto {
+                    offset: 5,
+                    data: b"abc"
+                },
+                Frame::ConnectionClose {
+                    frame_type: 0x1c,
+                    error_code: 0x178,
+                    reason: b"no"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn crypto_stream() {
+        let mut stream = CryptoStream::default();
+        stream.push(6, &[0; 2]);
+        assert!(stream.take_first_message().is_none());
+        stream.push(0, &[1, 0, 0, 4, 0, 0]);
+        stream.push(4, &[0; 3]);
+        assert_eq!(stream.data.len(), 8);
+        assert!(stream.take_first_message().is_some());
+        assert!(stream.take_first_message().is_none());
+    }
+}
@@ -35,6 +35,7 @@ use crate::{
                 ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, MetricKeyVal,
                 TraceInfo,
             },
+            plugin::custom_field_policy::{set_from_tag, ExtraField},
             set_captured_byte, swap_if, value_is_default, value_is_negative, AppProtoHead,
             L7ResponseStatus, LogMessageType, PrioField,
         },
@@ -45,9 +46,6 @@ use crate::{
 
 use self::consts::*;
 
-#[cfg(feature = "enterprise")]
-use enterprise_utils::l7::plugin::custom_field_policy::{set_from_tag, ExtraField};
-
 const TRACE_ID_MAX_LEN: usize = 1024;
 
 const HESSIAN2_SERIALIZATION_ID: u8 = 2;
@@ -258,7 +256,6 @@ impl DubboInfo {
         }
     }
 
-    #[cfg(feature = "enterprise")]
     fn merge_policy_tags_to_dubbo(
         &mut self,
         tags: &mut std::collections::HashMap<&'static str, String>,
@@ -851,19 +848,13 @@ impl DubboLog {
         config: &L7LogDynamicConfig,
         payload: &[u8],
         info: &mut DubboInfo,
-        #[cfg(feature = "enterprise")] direction: PacketDirection,
-        #[cfg(feature = "enterprise")] port: u16,
+        direction: PacketDirection,
+        port: u16,
     ) {
         match info.serial_id {
-            HESSIAN2_SERIALIZATION_ID => hessian2::get_req_body_info(
-                config,
-                payload,
-                info,
-                #[cfg(feature = "enterprise")]
-                direction,
-                #[cfg(feature = "enterprise")]
-                port,
-            ),
+            HESSIAN2_SERIALIZATION_ID => {
+                hessian2::get_req_body_info(config, payload, info, direction, port)
+            }
             KRYO_SERIALIZATION2_ID => kryo::get_req_body_info(config, payload, info),
             KRYO_SERIALIZATION_ID => kryo::get_req_body_info(config, payload, info),
             FASTJSON2_SERIALIZATION_ID => fastjson2::get_req_body_info(config, payload, info),
@@ -877,8 +868,8 @@ impl DubboLog {
         payload: &[u8],
         dubbo_header: &DubboHeader,
         info: &mut DubboInfo,
-        #[cfg(feature = "enterprise")] direction: PacketDirection,
-        #[cfg(feature = "enterprise")] port: u16,
+        direction: PacketDirection,
+        port: u16,
     ) {
         info.msg_type = LogMessageType::Request;
         info.event = dubbo_header.event;
@@ -887,15 +878,7 @@ impl DubboLog {
         info.serial_id = dubbo_header.serial_id;
         info.request_id = dubbo_header.request_id;
 
-        Self::decode_body(
-            config,
-            &payload[DUBBO_HEADER_LEN..],
-            info,
-            #[cfg(feature = "enterprise")]
-            direction,
-            #[cfg(feature = "enterprise")]
-            port,
-        );
+        Self::decode_body(config, &payload[DUBBO_HEADER_LEN..], info, direction, port);
     }
 
     fn set_status(&mut self, status_code: u8, info: &mut DubboInfo) {
@@ -943,9 +926,7 @@ impl DubboLog {
                     payload,
                     &dubbo_header,
                     info,
-                    #[cfg(feature = "enterprise")]
                     param.direction,
-                    #[cfg(feature = "enterprise")]
                     param.port_dst,
                 );
             }
@@ -1041,18 +1022,14 @@ mod tests {
         utils::test::Capture,
     };
 
-    cfg_if::cfg_if! {
-    if #[cfg(feature = "enterprise")] {
-            use std::collections::HashMap;
+    use std::collections::HashMap;
 
-            use enterprise_utils::l7::plugin::custom_field_policy::{ExtraCustomFieldPolicy, ExtraField};
-            use crate::flow_generator::protocol_logs::LogMessageType;
-            use crate::common::flow::L7ProtocolEnum;
-            use crate::config::config::ExtraCustomFieldPolicyMap;
-            use public::segment_map::SegmentBuilder;
-            use public::enums::{FieldType, MatchType};
-        }
-    }
+    use public::enums::{FieldType, MatchType};
+    use public::segment_map::SegmentBuilder;
+
+    use crate::common::flow::L7ProtocolEnum;
+    use crate::config::config::ExtraCustomFieldPolicyMap;
+    use crate::flow_generator::protocol_logs::plugin::custom_field_policy::ExtraCustomFieldPolicy;
 
     const FILE_DIR: &str = "resources/test/flow_generator/dubbo";
 
@@ -1091,7 +1068,6 @@ mod tests {
                     ],
                     ExtraLogFields::default(),
                     false,
-                    #[cfg(feature = "enterprise")]
                     HashMap::new(),
                 ),
                 ..Default::default()
@@ -1190,7 +1166,6 @@ mod tests {
                 vec![TraceType::Sw8],
                 ExtraLogFields::default(),
                 false,
-                #[cfg(feature = "enterprise")]
                 HashMap::new(),
             ),
             ..Default::default()
@@ -1293,7 +1268,6 @@ mod tests {
                 vec![],
                 ExtraLogFields::default(),
                 false,
-                #[cfg(feature = "enterprise")]
                 HashMap::new(),
             ),
             ..Default::default()
@@ -1324,8 +1298,7 @@ mod tests {
         };
 
         assert_eq!(is_dubbo, true);
-        // EE: assert_eq!(info.trace_id, "00000000-332d-351f-ffff-ffff84afb1ba");
-        assert_eq!(info.trace_id.field, "JAVA:0:6904424666057469:6865509588089802:3363838533311866:00000000-332d-351f-ffff-ffff84afb1ba:7502612320163056:00000000-39d7-c9b2-ffff-ffff93cf32e0:ffffffff-df3a-bfff-0000-000001c43ef4:dilinkapp_dilinkapp-vehicle-provide-test:-1:-1");
+        assert_eq!(info.trace_id.field, "00000000-332d-351f-ffff-ffff84afb1ba");
     }
 
     #[test]
@@ -1398,7 +1371,6 @@ mod tests {
                 ],
                 ExtraLogFields::default(),
                 false,
-                #[cfg(feature = "enterprise")]
                 HashMap::new(),
             ),
             ..Default::default()
@@ -1428,7 +1400,6 @@ mod tests {
         dubbo.perf_stats.unwrap()
     }
 
-    #[cfg(feature = "enterprise")]
     #[test]
     fn test_parse_hessian2_payload() {
         let capture = Capture::load_pcap(Path::new(FILE_DIR).join("dubbo-sw8.pcap"));
@@ -1519,7 +1490,6 @@ mod tests {
         }
     }
 
-    #[cfg(feature = "enterprise")]
     #[test]
     fn test_parse_hessian2_payload_with_args() {
         let hessian_payload = vec![
@@ -19,20 +19,17 @@ use std::collections::HashMap;
 use std::fmt::Display;
 
 use nom::FindSubstring;
+use public::enums::FieldType;
 use regex::Regex;
 
 use super::consts::*;
 use super::{DubboInfo, BODY_PARAM_MAX, BODY_PARAM_MIN};
+use crate::common::flow::{L7Protocol, L7ProtocolEnum, PacketDirection};
 use crate::config::handler::{L7LogDynamicConfig, TraceType};
-
-cfg_if::cfg_if! {
-if #[cfg(feature = "enterprise")] {
-        use crate::common::flow::{L7Protocol, PacketDirection, L7ProtocolEnum};
-        use crate::flow_generator::protocol_logs::pb_adapter::{KeyVal, MetricKeyVal};
-        use public::enums::{FieldType};
-        use enterprise_utils::l7::plugin::custom_field_policy::ExtraField;
-    }
-}
+use crate::flow_generator::protocol_logs::{
+    pb_adapter::{KeyVal, MetricKeyVal},
+    plugin::custom_field_policy::ExtraField,
+};
 
 #[derive(Debug)]
 enum HessianValue {
@@ -654,8 +651,8 @@ pub fn get_req_body_info(
     config: &L7LogDynamicConfig,
     payload: &[u8],
     info: &mut DubboInfo,
-    #[cfg(feature = "enterprise")] direction: PacketDirection,
-    #[cfg(feature = "enterprise")] port: u16,
+    direction: PacketDirection,
+    port: u16,
 ) {
     let mut n = BODY_PARAM_MIN;
     let mut para_index = 0;
@@ -724,11 +721,9 @@ pub fn get_req_body_info(
         }
     }
 
-    #[cfg(feature = "enterprise")]
     on_payload_and_header(config, direction, port, payload, para_index, info);
 }
 
-#[cfg(feature = "enterprise")]
 fn on_payload_and_header(
     config: &L7LogDynamicConfig,
     direction: PacketDirection,
@@ -18,11 +18,12 @@ use serde::Serialize;
 
 use crate::{
     common::{
-        flow::{L7PerfStats, L7Protocol, PacketDirection},
+        flow::{L7PerfStats, L7Protocol},
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
         l7_protocol_log::{L7ParseResult, L7ProtocolParserInterface, ParseParam},
         meta_packet::EbpfFlags,
     },
+    config::handler::LogParserConfig,
     flow_generator::{
         error::{Error, Result},
         protocol_logs::{
@@ -33,8 +34,8 @@ use crate::{
     },
 };
 use l7::some_ip::{
-    SomeIpHeader, E_OK, E_UNKNOWN_METHOD, E_UNKNOWN_SERVICE, E_WRONG_INTERFACE_VERSION,
-    E_WRONG_MESSAGE_TYPE, E_WRONG_PROTOCOL_VERSION,
+    SomeIpHeader, SomeIpSdEntry, SomeIpTpHeader, E_OK, E_UNKNOWN_METHOD, E_UNKNOWN_SERVICE,
+    E_WRONG_INTERFACE_VERSION, E_WRONG_MESSAGE_TYPE, E_WRONG_PROTOCOL_VERSION,
 };
 
 #[derive(Serialize, Debug, Default, Clone)]
@@ -50,6 +51,9 @@ pub struct SomeIpInfo {
     pub message_type: String,
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub service_id: u16,
+    // services offered in a SOME/IP-SD message, used as request resource if present
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_offers: Option<String>,
     pub client_id: u16,
     pub session_id: u16,
     #[serde(rename = "response_code", skip_serializing_if = "value_is_default")]
@@ -64,10 +68,19 @@ pub struct SomeIpInfo {
     #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
     pub resp_msg_size: Option<u32>,
 
+    // SOME/IP-TP segment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tp_offset: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tp_more_segments: Option<bool>,
+
     captured_request_byte: u32,
     captured_response_byte: u32,
 
     rrt: u64,
+
+    #[serde(skip)]
+    is_on_blacklist: bool,
 }
 
 impl SomeIpInfo {
@@ -75,6 +88,9 @@ impl SomeIpInfo {
         if other.is_tls {
             self.is_tls = other.is_tls;
         }
+        if other.is_on_blacklist {
+            self.is_on_blacklist = other.is_on_blacklist;
+        }
 
         swap_if!(self, version, is_empty, other);
         swap_if!(self, message_type, is_empty, other);
@@ -97,6 +113,21 @@ impl SomeIpInfo {
             self.rrt = other.rrt;
         }
     }
+
+    fn request_resource(&self) -> String {
+        match self.service_offers.as_ref() {
+            Some(offers) => offers.clone(),
+            None => self.service_id.to_string(),
+        }
+    }
+
+    fn set_is_on_blacklist(&mut self, config: &LogParserConfig) {
+        if let Some(t) = config.l7_log_blacklist_trie.get(&L7Protocol::SomeIp) {
+            self.is_on_blacklist = t.request_resource.is_on_blacklist(&self.request_resource())
+                || t.request_type.is_on_blacklist(&self.message_type)
+                || t.endpoint.is_on_blacklist(&self.endpoint.to_string());
+        }
+    }
 }
 
 impl L7ProtocolInfoInterface for SomeIpInfo {
@@ -122,6 +153,14 @@ impl L7ProtocolInfoInterface for SomeIpInfo {
     fn is_tls(&self) -> bool {
         self.is_tls
     }
+
+    fn get_request_resource_length(&self) -> usize {
+        self.request_resource().len()
+    }
+
+    fn is_on_blacklist(&self) -> bool {
+        self.is_on_blacklist
+    }
 }
 
 impl From<SomeIpInfo> for L7ProtocolSendLog {
@@ -131,10 +170,23 @@ impl From<SomeIpInfo> for L7ProtocolSendLog {
         } else {
             EbpfFlags::NONE.bits()
         };
-        let attributes = vec![KeyVal {
+        let mut attributes = vec![KeyVal {
             key: "client_id".to_string(),
             val: f.client_id.to_string(),
         }];
+        if let Some(offset) = f.tp_offset {
+            attributes.push(KeyVal {
+                key: "tp_offset".to_string(),
+                val: offset.to_string(),
+            });
+        }
+        if let Some(more_segments) = f.tp_more_segments {
+            attributes.push(KeyVal {
+                key: "tp_more_segments".to_string(),
+                val: more_segments.to_string(),
+            });
+        }
+        let resource = f.request_resource();
 
         L7ProtocolSendLog {
             captured_request_byte: f.captured_request_byte,
@@ -143,7 +195,7 @@ impl From<SomeIpInfo> for L7ProtocolSendLog {
             resp_len: f.resp_msg_size,
             version: Some(f.version),
             req: L7Request {
-                resource: f.service_id.to_string(),
+                resource,
                 req_type: f.message_type,
                 endpoint: f.endpoint.to_string(),
                 ..Default::default()
@@ -189,9 +241,39 @@ impl L7ProtocolParserInterface for SomeIpLog {
             self.perf_stats = Some(L7PerfStats::default())
         };
         let mut info = SomeIpInfo::default();
-        self.parse(payload, &mut info, param)?;
+        self.parse(payload, &mut info)?;
         info.is_tls = param.is_tls();
         set_captured_byte!(info, param);
+        if let Some(config) = param.parse_config {
+            info.set_is_on_blacklist(config);
+        }
+        if !info.is_on_blacklist && !self.last_is_on_blacklist {
+            match info.msg_type {
+                LogMessageType::Request => {
+                    self.perf_stats.as_mut().map(|p| p.inc_req());
+                }
+                LogMessageType::Response => {
+                    self.perf_stats.as_mut().map(|p| p.inc_resp());
+                }
+                _ => {}
+            }
+            match info.resp_status {
+                L7ResponseStatus::ClientError => {
+                    self.perf_stats.as_mut().map(|p| p.inc_req_err());
+                }
+                L7ResponseStatus::ServerError => {
+                    self.perf_stats.as_mut().map(|p| p.inc_resp_err());
+                }
+                _ => {}
+            }
+            if info.msg_type != LogMessageType::Session {
+                info.cal_rrt(param, &None).map(|(rrt, _)| {
+                    info.rrt = rrt;
+                    self.perf_stats.as_mut().map(|p| p.update_rrt(rrt));
+                });
+            }
+        }
+        self.last_is_on_blacklist = info.is_on_blacklist;
         if param.parse_log {
             Ok(L7ParseResult::Single(L7ProtocolInfo::SomeIpInfo(info)))
         } else {
@@ -209,8 +291,15 @@ impl L7ProtocolParserInterface for SomeIpLog {
 }
 
 impl SomeIpLog {
-    fn request(&mut self, header: &SomeIpHeader, info: &mut SomeIpInfo) {
-        info.msg_type = LogMessageType::Request;
+    fn request(&mut self, header: &SomeIpHeader, payload: &[u8], info: &mut SomeIpInfo) {
+        // REQUEST_NO_RETURN and NOTIFICATION, including SOME/IP-SD, are one-way messages
+        if header.expects_response() {
+            info.msg_type = LogMessageType::Request;
+        } else {
+            info.msg_type = LogMessageType::Session;
+        }
+        // requests always carry E_OK, the status is overridden by the response or a timeout
+        info.resp_status = L7ResponseStatus::Ok;
         info.message_type = header.to_message_type();
         info.version = header.to_version();
         info.service_id = header.service_id;
@@ -218,6 +307,17 @@ impl SomeIpLog {
         info.client_id = header.client_id;
         info.endpoint = header.method_id;
         info.req_msg_size = Some(header.length);
+
+        if header.is_sd() {
+            let offers = SomeIpSdEntry::parse_entries(&payload[SomeIpHeader::HEADER_LEN..])
+                .into_iter()
+                .filter(|e| e.is_offer_service())
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>();
+            if !offers.is_empty() {
+                info.service_offers = Some(offers.join(","));
+            }
+        }
     }
 
     fn set_status(&mut self, status_code: u8, info: &mut SomeIpInfo) {
@@ -227,14 +327,8 @@ impl SomeIpLog {
             | E_UNKNOWN_METHOD
             | E_WRONG_PROTOCOL_VERSION
             | E_WRONG_INTERFACE_VERSION
-            | E_WRONG_MESSAGE_TYPE => {
-                self.perf_stats.as_mut().map(|p| p.inc_req_err());
-                L7ResponseStatus::ClientError
-            }
-            _ => {
-                self.perf_stats.as_mut().map(|p| p.inc_resp_err());
-                L7ResponseStatus::ServerError
-            }
+            | E_WRONG_MESSAGE_TYPE => L7ResponseStatus::ClientError,
+            _ => L7ResponseStatus::ServerError,
         }
     }
 
@@ -252,20 +346,24 @@ impl SomeIpLog {
         self.set_status(header.return_code, info);
     }
 
-    fn parse(&mut self, payload: &[u8], info: &mut SomeIpInfo, param: &ParseParam) -> Result<()> {
-        let direction = param.direction;
+    fn parse(&mut self, payload: &[u8], info: &mut SomeIpInfo) -> Result<()> {
         let header =
             SomeIpHeader::try_from(payload).map_err(|_| Error::InsufficientPayloadLength)?;
         if !header.does_supported() {
             return Err(Error::SomeIpUnsupportedMessageType);
         }
 
-        match direction {
-            PacketDirection::ClientToServer => {
-                self.request(&header, info);
-            }
-            PacketDirection::ServerToClient => {
-                self.response(&header, info);
+        // the message type tells the direction, notifications are sent by servers
+        if header.is_request() {
+            self.request(&header, payload, info);
+        } else {
+            self.response(&header, info);
+        }
+
+        if header.is_tp() {
+            if let Ok(tp) = SomeIpTpHeader::try_from(&payload[SomeIpHeader::HEADER_LEN..]) {
+                info.tp_offset = Some(tp.offset);
+                info.tp_more_segments = Some(tp.more_segments);
             }
         }
         Ok(())
@@ -335,7 +433,6 @@ mod tests {
                 vec![TraceType::Sw8, TraceType::TraceParent],
                 ExtraLogFields::default(),
                 false,
-                #[cfg(feature = "enterprise")]
                 std::collections::HashMap::new(),
             );
             let parse_config = &LogParserConfig {
@@ -1680,7 +1680,6 @@ mod tests {
             vec![TraceType::TraceParent],
             ExtraLogFields::default(),
             false,
-            #[cfg(feature = "enterprise")]
             std::collections::HashMap::new(),
         );
         for (input, tid, sid) in testcases {
@@ -37,9 +37,7 @@ use crate::{
     },
 };
 
-use enterprise_utils::l7::sql::oracle::{
-    Body, CallId, DataFlags, DataId, OracleParseConfig, OracleParser, TnsPacketType,
-};
+use l7::oracle::{Body, CallId, DataFlags, DataId, OracleParseConfig, OracleParser, TnsPacketType};
 use public::l7_protocol::L7Protocol;
 
 #[derive(Serialize, Debug, Default, Clone, PartialEq)]
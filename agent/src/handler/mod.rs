@@ -162,6 +162,7 @@ impl PacketHandler {
 
                 let mini_packet = packet::MiniPacket {
                     packet: packet.packet[..max_raw_len].to_vec(),
+                    packet_len: packet.packet_len,
                     flow_id: packet.flow_id,
                     timestamp: Duration::from_nanos(packet.timestamp),
                     acl_gids: Vec::from(acl_gids.list()),
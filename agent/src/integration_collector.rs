@@ -14,6 +14,13 @@
  * limitations under the License.
  */
 
+mod jaeger;
+mod otlp;
+mod prometheus_scrape;
+mod prometheus_text;
+mod span_convert;
+mod zipkin;
+
 use std::collections::HashMap;
 use std::fmt::{self, Debug, Formatter};
 use std::io::{Read, Write};
@@ -41,10 +48,11 @@ use public::{
 use tokio::{
     runtime::Runtime,
     select,
-    sync::{mpsc, oneshot},
+    sync::{mpsc, oneshot, watch},
     task::JoinHandle,
     time,
 };
+use tonic::codec::CompressionEncoding;
 use zstd::bulk::compress;
 
 use crate::{
@@ -53,7 +61,7 @@ use crate::{
         lookup_key::LookupKey,
         TaggedFlow, Timestamp,
     },
-    config::{handler::LogParserConfig, PrometheusExtraLabels},
+    config::{handler::LogParserConfig, PrometheusExtraLabels, PrometheusScrape},
     exception::ExceptionHandler,
     flow_generator::protocol_logs::{http::handle_endpoint, L7ResponseStatus},
     metric::document::{Direction, TapSide},
@@ -71,6 +79,20 @@ use public::{
         agent::Exception,
         flow_log,
         integration::opentelemetry::proto::{
+            collector::{
+                logs::v1::{
+                    logs_service_server::{LogsService, LogsServiceServer},
+                    ExportLogsServiceRequest, ExportLogsServiceResponse,
+                },
+                metrics::v1::{
+                    metrics_service_server::{MetricsService, MetricsServiceServer},
+                    ExportMetricsServiceRequest, ExportMetricsServiceResponse,
+                },
+                trace::v1::{
+                    trace_service_server::{TraceService, TraceServiceServer},
+                    ExportTraceServiceRequest, ExportTraceServiceResponse,
+                },
+            },
             common::v1::{
                 any_value::Value::{IntValue, StringValue},
                 AnyValue, KeyValue,
@@ -255,6 +277,12 @@ impl Sendable for ApplicationLog {
     }
 }
 
+impl From<Vec<u8>> for ApplicationLog {
+    fn from(data: Vec<u8>) -> Self {
+        Self(data)
+    }
+}
+
 fn decode_otel_trace_data(
     peer_addr: SocketAddr,
     data: Vec<u8>,
@@ -264,8 +292,28 @@ fn decode_otel_trace_data(
     flow_id: Arc<AtomicU64>,
     log_parser_config: Arc<LogParserConfig>,
 ) -> Result<(Vec<u8>, Vec<BatchedBox<L7Stats>>), GenericError> {
+    let d = TracesData::decode(data.as_slice())?;
+    Ok(fill_otel_trace_data(
+        peer_addr,
+        d,
+        local_epc_id,
+        policy_getter,
+        time_diff,
+        flow_id,
+        log_parser_config,
+    ))
+}
+
+fn fill_otel_trace_data(
+    peer_addr: SocketAddr,
+    mut d: TracesData,
+    local_epc_id: u32,
+    policy_getter: Arc<PolicyGetter>,
+    time_diff: i64,
+    flow_id: Arc<AtomicU64>,
+    log_parser_config: Arc<LogParserConfig>,
+) -> (Vec<u8>, Vec<BatchedBox<L7Stats>>) {
     let mut l7_stats: Vec<BatchedBox<L7Stats>> = vec![];
-    let mut d = TracesData::decode(data.as_slice())?;
     // 因为collector传过来traceData的全部resource都有"app.host.ip"的属性，所以只检查第一个resource有没有“app.host.ip”即可，
     // sdk传过来的traceData因没有该属性则要补上(key: “app.host.ip”, value: 对端IP)属性值
     // =======================================================================
@@ -347,7 +395,81 @@ fn decode_otel_trace_data(
     }
     let sdk_data = d.encode_to_vec();
     debug!("send otel sdk traces_data to sender: {:?}", d);
-    return Ok((sdk_data, l7_stats));
+    (sdk_data, l7_stats)
+}
+
+// L7 protocol and response status of a span, from its semantic convention attributes and status
+fn otel_span_l7_info(span: &Span) -> (L7Protocol, L7ResponseStatus) {
+    let mut l7_protocol = L7Protocol::Unknown;
+    let mut status = L7ResponseStatus::Unknown;
+    let mut is_http2 = false;
+    for attr in &span.attributes {
+        match attr.key.as_str() {
+            // According to https://opentelemetry.io/docs/reference/specification/trace/semantic_conventions/
+            // the format such as:
+            // {
+            //     "scope_spans": [
+            //         {
+            //             "spans": [
+            //                 {
+            //                     "attributes": [
+            //                         {
+            //                             "key": "rpc.system",
+            //                             "value": "grpc"
+            //                         }
+            //                     ]
+            //                 }
+            //             ]
+            //         }
+            //     ]
+            // }
+            "http.scheme" | "db.system" | "rpc.system" | "messaging.system"
+            | "messaging.protocol" => {
+                if let Some(StringValue(val)) = attr.value.as_ref().and_then(|v| v.value.as_ref()) {
+                    l7_protocol = L7Protocol::from(val);
+                }
+            }
+            // Format as above, "http.status_code": 200
+            "http.status_code" => {
+                if let Some(value) = attr.value.as_ref() {
+                    if let Some(IntValue(val)) = value.value.as_ref() {
+                        status = http_code_to_response_status(*val);
+                    }
+                }
+            }
+            // Format as above, "http.flavor": "1.1"
+            "http.flavor" => {
+                if let Some(value) = attr.value.as_ref() {
+                    if let Some(StringValue(val)) = value.value.as_ref() {
+                        if val == "2.0" {
+                            is_http2 = true;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if is_http2 {
+        if l7_protocol == L7Protocol::Http1 {
+            l7_protocol = L7Protocol::Http2;
+        }
+    }
+
+    // According to https://github.com/open-telemetry/opentelemetry-specification/blob/main/specification/trace/api.md#set-status
+    // Unset = 0, Ok = 1, Error = 2
+    if status == L7ResponseStatus::Unknown {
+        if let Some(s) = span.status.as_ref() {
+            match s.code {
+                1 => status = L7ResponseStatus::Ok,
+                2 => status = L7ResponseStatus::ServerError,
+                _ => (),
+            }
+        }
+    }
+
+    (l7_protocol, status)
 }
 
 fn fill_l7_stats(
@@ -375,9 +497,7 @@ fn fill_l7_stats(
         )
     };
     let mut l4_protocol = L4Protocol::Tcp;
-    let mut l7_protocol = L7Protocol::Unknown;
-    let mut status = L7ResponseStatus::Unknown;
-    let mut is_http2 = false;
+    let (l7_protocol, status) = otel_span_l7_info(span);
     let (mut l2_end_0, mut l2_end_1) = (false, false);
 
     let mut flow = Flow::default();
@@ -399,34 +519,9 @@ fn fill_l7_stats(
         ip1 = ip;
         flow.directions = [Direction::None, Direction::None];
     }
-    let mut last_endpoint = None;
     for attr in &span.attributes {
         match attr.key.as_str() {
-            // According to https://opentelemetry.io/docs/reference/specification/trace/semantic_conventions/
-            // the format such as:
-            // {
-            //     "scope_spans": [
-            //         {
-            //             "spans": [
-            //                 {
-            //                     "attributes": [
-            //                         {
-            //                             "key": "rpc.system",
-            //                             "value": "grpc"
-            //                         }
-            //                     ]
-            //                 }
-            //             ]
-            //         }
-            //     ]
-            // }
-            "http.scheme" | "db.system" | "rpc.system" | "messaging.system"
-            | "messaging.protocol" => {
-                if let Some(StringValue(val)) = attr.value.as_ref().and_then(|v| v.value.as_ref()) {
-                    l7_protocol = L7Protocol::from(val);
-                }
-            }
-            // Format as above, "net.peer.ip": "0.0.0.0"
+            // Format as otel_span_l7_info, "net.peer.ip": "0.0.0.0"
             "net.peer.ip" => {
                 if let Some(value) = attr.value.as_ref() {
                     if let Some(StringValue(val)) = value.value.as_ref() {
@@ -441,7 +536,7 @@ fn fill_l7_stats(
                     }
                 }
             }
-            // Format as above, "net.transport": "ip_tcp"
+            // Format as otel_span_l7_info, "net.transport": "ip_tcp"
             "net.transport" => {
                 if let Some(value) = attr.value.as_ref() {
                     if let Some(StringValue(val)) = value.value.as_ref() {
@@ -449,56 +544,22 @@ fn fill_l7_stats(
                     }
                 }
             }
-            // Format as above, "http.status_code": 200
-            "http.status_code" => {
-                if let Some(value) = attr.value.as_ref() {
-                    if let Some(IntValue(val)) = value.value.as_ref() {
-                        status = http_code_to_response_status(*val);
-                    }
-                }
-            }
-            // Format as above, "http.flavor": "1.1"
-            "http.flavor" => {
-                if let Some(value) = attr.value.as_ref() {
-                    if let Some(StringValue(val)) = value.value.as_ref() {
-                        if val == "2.0" {
-                            is_http2 = true;
-                        }
-                    }
-                }
-            }
             _ => {}
         }
+    }
+    let last_endpoint = if span.attributes.is_empty() {
+        None
+    } else {
         match l7_protocol {
             L7Protocol::Http1 | L7Protocol::Http2 => {
-                last_endpoint = Some(handle_endpoint(log_parser_config.as_ref(), &span.name))
+                Some(handle_endpoint(log_parser_config.as_ref(), &span.name))
             }
-            _ => {
-                last_endpoint = Some(span.name.clone());
-            }
-        }
-    }
-
-    if is_http2 {
-        if l7_protocol == L7Protocol::Http1 {
-            l7_protocol = L7Protocol::Http2;
+            _ => Some(span.name.clone()),
         }
-    }
+    };
 
     (flow.flow_key.ip_src, flow.flow_key.ip_dst) = (ip0, ip1);
 
-    // According to https://github.com/open-telemetry/opentelemetry-specification/blob/main/specification/trace/api.md#set-status
-    // Unset = 0, Ok = 1, Error = 2
-    if status == L7ResponseStatus::Unknown {
-        if let Some(s) = span.status.as_ref() {
-            match s.code {
-                1 => status = L7ResponseStatus::Ok,
-                2 => status = L7ResponseStatus::ServerError,
-                _ => (),
-            }
-        }
-    }
-
     let start_time = span.start_time_unix_nano;
     let end_time = span.end_time_unix_nano;
     if time_diff >= 0 {
@@ -594,6 +655,86 @@ fn http_code_to_response_status(status_code: i64) -> L7ResponseStatus {
     }
 }
 
+fn send_otel_trace_data(
+    (sdk_data, mut l7_stats): (Vec<u8>, Vec<BatchedBox<L7Stats>>),
+    otel_sender: &DebugSender<OpenTelemetry>,
+    compressed_otel_sender: &DebugSender<OpenTelemetryCompressed>,
+    otel_l7_stats_sender: &DebugSender<BatchedBox<L7Stats>>,
+    compressed: bool,
+    counter: &CompressedMetric,
+) -> std::io::Result<()> {
+    if !l7_stats.is_empty() {
+        if let Err(e) = otel_l7_stats_sender.send_all(&mut l7_stats) {
+            warn!("otel_l7_stats_sender failed to send data, because {:?}", e);
+        }
+    }
+    if compressed {
+        counter
+            .uncompressed
+            .fetch_add(sdk_data.len() as u64, Ordering::Relaxed);
+        let compressed_data = compress_data(sdk_data)?;
+        counter
+            .compressed
+            .fetch_add(compressed_data.len() as u64, Ordering::Relaxed);
+        if let Err(e) = compressed_otel_sender.send(OpenTelemetryCompressed(compressed_data)) {
+            warn!(
+                "compressed_otel_sender failed to send data, because {:?}",
+                e
+            );
+        }
+    } else if let Err(e) = otel_sender.send(OpenTelemetry(sdk_data)) {
+        warn!("otel_sender failed to send data, because {:?}", e);
+    }
+    Ok(())
+}
+
+fn send_otel_metrics(
+    peer_addr: SocketAddr,
+    request: &ExportMetricsServiceRequest,
+    telegraf_sender: &DebugSender<TelegrafMetric>,
+) {
+    let Some(data) = otlp::metrics_to_telegraf(peer_addr, &request.resource_metrics) else {
+        return;
+    };
+    if let Err(e) = telegraf_sender.send(TelegrafMetric(data)) {
+        warn!("telegraf_sender failed to send data, because {:?}", e);
+    }
+}
+
+fn send_otel_logs(
+    peer_addr: SocketAddr,
+    request: &ExportLogsServiceRequest,
+    application_log_sender: &DebugSender<ApplicationLog>,
+) {
+    let Some(data) = otlp::logs_to_application_log(peer_addr, &request.resource_logs) else {
+        return;
+    };
+    if let Err(e) = application_log_sender.send(ApplicationLog(data)) {
+        warn!(
+            "application_log_sender failed to send data, because {:?}",
+            e
+        );
+    }
+}
+
+// OTLP/HTTP only supports the binary protobuf encoding for now
+fn otlp_http_response(headers: &HeaderMap) -> Option<Response<Body>> {
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("application/json"))
+        .unwrap_or_default();
+    if is_json {
+        return Some(
+            Response::builder()
+                .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                .body(Body::empty())
+                .unwrap(),
+        );
+    }
+    None
+}
+
 fn compress_data(input: Vec<u8>) -> std::io::Result<Vec<u8>> {
     let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
     e.write_all(input.as_slice())?;
@@ -650,7 +791,7 @@ async fn handler(
             };
             let tracing_data = decode_metric(whole_body, &part.headers)?;
             let time_diff = time_diff.load(Ordering::Relaxed);
-            let mut decode_data = decode_otel_trace_data(
+            let decode_data = decode_otel_trace_data(
                 peer_addr,
                 tracing_data,
                 local_epc_id,
@@ -663,34 +804,73 @@ async fn handler(
                 debug!("decode otel trace data error: {}", e);
                 e
             })?;
-            if !decode_data.1.is_empty() {
-                if let Err(e) = otel_l7_stats_sender.send_all(&mut decode_data.1) {
-                    warn!("otel_l7_stats_sender failed to send data, because {:?}", e);
-                }
+            send_otel_trace_data(
+                decode_data,
+                &otel_sender,
+                &compressed_otel_sender,
+                &otel_l7_stats_sender,
+                compressed,
+                &counter,
+            )?;
+
+            Ok(Response::builder().body(Body::empty()).unwrap())
+        }
+        // Zipkin v2 and Jaeger thrift trace integration, converted to OpenTelemetry spans
+        (&Method::POST, "/api/v2/spans" | "/api/traces") => {
+            if external_trace_integration_disabled {
+                return Ok(Response::builder().body(Body::empty()).unwrap());
             }
-            if compressed {
-                counter
-                    .uncompressed
-                    .fetch_add(decode_data.0.len() as u64, Ordering::Relaxed);
-                let compressed_data = compress_data(decode_data.0)?;
-                counter
-                    .compressed
-                    .fetch_add(compressed_data.len() as u64, Ordering::Relaxed);
-                if let Err(e) =
-                    compressed_otel_sender.send(OpenTelemetryCompressed(compressed_data))
-                {
-                    warn!(
-                        "compressed_otel_sender failed to send data, because {:?}",
-                        e
-                    );
+            let (part, body) = req.into_parts();
+            let whole_body = match aggregate_with_catch_exception(body, &exception_handler).await {
+                Ok(b) => b,
+                Err(e) => {
+                    return Ok(e);
                 }
+            };
+            let data = decode_metric(whole_body, &part.headers)?;
+            let traces = if part.uri.path() == "/api/traces" {
+                jaeger::decode_jaeger_batch(&data)
             } else {
-                if let Err(e) = otel_sender.send(OpenTelemetry(decode_data.0)) {
-                    warn!("otel_sender failed to send data, because {:?}", e);
+                let is_proto = part
+                    .headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.starts_with("application/x-protobuf"))
+                    .unwrap_or_default();
+                zipkin::decode_zipkin_spans(&data, is_proto)
+            };
+            let traces = match traces {
+                Ok(t) => t,
+                Err(e) => {
+                    debug!("decode {} trace data error: {}", part.uri.path(), e);
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from(e.to_string()))
+                        .unwrap());
                 }
-            }
+            };
+            let decode_data = fill_otel_trace_data(
+                peer_addr,
+                traces,
+                local_epc_id,
+                policy_getter,
+                time_diff.load(Ordering::Relaxed),
+                flow_id.clone(),
+                log_parser_config.clone(),
+            );
+            send_otel_trace_data(
+                decode_data,
+                &otel_sender,
+                &compressed_otel_sender,
+                &otel_l7_stats_sender,
+                compressed,
+                &counter,
+            )?;
 
-            Ok(Response::builder().body(Body::empty()).unwrap())
+            Ok(Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::empty())
+                .unwrap())
         }
         // Prometheus integration
         (&Method::POST, "/api/v1/prometheus") => {
@@ -821,6 +1001,54 @@ async fn handler(
 
             Ok(Response::builder().body(Body::empty()).unwrap())
         }
+        // OpenTelemetry metrics integration, OTLP/HTTP
+        (&Method::POST, "/v1/metrics") => {
+            if external_metric_integration_disabled {
+                return Ok(Response::builder().body(Body::empty()).unwrap());
+            }
+            if let Some(resp) = otlp_http_response(req.headers()) {
+                return Ok(resp);
+            }
+            let (part, body) = req.into_parts();
+            let whole_body = match aggregate_with_catch_exception(body, &exception_handler).await {
+                Ok(b) => b,
+                Err(e) => {
+                    return Ok(e);
+                }
+            };
+            let data = decode_metric(whole_body, &part.headers)?;
+            let request = ExportMetricsServiceRequest::decode(data.as_slice())?;
+            send_otel_metrics(peer_addr, &request, &telegraf_sender);
+
+            Ok(Response::builder()
+                .header(CONTENT_TYPE, "application/x-protobuf")
+                .body(Body::empty())
+                .unwrap())
+        }
+        // OpenTelemetry logs integration, OTLP/HTTP
+        (&Method::POST, "/v1/logs") => {
+            if external_log_integration_disabled {
+                return Ok(Response::builder().body(Body::empty()).unwrap());
+            }
+            if let Some(resp) = otlp_http_response(req.headers()) {
+                return Ok(resp);
+            }
+            let (part, body) = req.into_parts();
+            let whole_body = match aggregate_with_catch_exception(body, &exception_handler).await {
+                Ok(b) => b,
+                Err(e) => {
+                    return Ok(e);
+                }
+            };
+            let data = decode_metric(whole_body, &part.headers)?;
+            let request = ExportLogsServiceRequest::decode(data.as_slice())?;
+            send_otel_logs(peer_addr, &request, &application_log_sender);
+
+            Ok(Response::builder()
+                .header(CONTENT_TYPE, "application/x-protobuf")
+                .body(Body::empty())
+                .unwrap())
+        }
         // log integration
         (&Method::POST, "/api/v1/log") => {
             if external_log_integration_disabled {
@@ -982,6 +1210,99 @@ fn parse_dd_headers(headers: &HeaderMap, third_party_data: &mut flow_log::ThirdP
     }
 }
 
+// OTLP/gRPC receiver, shares the decoding and sending path with the HTTP routes
+struct OtlpService {
+    otel_sender: DebugSender<OpenTelemetry>,
+    compressed_otel_sender: DebugSender<OpenTelemetryCompressed>,
+    otel_l7_stats_sender: DebugSender<BatchedBox<L7Stats>>,
+    telegraf_sender: DebugSender<TelegrafMetric>,
+    application_log_sender: DebugSender<ApplicationLog>,
+    compressed: Arc<AtomicBool>,
+    counter: Arc<CompressedMetric>,
+    local_epc_id: u32,
+    policy_getter: Arc<PolicyGetter>,
+    time_diff: Arc<AtomicI64>,
+    log_parser_config: Arc<LogParserConfig>,
+    flow_id: Arc<AtomicU64>,
+    external_trace_integration_disabled: bool,
+    external_metric_integration_disabled: bool,
+    external_log_integration_disabled: bool,
+}
+
+#[tonic::async_trait]
+impl TraceService for OtlpService {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        if self.external_trace_integration_disabled {
+            return Ok(tonic::Response::new(ExportTraceServiceResponse::default()));
+        }
+        let Some(peer_addr) = request.remote_addr() else {
+            return Err(tonic::Status::internal("unknown peer address"));
+        };
+        let decode_data = fill_otel_trace_data(
+            peer_addr,
+            TracesData {
+                resource_spans: request.into_inner().resource_spans,
+            },
+            self.local_epc_id,
+            self.policy_getter.clone(),
+            self.time_diff.load(Ordering::Relaxed),
+            self.flow_id.clone(),
+            self.log_parser_config.clone(),
+        );
+        send_otel_trace_data(
+            decode_data,
+            &self.otel_sender,
+            &self.compressed_otel_sender,
+            &self.otel_l7_stats_sender,
+            self.compressed.load(Ordering::Relaxed),
+            &self.counter,
+        )
+        .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+#[tonic::async_trait]
+impl MetricsService for OtlpService {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportMetricsServiceRequest>,
+    ) -> Result<tonic::Response<ExportMetricsServiceResponse>, tonic::Status> {
+        if self.external_metric_integration_disabled {
+            return Ok(tonic::Response::new(ExportMetricsServiceResponse::default()));
+        }
+        let Some(peer_addr) = request.remote_addr() else {
+            return Err(tonic::Status::internal("unknown peer address"));
+        };
+        send_otel_metrics(peer_addr, &request.into_inner(), &self.telegraf_sender);
+        Ok(tonic::Response::new(ExportMetricsServiceResponse::default()))
+    }
+}
+
+#[tonic::async_trait]
+impl LogsService for OtlpService {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportLogsServiceRequest>,
+    ) -> Result<tonic::Response<ExportLogsServiceResponse>, tonic::Status> {
+        if self.external_log_integration_disabled {
+            return Ok(tonic::Response::new(ExportLogsServiceResponse::default()));
+        }
+        let Some(peer_addr) = request.remote_addr() else {
+            return Err(tonic::Status::internal("unknown peer address"));
+        };
+        send_otel_logs(
+            peer_addr,
+            &request.into_inner(),
+            &self.application_log_sender,
+        );
+        Ok(tonic::Response::new(ExportLogsServiceResponse::default()))
+    }
+}
+
 #[derive(Default)]
 struct CompressedMetric {
     compressed: AtomicU64,   // unit (bytes)
@@ -991,6 +1312,7 @@ struct CompressedMetric {
 #[derive(Default)]
 pub struct IntegrationCounter {
     metrics: Arc<CompressedMetric>,
+    scrape: Arc<prometheus_scrape::ScrapeCounter>,
 }
 
 impl OwnedCountable for IntegrationCounter {
@@ -999,7 +1321,7 @@ impl OwnedCountable for IntegrationCounter {
             self.metrics.compressed.swap(0, Ordering::Relaxed),
             self.metrics.uncompressed.swap(0, Ordering::Relaxed),
         );
-        vec![
+        let mut counters = vec![
             (
                 "compressed",
                 CounterType::Counted,
@@ -1020,7 +1342,9 @@ impl OwnedCountable for IntegrationCounter {
                     uncomressed as f64 / compressed as f64
                 }),
             ),
-        ]
+        ];
+        counters.extend(self.scrape.get_counters());
+        counters
     }
 
     fn closed(&self) -> bool {
@@ -1043,8 +1367,13 @@ pub struct MetricServer {
     skywalking_sender: DebugSender<SkyWalkingExtra>,
     datadog_sender: DebugSender<Datadog>,
     port: Arc<AtomicU16>,
+    otlp_grpc_port: Arc<AtomicU16>,
     exception_handler: ExceptionHandler,
     server_shutdown_tx: Mutex<Option<mpsc::Sender<()>>>,
+    otlp_grpc_thread: Mutex<Option<JoinHandle<()>>>,
+    prometheus_scrape: watch::Sender<PrometheusScrape>,
+    prometheus_scrape_thread: Mutex<Option<JoinHandle<()>>>,
+    scrape_counter: Arc<prometheus_scrape::ScrapeCounter>,
     counter: Arc<CompressedMetric>,
     compressed: Arc<AtomicBool>,
     profile_compressed: Arc<AtomicBool>,
@@ -1072,6 +1401,8 @@ impl MetricServer {
         skywalking_sender: DebugSender<SkyWalkingExtra>,
         datadog_sender: DebugSender<Datadog>,
         port: u16,
+        otlp_grpc_port: u16,
+        prometheus_scrape: PrometheusScrape,
         exception_handler: ExceptionHandler,
         compressed: bool,
         profile_compressed: bool,
@@ -1102,8 +1433,13 @@ impl MetricServer {
                 skywalking_sender,
                 datadog_sender,
                 port: Arc::new(AtomicU16::new(port)),
+                otlp_grpc_port: Arc::new(AtomicU16::new(otlp_grpc_port)),
                 exception_handler,
                 server_shutdown_tx: Default::default(),
+                otlp_grpc_thread: Default::default(),
+                prometheus_scrape: watch::channel(prometheus_scrape).0,
+                prometheus_scrape_thread: Default::default(),
+                scrape_counter: counter.scrape.clone(),
                 counter: counter.metrics.clone(),
                 local_epc_id,
                 policy_getter: Arc::new(policy_getter),
@@ -1138,11 +1474,82 @@ impl MetricServer {
         }
     }
 
+    pub fn set_otlp_grpc_port(&self, port: u16) {
+        if self.otlp_grpc_port.swap(port, Ordering::Release) != port {
+            info!("otlp grpc port changes to {}", port);
+            self.spawn_otlp_grpc();
+        }
+    }
+
+    // The OTLP/gRPC receiver listens on its own port, 0 means disabled. The running receiver is
+    // aborted and a new one is spawned, so that port changes take effect without blocking.
+    fn spawn_otlp_grpc(&self) {
+        let mut thread = self.otlp_grpc_thread.lock().unwrap();
+        // checked with the lock held, a receiver spawned before `stop` is aborted by it
+        if !self.running.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(t) = thread.take() {
+            t.abort();
+        }
+        let port = self.otlp_grpc_port.load(Ordering::Acquire);
+        if port == 0 {
+            return;
+        }
+        let service = Arc::new(OtlpService {
+            otel_sender: self.otel_sender.clone(),
+            compressed_otel_sender: self.compressed_otel_sender.clone(),
+            otel_l7_stats_sender: self.otel_l7_stats_sender.clone(),
+            telegraf_sender: self.telegraf_sender.clone(),
+            application_log_sender: self.application_log_sender.clone(),
+            compressed: self.compressed.clone(),
+            counter: self.counter.clone(),
+            local_epc_id: self.local_epc_id,
+            policy_getter: self.policy_getter.clone(),
+            time_diff: self.time_diff.clone(),
+            log_parser_config: self.log_parser_config.clone(),
+            flow_id: Arc::new(AtomicU64::new(0)),
+            external_trace_integration_disabled: self.external_trace_integration_disabled,
+            external_metric_integration_disabled: self.external_metric_integration_disabled,
+            external_log_integration_disabled: self.external_log_integration_disabled,
+        });
+        thread.replace(self.runtime.spawn(Self::serve_otlp_grpc(
+            port,
+            service,
+            self.exception_handler.clone(),
+        )));
+    }
+
+    pub fn set_prometheus_scrape(&self, config: &PrometheusScrape) {
+        self.prometheus_scrape.send_if_modified(|c| {
+            if c == config {
+                return false;
+            }
+            info!("prometheus scrape config changes to {:?}", config);
+            *c = config.clone();
+            true
+        });
+    }
+
     pub fn start(&self) {
         if self.running.swap(true, Ordering::Relaxed) {
             return;
         }
 
+        self.spawn_otlp_grpc();
+
+        if !self.external_metric_integration_disabled {
+            self.prometheus_scrape_thread
+                .lock()
+                .unwrap()
+                .replace(self.runtime.spawn(prometheus_scrape::run(
+                    self.prometheus_scrape.subscribe(),
+                    self.prometheus_sender.clone(),
+                    self.scrape_counter.clone(),
+                    self.time_diff.clone(),
+                )));
+        }
+
         let otel_sender = self.otel_sender.clone();
         let compressed_otel_sender = self.compressed_otel_sender.clone();
         let otel_l7_stats_sender = self.otel_l7_stats_sender.clone();
@@ -1317,10 +1724,50 @@ impl MetricServer {
         if let Some(t) = self.thread.lock().unwrap().take() {
             t.abort();
         }
+        if let Some(t) = self.otlp_grpc_thread.lock().unwrap().take() {
+            t.abort();
+        }
+        if let Some(t) = self.prometheus_scrape_thread.lock().unwrap().take() {
+            t.abort();
+        }
 
         info!("integration collector stopped");
     }
 
+    async fn serve_otlp_grpc(
+        port: u16,
+        service: Arc<OtlpService>,
+        exception_handler: ExceptionHandler,
+    ) {
+        let addr: SocketAddr = if ipv6_enabled() {
+            (Ipv6Addr::UNSPECIFIED, port).into()
+        } else {
+            (Ipv4Addr::UNSPECIFIED, port).into()
+        };
+        loop {
+            info!("otlp grpc receiver listening on {}", addr);
+            let server = tonic::transport::Server::builder()
+                .add_service(
+                    TraceServiceServer::from_arc(service.clone())
+                        .accept_compressed(CompressionEncoding::Gzip),
+                )
+                .add_service(
+                    MetricsServiceServer::from_arc(service.clone())
+                        .accept_compressed(CompressionEncoding::Gzip),
+                )
+                .add_service(
+                    LogsServiceServer::from_arc(service.clone())
+                        .accept_compressed(CompressionEncoding::Gzip),
+                )
+                .serve(addr);
+            if let Err(e) = server.await {
+                error!("otlp grpc receiver error: {} with addr={}", e, addr);
+                exception_handler.set(Exception::IntegrationSocketError);
+                time::sleep(Duration::from_secs(60)).await;
+            }
+        }
+    }
+
     //FIXME: 现在integration collector 在K8S环境下，会概率性出现监听端口一段时间后会失去监听。所以先探测下发的端口是否监听，
     // 没监听的话重启collector再监听。等找到根因后再去掉下面的代码
     // =============================================
@@ -1350,3 +1797,109 @@ impl MetricServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use public::{
+        debug::QueueDebugger,
+        proto::integration::opentelemetry::proto::{
+            collector::trace::v1::trace_service_client::TraceServiceClient,
+            resource::v1::Resource,
+            trace::v1::{ResourceSpans, ScopeSpans},
+        },
+        queue,
+    };
+
+    use super::*;
+    use crate::policy::Policy;
+
+    #[test]
+    fn otlp_grpc_export() {
+        let queue_debugger = QueueDebugger::new();
+        let (otel_sender, otel_receiver, _) = queue::bounded_with_debug(16, "", &queue_debugger);
+        let (compressed_otel_sender, _, _) = queue::bounded_with_debug(16, "", &queue_debugger);
+        let (otel_l7_stats_sender, _, _) = queue::bounded_with_debug(16, "", &queue_debugger);
+        let (telegraf_sender, _, _) = queue::bounded_with_debug(16, "", &queue_debugger);
+        let (application_log_sender, _, _) = queue::bounded_with_debug(16, "", &queue_debugger);
+        let (_policy_setter, mut policy_getter) = Policy::new(1, 0, 1 << 10, 1 << 14, false, false);
+        policy_getter.disable();
+        let service = Arc::new(OtlpService {
+            otel_sender,
+            compressed_otel_sender,
+            otel_l7_stats_sender,
+            telegraf_sender,
+            application_log_sender,
+            compressed: Default::default(),
+            counter: Default::default(),
+            local_epc_id: 0,
+            policy_getter: Arc::new(policy_getter),
+            time_diff: Default::default(),
+            log_parser_config: Default::default(),
+            flow_id: Default::default(),
+            external_trace_integration_disabled: false,
+            external_metric_integration_disabled: false,
+            external_log_integration_disabled: false,
+        });
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let runtime = Runtime::new().unwrap();
+        runtime.spawn(MetricServer::serve_otlp_grpc(
+            port,
+            service,
+            ExceptionHandler::default(),
+        ));
+        let request = ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: Some(Resource::default()),
+                scope_spans: vec![ScopeSpans {
+                    spans: vec![Span {
+                        trace_id: vec![1; 16],
+                        span_id: vec![2; 8],
+                        name: "GET /".to_owned(),
+                        kind: SpanKind::Server as i32,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+        runtime.block_on(async {
+            let endpoint = format!("http://127.0.0.1:{}", port);
+            // the receiver may not be listening yet
+            let mut client = None;
+            for _ in 0..100 {
+                if let Ok(c) = TraceServiceClient::connect(endpoint.clone()).await {
+                    client = Some(c);
+                    break;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+            client.unwrap().export(request).await.unwrap();
+        });
+
+        let OpenTelemetry(data) = otel_receiver.recv(Some(Duration::from_secs(1))).unwrap();
+        let traces = TracesData::decode(data.as_slice()).unwrap();
+        assert_eq!(
+            traces.resource_spans[0].scope_spans[0].spans[0].name,
+            "GET /"
+        );
+        // the peer address is added as app.host.ip
+        let attributes = &traces.resource_spans[0]
+            .resource
+            .as_ref()
+            .unwrap()
+            .attributes;
+        assert_eq!(attributes[0].key, "app.host.ip");
+        assert_eq!(
+            attributes[0].value.as_ref().unwrap().value,
+            Some(StringValue("127.0.0.1".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,409 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Jaeger batches in Thrift binary protocol, as posted by jaeger clients to the collector's
+// `/api/traces`, https://github.com/jaegertracing/jaeger-idl/blob/main/thrift/jaeger.thrift
+
+use public::proto::integration::opentelemetry::proto::{
+    common::v1::KeyValue,
+    trace::v1::{span::Event, Span, TracesData},
+};
+
+use super::{
+    span_convert::{
+        finish_span, key_value, micros_to_nanos, set_tag, span_kind, TagValue, TracesBuilder,
+        HOST_IP, SERVICE_NAME,
+    },
+    GenericError,
+};
+
+const T_STOP: u8 = 0;
+const T_BOOL: u8 = 2;
+const T_BYTE: u8 = 3;
+const T_DOUBLE: u8 = 4;
+const T_I16: u8 = 6;
+const T_I32: u8 = 8;
+const T_I64: u8 = 10;
+const T_STRING: u8 = 11;
+const T_STRUCT: u8 = 12;
+const T_MAP: u8 = 13;
+const T_SET: u8 = 14;
+const T_LIST: u8 = 15;
+
+const MAX_DEPTH: usize = 32;
+
+// jaeger TagType
+const TAG_STRING: i32 = 0;
+const TAG_DOUBLE: i32 = 1;
+const TAG_BOOL: i32 = 2;
+const TAG_LONG: i32 = 3;
+const TAG_BINARY: i32 = 4;
+
+// jaeger SpanRefType
+const REF_CHILD_OF: i32 = 0;
+
+// jaeger times are in microseconds
+fn time_nanos(micros: i64) -> Result<u64, GenericError> {
+    match u64::try_from(micros) {
+        Ok(micros) => micros_to_nanos(micros),
+        Err(_) => Err(format!("negative jaeger time {}", micros).into()),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], GenericError> {
+        if self.data.len() - self.offset < len {
+            return Err(format!(
+                "thrift data truncated at {}, need {} bytes",
+                self.offset, len
+            )
+            .into());
+        }
+        let b = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> Result<u8, GenericError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, GenericError> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, GenericError> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64, GenericError> {
+        Ok(i64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64, GenericError> {
+        Ok(f64::from_bits(self.i64()? as u64))
+    }
+
+    fn bool(&mut self) -> Result<bool, GenericError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn len(&mut self) -> Result<usize, GenericError> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Err(format!("negative thrift length {}", len).into());
+        }
+        Ok(len as usize)
+    }
+
+    fn binary(&mut self) -> Result<&'a [u8], GenericError> {
+        let len = self.len()?;
+        self.bytes(len)
+    }
+
+    fn string(&mut self) -> Result<String, GenericError> {
+        Ok(String::from_utf8_lossy(self.binary()?).into_owned())
+    }
+
+    // returns the field type and id, None on T_STOP
+    fn field(&mut self) -> Result<Option<(u8, i16)>, GenericError> {
+        let field_type = self.u8()?;
+        if field_type == T_STOP {
+            return Ok(None);
+        }
+        Ok(Some((field_type, self.i16()?)))
+    }
+
+    // returns the element type and count
+    fn list(&mut self) -> Result<(u8, usize), GenericError> {
+        let element_type = self.u8()?;
+        let size = self.len()?;
+        // every element takes at least one byte, reject lengths that can not fit
+        if size > self.data.len() - self.offset {
+            return Err(format!("thrift list size {} too large", size).into());
+        }
+        Ok((element_type, size))
+    }
+
+    fn skip(&mut self, field_type: u8, depth: usize) -> Result<(), GenericError> {
+        if depth > MAX_DEPTH {
+            return Err("thrift data nested too deep".into());
+        }
+        match field_type {
+            T_BOOL | T_BYTE => {
+                self.bytes(1)?;
+            }
+            T_I16 => {
+                self.bytes(2)?;
+            }
+            T_I32 => {
+                self.bytes(4)?;
+            }
+            T_DOUBLE | T_I64 => {
+                self.bytes(8)?;
+            }
+            T_STRING => {
+                self.binary()?;
+            }
+            T_STRUCT => {
+                while let Some((t, _)) = self.field()? {
+                    self.skip(t, depth + 1)?;
+                }
+            }
+            T_MAP => {
+                let key_type = self.u8()?;
+                let value_type = self.u8()?;
+                let size = self.len()?;
+                for _ in 0..size {
+                    self.skip(key_type, depth + 1)?;
+                    self.skip(value_type, depth + 1)?;
+                }
+            }
+            T_SET | T_LIST => {
+                let (t, size) = self.list()?;
+                for _ in 0..size {
+                    self.skip(t, depth + 1)?;
+                }
+            }
+            _ => return Err(format!("unknown thrift type {}", field_type).into()),
+        }
+        Ok(())
+    }
+
+    fn struct_list<T>(
+        &mut self,
+        read: impl Fn(&mut Self) -> Result<T, GenericError>,
+    ) -> Result<Vec<T>, GenericError> {
+        let (t, size) = self.list()?;
+        if t != T_STRUCT {
+            return Err(format!("unexpected thrift list element type {}", t).into());
+        }
+        (0..size).map(|_| read(self)).collect()
+    }
+}
+
+struct Tag {
+    key: String,
+    value: TagValue,
+}
+
+fn read_tag(r: &mut Reader) -> Result<Tag, GenericError> {
+    let mut key = String::new();
+    let mut v_type = TAG_STRING;
+    let (mut v_str, mut v_double, mut v_bool, mut v_long, mut v_binary) =
+        (String::new(), 0.0, false, 0, vec![]);
+    while let Some((t, id)) = r.field()? {
+        match (id, t) {
+            (1, T_STRING) => key = r.string()?,
+            (2, T_I32) => v_type = r.i32()?,
+            (3, T_STRING) => v_str = r.string()?,
+            (4, T_DOUBLE) => v_double = r.f64()?,
+            (5, T_BOOL) => v_bool = r.bool()?,
+            (6, T_I64) => v_long = r.i64()?,
+            (7, T_STRING) => v_binary = r.binary()?.to_vec(),
+            _ => r.skip(t, 0)?,
+        }
+    }
+    let value = match v_type {
+        TAG_DOUBLE => TagValue::Double(v_double),
+        TAG_BOOL => TagValue::Bool(v_bool),
+        TAG_LONG => TagValue::Int(v_long),
+        TAG_BINARY => TagValue::Bytes(v_binary),
+        _ => TagValue::Str(v_str),
+    };
+    Ok(Tag { key, value })
+}
+
+fn read_log(r: &mut Reader) -> Result<Event, GenericError> {
+    let mut event = Event::default();
+    while let Some((t, id)) = r.field()? {
+        match (id, t) {
+            (1, T_I64) => event.time_unix_nano = time_nanos(r.i64()?)?,
+            (2, T_LIST) => {
+                for tag in r.struct_list(read_tag)? {
+                    // opentracing log events carry their name in the "event" field
+                    match (tag.key.as_str(), tag.value) {
+                        ("event", TagValue::Str(name)) if event.name.is_empty() => {
+                            event.name = name
+                        }
+                        (_, value) => event.attributes.push(key_value(&tag.key, value)),
+                    }
+                }
+            }
+            _ => r.skip(t, 0)?,
+        }
+    }
+    Ok(event)
+}
+
+struct SpanRef {
+    ref_type: i32,
+    span_id: i64,
+}
+
+fn read_span_ref(r: &mut Reader) -> Result<SpanRef, GenericError> {
+    let mut span_ref = SpanRef {
+        ref_type: REF_CHILD_OF,
+        span_id: 0,
+    };
+    while let Some((t, id)) = r.field()? {
+        match (id, t) {
+            (1, T_I32) => span_ref.ref_type = r.i32()?,
+            (4, T_I64) => span_ref.span_id = r.i64()?,
+            _ => r.skip(t, 0)?,
+        }
+    }
+    Ok(span_ref)
+}
+
+fn read_span(r: &mut Reader) -> Result<Span, GenericError> {
+    let mut span = Span::default();
+    let (mut trace_id_low, mut trace_id_high) = (0i64, 0i64);
+    let mut parent_span_id = 0;
+    let mut references = vec![];
+    let (mut start_time, mut duration) = (0i64, 0i64);
+    let mut tags = vec![];
+    while let Some((t, id)) = r.field()? {
+        match (id, t) {
+            (1, T_I64) => trace_id_low = r.i64()?,
+            (2, T_I64) => trace_id_high = r.i64()?,
+            (3, T_I64) => span.span_id = r.i64()?.to_be_bytes().to_vec(),
+            (4, T_I64) => parent_span_id = r.i64()?,
+            (5, T_STRING) => span.name = r.string()?,
+            (6, T_LIST) => references = r.struct_list(read_span_ref)?,
+            (8, T_I64) => start_time = r.i64()?,
+            (9, T_I64) => duration = r.i64()?,
+            (10, T_LIST) => tags = r.struct_list(read_tag)?,
+            (11, T_LIST) => span.events = r.struct_list(read_log)?,
+            _ => r.skip(t, 0)?,
+        }
+    }
+
+    span.trace_id = [trace_id_high.to_be_bytes(), trace_id_low.to_be_bytes()].concat();
+    // parentSpanId is 0 for root spans and newer clients use references only
+    if parent_span_id == 0 {
+        if let Some(r) = references.iter().find(|r| r.ref_type == REF_CHILD_OF) {
+            parent_span_id = r.span_id;
+        }
+    }
+    if parent_span_id != 0 {
+        span.parent_span_id = parent_span_id.to_be_bytes().to_vec();
+    }
+    span.start_time_unix_nano = time_nanos(start_time)?;
+    span.end_time_unix_nano = time_nanos(start_time.saturating_add(duration))?;
+    span.kind = span_kind("") as i32;
+    for tag in tags {
+        set_tag(&mut span, &tag.key, tag.value);
+    }
+    finish_span(&mut span);
+    Ok(span)
+}
+
+fn read_process(r: &mut Reader) -> Result<Vec<KeyValue>, GenericError> {
+    let mut service_name = String::new();
+    let mut tags = vec![];
+    while let Some((t, id)) = r.field()? {
+        match (id, t) {
+            (1, T_STRING) => service_name = r.string()?,
+            (2, T_LIST) => tags = r.struct_list(read_tag)?,
+            _ => r.skip(t, 0)?,
+        }
+    }
+    let mut attributes = vec![key_value(SERVICE_NAME, TagValue::Str(service_name))];
+    for tag in tags {
+        // jaeger clients report the host ip in the "ip" process tag
+        let key = if tag.key == "ip" {
+            HOST_IP
+        } else {
+            tag.key.as_str()
+        };
+        attributes.push(key_value(key, tag.value));
+    }
+    Ok(attributes)
+}
+
+/// Decodes a `POST /api/traces` body, a jaeger `Batch` in Thrift binary protocol
+pub(super) fn decode_jaeger_batch(data: &[u8]) -> Result<TracesData, GenericError> {
+    let mut r = Reader::new(data);
+    let mut process = vec![];
+    let mut spans = vec![];
+    while let Some((t, id)) = r.field()? {
+        match (id, t) {
+            (1, T_STRUCT) => process = read_process(&mut r)?,
+            (2, T_LIST) => spans = r.struct_list(read_span)?,
+            _ => r.skip(t, 0)?,
+        }
+    }
+    let mut builder = TracesBuilder::default();
+    for span in spans {
+        builder.push(process.clone(), span);
+    }
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::integration_collector::span_convert::render;
+
+    const FILE_DIR: &str = "resources/test/integration_collector";
+
+    #[test]
+    fn check() {
+        let data = fs::read(Path::new(FILE_DIR).join("jaeger.thrift")).unwrap();
+        let expected = fs::read_to_string(Path::new(FILE_DIR).join("jaeger.result")).unwrap();
+        let output = render(&decode_jaeger_batch(&data).unwrap());
+        if output != expected {
+            let output_path = Path::new("actual.txt");
+            fs::write(output_path, &output).unwrap();
+            assert!(
+                output == expected,
+                "output different from expected jaeger.result, written to {:?}",
+                output_path
+            );
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let data = fs::read(Path::new(FILE_DIR).join("jaeger.thrift")).unwrap();
+        assert!(decode_jaeger_batch(&data[..data.len() / 2]).is_err());
+        // a list header claiming more elements than the data holds
+        assert!(decode_jaeger_batch(&[T_LIST, 0, 2, T_STRUCT, 0x7f, 0xff, 0xff, 0xff]).is_err());
+        // nested lists beyond the depth limit
+        let mut nested = vec![T_LIST, 0, 3];
+        for _ in 0..MAX_DEPTH + 2 {
+            nested.extend([T_LIST, 0, 0, 0, 1]);
+        }
+        assert!(decode_jaeger_batch(&nested).is_err());
+        // a span starting at i64::MAX microseconds, beyond the nanoseconds of u64
+        let mut span = vec![T_LIST, 0, 2, T_STRUCT, 0, 0, 0, 1, T_I64, 0, 8];
+        span.extend(i64::MAX.to_be_bytes());
+        span.extend([T_STOP, T_STOP]);
+        assert!(decode_jaeger_batch(&span).is_err());
+    }
+}
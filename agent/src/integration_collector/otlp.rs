@@ -0,0 +1,533 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Conversion of OTLP metrics and logs into the payloads of the existing integrations:
+// metrics are written as telegraf line protocol, following the prometheus-v1 schema of
+// telegraf's own OpenTelemetry input, and logs as the JSON entries of `/api/v1/log`.
+// `SendMessageType::Metrics` only carries the fixed meters of flow metrics documents, which
+// have no room for named metrics, so OTLP metrics go out as `SendMessageType::Telegraf`.
+
+use std::{
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Map, Value};
+
+use public::proto::integration::opentelemetry::proto::{
+    common::v1::{any_value, AnyValue, KeyValue},
+    logs::v1::ResourceLogs,
+    metrics::v1::{metric, number_data_point, NumberDataPoint, ResourceMetrics},
+    resource::v1::Resource,
+};
+
+use super::get_ip;
+
+const HOST_IP: &str = "app.host.ip";
+const SERVICE_NAME: &str = "service.name";
+const K8S_POD_NAME: &str = "k8s.pod.name";
+const K8S_POD_IP: &str = "k8s.pod.ip";
+const K8S_PREFIX: &str = "k8s.";
+const OTEL_HOST_IP: &str = "host.ip";
+
+const SEVERITY_NAMES: [&str; 6] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR", "FATAL"];
+
+fn any_value_to_json(value: &AnyValue) -> Value {
+    match value.value.as_ref() {
+        Some(any_value::Value::StringValue(s)) => Value::String(s.clone()),
+        Some(any_value::Value::BoolValue(b)) => Value::Bool(*b),
+        Some(any_value::Value::IntValue(i)) => Value::from(*i),
+        Some(any_value::Value::DoubleValue(d)) => Value::from(*d),
+        Some(any_value::Value::ArrayValue(a)) => {
+            Value::Array(a.values.iter().map(any_value_to_json).collect())
+        }
+        Some(any_value::Value::KvlistValue(kv)) => Value::Object(
+            kv.values
+                .iter()
+                .map(|kv| {
+                    (
+                        kv.key.clone(),
+                        kv.value.as_ref().map(any_value_to_json).unwrap_or_default(),
+                    )
+                })
+                .collect(),
+        ),
+        Some(any_value::Value::BytesValue(b)) => Value::String(hex_string(b)),
+        None => Value::Null,
+    }
+}
+
+fn any_value_to_string(value: &AnyValue) -> String {
+    match value.value.as_ref() {
+        Some(any_value::Value::StringValue(s)) => s.clone(),
+        None => String::new(),
+        _ => any_value_to_json(value).to_string(),
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(s, "{:02x}", b);
+    }
+    s
+}
+
+fn attributes(kvs: &[KeyValue]) -> impl Iterator<Item = (&str, String)> {
+    kvs.iter().map(|kv| {
+        (
+            kv.key.as_str(),
+            kv.value
+                .as_ref()
+                .map(any_value_to_string)
+                .unwrap_or_default(),
+        )
+    })
+}
+
+// Resource attributes with "app.host.ip" filled by the peer IP if missing, same as traces
+fn resource_attributes(resource: Option<&Resource>, peer_ip: IpAddr) -> Vec<(&str, String)> {
+    let mut attrs: Vec<_> = resource
+        .map(|r| attributes(&r.attributes).collect())
+        .unwrap_or_default();
+    if attrs.iter().all(|(k, _)| *k != HOST_IP) {
+        attrs.push((HOST_IP, peer_ip.to_string()));
+    }
+    attrs
+}
+
+// Resource attributes kept as metric tags, others such as `process.pid` or `telemetry.sdk.*`
+// would only add series
+fn is_resource_tag(key: &str) -> bool {
+    key == SERVICE_NAME || key == HOST_IP || key == OTEL_HOST_IP || key.starts_with(K8S_PREFIX)
+}
+
+fn rfc3339(time_unix_nano: u64) -> String {
+    let time = if time_unix_nano == 0 {
+        SystemTime::now()
+    } else {
+        UNIX_EPOCH + Duration::from_nanos(time_unix_nano)
+    };
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+// Severity text such as "INFO" or "WARN2" from `SeverityNumber` when the record has no text
+fn severity_text(severity_number: i32) -> String {
+    if !(1..=24).contains(&severity_number) {
+        return String::new();
+    }
+    let (level, sub) = ((severity_number - 1) / 4, (severity_number - 1) % 4);
+    if sub == 0 {
+        SEVERITY_NAMES[level as usize].to_string()
+    } else {
+        format!("{}{}", SEVERITY_NAMES[level as usize], sub + 1)
+    }
+}
+
+/// Converts OTLP logs into a JSON array of application log entries
+pub(super) fn logs_to_application_log(
+    peer_addr: SocketAddr,
+    resource_logs: &[ResourceLogs],
+) -> Option<Vec<u8>> {
+    let peer_ip = get_ip(peer_addr.ip());
+    let mut entries = vec![];
+    for resource_log in resource_logs.iter() {
+        let resource_attrs = resource_attributes(resource_log.resource.as_ref(), peer_ip);
+        let mut app_service = "";
+        let mut pod_name = "";
+        let mut pod_ip = None;
+        let mut host_ip = "";
+        for (key, value) in resource_attrs.iter() {
+            match *key {
+                SERVICE_NAME => app_service = value,
+                K8S_POD_NAME => pod_name = value,
+                K8S_POD_IP => pod_ip = Some(value.as_str()),
+                HOST_IP => host_ip = value,
+                _ => (),
+            }
+        }
+        let pod_ip = pod_ip.unwrap_or(host_ip);
+
+        for record in resource_log
+            .scope_logs
+            .iter()
+            .flat_map(|s| s.log_records.iter())
+        {
+            let mut json = Map::new();
+            for (key, value) in resource_attrs.iter() {
+                json.insert(key.to_string(), Value::String(value.clone()));
+            }
+            for kv in record.attributes.iter() {
+                json.insert(
+                    kv.key.clone(),
+                    kv.value.as_ref().map(any_value_to_json).unwrap_or_default(),
+                );
+            }
+            if !record.trace_id.is_empty() {
+                json.insert("trace_id".into(), hex_string(&record.trace_id).into());
+            }
+            if !record.span_id.is_empty() {
+                json.insert("span_id".into(), hex_string(&record.span_id).into());
+            }
+
+            let level = if record.severity_text.is_empty() {
+                severity_text(record.severity_number)
+            } else {
+                record.severity_text.clone()
+            };
+            let time = if record.time_unix_nano != 0 {
+                record.time_unix_nano
+            } else {
+                record.observed_time_unix_nano
+            };
+            let mut entry = Map::new();
+            entry.insert("timestamp".into(), rfc3339(time).into());
+            entry.insert(
+                "message".into(),
+                record
+                    .body
+                    .as_ref()
+                    .map(any_value_to_string)
+                    .unwrap_or_default()
+                    .into(),
+            );
+            entry.insert("level".into(), level.into());
+            entry.insert("app_service".into(), app_service.into());
+            let mut kubernetes = Map::new();
+            kubernetes.insert("pod_name".into(), pod_name.into());
+            kubernetes.insert("pod_ip".into(), pod_ip.into());
+            entry.insert("kubernetes".into(), Value::Object(kubernetes));
+            entry.insert("json".into(), Value::Object(json));
+            entries.push(Value::Object(entry));
+        }
+    }
+    if entries.is_empty() {
+        return None;
+    }
+    serde_json::to_vec(&entries).ok()
+}
+
+fn escape_into(out: &mut String, s: &str, special: &[char]) {
+    for c in s.chars() {
+        match c {
+            '\n' | '\r' => out.push(' '),
+            c if special.contains(&c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+// Writes one line of telegraf line protocol, skipping empty tags and non-finite fields
+fn write_line<'a>(
+    out: &mut String,
+    measurement: &str,
+    tags: impl Iterator<Item = (&'a str, &'a str)>,
+    fields: &[(String, f64)],
+    time_unix_nano: u64,
+) {
+    if measurement.is_empty() || fields.iter().all(|(_, v)| !v.is_finite()) {
+        return;
+    }
+    escape_into(out, measurement, &[',', ' ']);
+    for (key, value) in tags {
+        if key.is_empty() || value.is_empty() {
+            continue;
+        }
+        out.push(',');
+        escape_into(out, key, &[',', '=', ' ']);
+        out.push('=');
+        escape_into(out, value, &[',', '=', ' ']);
+    }
+    let mut separator = ' ';
+    for (key, value) in fields.iter().filter(|(_, v)| v.is_finite()) {
+        out.push(separator);
+        escape_into(out, key, &[',', '=', ' ']);
+        let _ = write!(out, "={}", value);
+        separator = ',';
+    }
+    if time_unix_nano != 0 {
+        let _ = write!(out, " {}", time_unix_nano);
+    }
+    out.push('\n');
+}
+
+fn number_value(point: &NumberDataPoint) -> f64 {
+    match point.value {
+        Some(number_data_point::Value::AsDouble(d)) => d,
+        Some(number_data_point::Value::AsInt(i)) => i as f64,
+        None => f64::NAN,
+    }
+}
+
+/// Converts OTLP metrics into telegraf line protocol
+pub(super) fn metrics_to_telegraf(
+    peer_addr: SocketAddr,
+    resource_metrics: &[ResourceMetrics],
+) -> Option<Vec<u8>> {
+    let peer_ip = get_ip(peer_addr.ip());
+    let mut out = String::new();
+    for resource_metric in resource_metrics.iter() {
+        let mut resource_attrs = resource_attributes(resource_metric.resource.as_ref(), peer_ip);
+        resource_attrs.retain(|(k, _)| is_resource_tag(k));
+        for metric in resource_metric
+            .scope_metrics
+            .iter()
+            .flat_map(|s| s.metrics.iter())
+        {
+            let mut write = |point_attrs: &[KeyValue], fields: &[(String, f64)], time: u64| {
+                let point_attrs: Vec<_> = attributes(point_attrs).collect();
+                let tags = resource_attrs
+                    .iter()
+                    .chain(point_attrs.iter())
+                    .map(|(k, v)| (*k, v.as_str()));
+                write_line(&mut out, &metric.name, tags, fields, time);
+            };
+            match metric.data.as_ref() {
+                Some(metric::Data::Gauge(gauge)) => {
+                    for p in gauge.data_points.iter() {
+                        write(
+                            &p.attributes,
+                            &[("gauge".to_string(), number_value(p))],
+                            p.time_unix_nano,
+                        );
+                    }
+                }
+                Some(metric::Data::Sum(sum)) => {
+                    let field = if sum.is_monotonic { "counter" } else { "gauge" };
+                    for p in sum.data_points.iter() {
+                        write(
+                            &p.attributes,
+                            &[(field.to_string(), number_value(p))],
+                            p.time_unix_nano,
+                        );
+                    }
+                }
+                Some(metric::Data::Histogram(histogram)) => {
+                    for p in histogram.data_points.iter() {
+                        let mut fields = vec![("count".to_string(), p.count as f64)];
+                        if let Some(sum) = p.sum {
+                            fields.push(("sum".to_string(), sum));
+                        }
+                        // buckets are cumulative with the upper bound as field key, same as prometheus
+                        let mut cumulative = 0;
+                        for (i, count) in p.bucket_counts.iter().enumerate() {
+                            cumulative += count;
+                            let bound = match p.explicit_bounds.get(i) {
+                                Some(bound) => bound.to_string(),
+                                None => "+Inf".to_string(),
+                            };
+                            fields.push((bound, cumulative as f64));
+                        }
+                        write(&p.attributes, &fields, p.time_unix_nano);
+                    }
+                }
+                Some(metric::Data::ExponentialHistogram(histogram)) => {
+                    for p in histogram.data_points.iter() {
+                        let mut fields = vec![("count".to_string(), p.count as f64)];
+                        if let Some(sum) = p.sum {
+                            fields.push(("sum".to_string(), sum));
+                        }
+                        write(&p.attributes, &fields, p.time_unix_nano);
+                    }
+                }
+                Some(metric::Data::Summary(summary)) => {
+                    for p in summary.data_points.iter() {
+                        let mut fields = vec![
+                            ("count".to_string(), p.count as f64),
+                            ("sum".to_string(), p.sum),
+                        ];
+                        for q in p.quantile_values.iter() {
+                            fields.push((q.quantile.to_string(), q.value));
+                        }
+                        write(&p.attributes, &fields, p.time_unix_nano);
+                    }
+                }
+                None => (),
+            }
+        }
+    }
+    if out.is_empty() {
+        return None;
+    }
+    Some(out.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use public::proto::integration::opentelemetry::proto::{
+        logs::v1::{LogRecord, ScopeLogs},
+        metrics::v1::{
+            Gauge, Histogram, HistogramDataPoint, Metric, ScopeMetrics, Sum, Summary,
+            SummaryDataPoint,
+        },
+    };
+
+    fn kv(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(value.to_string())),
+            }),
+        }
+    }
+
+    fn resource(attributes: Vec<KeyValue>) -> Option<Resource> {
+        Some(Resource {
+            attributes,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn metrics() {
+        let peer_addr = "[::ffff:10.1.2.3]:4317".parse().unwrap();
+        let metrics = vec![
+            Metric {
+                name: "cpu usage".to_string(),
+                data: Some(metric::Data::Gauge(Gauge {
+                    data_points: vec![NumberDataPoint {
+                        attributes: vec![kv("cpu", "0"), kv("empty", "")],
+                        time_unix_nano: 1700000000000000000,
+                        value: Some(number_data_point::Value::AsDouble(0.5)),
+                        ..Default::default()
+                    }],
+                })),
+                ..Default::default()
+            },
+            Metric {
+                name: "requests".to_string(),
+                data: Some(metric::Data::Sum(Sum {
+                    data_points: vec![NumberDataPoint {
+                        time_unix_nano: 1700000000000000000,
+                        value: Some(number_data_point::Value::AsInt(42)),
+                        ..Default::default()
+                    }],
+                    is_monotonic: true,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            Metric {
+                name: "latency".to_string(),
+                data: Some(metric::Data::Histogram(Histogram {
+                    data_points: vec![HistogramDataPoint {
+                        time_unix_nano: 1700000000000000000,
+                        count: 3,
+                        sum: Some(1.5),
+                        bucket_counts: vec![1, 2],
+                        explicit_bounds: vec![0.5],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+            Metric {
+                name: "duration".to_string(),
+                data: Some(metric::Data::Summary(Summary {
+                    data_points: vec![SummaryDataPoint {
+                        time_unix_nano: 1700000000000000000,
+                        count: 2,
+                        sum: 3.0,
+                        quantile_values: vec![Default::default()],
+                        ..Default::default()
+                    }],
+                })),
+                ..Default::default()
+            },
+        ];
+        let resource_metrics = vec![ResourceMetrics {
+            resource: resource(vec![
+                kv(SERVICE_NAME, "svc"),
+                kv("process.pid", "1"),
+                kv(K8S_POD_NAME, "pod-0"),
+            ]),
+            scope_metrics: vec![ScopeMetrics {
+                metrics,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+
+        let lines = metrics_to_telegraf(peer_addr, &resource_metrics).unwrap();
+        assert_eq!(
+            String::from_utf8(lines).unwrap(),
+            "cpu\\ usage,service.name=svc,k8s.pod.name=pod-0,app.host.ip=10.1.2.3,cpu=0 gauge=0.5 1700000000000000000\n\
+             requests,service.name=svc,k8s.pod.name=pod-0,app.host.ip=10.1.2.3 counter=42 1700000000000000000\n\
+             latency,service.name=svc,k8s.pod.name=pod-0,app.host.ip=10.1.2.3 count=3,sum=1.5,0.5=1,+Inf=3 1700000000000000000\n\
+             duration,service.name=svc,k8s.pod.name=pod-0,app.host.ip=10.1.2.3 count=2,sum=3,0=0 1700000000000000000\n"
+        );
+        assert!(metrics_to_telegraf(peer_addr, &[]).is_none());
+    }
+
+    #[test]
+    fn logs() {
+        let peer_addr = "10.1.2.3:4318".parse().unwrap();
+        let resource_logs = vec![ResourceLogs {
+            resource: resource(vec![
+                kv(SERVICE_NAME, "svc"),
+                kv(K8S_POD_NAME, "pod-0"),
+                kv(HOST_IP, "192.168.0.1"),
+            ]),
+            scope_logs: vec![ScopeLogs {
+                log_records: vec![
+                    LogRecord {
+                        time_unix_nano: 1700000000123456000,
+                        severity_number: 14,
+                        body: Some(AnyValue {
+                            value: Some(any_value::Value::StringValue("hello".to_string())),
+                        }),
+                        attributes: vec![kv("user", "alice")],
+                        trace_id: vec![0xab; 16],
+                        span_id: vec![0xcd; 8],
+                        ..Default::default()
+                    },
+                    LogRecord {
+                        observed_time_unix_nano: 1700000000000000000,
+                        severity_text: "Error".to_string(),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+
+        let data = logs_to_application_log(peer_addr, &resource_logs).unwrap();
+        let entries: Vec<Value> = serde_json::from_slice(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["timestamp"], "2023-11-14T22:13:20.123456Z");
+        assert_eq!(entries[0]["message"], "hello");
+        assert_eq!(entries[0]["level"], "WARN2");
+        assert_eq!(entries[0]["app_service"], "svc");
+        assert_eq!(entries[0]["kubernetes"]["pod_name"], "pod-0");
+        assert_eq!(entries[0]["kubernetes"]["pod_ip"], "192.168.0.1");
+        assert_eq!(entries[0]["json"]["user"], "alice");
+        assert_eq!(entries[0]["json"]["trace_id"], "ab".repeat(16));
+        assert_eq!(entries[0]["json"]["span_id"], "cd".repeat(8));
+        assert_eq!(entries[1]["timestamp"], "2023-11-14T22:13:20.000000Z");
+        assert_eq!(entries[1]["level"], "Error");
+        assert_eq!(entries[1]["message"], "");
+        assert!(logs_to_application_log(peer_addr, &[]).is_none());
+    }
+}
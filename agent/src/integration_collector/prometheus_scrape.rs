@@ -0,0 +1,810 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Prometheus scrape (pull) mode. Targets come from the static config and, on kubernetes, from
+// the annotated pods of this node. Each scrape is encoded as a remote write request, the same
+// payload received by `/api/v1/prometheus`, so that the ingester handles both in one way.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicI64, AtomicU64, Ordering},
+    Arc,
+};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::future::join_all;
+use http::{
+    header::{ACCEPT, CONTENT_TYPE},
+    uri::Scheme,
+};
+use hyper::{
+    body::HttpBody,
+    client::{
+        connect::{Connected, Connection},
+        HttpConnector,
+    },
+    service::Service,
+    Body, Client, Request, Uri,
+};
+use log::{debug, info, warn};
+use prost::Message;
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    select,
+    sync::watch,
+    time,
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+
+use super::{
+    prometheus_text::{self, METRIC_NAME_LABEL},
+    BoxedPrometheusExtra, PrometheusExtra,
+};
+use crate::{config::PrometheusScrape, sender::tls::load_root_certs};
+use public::{
+    counter::{Counter, CounterType, CounterValue},
+    queue::DebugSender,
+};
+
+const ACCEPT_HEADER: &str = "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text";
+const SCRAPE_TIMEOUT_HEADER: &str = "X-Prometheus-Scrape-Timeout-Seconds";
+// larger responses fail the scrape
+const MAX_BODY_SIZE: usize = 16 << 20;
+
+const JOB_LABEL: &str = "job";
+const INSTANCE_LABEL: &str = "instance";
+const EXPORTED_PREFIX: &str = "exported_";
+const STATIC_JOB: &str = "static";
+const KUBERNETES_PODS_JOB: &str = "kubernetes-pods";
+
+// The remote write protocol, https://github.com/prometheus/prometheus/blob/main/prompb/types.proto
+mod prompb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WriteRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub timeseries: Vec<TimeSeries>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TimeSeries {
+        #[prost(message, repeated, tag = "1")]
+        pub labels: Vec<Label>,
+        #[prost(message, repeated, tag = "2")]
+        pub samples: Vec<Sample>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Label {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub value: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Sample {
+        #[prost(double, tag = "1")]
+        pub value: f64,
+        #[prost(int64, tag = "2")]
+        pub timestamp: i64,
+    }
+}
+
+#[derive(Default)]
+pub(super) struct ScrapeCounter {
+    targets: AtomicU64,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+    samples: AtomicU64,
+}
+
+impl ScrapeCounter {
+    pub(super) fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "scrape_targets",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.targets.load(Ordering::Relaxed)),
+            ),
+            (
+                "scrape_succeeded",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.succeeded.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "scrape_failed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.failed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "scraped_samples",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.samples.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Target {
+    url: String,
+    // `job`, `instance` and the labels of discovery
+    labels: Vec<(String, String)>,
+}
+
+impl Target {
+    fn new(url: String, job: &str, extra_labels: Vec<(String, String)>) -> Option<Self> {
+        let uri = match url.parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(e) => {
+                warn!("invalid prometheus scrape target {}: {}", url, e);
+                return None;
+            }
+        };
+        if uri.scheme() != Some(&Scheme::HTTP) && uri.scheme() != Some(&Scheme::HTTPS) {
+            warn!(
+                "invalid prometheus scrape target {}: scheme is not http or https",
+                url
+            );
+            return None;
+        }
+        let Some(authority) = uri.authority() else {
+            warn!("invalid prometheus scrape target {}: no host", url);
+            return None;
+        };
+        let mut labels = vec![
+            (JOB_LABEL.to_string(), job.to_string()),
+            (INSTANCE_LABEL.to_string(), authority.to_string()),
+        ];
+        labels.extend(extra_labels);
+        Some(Self { url, labels })
+    }
+}
+
+fn static_targets(config: &PrometheusScrape) -> Vec<Target> {
+    config
+        .static_targets
+        .iter()
+        .filter_map(|url| Target::new(url.clone(), STATIC_JOB, vec![]))
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+async fn kubernetes_pod_targets(client: &mut Option<kube::Client>) -> Vec<Target> {
+    use crate::platform::kubernetes::list_pod_scrape_targets;
+    use crate::utils::environment::get_k8s_node_name;
+
+    let Some(node_name) = get_k8s_node_name() else {
+        debug!("prometheus scrape of kubernetes pods requires the node name of the agent");
+        return vec![];
+    };
+    if client.is_none() {
+        let mut config = match kube::Config::infer().await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("failed to infer kubernetes config: {}", e);
+                return vec![];
+            }
+        };
+        config.accept_invalid_certs = true;
+        match kube::Client::try_from(config) {
+            Ok(c) => *client = Some(c),
+            Err(e) => {
+                warn!("failed to create kubernetes client: {}", e);
+                return vec![];
+            }
+        }
+    }
+    match list_pod_scrape_targets(client.clone().unwrap(), &node_name).await {
+        Ok(targets) => targets
+            .into_iter()
+            .filter_map(|t| {
+                Target::new(
+                    t.url,
+                    KUBERNETES_PODS_JOB,
+                    vec![
+                        ("namespace".to_string(), t.namespace),
+                        ("pod".to_string(), t.pod),
+                    ],
+                )
+            })
+            .collect(),
+        Err(e) => {
+            warn!("failed to list pods for prometheus scrape: {}", e);
+            vec![]
+        }
+    }
+}
+
+// Accepts any server certificate for `insecure_skip_verify`, signatures are still checked
+#[derive(Debug)]
+struct InsecureVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+// TLS config of https targets, none if neither `ca_cert_file` nor `insecure_skip_verify` is set
+fn tls_config(config: &PrometheusScrape) -> Result<Option<ClientConfig>, String> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    if config.insecure_skip_verify {
+        return Ok(Some(
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(InsecureVerifier(provider)))
+                .with_no_client_auth(),
+        ));
+    }
+    if config.ca_cert_file.is_empty() {
+        return Ok(None);
+    }
+    let roots = load_root_certs(&config.ca_cert_file)?;
+    Ok(Some(
+        builder.with_root_certificates(roots).with_no_client_auth(),
+    ))
+}
+
+enum ScrapeStream {
+    Http(TcpStream),
+    Https(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection for ScrapeStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for ScrapeStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Http(s) => Pin::new(s).poll_read(cx, buf),
+            Self::Https(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ScrapeStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Http(s) => Pin::new(s).poll_write(cx, buf),
+            Self::Https(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Http(s) => Pin::new(s).poll_flush(cx),
+            Self::Https(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Http(s) => Pin::new(s).poll_shutdown(cx),
+            Self::Https(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+// Connects to http targets, and to https targets if TLS is configured
+#[derive(Clone)]
+struct ScrapeConnector {
+    http: HttpConnector,
+    tls: Option<TlsConnector>,
+}
+
+impl ScrapeConnector {
+    fn new(config: &PrometheusScrape) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        let tls = match tls_config(config) {
+            Ok(c) => c.map(|c| TlsConnector::from(Arc::new(c))),
+            Err(e) => {
+                warn!("prometheus scrape of https targets disabled: {}", e);
+                None
+            }
+        };
+        Self { http, tls }
+    }
+}
+
+impl Service<Uri> for ScrapeConnector {
+    type Response = ScrapeStream;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let mut http = self.http.clone();
+        let tls = self.tls.clone();
+        Box::pin(async move {
+            if uri.scheme() != Some(&Scheme::HTTPS) {
+                return Ok(ScrapeStream::Http(http.call(uri).await?));
+            }
+            let Some(tls) = tls else {
+                return Err("https targets require ca_cert_file or insecure_skip_verify".into());
+            };
+            // IPv6 hosts are in brackets
+            let host = uri.host().unwrap_or_default();
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            let server_name = ServerName::try_from(host.to_owned())?;
+            let stream = http.call(uri).await?;
+            Ok(ScrapeStream::Https(Box::new(
+                tls.connect(server_name, stream).await?,
+            )))
+        })
+    }
+}
+
+// Target labels take precedence, conflicting labels of the scraped samples are renamed
+// with the `exported_` prefix as `honor_labels: false` of prometheus does
+fn attach_labels(labels: &mut Vec<(String, String)>, target_labels: &[(String, String)]) {
+    for (name, value) in target_labels {
+        if let Some(l) = labels.iter_mut().find(|(n, _)| n == name) {
+            l.0 = format!("{}{}", EXPORTED_PREFIX, name);
+        }
+        labels.push((name.clone(), value.clone()));
+    }
+}
+
+fn time_series(
+    mut labels: Vec<(String, String)>,
+    target_labels: &[(String, String)],
+    value: f64,
+    timestamp: i64,
+) -> prompb::TimeSeries {
+    attach_labels(&mut labels, target_labels);
+    labels.sort_unstable();
+    prompb::TimeSeries {
+        labels: labels
+            .into_iter()
+            .map(|(name, value)| prompb::Label { name, value })
+            .collect(),
+        samples: vec![prompb::Sample { value, timestamp }],
+    }
+}
+
+fn synthetic_series(name: &str, target: &Target, value: f64, timestamp: i64) -> prompb::TimeSeries {
+    time_series(
+        vec![(METRIC_NAME_LABEL.to_string(), name.to_string())],
+        &target.labels,
+        value,
+        timestamp,
+    )
+}
+
+/// Encodes a scrape as a snappy compressed remote write request, with the `up`,
+/// `scrape_duration_seconds` and `scrape_samples_scraped` series added as prometheus does
+fn encode(
+    target: &Target,
+    samples: Result<Vec<prometheus_text::Sample>, String>,
+    duration: Duration,
+    timestamp: i64,
+) -> Vec<u8> {
+    let (up, samples) = match samples {
+        Ok(s) => (1.0, s),
+        Err(_) => (0.0, vec![]),
+    };
+    let mut request = prompb::WriteRequest {
+        timeseries: Vec::with_capacity(samples.len() + 3),
+    };
+    let scraped = samples.len();
+    for sample in samples {
+        request.timeseries.push(time_series(
+            sample.labels,
+            &target.labels,
+            sample.value,
+            sample.timestamp.unwrap_or(timestamp),
+        ));
+    }
+    request
+        .timeseries
+        .push(synthetic_series("up", target, up, timestamp));
+    request.timeseries.push(synthetic_series(
+        "scrape_duration_seconds",
+        target,
+        duration.as_secs_f64(),
+        timestamp,
+    ));
+    request.timeseries.push(synthetic_series(
+        "scrape_samples_scraped",
+        target,
+        scraped as f64,
+        timestamp,
+    ));
+    // snappy compression never fails on buffers below 4GB
+    snap::raw::Encoder::new()
+        .compress_vec(&request.encode_to_vec())
+        .unwrap_or_default()
+}
+
+async fn fetch(
+    client: &Client<ScrapeConnector>,
+    url: &str,
+    timeout: Duration,
+) -> Result<Vec<prometheus_text::Sample>, String> {
+    let request = Request::get(url)
+        .header(ACCEPT, ACCEPT_HEADER)
+        .header(SCRAPE_TIMEOUT_HEADER, format!("{}", timeout.as_secs_f64()))
+        .body(Body::empty())
+        .map_err(|e| e.to_string())?;
+    let response = client.request(request).await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("server returned HTTP status {}", response.status()));
+    }
+    let openmetrics = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with(OPENMETRICS_CONTENT_TYPE))
+        .unwrap_or(false);
+    let mut body = response.into_body();
+    let mut text = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        if text.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(format!("body size exceeds {} bytes", MAX_BODY_SIZE));
+        }
+        text.extend_from_slice(&chunk);
+    }
+    let text = String::from_utf8(text).map_err(|e| e.to_string())?;
+    prometheus_text::parse(&text, openmetrics).map_err(|e| e.to_string())
+}
+
+async fn scrape(
+    client: &Client<ScrapeConnector>,
+    target: &Target,
+    timeout: Duration,
+    time_diff: i64,
+    sender: &DebugSender<BoxedPrometheusExtra>,
+    counter: &ScrapeCounter,
+) {
+    let start = Instant::now();
+    let timestamp = (SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64
+        + time_diff)
+        / 1_000_000;
+    let samples = match time::timeout(timeout, fetch(client, &target.url, timeout)).await {
+        Ok(r) => r,
+        Err(_) => Err(format!("timeout after {:?}", timeout)),
+    };
+    match &samples {
+        Ok(s) => {
+            counter.succeeded.fetch_add(1, Ordering::Relaxed);
+            counter.samples.fetch_add(s.len() as u64, Ordering::Relaxed);
+        }
+        Err(e) => {
+            counter.failed.fetch_add(1, Ordering::Relaxed);
+            debug!("prometheus scrape {} failed: {}", target.url, e);
+        }
+    }
+    let metrics = encode(target, samples, start.elapsed(), timestamp);
+    let extra = PrometheusExtra {
+        metrics,
+        extra_label_names: vec![],
+        extra_label_values: vec![],
+    };
+    if let Err(e) = sender.send(BoxedPrometheusExtra(Box::new(extra))) {
+        warn!("prometheus_sender failed to send data, because {:?}", e);
+    }
+}
+
+/// Scrapes all targets every interval, until `config` is dropped
+pub(super) async fn run(
+    mut config: watch::Receiver<PrometheusScrape>,
+    sender: DebugSender<BoxedPrometheusExtra>,
+    counter: Arc<ScrapeCounter>,
+    time_diff: Arc<AtomicI64>,
+) {
+    let mut client = None;
+    #[cfg(target_os = "linux")]
+    let mut kube_client = None;
+    info!("prometheus scrape started");
+    loop {
+        let c = config.borrow_and_update().clone();
+        if !c.enabled {
+            counter.targets.store(0, Ordering::Relaxed);
+            if config.changed().await.is_err() {
+                break;
+            }
+            continue;
+        }
+
+        // rebuilt with the TLS config, which also reloads the CA certificates
+        let tls = (c.ca_cert_file.clone(), c.insecure_skip_verify);
+        if !matches!(&client, Some((t, _)) if *t == tls) {
+            client = Some((tls, Client::builder().build(ScrapeConnector::new(&c))));
+        }
+        let client = &client.as_ref().unwrap().1;
+
+        let start = time::Instant::now();
+        #[allow(unused_mut)]
+        let mut targets = static_targets(&c);
+        #[cfg(target_os = "linux")]
+        if c.kubernetes_pods {
+            targets.extend(kubernetes_pod_targets(&mut kube_client).await);
+        }
+        counter
+            .targets
+            .store(targets.len() as u64, Ordering::Relaxed);
+        let time_diff = time_diff.load(Ordering::Relaxed);
+        join_all(
+            targets
+                .iter()
+                .map(|t| scrape(client, t, c.timeout, time_diff, &sender, &counter)),
+        )
+        .await;
+
+        select! {
+            _ = time::sleep_until(start + c.interval) => (),
+            r = config.changed() => if r.is_err() {
+                break;
+            },
+        }
+    }
+    info!("prometheus scrape stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use rustls::{
+        pki_types::{pem::PemObject, PrivateKeyDer},
+        ServerConfig, ServerConnection, StreamOwned,
+    };
+    use tokio::runtime::Runtime;
+
+    const TLS_RESOURCES: &str = "resources/test/sender/tls";
+
+    fn decode(metrics: &[u8]) -> prompb::WriteRequest {
+        let data = snap::raw::Decoder::new().decompress_vec(metrics).unwrap();
+        prompb::WriteRequest::decode(data.as_slice()).unwrap()
+    }
+
+    fn labels(series: &prompb::TimeSeries) -> Vec<String> {
+        series
+            .labels
+            .iter()
+            .map(|l| format!("{}={}", l.name, l.value))
+            .collect()
+    }
+
+    #[test]
+    fn targets() {
+        let config = PrometheusScrape {
+            static_targets: vec![
+                "http://10.0.0.1:9100/metrics".to_string(),
+                "/metrics".to_string(),
+                "ftp://10.0.0.1:9100/metrics".to_string(),
+                "https://10.0.0.2:9100/metrics".to_string(),
+            ],
+            ..Default::default()
+        };
+        let targets = static_targets(&config);
+        assert_eq!(targets.len(), 2);
+        assert_eq!(
+            targets[0].labels,
+            vec![
+                (JOB_LABEL.to_string(), STATIC_JOB.to_string()),
+                (INSTANCE_LABEL.to_string(), "10.0.0.1:9100".to_string()),
+            ]
+        );
+        assert_eq!(targets[1].url, "https://10.0.0.2:9100/metrics");
+    }
+
+    // serves one https request with the test certificate of ingester.deepflow
+    fn serve_https() -> (String, thread::JoinHandle<()>) {
+        let certs = CertificateDer::pem_file_iter(format!("{}/server.pem", TLS_RESOURCES))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(format!("{}/server.key", TLS_RESOURCES)).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://{}/metrics", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let conn = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(conn, sock);
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(n) if n > 0 => request.extend_from_slice(&buf[..n]),
+                    // handshake rejected by client
+                    _ => return,
+                }
+            }
+            let body = "up 1\n";
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.conn.send_close_notify();
+            let _ = stream.flush();
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn https_scrape() {
+        let runtime = Runtime::new().unwrap();
+        let fetch_with = |config: &PrometheusScrape, url: &str| {
+            let client = Client::builder().build(ScrapeConnector::new(config));
+            runtime.block_on(fetch(&client, url, Duration::from_secs(5)))
+        };
+
+        let (url, server) = serve_https();
+        let config = PrometheusScrape {
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        let samples = fetch_with(&config, &url).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].value, 1.0);
+        server.join().unwrap();
+
+        // 127.0.0.1 is not in the server certificate
+        let (url, server) = serve_https();
+        let config = PrometheusScrape {
+            ca_cert_file: format!("{}/ca.pem", TLS_RESOURCES),
+            ..Default::default()
+        };
+        assert!(fetch_with(&config, &url).is_err());
+        server.join().unwrap();
+
+        let e =
+            fetch_with(&PrometheusScrape::default(), "https://127.0.0.1:1/metrics").unwrap_err();
+        assert!(e.contains("ca_cert_file or insecure_skip_verify"), "{}", e);
+    }
+
+    #[test]
+    fn encoding() {
+        let target = Target::new(
+            "http://10.0.0.1:9100/metrics".to_string(),
+            KUBERNETES_PODS_JOB,
+            vec![("pod".to_string(), "app-0".to_string())],
+        )
+        .unwrap();
+        let samples = prometheus_text::parse(
+            "requests_total{code=\"200\",job=\"app\"} 3\nprocess_start_time_seconds 17 1000\n",
+            false,
+        )
+        .unwrap();
+        let request = decode(&encode(
+            &target,
+            Ok(samples),
+            Duration::from_millis(500),
+            2000,
+        ));
+        assert_eq!(request.timeseries.len(), 5);
+        assert_eq!(
+            labels(&request.timeseries[0]),
+            vec![
+                "__name__=requests_total",
+                "code=200",
+                "exported_job=app",
+                "instance=10.0.0.1:9100",
+                "job=kubernetes-pods",
+                "pod=app-0"
+            ]
+        );
+        assert_eq!(
+            request.timeseries[0].samples,
+            vec![prompb::Sample {
+                value: 3.0,
+                timestamp: 2000
+            }]
+        );
+        assert_eq!(request.timeseries[1].samples[0].timestamp, 1000);
+        let up = &request.timeseries[2];
+        assert_eq!(labels(up)[0], "__name__=up");
+        assert_eq!(up.samples[0].value, 1.0);
+        assert_eq!(request.timeseries[3].samples[0].value, 0.5);
+        assert_eq!(request.timeseries[4].samples[0].value, 2.0);
+
+        let request = decode(&encode(
+            &target,
+            Err("connection refused".to_string()),
+            Duration::from_millis(1),
+            2000,
+        ));
+        assert_eq!(request.timeseries.len(), 3);
+        assert_eq!(request.timeseries[0].samples[0].value, 0.0);
+        assert_eq!(request.timeseries[2].samples[0].value, 0.0);
+    }
+}
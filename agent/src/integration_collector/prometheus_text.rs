@@ -0,0 +1,322 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Parser of the Prometheus text exposition format and of OpenMetrics text,
+// https://prometheus.io/docs/instrumenting/exposition_formats/
+// https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+//
+// Metric families are flattened into samples as in the remote write protocol, e.g. histogram
+// buckets are `_bucket` samples with a `le` label. Comments and metadata are skipped.
+
+pub(super) const METRIC_NAME_LABEL: &str = "__name__";
+
+#[derive(Debug, PartialEq)]
+pub(super) struct Sample {
+    // includes `__name__`
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+    // in milliseconds
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn is_name_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c == b':'
+}
+
+fn is_name_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b':'
+}
+
+struct Cursor<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.line.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, String> {
+        let start = self.pos;
+        match self.peek() {
+            Some(c) if is_name_start(c) => self.pos += 1,
+            _ => return Err(format!("invalid name at column {}", self.pos + 1)),
+        }
+        while matches!(self.peek(), Some(c) if is_name_char(c)) {
+            self.pos += 1;
+        }
+        Ok(&self.line[start..self.pos])
+    }
+
+    fn label_value(&mut self) -> Result<String, String> {
+        if !self.eat(b'"') {
+            return Err(format!("expected '\"' at column {}", self.pos + 1));
+        }
+        let mut value = String::new();
+        let mut chars = self.line[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(value);
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, c)) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err("unterminated label value".to_string())
+    }
+
+    fn labels(&mut self, labels: &mut Vec<(String, String)>) -> Result<(), String> {
+        if !self.eat(b'{') {
+            return Ok(());
+        }
+        loop {
+            self.skip_spaces();
+            if self.eat(b'}') {
+                return Ok(());
+            }
+            let name = self.name()?.to_string();
+            self.skip_spaces();
+            if !self.eat(b'=') {
+                return Err(format!("expected '=' at column {}", self.pos + 1));
+            }
+            self.skip_spaces();
+            let value = self.label_value()?;
+            if labels.iter().any(|(n, _)| *n == name) {
+                return Err(format!("duplicate label {}", name));
+            }
+            labels.push((name, value));
+            self.skip_spaces();
+            if !self.eat(b',') {
+                self.skip_spaces();
+                if self.eat(b'}') {
+                    return Ok(());
+                }
+                return Err(format!("expected ',' or '}}' at column {}", self.pos + 1));
+            }
+        }
+    }
+
+    fn token(&mut self) -> Option<&'a str> {
+        self.skip_spaces();
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c != b' ' && c != b'\t') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            None
+        } else {
+            Some(&self.line[start..self.pos])
+        }
+    }
+}
+
+fn parse_value(s: &str) -> Option<f64> {
+    match s {
+        "+Inf" | "Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        s => s.parse().ok(),
+    }
+}
+
+fn parse_sample(line: &str, openmetrics: bool) -> Result<Sample, String> {
+    // exemplars follow the value and the timestamp after a `#`
+    let line = match line.find(" # ") {
+        Some(i) if openmetrics => &line[..i],
+        _ => line,
+    };
+    let mut cursor = Cursor { line, pos: 0 };
+    let name = cursor.name()?;
+    let mut labels = vec![(METRIC_NAME_LABEL.to_string(), name.to_string())];
+    cursor.labels(&mut labels)?;
+    let Some(value) = cursor.token() else {
+        return Err("missing value".to_string());
+    };
+    let Some(value) = parse_value(value) else {
+        return Err(format!("invalid value {}", value));
+    };
+    let timestamp = match cursor.token() {
+        // milliseconds in the text format and seconds in OpenMetrics
+        Some(ts) if openmetrics => match ts.parse::<f64>() {
+            Ok(ts) => Some((ts * 1000.0) as i64),
+            Err(_) => return Err(format!("invalid timestamp {}", ts)),
+        },
+        Some(ts) => match ts.parse::<i64>() {
+            Ok(ts) => Some(ts),
+            Err(_) => return Err(format!("invalid timestamp {}", ts)),
+        },
+        None => None,
+    };
+    if let Some(t) = cursor.token() {
+        return Err(format!("unexpected {}", t));
+    }
+    Ok(Sample {
+        labels,
+        value,
+        timestamp,
+    })
+}
+
+/// Parses the body of a scrape, `openmetrics` is set if the response content type is
+/// `application/openmetrics-text`
+pub(super) fn parse(text: &str, openmetrics: bool) -> Result<Vec<Sample>, ParseError> {
+    let mut samples = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            if openmetrics && line == "# EOF" {
+                break;
+            }
+            continue;
+        }
+        match parse_sample(line.trim_start(), openmetrics) {
+            Ok(s) => samples.push(s),
+            Err(message) => {
+                return Err(ParseError {
+                    line: i + 1,
+                    message,
+                })
+            }
+        }
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(sample: &Sample) -> Vec<String> {
+        sample
+            .labels
+            .iter()
+            .map(|(n, v)| format!("{}={}", n, v))
+            .collect()
+    }
+
+    #[test]
+    fn text_format() {
+        let text = r#"# HELP http_requests_total The total number of HTTP requests.
+# TYPE http_requests_total counter
+http_requests_total{method="post",code="200"} 1027 1395066363000
+http_requests_total{method="post",code="400",} 3 1395066363000
+
+# Escaping in label values:
+msdos_file_access_time_seconds{path="C:\\DIR\\FILE.TXT",error="Cannot find file:\n\"FILE.TXT\""} 1.458255915e9
+metric_without_timestamp_and_labels 12.47
+something_weird{problem="division by zero"} +Inf -3982045
+  rpc_duration_seconds_bucket{le="0.5"}   129389
+"#;
+        let samples = parse(text, false).unwrap();
+        assert_eq!(samples.len(), 6);
+        assert_eq!(
+            labels(&samples[0]),
+            vec!["__name__=http_requests_total", "method=post", "code=200"]
+        );
+        assert_eq!(samples[0].value, 1027.0);
+        assert_eq!(samples[0].timestamp, Some(1395066363000));
+        assert_eq!(samples[1].labels.len(), 3);
+        assert_eq!(
+            labels(&samples[2])[1..],
+            [
+                "path=C:\\DIR\\FILE.TXT",
+                "error=Cannot find file:\n\"FILE.TXT\""
+            ]
+        );
+        assert_eq!(samples[3].timestamp, None);
+        assert_eq!(samples[4].value, f64::INFINITY);
+        assert_eq!(samples[4].timestamp, Some(-3982045));
+        assert_eq!(
+            labels(&samples[5]),
+            vec!["__name__=rpc_duration_seconds_bucket", "le=0.5"]
+        );
+    }
+
+    #[test]
+    fn openmetrics() {
+        let text = r#"# TYPE foo_seconds histogram
+# UNIT foo_seconds seconds
+foo_seconds_bucket{le="0.01"} 0 # {trace_id="KOO5S4vxi0o"} 0.67
+foo_seconds_bucket{le="+Inf"} 17 1520879607.789
+foo_seconds_count NaN
+# EOF
+ignored 1
+"#;
+        let samples = parse(text, true).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].value, 0.0);
+        assert_eq!(samples[1].timestamp, Some(1520879607789));
+        assert!(samples[2].value.is_nan());
+    }
+
+    #[test]
+    fn invalid() {
+        for (text, line) in [
+            ("1abc 1", 1),
+            ("ok 1\nmissing_value\n", 2),
+            ("a{b=\"c\" 1", 1),
+            ("a{b=\"c} 1", 1),
+            ("a{b=\"c\",b=\"d\"} 1", 1),
+            ("a 1 2 3", 1),
+            ("a one", 1),
+            ("a 1 1.5", 1),
+        ] {
+            assert_eq!(parse(text, false).unwrap_err().line, line, "{}", text);
+        }
+    }
+}
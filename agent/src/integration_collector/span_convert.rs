@@ -0,0 +1,313 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Helpers shared by the Zipkin and Jaeger receivers to build OTel spans. Tags of these tracers
+// follow the OpenTracing conventions, they are mapped to the OTel semantic conventions used by
+// `otel_span_l7_info` so that the spans get the same L7 protocol and response status.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use public::proto::integration::opentelemetry::proto::{
+    common::v1::{any_value::Value, AnyValue, KeyValue},
+    resource::v1::Resource,
+    trace::v1::{
+        span::SpanKind, status::StatusCode, ResourceSpans, ScopeSpans, Span, Status, TracesData,
+    },
+};
+
+use super::GenericError;
+
+pub(super) const SERVICE_NAME: &str = "service.name";
+pub(super) const HOST_IP: &str = "app.host.ip";
+
+const HTTP_SCHEME: &str = "http.scheme";
+const PROTOCOL_ATTRIBUTES: [&str; 5] = [
+    HTTP_SCHEME,
+    "db.system",
+    "rpc.system",
+    "messaging.system",
+    "messaging.protocol",
+];
+const HTTP_ATTRIBUTES: [&str; 4] = ["http.method", "http.url", "http.path", "http.route"];
+
+#[derive(Clone, Debug)]
+pub(super) enum TagValue {
+    Str(String),
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Bytes(Vec<u8>),
+}
+
+impl From<TagValue> for AnyValue {
+    fn from(v: TagValue) -> Self {
+        let value = match v {
+            TagValue::Str(s) => Value::StringValue(s),
+            TagValue::Bool(b) => Value::BoolValue(b),
+            TagValue::Int(i) => Value::IntValue(i),
+            TagValue::Double(d) => Value::DoubleValue(d),
+            TagValue::Bytes(b) => Value::BytesValue(b),
+        };
+        AnyValue { value: Some(value) }
+    }
+}
+
+pub(super) fn key_value(key: &str, value: TagValue) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(value.into()),
+    }
+}
+
+pub(super) fn span_kind(kind: &str) -> SpanKind {
+    match kind.to_ascii_lowercase().as_str() {
+        "client" => SpanKind::Client,
+        "server" => SpanKind::Server,
+        "producer" => SpanKind::Producer,
+        "consumer" => SpanKind::Consumer,
+        _ => SpanKind::Internal,
+    }
+}
+
+// Converts zipkin and jaeger times in microseconds, rejecting those out of the range of OTel
+pub(super) fn micros_to_nanos(micros: u64) -> Result<u64, GenericError> {
+    micros
+        .checked_mul(1000)
+        .ok_or_else(|| format!("time {}us out of range", micros).into())
+}
+
+// Decodes a hex id, left padded with zeros to `len` bytes, e.g. a 64 bits zipkin trace id
+pub(super) fn id_from_hex(s: &str, len: usize) -> Option<Vec<u8>> {
+    if s.is_empty() || s.len() > len * 2 || !s.is_ascii() {
+        return None;
+    }
+    let padded = format!("{:0>width$}", s, width = len * 2);
+    (0..len)
+        .map(|i| u8::from_str_radix(&padded[i * 2..i * 2 + 2], 16).ok())
+        .collect()
+}
+
+fn set_status(span: &mut Span, code: StatusCode, message: String) {
+    span.status = Some(Status {
+        message,
+        code: code as i32,
+    });
+}
+
+/// Adds a tag to the span, converting OpenTracing conventions to OTel ones
+pub(super) fn set_tag(span: &mut Span, key: &str, value: TagValue) {
+    let value = match (key, value) {
+        ("error", TagValue::Bool(false)) => TagValue::Bool(false),
+        // zipkin sets the error message as value, jaeger sets true
+        ("error", value) => {
+            let message = match &value {
+                TagValue::Str(s) => s.clone(),
+                _ => String::new(),
+            };
+            set_status(span, StatusCode::Error, message);
+            value
+        }
+        ("otel.status_code", TagValue::Str(s)) => {
+            match s.to_ascii_uppercase().as_str() {
+                "OK" => set_status(span, StatusCode::Ok, String::new()),
+                "ERROR" => set_status(span, StatusCode::Error, String::new()),
+                _ => (),
+            }
+            TagValue::Str(s)
+        }
+        ("otel.status_description", TagValue::Str(s)) => {
+            if let Some(status) = span.status.as_mut() {
+                status.message = s.clone();
+            }
+            TagValue::Str(s)
+        }
+        ("span.kind", TagValue::Str(s)) => {
+            span.kind = span_kind(&s) as i32;
+            return;
+        }
+        ("http.status_code", TagValue::Str(s)) => match s.parse() {
+            Ok(code) => TagValue::Int(code),
+            Err(_) => TagValue::Str(s),
+        },
+        // jaeger clients may report ipv4 as int32
+        ("peer.ipv4", TagValue::Int(ip)) => {
+            let peer_ip = Ipv4Addr::from(ip as u32);
+            span.attributes
+                .push(key_value("net.peer.ip", TagValue::Str(peer_ip.to_string())));
+            TagValue::Int(ip)
+        }
+        ("peer.ipv4" | "peer.ipv6", TagValue::Str(s)) => {
+            if s.parse::<IpAddr>().is_ok() {
+                span.attributes
+                    .push(key_value("net.peer.ip", TagValue::Str(s.clone())));
+            }
+            TagValue::Str(s)
+        }
+        ("peer.port", value) => {
+            span.attributes
+                .push(key_value("net.peer.port", value.clone()));
+            value
+        }
+        ("db.type", TagValue::Str(s)) => {
+            span.attributes
+                .push(key_value("db.system", TagValue::Str(s.clone())));
+            TagValue::Str(s)
+        }
+        (_, value) => value,
+    };
+    span.attributes.push(key_value(key, value));
+}
+
+/// Adds "http.scheme" to spans with http tags only, which is how the L7 protocol is recognized
+pub(super) fn finish_span(span: &mut Span) {
+    if span
+        .attributes
+        .iter()
+        .any(|kv| PROTOCOL_ATTRIBUTES.contains(&kv.key.as_str()))
+    {
+        return;
+    }
+    let mut scheme = None;
+    for kv in span.attributes.iter() {
+        if !HTTP_ATTRIBUTES.contains(&kv.key.as_str()) {
+            continue;
+        }
+        scheme = Some("http");
+        if kv.key == "http.url" {
+            if let Some(Value::StringValue(url)) = kv.value.as_ref().and_then(|v| v.value.as_ref())
+            {
+                if url.starts_with("https://") {
+                    scheme = Some("https");
+                }
+            }
+        }
+    }
+    if let Some(scheme) = scheme {
+        span.attributes
+            .push(key_value(HTTP_SCHEME, TagValue::Str(scheme.to_string())));
+    }
+}
+
+/// Groups spans into `ResourceSpans` by their resource attributes
+#[derive(Default)]
+pub(super) struct TracesBuilder {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+impl TracesBuilder {
+    pub(super) fn push(&mut self, resource_attributes: Vec<KeyValue>, span: Span) {
+        let found = self.resource_spans.iter_mut().find(|rs| {
+            rs.resource
+                .as_ref()
+                .map(|r| r.attributes == resource_attributes)
+                .unwrap_or_default()
+        });
+        match found {
+            Some(rs) => rs.scope_spans[0].spans.push(span),
+            None => self.resource_spans.push(ResourceSpans {
+                resource: Some(Resource {
+                    attributes: resource_attributes,
+                    ..Default::default()
+                }),
+                scope_spans: vec![ScopeSpans {
+                    spans: vec![span],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub(super) fn build(self) -> TracesData {
+        TracesData {
+            resource_spans: self.resource_spans,
+        }
+    }
+}
+
+// Renders the converted spans and their L7 enrichment for the golden files
+#[cfg(test)]
+pub(super) fn render(traces: &TracesData) -> String {
+    use std::fmt::Write;
+
+    let hex = |b: &[u8]| b.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let kv = |kv: &KeyValue| {
+        format!(
+            "{}={:?}",
+            kv.key,
+            kv.value.as_ref().and_then(|v| v.value.as_ref())
+        )
+    };
+    let mut out = String::new();
+    for rs in traces.resource_spans.iter() {
+        let attrs = rs
+            .resource
+            .as_ref()
+            .map(|r| r.attributes.iter().map(kv).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let _ = writeln!(out, "resource {}", attrs.join(" "));
+        for span in rs.scope_spans.iter().flat_map(|s| s.spans.iter()) {
+            let (l7_protocol, status) = super::otel_span_l7_info(span);
+            let _ = writeln!(
+                out,
+                "  span {:?} kind={:?} trace_id={} span_id={} parent_span_id={} start={} end={} status={:?} l7_protocol={:?} response_status={:?}",
+                span.name,
+                SpanKind::try_from(span.kind).unwrap_or(SpanKind::Unspecified),
+                hex(&span.trace_id),
+                hex(&span.span_id),
+                hex(&span.parent_span_id),
+                span.start_time_unix_nano,
+                span.end_time_unix_nano,
+                span.status.as_ref().map(|s| (s.code, s.message.as_str())),
+                l7_protocol,
+                status,
+            );
+            for attr in span.attributes.iter() {
+                let _ = writeln!(out, "    attribute {}", kv(attr));
+            }
+            for event in span.events.iter() {
+                let attrs = event.attributes.iter().map(kv).collect::<Vec<_>>();
+                let _ = writeln!(
+                    out,
+                    "    event {} {:?} {}",
+                    event.time_unix_nano,
+                    event.name,
+                    attrs.join(" ")
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_id() {
+        assert_eq!(
+            id_from_hex("463ac35c9f6413ad", 16),
+            Some(vec![
+                0, 0, 0, 0, 0, 0, 0, 0, 0x46, 0x3a, 0xc3, 0x5c, 0x9f, 0x64, 0x13, 0xad
+            ])
+        );
+        assert_eq!(id_from_hex("abc", 2), Some(vec![0x0a, 0xbc]));
+        assert_eq!(id_from_hex("xyz0", 2), None);
+        assert_eq!(id_from_hex("", 8), None);
+        assert_eq!(id_from_hex("0123456789", 4), None);
+    }
+}
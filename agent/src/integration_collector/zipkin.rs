@@ -0,0 +1,346 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Zipkin v2 spans, https://github.com/openzipkin/zipkin-api/blob/master/zipkin2-api.yaml
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use prost::Message;
+use serde::Deserialize;
+
+use public::proto::integration::opentelemetry::proto::trace::v1::{span::Event, Span, TracesData};
+
+use super::{
+    span_convert::{
+        finish_span, id_from_hex, key_value, micros_to_nanos, set_tag, span_kind, TagValue,
+        TracesBuilder, HOST_IP, SERVICE_NAME,
+    },
+    GenericError,
+};
+
+// https://github.com/openzipkin/zipkin-api/blob/master/zipkin.proto
+mod proto3 {
+    use std::collections::BTreeMap;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Endpoint {
+        #[prost(string, tag = "1")]
+        pub service_name: String,
+        #[prost(bytes = "vec", tag = "2")]
+        pub ipv4: Vec<u8>,
+        #[prost(bytes = "vec", tag = "3")]
+        pub ipv6: Vec<u8>,
+        #[prost(int32, tag = "4")]
+        pub port: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Annotation {
+        #[prost(fixed64, tag = "1")]
+        pub timestamp: u64,
+        #[prost(string, tag = "2")]
+        pub value: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Span {
+        #[prost(bytes = "vec", tag = "1")]
+        pub trace_id: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub parent_id: Vec<u8>,
+        #[prost(bytes = "vec", tag = "3")]
+        pub id: Vec<u8>,
+        #[prost(int32, tag = "4")]
+        pub kind: i32,
+        #[prost(string, tag = "5")]
+        pub name: String,
+        #[prost(fixed64, tag = "6")]
+        pub timestamp: u64,
+        #[prost(uint64, tag = "7")]
+        pub duration: u64,
+        #[prost(message, optional, tag = "8")]
+        pub local_endpoint: Option<Endpoint>,
+        #[prost(message, optional, tag = "9")]
+        pub remote_endpoint: Option<Endpoint>,
+        #[prost(message, repeated, tag = "10")]
+        pub annotations: Vec<Annotation>,
+        #[prost(btree_map = "string, string", tag = "11")]
+        pub tags: BTreeMap<String, String>,
+        #[prost(bool, tag = "12")]
+        pub debug: bool,
+        #[prost(bool, tag = "13")]
+        pub shared: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ListOfSpans {
+        #[prost(message, repeated, tag = "1")]
+        pub spans: Vec<Span>,
+    }
+
+    pub const CLIENT: i32 = 1;
+    pub const SERVER: i32 = 2;
+    pub const PRODUCER: i32 = 3;
+    pub const CONSUMER: i32 = 4;
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEndpoint {
+    service_name: Option<String>,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    port: Option<i32>,
+}
+
+impl From<JsonEndpoint> for proto3::Endpoint {
+    fn from(e: JsonEndpoint) -> Self {
+        Self {
+            service_name: e.service_name.unwrap_or_default(),
+            ipv4: e
+                .ipv4
+                .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+                .map(|ip| ip.octets().to_vec())
+                .unwrap_or_default(),
+            ipv6: e
+                .ipv6
+                .and_then(|ip| ip.parse::<Ipv6Addr>().ok())
+                .map(|ip| ip.octets().to_vec())
+                .unwrap_or_default(),
+            port: e.port.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonAnnotation {
+    timestamp: u64,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSpan {
+    trace_id: String,
+    parent_id: Option<String>,
+    id: String,
+    kind: Option<String>,
+    name: Option<String>,
+    timestamp: Option<u64>,
+    duration: Option<u64>,
+    local_endpoint: Option<JsonEndpoint>,
+    remote_endpoint: Option<JsonEndpoint>,
+    #[serde(default)]
+    annotations: Vec<JsonAnnotation>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+impl TryFrom<JsonSpan> for proto3::Span {
+    type Error = GenericError;
+
+    fn try_from(s: JsonSpan) -> Result<Self, Self::Error> {
+        let Some(trace_id) = id_from_hex(&s.trace_id, 16) else {
+            return Err(format!("invalid zipkin trace id {}", s.trace_id).into());
+        };
+        let Some(id) = id_from_hex(&s.id, 8) else {
+            return Err(format!("invalid zipkin span id {}", s.id).into());
+        };
+        let kind = match s.kind.as_deref() {
+            Some("CLIENT") => proto3::CLIENT,
+            Some("SERVER") => proto3::SERVER,
+            Some("PRODUCER") => proto3::PRODUCER,
+            Some("CONSUMER") => proto3::CONSUMER,
+            _ => 0,
+        };
+        Ok(Self {
+            trace_id,
+            parent_id: s
+                .parent_id
+                .and_then(|id| id_from_hex(&id, 8))
+                .unwrap_or_default(),
+            id,
+            kind,
+            name: s.name.unwrap_or_default(),
+            timestamp: s.timestamp.unwrap_or_default(),
+            duration: s.duration.unwrap_or_default(),
+            local_endpoint: s.local_endpoint.map(Into::into),
+            remote_endpoint: s.remote_endpoint.map(Into::into),
+            annotations: s
+                .annotations
+                .into_iter()
+                .map(|a| proto3::Annotation {
+                    timestamp: a.timestamp,
+                    value: a.value,
+                })
+                .collect(),
+            tags: s.tags,
+            ..Default::default()
+        })
+    }
+}
+
+fn endpoint_ip(e: &proto3::Endpoint) -> Option<IpAddr> {
+    if let Ok(ip) = <[u8; 4]>::try_from(e.ipv4.as_slice()) {
+        return Some(IpAddr::from(ip));
+    }
+    if let Ok(ip) = <[u8; 16]>::try_from(e.ipv6.as_slice()) {
+        return Some(IpAddr::from(ip));
+    }
+    None
+}
+
+// Left pads ids to the OTel length, zipkin trace ids may be 64 bits
+fn pad_id(id: Vec<u8>, len: usize) -> Vec<u8> {
+    if id.is_empty() || id.len() >= len {
+        return id;
+    }
+    let mut padded = vec![0; len - id.len()];
+    padded.extend(id);
+    padded
+}
+
+fn convert(spans: Vec<proto3::Span>) -> Result<TracesData, GenericError> {
+    let mut builder = TracesBuilder::default();
+    for s in spans {
+        let kind = match s.kind {
+            proto3::CLIENT => "client",
+            proto3::SERVER => "server",
+            proto3::PRODUCER => "producer",
+            proto3::CONSUMER => "consumer",
+            _ => "",
+        };
+        // zipkin timestamps are in microseconds
+        let end_time = micros_to_nanos(s.timestamp.saturating_add(s.duration))?;
+        let mut span = Span {
+            trace_id: pad_id(s.trace_id, 16),
+            span_id: s.id,
+            parent_span_id: s.parent_id,
+            name: s.name,
+            kind: span_kind(kind) as i32,
+            start_time_unix_nano: micros_to_nanos(s.timestamp)?,
+            end_time_unix_nano: end_time,
+            events: s
+                .annotations
+                .into_iter()
+                .map(|a| {
+                    Ok(Event {
+                        time_unix_nano: micros_to_nanos(a.timestamp)?,
+                        name: a.value,
+                        ..Default::default()
+                    })
+                })
+                .collect::<Result<_, GenericError>>()?,
+            ..Default::default()
+        };
+        if let Some(remote) = s.remote_endpoint.as_ref() {
+            if !remote.service_name.is_empty() {
+                set_tag(
+                    &mut span,
+                    "peer.service",
+                    TagValue::Str(remote.service_name.clone()),
+                );
+            }
+            if let Some(ip) = endpoint_ip(remote) {
+                span.attributes
+                    .push(key_value("net.peer.ip", TagValue::Str(ip.to_string())));
+            }
+            if remote.port != 0 {
+                span.attributes.push(key_value(
+                    "net.peer.port",
+                    TagValue::Int(remote.port as i64),
+                ));
+            }
+        }
+        for (key, value) in s.tags {
+            set_tag(&mut span, &key, TagValue::Str(value));
+        }
+        finish_span(&mut span);
+
+        let mut resource_attributes = vec![];
+        if let Some(local) = s.local_endpoint.as_ref() {
+            if !local.service_name.is_empty() {
+                resource_attributes.push(key_value(
+                    SERVICE_NAME,
+                    TagValue::Str(local.service_name.clone()),
+                ));
+            }
+            if let Some(ip) = endpoint_ip(local) {
+                resource_attributes.push(key_value(HOST_IP, TagValue::Str(ip.to_string())));
+            }
+        }
+        builder.push(resource_attributes, span);
+    }
+    Ok(builder.build())
+}
+
+/// Decodes a `POST /api/v2/spans` body, in JSON or in proto3 if `is_proto` is set
+pub(super) fn decode_zipkin_spans(data: &[u8], is_proto: bool) -> Result<TracesData, GenericError> {
+    let spans = if is_proto {
+        proto3::ListOfSpans::decode(data)?.spans
+    } else {
+        serde_json::from_slice::<Vec<JsonSpan>>(data)?
+            .into_iter()
+            .map(proto3::Span::try_from)
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    convert(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::integration_collector::span_convert::render;
+
+    const FILE_DIR: &str = "resources/test/integration_collector";
+
+    #[test]
+    fn check() {
+        let json = fs::read(Path::new(FILE_DIR).join("zipkin-v2.json")).unwrap();
+        let expected = fs::read_to_string(Path::new(FILE_DIR).join("zipkin-v2.result")).unwrap();
+        let output = render(&decode_zipkin_spans(&json, false).unwrap());
+        if output != expected {
+            let output_path = Path::new("actual.txt");
+            fs::write(output_path, &output).unwrap();
+            assert!(
+                output == expected,
+                "output different from expected zipkin-v2.result, written to {:?}",
+                output_path
+            );
+        }
+
+        // the proto3 file encodes the same spans
+        let pb = fs::read(Path::new(FILE_DIR).join("zipkin-v2.pb")).unwrap();
+        assert_eq!(render(&decode_zipkin_spans(&pb, true).unwrap()), expected);
+    }
+
+    #[test]
+    fn invalid_id() {
+        let json = br#"[{"traceId":"not-hex","id":"0000000000000001"}]"#;
+        assert!(decode_zipkin_spans(json, false).is_err());
+    }
+
+    #[test]
+    fn time_out_of_range() {
+        let json = br#"[{"traceId":"1","id":"2","timestamp":18446744073709552}]"#;
+        assert!(decode_zipkin_spans(json, false).is_err());
+        let json = br#"[{"traceId":"1","id":"2","timestamp":1,"duration":18446744073709551615}]"#;
+        assert!(decode_zipkin_spans(json, false).is_err());
+    }
+}
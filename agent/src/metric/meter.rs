@@ -14,9 +14,9 @@
  * limitations under the License.
  */
 
-use std::mem::swap;
+use std::{fmt, mem::swap};
 
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 
 use public::proto::metric;
 
@@ -24,7 +24,7 @@ const FLOW_ID: u32 = 1;
 const USAGE_ID: u32 = 4;
 const APP_ID: u32 = 5;
 
-#[derive(Serialize, Debug, Clone, Copy)]
+#[derive(Serialize, Debug, Clone)]
 pub enum Meter {
     Flow(FlowMeter),
     App(AppMeter),
@@ -84,7 +84,7 @@ impl From<Meter> for metric::Meter {
     }
 }
 
-#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
 pub struct FlowMeter {
     pub traffic: Traffic,
     pub latency: Latency,
@@ -198,7 +198,79 @@ impl From<Traffic> for metric::Traffic {
     }
 }
 
-#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+// Log-linear buckets of latency in microseconds: values below 4 have a bucket each, and every
+// larger power of two is split into 4 linear sub-buckets, so that a bucket is at most 1/4 of its
+// lower bound wide. The last bucket also counts all values above it, from 7 << 22us (about 29s).
+const LATENCY_HISTOGRAM_SUB_BUCKET_BITS: u32 = 2;
+const LATENCY_HISTOGRAM_SUB_BUCKETS: u32 = 1 << LATENCY_HISTOGRAM_SUB_BUCKET_BITS;
+const LATENCY_HISTOGRAM_BUCKETS: usize = 96;
+
+fn latency_bucket(us: u32) -> usize {
+    if us < LATENCY_HISTOGRAM_SUB_BUCKETS {
+        return us as usize;
+    }
+    let shift = u32::BITS - 1 - us.leading_zeros() - LATENCY_HISTOGRAM_SUB_BUCKET_BITS;
+    let sub_bucket = (us >> shift) & (LATENCY_HISTOGRAM_SUB_BUCKETS - 1);
+    let bucket = ((shift + 1) << LATENCY_HISTOGRAM_SUB_BUCKET_BITS) + sub_bucket;
+    (bucket as usize).min(LATENCY_HISTOGRAM_BUCKETS - 1)
+}
+
+/// A sparse histogram of latency, mergeable across flows and time slots so that percentiles
+/// can be estimated from the aggregated metrics
+///
+/// Only non-empty buckets are kept, sorted by bucket index, so histograms without samples do not
+/// allocate and a meter holds a pointer-sized vector per latency instead of all buckets.
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram(Vec<(u8, u32)>);
+
+impl LatencyHistogram {
+    fn add(&mut self, bucket: u8, count: u32) {
+        match self.0.binary_search_by_key(&bucket, |(b, _)| *b) {
+            Ok(i) => self.0[i].1 = self.0[i].1.saturating_add(count),
+            Err(i) => self.0.insert(i, (bucket, count)),
+        }
+    }
+
+    pub fn record(&mut self, us: u32) {
+        self.add(latency_bucket(us) as u8, 1);
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        if self.0.is_empty() {
+            self.0.extend_from_slice(&other.0);
+            return;
+        }
+        for (bucket, count) in other.0.iter() {
+            self.add(*bucket, *count);
+        }
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(b, c)| (b, c)))
+            .finish()
+    }
+}
+
+impl Serialize for LatencyHistogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(b, c)| (b, c)))
+    }
+}
+
+impl From<LatencyHistogram> for Option<metric::LatencyHistogram> {
+    fn from(h: LatencyHistogram) -> Self {
+        if h.0.is_empty() {
+            return None;
+        }
+        let (indexes, counts) = h.0.into_iter().map(|(b, c)| (b as u32, c)).unzip();
+        Some(metric::LatencyHistogram { indexes, counts })
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
 pub struct Latency {
     pub rtt_max: u32,
     pub rtt_client_max: u32,
@@ -226,6 +298,13 @@ pub struct Latency {
     pub rrt_count: u32,
     pub cit_count: u32,
     pub tls_rtt_count: u32,
+
+    pub rtt_histogram: LatencyHistogram,
+    pub srt_histogram: LatencyHistogram,
+    pub art_histogram: LatencyHistogram,
+    pub rrt_histogram: LatencyHistogram,
+    pub cit_histogram: LatencyHistogram,
+    pub tls_rtt_histogram: LatencyHistogram,
 }
 
 impl Latency {
@@ -272,6 +351,13 @@ impl Latency {
         self.rrt_count += other.rrt_count;
         self.cit_count += other.cit_count;
         self.tls_rtt_count += other.tls_rtt_count;
+
+        self.rtt_histogram.merge(&other.rtt_histogram);
+        self.srt_histogram.merge(&other.srt_histogram);
+        self.art_histogram.merge(&other.art_histogram);
+        self.rrt_histogram.merge(&other.rrt_histogram);
+        self.cit_histogram.merge(&other.cit_histogram);
+        self.tls_rtt_histogram.merge(&other.tls_rtt_histogram);
     }
 }
 
@@ -301,6 +387,13 @@ impl From<Latency> for metric::Latency {
             art_count: m.art_count,
             rrt_count: m.rrt_count,
             cit_count: m.cit_count,
+
+            rtt_histogram: m.rtt_histogram.into(),
+            srt_histogram: m.srt_histogram.into(),
+            art_histogram: m.art_histogram.into(),
+            rrt_histogram: m.rrt_histogram.into(),
+            cit_histogram: m.cit_histogram.into(),
+            tls_rtt_histogram: m.tls_rtt_histogram.into(),
         }
     }
 }
@@ -313,6 +406,20 @@ pub struct Performance {
     pub zero_win_rx: u64,
     pub retrans_syn: u32,
     pub retrans_synack: u32,
+    pub sack_tx: u64,
+    pub sack_rx: u64,
+    pub dsack_tx: u64,
+    pub dsack_rx: u64,
+    pub out_of_order_tx: u64,
+    pub out_of_order_rx: u64,
+    pub lost_tx: u64,
+    pub lost_rx: u64,
+    pub fast_retrans_tx: u64,
+    pub fast_retrans_rx: u64,
+    pub rto_tx: u64,
+    pub rto_rx: u64,
+    pub bytes_in_flight_max_tx: u32,
+    pub bytes_in_flight_max_rx: u32,
 }
 
 impl Performance {
@@ -323,6 +430,24 @@ impl Performance {
         self.zero_win_rx += other.zero_win_rx;
         self.retrans_syn += other.retrans_syn;
         self.retrans_synack += other.retrans_synack;
+        self.sack_tx += other.sack_tx;
+        self.sack_rx += other.sack_rx;
+        self.dsack_tx += other.dsack_tx;
+        self.dsack_rx += other.dsack_rx;
+        self.out_of_order_tx += other.out_of_order_tx;
+        self.out_of_order_rx += other.out_of_order_rx;
+        self.lost_tx += other.lost_tx;
+        self.lost_rx += other.lost_rx;
+        self.fast_retrans_tx += other.fast_retrans_tx;
+        self.fast_retrans_rx += other.fast_retrans_rx;
+        self.rto_tx += other.rto_tx;
+        self.rto_rx += other.rto_rx;
+        self.bytes_in_flight_max_tx = self
+            .bytes_in_flight_max_tx
+            .max(other.bytes_in_flight_max_tx);
+        self.bytes_in_flight_max_rx = self
+            .bytes_in_flight_max_rx
+            .max(other.bytes_in_flight_max_rx);
     }
 }
 
@@ -335,6 +460,20 @@ impl From<Performance> for metric::Performance {
             zero_win_rx: m.zero_win_rx,
             retrans_syn: m.retrans_syn,
             retrans_synack: m.retrans_synack,
+            sack_tx: m.sack_tx,
+            sack_rx: m.sack_rx,
+            dsack_tx: m.dsack_tx,
+            dsack_rx: m.dsack_rx,
+            out_of_order_tx: m.out_of_order_tx,
+            out_of_order_rx: m.out_of_order_rx,
+            lost_tx: m.lost_tx,
+            lost_rx: m.lost_rx,
+            fast_retrans_tx: m.fast_retrans_tx,
+            fast_retrans_rx: m.fast_retrans_rx,
+            rto_tx: m.rto_tx,
+            rto_rx: m.rto_rx,
+            bytes_in_flight_max_tx: m.bytes_in_flight_max_tx,
+            bytes_in_flight_max_rx: m.bytes_in_flight_max_rx,
         }
     }
 }
@@ -429,7 +568,7 @@ impl From<FlowLoad> for metric::FlowLoad {
     }
 }
 
-#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
 pub struct AppMeter {
     pub traffic: AppTraffic,
     pub latency: AppLatency,
@@ -491,11 +630,12 @@ impl From<AppTraffic> for metric::AppTraffic {
     }
 }
 
-#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
 pub struct AppLatency {
     pub rrt_max: u32,
     pub rrt_sum: u64,
     pub rrt_count: u32,
+    pub rrt_histogram: LatencyHistogram,
 }
 
 impl AppLatency {
@@ -505,6 +645,7 @@ impl AppLatency {
         }
         self.rrt_sum += other.rrt_sum;
         self.rrt_count += other.rrt_count;
+        self.rrt_histogram.merge(&other.rrt_histogram);
     }
 }
 
@@ -514,6 +655,7 @@ impl From<AppLatency> for metric::AppLatency {
             rrt_max: m.rrt_max,
             rrt_sum: m.rrt_sum,
             rrt_count: m.rrt_count,
+            rrt_histogram: m.rrt_histogram.into(),
         }
     }
 }
@@ -589,3 +731,96 @@ impl From<UsageMeter> for metric::UsageMeter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::mem;
+
+    #[test]
+    fn latency_histogram() {
+        let mut h = LatencyHistogram::default();
+        assert_eq!(h, LatencyHistogram::default());
+        for us in [0, 1, 3, 4, 7, 8, 9, 10, 1000, 1023, 1024, u32::MAX] {
+            h.record(us);
+        }
+        assert_eq!(
+            h.0,
+            vec![
+                (0, 1),
+                (1, 1),
+                (3, 1),
+                (4, 1),
+                (7, 1),
+                (8, 2),
+                (9, 1),
+                (35, 2),
+                (36, 1),
+                (95, 1)
+            ]
+        );
+
+        let mut merged = LatencyHistogram::default();
+        merged.merge(&h);
+        assert_eq!(merged, h);
+        merged.record(1000);
+        merged.record(u32::MAX);
+        merged.merge(&h);
+        assert_eq!(merged.0.len(), h.0.len());
+        assert_eq!(merged.0[0], (0, 2));
+        assert_eq!(merged.0[7], (35, 5));
+        assert_eq!(merged.0[9], (95, 3));
+
+        let encoded: Option<metric::LatencyHistogram> = LatencyHistogram::default().into();
+        assert!(encoded.is_none());
+        let mut h = LatencyHistogram::default();
+        h.record(5);
+        h.record(3000);
+        h.record(5);
+        let encoded = Option::<metric::LatencyHistogram>::from(h).unwrap();
+        assert_eq!(encoded.indexes, vec![5, 41]);
+        assert_eq!(encoded.counts, vec![2, 1]);
+    }
+
+    #[test]
+    fn latency_size() {
+        // histograms are a Vec each in the meters, instead of LATENCY_HISTOGRAM_BUCKETS counters
+        let histogram_size = mem::size_of::<LatencyHistogram>();
+        assert_eq!(histogram_size, mem::size_of::<Vec<(u8, u32)>>());
+        assert!(mem::size_of::<Latency>() <= 128 + 6 * histogram_size);
+        assert!(mem::size_of::<AppLatency>() <= 16 + histogram_size);
+    }
+
+    #[test]
+    fn latency_histogram_error() {
+        // lower bound of a bucket, as documented in metric.proto
+        fn lower_bound(bucket: usize) -> u64 {
+            let bucket = bucket as u64;
+            if bucket < 4 {
+                return bucket;
+            }
+            (4 + bucket % 4) << (bucket / 4 - 1)
+        }
+
+        let last = LATENCY_HISTOGRAM_BUCKETS - 1;
+        let mut us = 0u32;
+        loop {
+            let bucket = latency_bucket(us);
+            let lower = lower_bound(bucket);
+            assert!(lower <= us as u64, "{} in {}", us, bucket);
+            if bucket < last {
+                let upper = lower_bound(bucket + 1);
+                assert!((us as u64) < upper, "{} in {}", us, bucket);
+                assert!((upper - lower) * 4 <= lower.max(4), "{} in {}", us, bucket);
+            }
+            us = match us.checked_add(1 + us / 64) {
+                Some(us) => us,
+                None if us < u32::MAX => u32::MAX,
+                None => break,
+            };
+        }
+        assert_eq!(latency_bucket((7 << 22) - 1), last - 1);
+        assert_eq!(latency_bucket(7 << 22), last);
+    }
+}
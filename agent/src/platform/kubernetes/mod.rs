@@ -26,10 +26,12 @@ mod active_poller;
 mod api_watcher;
 mod crd;
 mod passive_poller;
+mod prometheus_targets;
 mod sidecar_poller;
 pub use active_poller::{ActivePoller, InterfaceInfoStore};
 pub use api_watcher::ApiWatcher;
 pub use passive_poller::PassivePoller;
+pub use prometheus_targets::list_pod_scrape_targets;
 pub use sidecar_poller::SidecarPoller;
 
 mod resource_watcher;
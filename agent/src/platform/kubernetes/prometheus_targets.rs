@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Discovers Prometheus scrape targets from the conventional pod annotations:
+//   prometheus.io/scrape: "true"
+//   prometheus.io/port: the port to scrape, the first declared TCP container port by default
+//   prometheus.io/path: /metrics by default
+//   prometheus.io/scheme: http by default
+
+use std::net::{IpAddr, SocketAddr};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::{api::ListParams, Api, Client, Error as ClientErr};
+
+const SCRAPE_ANNOTATION: &str = "prometheus.io/scrape";
+const PORT_ANNOTATION: &str = "prometheus.io/port";
+const PATH_ANNOTATION: &str = "prometheus.io/path";
+const SCHEME_ANNOTATION: &str = "prometheus.io/scheme";
+
+const DEFAULT_PATH: &str = "/metrics";
+const DEFAULT_SCHEME: &str = "http";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PodScrapeTarget {
+    pub namespace: String,
+    pub pod: String,
+    pub url: String,
+}
+
+pub fn pod_scrape_target(pod: &Pod) -> Option<PodScrapeTarget> {
+    let annotations = pod.metadata.annotations.as_ref()?;
+    if annotations.get(SCRAPE_ANNOTATION).map(|s| s.as_str()) != Some("true") {
+        return None;
+    }
+    let status = pod.status.as_ref()?;
+    if status.phase.as_deref() != Some("Running") {
+        return None;
+    }
+    let ip = status.pod_ip.as_ref()?.parse::<IpAddr>().ok()?;
+    let port = match annotations.get(PORT_ANNOTATION) {
+        Some(port) => port.parse::<u16>().ok()?,
+        None => pod
+            .spec
+            .as_ref()?
+            .containers
+            .iter()
+            .filter_map(|c| c.ports.as_ref())
+            .flatten()
+            .find(|p| p.protocol.as_deref().unwrap_or("TCP") == "TCP")
+            .and_then(|p| u16::try_from(p.container_port).ok())?,
+    };
+    let path = annotations
+        .get(PATH_ANNOTATION)
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_PATH);
+    let scheme = annotations
+        .get(SCHEME_ANNOTATION)
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_SCHEME);
+    Some(PodScrapeTarget {
+        namespace: pod.metadata.namespace.clone().unwrap_or_default(),
+        pod: pod.metadata.name.clone().unwrap_or_default(),
+        url: format!(
+            "{}://{}{}{}",
+            scheme,
+            SocketAddr::new(ip, port),
+            if path.starts_with('/') { "" } else { "/" },
+            path
+        ),
+    })
+}
+
+/// Lists the annotated pods running on `node_name`
+pub async fn list_pod_scrape_targets(
+    client: Client,
+    node_name: &str,
+) -> Result<Vec<PodScrapeTarget>, ClientErr> {
+    let api: Api<Pod> = Api::all(client);
+    let params = ListParams::default().fields(&format!("spec.nodeName={}", node_name));
+    let pods = api.list(&params).await?;
+    Ok(pods.items.iter().filter_map(pod_scrape_target).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{Container, ContainerPort, PodSpec, PodStatus};
+
+    use super::*;
+
+    fn pod(annotations: &[(&str, &str)], ports: &[i32]) -> Pod {
+        let mut pod = Pod::default();
+        pod.metadata.namespace = Some("default".to_string());
+        pod.metadata.name = Some("app-0".to_string());
+        pod.metadata.annotations = Some(
+            annotations
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        );
+        pod.spec = Some(PodSpec {
+            containers: vec![Container {
+                name: "app".to_string(),
+                ports: Some(
+                    ports
+                        .iter()
+                        .map(|p| ContainerPort {
+                            container_port: *p,
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        pod.status = Some(PodStatus {
+            phase: Some("Running".to_string()),
+            pod_ip: Some("10.1.2.3".to_string()),
+            ..Default::default()
+        });
+        pod
+    }
+
+    #[test]
+    fn annotations() {
+        let target = pod_scrape_target(&pod(&[(SCRAPE_ANNOTATION, "true")], &[8080])).unwrap();
+        assert_eq!(target.url, "http://10.1.2.3:8080/metrics");
+        assert_eq!(target.namespace, "default");
+        assert_eq!(target.pod, "app-0");
+
+        let target = pod_scrape_target(&pod(
+            &[
+                (SCRAPE_ANNOTATION, "true"),
+                (PORT_ANNOTATION, "9100"),
+                (PATH_ANNOTATION, "stats/prometheus"),
+            ],
+            &[8080],
+        ))
+        .unwrap();
+        assert_eq!(target.url, "http://10.1.2.3:9100/stats/prometheus");
+
+        assert!(pod_scrape_target(&pod(&[], &[8080])).is_none());
+        assert!(pod_scrape_target(&pod(&[(SCRAPE_ANNOTATION, "false")], &[8080])).is_none());
+        // no port to scrape
+        assert!(pod_scrape_target(&pod(&[(SCRAPE_ANNOTATION, "true")], &[])).is_none());
+
+        let mut pending = pod(&[(SCRAPE_ANNOTATION, "true")], &[8080]);
+        pending.status.as_mut().unwrap().phase = Some("Pending".to_string());
+        assert!(pod_scrape_target(&pending).is_none());
+    }
+}
@@ -0,0 +1,501 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::VecDeque;
+use std::fs::{create_dir_all, read_dir, remove_file, rename, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+// A bounded FIFO of encoded frames kept in segment files under one directory.
+//
+// Each segment is a sequence of records:
+//
+// 0          8          16         24         32         40         48         56         64
+// +-------------------------------------------+-------------------------------------------+
+// | frame_len (LE)                            | crc32 of frame (LE)                       |
+// +-------------------------------------------+-------------------------------------------+
+// | frame ...                                                                             |
+// +---------------------------------------------------------------------------------------+
+//
+// Segments are named by an increasing sequence number, new frames are appended to the last one
+// and frames are replayed from the first one. A segment is deleted once it is fully replayed, or
+// evicted as a whole when the buffer exceeds its size limit. The read position is saved to the
+// cursor file by `sync()`, so at most the frames replayed since the last sync are sent again after
+// a restart. Torn records left by a crash are truncated when the buffer is opened.
+
+const SEGMENT_SUFFIX: &str = ".seg";
+const CURSOR_FILE: &str = "cursor";
+const CURSOR_TMP_FILE: &str = "cursor.tmp";
+const RECORD_HEADER_LEN: usize = 8;
+const CURSOR_LEN: usize = 16;
+// a sanity bound for record lengths read from disk, frames are much smaller than this
+const MAX_FRAME_LEN: usize = 64 << 20;
+
+#[derive(Debug)]
+struct Segment {
+    seq: u64,
+    size: u64,
+    records: u64,
+}
+
+pub struct DiskBuffer {
+    dir: PathBuf,
+    max_size: u64,
+    segment_size: u64,
+
+    segments: VecDeque<Segment>,
+    next_seq: u64,
+    size: u64,
+    pending: u64,
+
+    writer: Option<File>,
+    writer_dirty: bool,
+
+    reader: Option<File>,
+    read_offset: u64,
+    front_len: u64,
+    cursor_dirty: bool,
+}
+
+impl DiskBuffer {
+    pub fn open<P: AsRef<Path>>(dir: P, max_size: u64, segment_size: u64) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        create_dir_all(&dir)?;
+
+        let mut seqs = vec![];
+        for entry in read_dir(&dir)? {
+            let name = entry?.file_name();
+            let Some(seq) = name
+                .to_str()
+                .and_then(|n| n.strip_suffix(SEGMENT_SUFFIX))
+                .and_then(|n| n.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            seqs.push(seq);
+        }
+        seqs.sort_unstable();
+
+        let (cursor_seq, cursor_offset) = read_cursor(&dir.join(CURSOR_FILE)).unwrap_or_default();
+        let mut buffer = Self {
+            next_seq: seqs.last().copied().unwrap_or_default().max(cursor_seq) + 1,
+            dir,
+            max_size,
+            segment_size,
+            segments: VecDeque::new(),
+            size: 0,
+            pending: 0,
+            writer: None,
+            writer_dirty: false,
+            reader: None,
+            read_offset: 0,
+            front_len: 0,
+            cursor_dirty: false,
+        };
+        for seq in seqs {
+            let path = buffer.segment_path(seq);
+            if seq < cursor_seq {
+                remove_file(&path)?;
+                continue;
+            }
+            let start = if seq == cursor_seq { cursor_offset } else { 0 };
+            let (size, records, first_offset) = scan_segment(&path, start)?;
+            if records == 0 {
+                remove_file(&path)?;
+                continue;
+            }
+            if buffer.segments.is_empty() {
+                buffer.read_offset = first_offset;
+            }
+            buffer.segments.push_back(Segment { seq, size, records });
+            buffer.size += size;
+            buffer.pending += records;
+        }
+        buffer.evict()?;
+        Ok(buffer)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending == 0
+    }
+
+    // number of frames not replayed yet
+    pub fn len(&self) -> u64 {
+        self.pending
+    }
+
+    // bytes taken on disk by segment files
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Appends a frame, returns the number of frames evicted to keep the size limit
+    pub fn push(&mut self, frame: &[u8]) -> io::Result<u64> {
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("frame length {} exceeds {}", frame.len(), MAX_FRAME_LEN),
+            ));
+        }
+        let record_len = (RECORD_HEADER_LEN + frame.len()) as u64;
+        let rotate = match (self.writer.as_ref(), self.segments.back()) {
+            (Some(_), Some(s)) => s.size + record_len > self.segment_size,
+            _ => true,
+        };
+        if rotate {
+            self.rotate()?;
+        }
+
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[..4].copy_from_slice(&(frame.len() as u32).to_le_bytes());
+        header[4..].copy_from_slice(&crc32fast::hash(frame).to_le_bytes());
+        let writer = self.writer.as_mut().unwrap();
+        let segment = self.segments.back_mut().unwrap();
+        if let Err(e) = writer
+            .write_all(&header)
+            .and_then(|_| writer.write_all(frame))
+        {
+            // drop the partial record so that later appends stay readable
+            let _ = writer.set_len(segment.size);
+            self.writer = None;
+            if segment.records == 0 {
+                let seq = segment.seq;
+                self.segments.pop_back();
+                let _ = remove_file(self.segment_path(seq));
+            }
+            return Err(e);
+        }
+        self.writer_dirty = true;
+        segment.size += record_len;
+        segment.records += 1;
+        self.size += record_len;
+        self.pending += 1;
+
+        self.evict()
+    }
+
+    /// Reads the oldest frame into `frame`, returns false if the buffer is empty.
+    ///
+    /// The frame stays in the buffer until `pop_front()` is called. A frame failing the checksum
+    /// is reported as `ErrorKind::InvalidData` and can be skipped with `pop_front()`.
+    pub fn front(&mut self, frame: &mut Vec<u8>) -> io::Result<bool> {
+        self.front_len = 0;
+        let Some(segment) = self.segments.front() else {
+            return Ok(false);
+        };
+        if self.reader.is_none() {
+            self.reader = Some(File::open(self.segment_path(segment.seq))?);
+        }
+        let reader = self.reader.as_mut().unwrap();
+        reader.seek(SeekFrom::Start(self.read_offset))?;
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if len > MAX_FRAME_LEN || self.read_offset + (RECORD_HEADER_LEN + len) as u64 > segment.size
+        {
+            // the rest of this segment can not be trusted
+            self.front_len = segment.size - self.read_offset;
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid frame length {} in segment {}", len, segment.seq),
+            ));
+        }
+        frame.clear();
+        frame.resize(len, 0);
+        reader.read_exact(frame)?;
+        self.front_len = (RECORD_HEADER_LEN + len) as u64;
+        if crc32fast::hash(frame) != crc {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("frame checksum mismatch in segment {}", segment.seq),
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Removes the frame returned by the last `front()`
+    pub fn pop_front(&mut self) -> io::Result<()> {
+        if self.front_len == 0 {
+            return Ok(());
+        }
+        let Some(segment) = self.segments.front_mut() else {
+            return Ok(());
+        };
+        self.read_offset += self.front_len;
+        self.front_len = 0;
+        self.cursor_dirty = true;
+        if self.read_offset >= segment.size {
+            return self.remove_front();
+        }
+        segment.records -= 1;
+        self.pending -= 1;
+        Ok(())
+    }
+
+    /// Flushes appended frames and saves the read position
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.writer_dirty {
+            if let Some(writer) = self.writer.as_ref() {
+                writer.sync_data()?;
+            }
+            self.writer_dirty = false;
+        }
+        if self.cursor_dirty {
+            if let Some(segment) = self.segments.front() {
+                let mut cursor = [0u8; CURSOR_LEN];
+                cursor[..8].copy_from_slice(&segment.seq.to_le_bytes());
+                cursor[8..].copy_from_slice(&self.read_offset.to_le_bytes());
+                let tmp = self.dir.join(CURSOR_TMP_FILE);
+                let mut f = File::create(&tmp)?;
+                f.write_all(&cursor)?;
+                f.sync_data()?;
+                rename(&tmp, self.dir.join(CURSOR_FILE))?;
+            }
+            self.cursor_dirty = false;
+        }
+        Ok(())
+    }
+
+    fn segment_path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{:020}{}", seq, SEGMENT_SUFFIX))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            if self.writer_dirty {
+                writer.sync_data()?;
+                self.writer_dirty = false;
+            }
+        }
+        let seq = self.next_seq;
+        let writer = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(self.segment_path(seq))?;
+        self.next_seq += 1;
+        self.writer = Some(writer);
+        self.segments.push_back(Segment {
+            seq,
+            size: 0,
+            records: 0,
+        });
+        Ok(())
+    }
+
+    fn evict(&mut self) -> io::Result<u64> {
+        let mut evicted = 0;
+        while self.size > self.max_size && self.segments.len() > 1 {
+            evicted += self.segments.front().unwrap().records;
+            self.remove_front()?;
+        }
+        Ok(evicted)
+    }
+
+    fn remove_front(&mut self) -> io::Result<()> {
+        let Some(segment) = self.segments.pop_front() else {
+            return Ok(());
+        };
+        self.size -= segment.size;
+        self.pending -= segment.records;
+        self.reader = None;
+        self.read_offset = 0;
+        self.front_len = 0;
+        self.cursor_dirty = true;
+        if self.segments.is_empty() {
+            self.writer = None;
+            self.writer_dirty = false;
+        }
+        match remove_file(self.segment_path(segment.seq)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for DiskBuffer {
+    fn drop(&mut self) {
+        if let Err(e) = self.sync() {
+            warn!("sync disk buffer {} failed: {}", self.dir.display(), e);
+        }
+    }
+}
+
+fn read_cursor(path: &Path) -> Option<(u64, u64)> {
+    let mut cursor = [0u8; CURSOR_LEN];
+    File::open(path).ok()?.read_exact(&mut cursor).ok()?;
+    Some((
+        u64::from_le_bytes(cursor[..8].try_into().unwrap()),
+        u64::from_le_bytes(cursor[8..].try_into().unwrap()),
+    ))
+}
+
+// Returns the valid size of the segment, the number of records starting at or after `start`
+// and the offset of the first one. A torn record at the end is truncated.
+fn scan_segment(path: &Path, start: u64) -> io::Result<(u64, u64, u64)> {
+    let mut f = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = f.metadata()?.len();
+    let mut offset = 0;
+    let mut records = 0;
+    let mut first_offset = None;
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    while offset + RECORD_HEADER_LEN as u64 <= len {
+        f.seek(SeekFrom::Start(offset))?;
+        f.read_exact(&mut header)?;
+        let frame_len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let next = offset + (RECORD_HEADER_LEN + frame_len) as u64;
+        if frame_len > MAX_FRAME_LEN || next > len {
+            break;
+        }
+        if offset >= start {
+            records += 1;
+            first_offset.get_or_insert(offset);
+        }
+        offset = next;
+    }
+    if offset < len {
+        warn!(
+            "truncate disk buffer segment {} from {} to {} bytes",
+            path.display(),
+            len,
+            offset
+        );
+        f.set_len(offset)?;
+    }
+    Ok((offset, records, first_offset.unwrap_or(offset)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(i: usize) -> Vec<u8> {
+        vec![i as u8; 100 + i]
+    }
+
+    fn drain(buffer: &mut DiskBuffer) -> Vec<Vec<u8>> {
+        let mut frames = vec![];
+        let mut f = vec![];
+        while buffer.front(&mut f).unwrap() {
+            frames.push(f.clone());
+            buffer.pop_front().unwrap();
+        }
+        frames
+    }
+
+    #[test]
+    fn fifo() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = DiskBuffer::open(dir.path(), 1 << 20, 1024).unwrap();
+        for i in 0..20 {
+            assert_eq!(buffer.push(&frame(i)).unwrap(), 0);
+        }
+        assert_eq!(buffer.len(), 20);
+        assert!(buffer.segments.len() > 1);
+        assert_eq!(drain(&mut buffer), (0..20).map(frame).collect::<Vec<_>>());
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.size(), 0);
+        // fully replayed segments are deleted
+        assert_eq!(
+            read_dir(dir.path())
+                .unwrap()
+                .filter(|e| e
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_str()
+                    .unwrap()
+                    .ends_with(SEGMENT_SUFFIX))
+                .count(),
+            0
+        );
+
+        buffer.push(&frame(1)).unwrap();
+        assert_eq!(drain(&mut buffer), vec![frame(1)]);
+    }
+
+    #[test]
+    fn evict() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = DiskBuffer::open(dir.path(), 2048, 1024).unwrap();
+        let mut evicted = 0;
+        for i in 0..40 {
+            evicted += buffer.push(&frame(i)).unwrap();
+        }
+        assert!(buffer.size() <= 2048);
+        assert_eq!(evicted + buffer.len(), 40);
+        let frames = drain(&mut buffer);
+        assert_eq!(frames.len() as u64, 40 - evicted);
+        // the newest frames are kept in order
+        assert_eq!(
+            frames,
+            (evicted as usize..40).map(frame).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = DiskBuffer::open(dir.path(), 1 << 20, 1024).unwrap();
+        for i in 0..10 {
+            buffer.push(&frame(i)).unwrap();
+        }
+        let mut f = vec![];
+        for _ in 0..3 {
+            assert!(buffer.front(&mut f).unwrap());
+            buffer.pop_front().unwrap();
+        }
+        let last_segment = buffer.segment_path(buffer.segments.back().unwrap().seq);
+        // the read position is saved on drop
+        drop(buffer);
+
+        // a torn record at the tail is truncated
+        let mut torn = OpenOptions::new().append(true).open(&last_segment).unwrap();
+        torn.write_all(&[200, 0, 0, 0, 1, 2, 3, 4, 5]).unwrap();
+        drop(torn);
+
+        let mut buffer = DiskBuffer::open(dir.path(), 1 << 20, 1024).unwrap();
+        assert_eq!(buffer.len(), 7);
+        buffer.push(&frame(10)).unwrap();
+        assert_eq!(drain(&mut buffer), (3..11).map(frame).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = DiskBuffer::open(dir.path(), 1 << 20, 1 << 10).unwrap();
+        buffer.push(&frame(0)).unwrap();
+        buffer.push(&frame(1)).unwrap();
+        let path = buffer.segment_path(buffer.segments.front().unwrap().seq);
+        buffer.sync().unwrap();
+
+        let mut f = OpenOptions::new().write(true).open(&path).unwrap();
+        f.seek(SeekFrom::Start(RECORD_HEADER_LEN as u64)).unwrap();
+        f.write_all(&[0xff]).unwrap();
+        drop(f);
+
+        let mut frame_1 = vec![];
+        assert_eq!(
+            buffer.front(&mut frame_1).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        buffer.pop_front().unwrap();
+        assert!(buffer.front(&mut frame_1).unwrap());
+        assert_eq!(frame_1, frame(1));
+    }
+}
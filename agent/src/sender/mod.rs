@@ -16,9 +16,11 @@
 
 use std::sync::atomic::{AtomicU8, Ordering};
 
+mod disk_buffer;
 // NpbBandwidthWatcher NewFragmenterBuilder NewCompressorBuilder NewPCapBuilder NewUniformCollectSender
 pub mod npb_sender;
 mod tcp_packet;
+pub(crate) mod tls;
 pub(crate) mod uniform_sender;
 
 static ID_COUNTER: AtomicU8 = AtomicU8::new(0);
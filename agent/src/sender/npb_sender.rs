@@ -55,7 +55,7 @@ use crate::dispatcher::af_packet::{Options, Tpacket};
 use crate::exception::ExceptionHandler;
 use crate::utils::stats;
 use npb_handler::{NpbHeader, NOT_SUPPORT};
-use npb_sender::ZmqSender;
+use npb_sender::{ZmqSender, ZmqSenderCounter, DEFAULT_SEND_HWM};
 use public::counter::{Countable, CounterType, CounterValue, OwnedCountable};
 use public::proto::agent::{Exception, SocketType};
 use public::queue::Receiver;
@@ -338,6 +338,17 @@ impl IpSender {
     fn close(&mut self) {}
 }
 
+// Strips the underlay headers of a TCP tunnel packet, the NPB header is kept and its length
+// updated to the overlay packet
+fn overlay_packet(packet: &mut [u8], overlay_packet_offset: usize) -> &mut [u8] {
+    let packet = &mut packet[overlay_packet_offset..];
+    let mut header = NpbHeader::default();
+    let _ = header.decode(packet);
+    header.total_length = packet.len() as u16;
+    let _ = header.encode(packet);
+    packet
+}
+
 #[derive(Debug)]
 struct TcpSender {
     socket: Option<Socket>,
@@ -419,13 +430,10 @@ impl TcpSender {
     ) -> IOResult<usize> {
         self.connect_check()?;
         let _ = arp.lookup_counter(&self.dst_ip);
-        let overlay_packet_offset = self.overlay_packet_offset + underlay_l2_opt_size;
-        let packet = &mut packet.as_mut_slice()[overlay_packet_offset..];
-
-        let mut header = NpbHeader::default();
-        let _ = header.decode(packet);
-        header.total_length = packet.len() as u16;
-        let _ = header.encode(packet);
+        let packet = overlay_packet(
+            &mut packet,
+            self.overlay_packet_offset + underlay_l2_opt_size,
+        );
         let n = self.socket.as_ref().unwrap().send(packet);
         match n {
             Ok(n) => Ok(n),
@@ -472,7 +480,16 @@ impl NpbSender {
             Self::TcpSender(s) => s.send(underlay_l2_opt_size, packet, arp),
             Self::ZmqSender(s) => {
                 let _ = arp.lookup_counter(&s.dst_ip);
-                s.send(underlay_l2_opt_size, packet)
+                let overlay_packet_offset = if s.dst_ip.is_ipv6() {
+                    TCP6_PACKET_SIZE
+                } else {
+                    TCP_PACKET_SIZE
+                };
+                let mut packet = packet;
+                s.send(overlay_packet(
+                    &mut packet,
+                    overlay_packet_offset + underlay_l2_opt_size,
+                ))
             }
         }
     }
@@ -483,6 +500,7 @@ pub struct NpbSenderCounter {
     pub tx: AtomicUsize,
     pub tx_bytes: AtomicUsize,
     pub tx_dropped: AtomicUsize,
+    pub zmq: Arc<ZmqSenderCounter>,
 }
 
 pub struct StatsNpbSenderCounter(Weak<NpbSenderCounter>);
@@ -511,6 +529,18 @@ impl OwnedCountable for StatsNpbSenderCounter {
                         CounterType::Counted,
                         CounterValue::Unsigned(x.tx_dropped.swap(0, Ordering::Relaxed) as u64),
                     ),
+                    (
+                        "zmq_hwm_dropped",
+                        CounterType::Counted,
+                        CounterValue::Unsigned(x.zmq.hwm_dropped.swap(0, Ordering::Relaxed) as u64),
+                    ),
+                    (
+                        "zmq_connect_failed",
+                        CounterType::Counted,
+                        CounterValue::Unsigned(
+                            x.zmq.connect_failed.swap(0, Ordering::Relaxed) as u64
+                        ),
+                    ),
                 ]
             }
             None => vec![],
@@ -837,7 +867,12 @@ impl NpbConnectionPool {
                 Ok(NpbSender::IpSender(sender.unwrap()))
             }
             SocketType::Zmq if protocol == IpProtocol::TCP => {
-                Ok(NpbSender::ZmqSender(ZmqSender::new(remote, self.npb_port)))
+                Ok(NpbSender::ZmqSender(ZmqSender::new(
+                    remote,
+                    self.npb_port,
+                    DEFAULT_SEND_HWM,
+                    self.counter.zmq.clone(),
+                )))
             }
             _ if protocol == IpProtocol::TCP => {
                 Ok(NpbSender::TcpSender(TcpSender::new(remote, self.npb_port)))
@@ -916,6 +951,15 @@ impl NpbConnectionPool {
         return ret;
     }
 
+    // Writes messages queued in ZeroMQ senders while no packets arrive
+    fn flush(&mut self) {
+        for conn in self.connections.values_mut() {
+            if let NpbSender::ZmqSender(s) = conn {
+                s.flush();
+            }
+        }
+    }
+
     pub fn clear(&mut self) {
         self.connections.clear();
     }
@@ -959,6 +1003,7 @@ impl NpbPacketSender {
                 .recv_all(&mut batch, Some(Duration::from_secs(1)))
                 .is_err()
             {
+                self.connections.lock().unwrap().flush();
                 continue;
             }
             for packet in batch.drain(..) {
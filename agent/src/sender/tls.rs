@@ -0,0 +1,356 @@
+/*
+ * Copyright (c) 2024 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{error, info};
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+
+use crate::config::IngesterTls;
+
+// the stream to ingester, in plain text or TLS
+pub enum Stream {
+    Tcp(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Stream {
+    pub fn shutdown(self) -> io::Result<()> {
+        match self {
+            Self::Tcp(s) => s.shutdown(Shutdown::Both),
+            Self::Tls(mut s) => {
+                s.conn.send_close_notify();
+                let _ = s.flush();
+                s.sock.shutdown(Shutdown::Both)
+            }
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(s) => s.write(buf),
+            Self::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(s) => s.flush(),
+            Self::Tls(s) => s.flush(),
+        }
+    }
+}
+
+// Builds TLS client configuration from the certificate files, and rebuilds it when the files
+// are rotated.
+pub struct TlsConnector {
+    config: IngesterTls,
+    client_config: Option<Arc<ClientConfig>>,
+    modified: Vec<Option<SystemTime>>,
+    last_check: Instant,
+}
+
+impl Default for TlsConnector {
+    fn default() -> Self {
+        Self {
+            config: IngesterTls::default(),
+            client_config: None,
+            modified: vec![],
+            last_check: Instant::now(),
+        }
+    }
+}
+
+impl TlsConnector {
+    const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Applies new configuration and reloads rotated certificates, returns true if connections
+    /// should be rebuilt
+    pub fn update(&mut self, config: &IngesterTls) -> bool {
+        if self.config != *config {
+            info!(
+                "ingester tls config changed from {:?} to {:?}",
+                self.config, config
+            );
+            self.config = config.clone();
+            self.client_config = None;
+            self.modified.clear();
+            if self.config.enabled {
+                self.reload();
+            }
+            return true;
+        }
+        if !self.config.enabled || self.last_check.elapsed() < Self::CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        if self.client_config.is_some() && self.files_modified() == self.modified {
+            return false;
+        }
+        self.reload()
+    }
+
+    /// Performs TLS handshake on the connected stream
+    pub fn connect(&self, server: &str, tcp_stream: TcpStream) -> io::Result<Stream> {
+        let Some(client_config) = self.client_config.as_ref() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no valid tls certificates loaded",
+            ));
+        };
+        let server_name = if self.config.server_name.is_empty() {
+            server
+        } else {
+            &self.config.server_name
+        };
+        let server_name = ServerName::try_from(server_name.to_owned())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let conn = ClientConnection::new(client_config.clone(), server_name)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut stream = StreamOwned::new(conn, tcp_stream);
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+        Ok(Stream::Tls(Box::new(stream)))
+    }
+
+    fn files(&self) -> [&str; 3] {
+        [
+            &self.config.ca_cert_file,
+            &self.config.client_cert_file,
+            &self.config.client_key_file,
+        ]
+    }
+
+    fn files_modified(&self) -> Vec<Option<SystemTime>> {
+        self.files()
+            .iter()
+            .map(|f| fs::metadata(f).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    // keeps the loaded certificates if the new ones are invalid, e.g. partially written
+    fn reload(&mut self) -> bool {
+        let modified = self.files_modified();
+        match load_client_config(&self.config) {
+            Ok(c) => {
+                if self.client_config.is_some() {
+                    info!("ingester tls certificates reloaded");
+                }
+                self.client_config = Some(Arc::new(c));
+                self.modified = modified;
+                true
+            }
+            Err(e) => {
+                error!("load ingester tls certificates failed: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Loads the CA certificates in PEM file `ca_cert_file`
+pub(crate) fn load_root_certs(ca_cert_file: &str) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca_cert_file)
+        .map_err(|e| format!("read {} failed: {}", ca_cert_file, e))?
+    {
+        let cert = cert.map_err(|e| format!("parse {} failed: {}", ca_cert_file, e))?;
+        roots
+            .add(cert)
+            .map_err(|e| format!("add ca {} failed: {}", ca_cert_file, e))?;
+    }
+    if roots.is_empty() {
+        return Err(format!("no ca certificate in {}", ca_cert_file));
+    }
+    Ok(roots)
+}
+
+fn load_client_config(config: &IngesterTls) -> Result<ClientConfig, String> {
+    let roots = load_root_certs(&config.ca_cert_file)?;
+    let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_root_certificates(roots);
+    if config.client_cert_file.is_empty() {
+        return Ok(builder.with_no_client_auth());
+    }
+    let certs = CertificateDer::pem_file_iter(&config.client_cert_file)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("read {} failed: {}", config.client_cert_file, e))?;
+    let key = PrivateKeyDer::from_pem_file(&config.client_key_file)
+        .map_err(|e| format!("read {} failed: {}", config.client_key_file, e))?;
+    builder
+        .with_client_auth_cert(certs, key)
+        .map_err(|e| format!("invalid client certificate: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::thread;
+
+    use rustls::{server::WebPkiClientVerifier, ServerConfig, ServerConnection};
+
+    const RESOURCES: &str = "resources/test/sender/tls";
+
+    fn file(name: &str) -> String {
+        Path::new(RESOURCES).join(name).to_str().unwrap().to_owned()
+    }
+
+    fn server_config(require_client_auth: bool) -> Arc<ServerConfig> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap();
+        let builder = if require_client_auth {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(file("ca.pem")).unwrap() {
+                roots.add(cert.unwrap()).unwrap();
+            }
+            builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .unwrap(),
+            )
+        } else {
+            builder.with_no_client_auth()
+        };
+        let certs = CertificateDer::pem_file_iter(file("server.pem"))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(file("server.key")).unwrap();
+        Arc::new(builder.with_single_cert(certs, key).unwrap())
+    }
+
+    // accepts one connection and returns what is received
+    fn serve(config: Arc<ServerConfig>) -> (u16, thread::JoinHandle<io::Result<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (sock, _) = listener.accept()?;
+            let conn = ServerConnection::new(config).unwrap();
+            let mut stream = StreamOwned::new(conn, sock);
+            let mut data = vec![];
+            stream.read_to_end(&mut data)?;
+            Ok(data)
+        });
+        (port, handle)
+    }
+
+    fn new_connector(client_auth: bool) -> TlsConnector {
+        let mut connector = TlsConnector::default();
+        let config = IngesterTls {
+            enabled: true,
+            ca_cert_file: file("ca.pem"),
+            client_cert_file: if client_auth {
+                file("client.pem")
+            } else {
+                String::new()
+            },
+            client_key_file: if client_auth {
+                file("client.key")
+            } else {
+                String::new()
+            },
+            server_name: "ingester.deepflow".to_owned(),
+        };
+        assert!(connector.update(&config));
+        assert!(connector.client_config.is_some());
+        connector
+    }
+
+    #[test]
+    fn mutual_tls() {
+        let (port, server) = serve(server_config(true));
+        let connector = new_connector(true);
+        let tcp_stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut stream = connector.connect("127.0.0.1", tcp_stream).unwrap();
+        stream.write_all(b"frame").unwrap();
+        stream.flush().unwrap();
+        stream.shutdown().unwrap();
+        assert_eq!(server.join().unwrap().unwrap(), b"frame");
+    }
+
+    #[test]
+    fn handshake_failure() {
+        // server name not in server certificate
+        let (port, server) = serve(server_config(false));
+        let mut connector = new_connector(true);
+        let mut config = connector.config.clone();
+        config.server_name = "other.deepflow".to_owned();
+        assert!(connector.update(&config));
+        let tcp_stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        assert!(connector.connect("127.0.0.1", tcp_stream).is_err());
+        assert!(server.join().unwrap().is_err());
+
+        // client certificate required by server
+        let (port, server) = serve(server_config(true));
+        let connector = new_connector(false);
+        let tcp_stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        if let Ok(mut stream) = connector.connect("127.0.0.1", tcp_stream) {
+            let _ = stream.write_all(b"frame").and_then(|_| stream.flush());
+        }
+        assert!(server.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = new_connector(true).config;
+        for name in ["ca.pem", "client.pem", "client.key"] {
+            fs::copy(file(name), dir.path().join(name)).unwrap();
+        }
+        config.ca_cert_file = dir.path().join("ca.pem").to_str().unwrap().to_owned();
+        config.client_cert_file = dir.path().join("client.pem").to_str().unwrap().to_owned();
+        config.client_key_file = dir.path().join("client.key").to_str().unwrap().to_owned();
+        let mut connector = TlsConnector::default();
+        assert!(connector.update(&config));
+        assert!(!connector.update(&config));
+
+        // invalid certificate keeps the loaded one
+        fs::write(&config.client_cert_file, "").unwrap();
+        connector.modified[1] = None;
+        connector.last_check -= TlsConnector::CHECK_INTERVAL;
+        assert!(!connector.update(&config));
+        assert!(connector.client_config.is_some());
+
+        fs::copy(file("client.pem"), &config.client_cert_file).unwrap();
+        connector.last_check -= TlsConnector::CHECK_INTERVAL;
+        assert!(connector.update(&config));
+        connector.last_check -= TlsConnector::CHECK_INTERVAL;
+        assert!(!connector.update(&config));
+    }
+}
@@ -14,15 +14,15 @@
  * limitations under the License.
  */
 
-use std::fs::{create_dir_all, rename, File, OpenOptions};
+use std::fs::{create_dir_all, read_dir, rename, File, OpenOptions};
 use std::io::{BufWriter, ErrorKind, Write};
 use std::marker::PhantomData;
-use std::net::{Shutdown, TcpStream};
-use std::path::Path;
-use std::sync::Mutex;
+use std::mem;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, AtomicU64, Ordering},
-    Arc, Weak,
+    Arc, Mutex, MutexGuard, Weak,
 };
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant, SystemTime};
@@ -36,11 +36,16 @@ use public::{
 };
 use rand::{thread_rng, RngCore};
 
-use super::{get_sender_id, QUEUE_BATCH_SIZE};
+use super::{
+    disk_buffer::DiskBuffer,
+    get_sender_id,
+    tls::{Stream, TlsConnector},
+    QUEUE_BATCH_SIZE,
+};
 
 use crate::config::{
     handler::{SenderAccess, SenderConfig},
-    TrafficOverflowAction,
+    IngesterDiskBuffer, TrafficOverflowAction,
 };
 use crate::exception::ExceptionHandler;
 use crate::trident::SenderEncoder;
@@ -61,6 +66,11 @@ pub struct SenderCounter {
     pub tx_bytes: AtomicU64,
     pub dropped: AtomicU64,
     pub waited: AtomicU64,
+    pub disk_buffer_filled: AtomicU64,
+    pub disk_buffer_replayed: AtomicU64,
+    pub disk_buffer_evicted: AtomicU64,
+    pub disk_buffer_bytes: AtomicU64,
+    pub tls_handshake_failed: AtomicU64,
 }
 
 impl RefCountable for SenderCounter {
@@ -102,6 +112,31 @@ impl RefCountable for SenderCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.waited.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "disk-buffer-filled",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.disk_buffer_filled.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "disk-buffer-replayed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.disk_buffer_replayed.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "disk-buffer-evicted",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.disk_buffer_evicted.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "disk-buffer-bytes",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.disk_buffer_bytes.load(Ordering::Relaxed)),
+            ),
+            (
+                "tls-handshake-failed",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.tls_handshake_failed.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
@@ -371,7 +406,8 @@ enum ConnectionType {
 }
 
 pub struct Connection {
-    tcp_stream: Option<TcpStream>,
+    stream: Option<Stream>,
+    tls: TlsConnector,
 
     reconnect_interval: u8,
 
@@ -385,7 +421,8 @@ pub struct Connection {
 impl Connection {
     pub fn new() -> Self {
         Self {
-            tcp_stream: None,
+            stream: None,
+            tls: TlsConnector::default(),
             reconnect_interval: 10,
             dest_ip: "127.0.0.1".to_string(),
             dest_port: 30033,
@@ -427,12 +464,22 @@ pub struct UniformSender<T> {
     written_size: u64,
 
     cached: bool,
+
+    // frames failed to send are kept on disk and replayed after reconnection,
+    // one buffer for each message type
+    disk_buffer_config: IngesterDiskBuffer,
+    disk_buffers: Vec<(String, DiskBuffer)>,
+    replay_bucket: Option<LeakyBucket>,
+    replay_frame: Vec<u8>,
+    disk_buffer_synced: Instant,
 }
 
 impl<T: Sendable> UniformSender<T> {
     const TCP_WRITE_TIMEOUT: u64 = 3; // s
     const QUEUE_READ_TIMEOUT: u64 = 3; // s
     const DEFAULT_RECONNECT_INTERVAL: u8 = 10; // s
+    const MAX_REPLAY_FRAMES: usize = 64; // each round
+    const DISK_BUFFER_SYNC_INTERVAL: u64 = 1; // s
 
     pub fn new(
         id: usize,
@@ -480,6 +527,11 @@ impl<T: Sendable> UniformSender<T> {
             pre_file_path: String::new(),
             written_size: 0,
             cached: true,
+            disk_buffer_config: IngesterDiskBuffer::default(),
+            disk_buffers: vec![],
+            replay_bucket: None,
+            replay_frame: vec![],
+            disk_buffer_synced: Instant::now(),
         }
     }
 
@@ -500,12 +552,12 @@ impl<T: Sendable> UniformSender<T> {
                 } else {
                     self.connection_type = ConnectionType::Private;
                 }
-                self.global_shared_conn.lock().unwrap().tcp_stream.take();
+                self.global_shared_conn.lock().unwrap().stream.take();
             } else {
                 self.connection_type = ConnectionType::Global;
-                self.private_conn.lock().unwrap().tcp_stream.take();
+                self.private_conn.lock().unwrap().stream.take();
                 if let Some(conn) = self.private_shared_conn.as_ref() {
-                    conn.lock().unwrap().tcp_stream.take();
+                    conn.lock().unwrap().stream.take();
                 }
             }
             if old_connnection_type != self.connection_type {
@@ -534,6 +586,11 @@ impl<T: Sendable> UniformSender<T> {
                 new_conn.last_reconnect = Duration::ZERO;
             }
         }
+
+        let mut conn = self.connection();
+        if conn.tls.update(&cfg.ingester_tls) {
+            conn.reconnect = true;
+        }
     }
 
     fn flush_encoder(&mut self, config: &SenderConfig) {
@@ -546,97 +603,136 @@ impl<T: Sendable> UniformSender<T> {
                 self.encoder.compress_buffer();
             }
             self.encoder.set_header_frame_size();
-            self.send_buffer(config);
+            if !self.send_buffer(config) {
+                self.spill_buffer();
+            }
             self.encoder.reset_buffer();
         }
     }
 
-    fn send_buffer(&mut self, config: &SenderConfig) {
-        if self.is_traffic_overflow(config) {
-            return;
-        }
-        let mut conn = match self.connection_type {
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        match self.connection_type {
             ConnectionType::Global => self.global_shared_conn.lock().unwrap(),
             ConnectionType::PrivateShared => {
-                self.private_shared_conn.as_mut().unwrap().lock().unwrap()
+                self.private_shared_conn.as_ref().unwrap().lock().unwrap()
             }
             ConnectionType::Private => self.private_conn.lock().unwrap(),
-        };
+        }
+    }
 
-        if conn.reconnect || conn.tcp_stream.is_none() {
-            if !self.running.load(Ordering::Relaxed) {
-                return;
-            }
-            if let Some(t) = conn.tcp_stream.take() {
-                if let Err(e) = t.shutdown(Shutdown::Both) {
-                    debug!("{} sender tcp stream shutdown failed {}", self.name, e);
-                }
-            }
-            let now = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap();
-            // If the local timestamp adjustment requires recalculating the interval
-            if conn.last_reconnect > now {
-                conn.last_reconnect = now;
-            }
-            if conn.last_reconnect + Duration::from_secs(conn.reconnect_interval as u64) > now {
-                return;
-            }
+    fn send_buffer(&mut self, config: &SenderConfig) -> bool {
+        if self.is_traffic_overflow(config) {
+            return false;
+        }
+        let mut conn = self.connection();
+        self.connect(&mut conn) && self.write_frame(&mut conn, self.encoder.get_buffer())
+    }
 
+    // returns false if the connection is not ready for writing
+    fn connect(&self, conn: &mut Connection) -> bool {
+        if !conn.reconnect && conn.stream.is_some() {
+            return true;
+        }
+        if !self.running.load(Ordering::Relaxed) {
+            return false;
+        }
+        if let Some(s) = conn.stream.take() {
+            if let Err(e) = s.shutdown() {
+                debug!("{} sender tcp stream shutdown failed {}", self.name, e);
+            }
+        }
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        // If the local timestamp adjustment requires recalculating the interval
+        if conn.last_reconnect > now {
             conn.last_reconnect = now;
-            conn.tcp_stream = TcpStream::connect((conn.dest_ip.clone(), conn.dest_port)).ok();
-            if let Some(tcp_stream) = conn.tcp_stream.as_mut() {
-                if let Err(e) =
-                    tcp_stream.set_write_timeout(Some(Duration::from_secs(Self::TCP_WRITE_TIMEOUT)))
-                {
-                    debug!(
-                        "{} sender tcp stream set write timeout failed {}",
-                        self.name, e
-                    );
-                    conn.tcp_stream.take();
-                    return;
-                }
-                info!(
-                    "{} sender tcp connection to {}:{} succeed.",
-                    self.name, conn.dest_ip, conn.dest_port
+        }
+        if conn.last_reconnect + Duration::from_secs(conn.reconnect_interval as u64) > now {
+            return false;
+        }
+
+        conn.last_reconnect = now;
+        if let Ok(tcp_stream) = TcpStream::connect((conn.dest_ip.clone(), conn.dest_port)) {
+            if let Err(e) =
+                tcp_stream.set_write_timeout(Some(Duration::from_secs(Self::TCP_WRITE_TIMEOUT)))
+            {
+                debug!(
+                    "{} sender tcp stream set write timeout failed {}",
+                    self.name, e
                 );
-                conn.reconnect = false;
-                conn.reconnect_interval = 0;
+                return false;
+            }
+            if !conn.tls.enabled() {
+                conn.stream = Some(Stream::Tcp(tcp_stream));
             } else {
-                if self.counter.dropped.load(Ordering::Relaxed) == 0 {
-                    self.exception_handler.set(Exception::AnalyzerSocketError);
-                    if conn.dest_ip.is_empty() || conn.dest_ip == "0.0.0.0" {
-                        warn!("'analyzer_ip' is not assigned, please check whether the Agent is successfully registered");
-                    } else {
+                // the handshake waits for ingester replies
+                let _ =
+                    tcp_stream.set_read_timeout(Some(Duration::from_secs(Self::TCP_WRITE_TIMEOUT)));
+                match conn.tls.connect(&conn.dest_ip, tcp_stream) {
+                    Ok(s) => conn.stream = Some(s),
+                    Err(e) => {
+                        self.counter
+                            .tls_handshake_failed
+                            .fetch_add(1, Ordering::Relaxed);
+                        self.exception_handler.set(Exception::AnalyzerSocketError);
                         error!(
-                            "{} sender tcp connection to {}:{} failed",
-                            self.name, conn.dest_ip, conn.dest_port,
+                            "{} sender tls handshake with {}:{} failed: {}",
+                            self.name, conn.dest_ip, conn.dest_port, e
                         );
+                        conn.reconnect_interval =
+                            Self::DEFAULT_RECONNECT_INTERVAL + (thread_rng().next_u64() % 5) as u8;
+                        return false;
                     }
                 }
-                self.counter.dropped.fetch_add(1, Ordering::Relaxed);
-                // reconnect after waiting 10 seconds + random 5 seconds to prevent frequent reconnection
-                conn.reconnect_interval =
-                    Self::DEFAULT_RECONNECT_INTERVAL + (thread_rng().next_u64() % 5) as u8;
-                return;
             }
+            info!(
+                "{} sender {} connection to {}:{} succeed.",
+                self.name,
+                if conn.tls.enabled() { "tls" } else { "tcp" },
+                conn.dest_ip,
+                conn.dest_port
+            );
+            conn.reconnect = false;
+            conn.reconnect_interval = 0;
+            true
+        } else {
+            if self.counter.dropped.load(Ordering::Relaxed) == 0 {
+                self.exception_handler.set(Exception::AnalyzerSocketError);
+                if conn.dest_ip.is_empty() || conn.dest_ip == "0.0.0.0" {
+                    warn!("'analyzer_ip' is not assigned, please check whether the Agent is successfully registered");
+                } else {
+                    error!(
+                        "{} sender tcp connection to {}:{} failed",
+                        self.name, conn.dest_ip, conn.dest_port,
+                    );
+                }
+            }
+            // reconnect after waiting 10 seconds + random 5 seconds to prevent frequent reconnection
+            conn.reconnect_interval =
+                Self::DEFAULT_RECONNECT_INTERVAL + (thread_rng().next_u64() % 5) as u8;
+            false
         }
+    }
 
-        let tcp_stream = conn.tcp_stream.as_mut().unwrap();
-        let buffer = &self.encoder.get_buffer();
+    fn write_frame(&self, conn: &mut Connection, buffer: &[u8]) -> bool {
+        let stream = conn.stream.as_mut().unwrap();
         let mut write_offset = 0usize;
         while self.running.load(Ordering::Relaxed) {
-            let result = tcp_stream.write(&buffer[write_offset..]);
+            let result = if write_offset < buffer.len() {
+                stream.write(&buffer[write_offset..])
+            } else {
+                // data is buffered in tls session until flushed
+                stream.flush().map(|_| 0)
+            };
             match result {
-                Ok(size) => {
-                    write_offset += size;
-                    if write_offset == buffer.len() {
-                        self.counter.tx.fetch_add(1, Ordering::Relaxed);
-                        self.counter
-                            .tx_bytes
-                            .fetch_add(buffer.len() as u64, Ordering::Relaxed);
-                        break;
-                    }
+                Ok(size) if write_offset < buffer.len() => write_offset += size,
+                Ok(_) => {
+                    self.counter.tx.fetch_add(1, Ordering::Relaxed);
+                    self.counter
+                        .tx_bytes
+                        .fetch_add(buffer.len() as u64, Ordering::Relaxed);
+                    return true;
                 }
                 Err(e) if e.kind() == ErrorKind::WouldBlock => {
                     debug!("{} sender tcp stream write data block {}", self.name, e);
@@ -650,12 +746,220 @@ impl<T: Sendable> UniformSender<T> {
                             self.name, conn.dest_ip, conn.dest_port, e
                         );
                     }
-                    self.counter.dropped.fetch_add(1, Ordering::Relaxed);
-                    conn.tcp_stream.take();
-                    break;
+                    conn.stream.take();
+                    return false;
                 }
             };
         }
+        false
+    }
+
+    fn disk_buffer_dir(&self) -> PathBuf {
+        Path::new(&self.disk_buffer_config.data_dir).join(self.name)
+    }
+
+    fn update_disk_buffer(&mut self, config: &SenderConfig) {
+        if self.disk_buffer_config == config.ingester_disk_buffer {
+            return;
+        }
+        info!(
+            "{} sender update disk buffer from {:?} to {:?}",
+            self.name, self.disk_buffer_config, config.ingester_disk_buffer
+        );
+        self.disk_buffer_config = config.ingester_disk_buffer.clone();
+        // buffers are synced when dropped and reopened with the new limits
+        self.disk_buffers.clear();
+        if !self.disk_buffer_config.enabled {
+            self.replay_bucket = None;
+            self.update_disk_buffer_bytes();
+            return;
+        }
+
+        let rate = Some(self.disk_buffer_config.max_replay_throughput << 17); // Mbit -> byte
+        match self.replay_bucket.as_ref() {
+            Some(bucket) => bucket.set_rate(rate),
+            None => self.replay_bucket = Some(LeakyBucket::new(rate)),
+        }
+        // pick up frames left by the last run
+        if let Ok(entries) = read_dir(self.disk_buffer_dir()) {
+            for entry in entries.flatten() {
+                if !entry.path().is_dir() {
+                    continue;
+                }
+                if let Some(key) = entry.file_name().to_str() {
+                    self.disk_buffer_index(key);
+                }
+            }
+        }
+        self.update_disk_buffer_bytes();
+    }
+
+    fn disk_buffer_index(&mut self, key: &str) -> Option<usize> {
+        if let Some(index) = self.disk_buffers.iter().position(|(k, _)| k == key) {
+            return Some(index);
+        }
+        match DiskBuffer::open(
+            self.disk_buffer_dir().join(key),
+            self.disk_buffer_config.max_size,
+            self.disk_buffer_config.segment_size,
+        ) {
+            Ok(buffer) => {
+                if !buffer.is_empty() {
+                    info!(
+                        "{} sender disk buffer {} has {} frames to replay",
+                        self.name,
+                        key,
+                        buffer.len()
+                    );
+                }
+                self.disk_buffers.push((key.to_owned(), buffer));
+                Some(self.disk_buffers.len() - 1)
+            }
+            Err(e) => {
+                if self.counter.dropped.load(Ordering::Relaxed) == 0 {
+                    warn!(
+                        "{} sender open disk buffer {} failed: {}",
+                        self.name,
+                        self.disk_buffer_dir().join(key).display(),
+                        e
+                    );
+                }
+                None
+            }
+        }
+    }
+
+    fn update_disk_buffer_bytes(&self) {
+        self.counter.disk_buffer_bytes.store(
+            self.disk_buffers.iter().map(|(_, b)| b.size()).sum(),
+            Ordering::Relaxed,
+        );
+    }
+
+    // keeps the frame in encoder on disk, or drops it if disk buffer is disabled
+    fn spill_buffer(&mut self) {
+        if !self.disk_buffer_config.enabled {
+            self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let key = self.encoder.header.msg_type.to_string().replace(' ', "_");
+        let Some(index) = self.disk_buffer_index(&key) else {
+            self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        match self.disk_buffers[index].1.push(self.encoder.get_buffer()) {
+            Ok(evicted) => {
+                self.counter
+                    .disk_buffer_filled
+                    .fetch_add(1, Ordering::Relaxed);
+                self.counter
+                    .disk_buffer_evicted
+                    .fetch_add(evicted, Ordering::Relaxed);
+            }
+            Err(e) => {
+                if self.counter.dropped.load(Ordering::Relaxed) == 0 {
+                    warn!(
+                        "{} sender write disk buffer {} failed: {}",
+                        self.name, key, e
+                    );
+                }
+                self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.update_disk_buffer_bytes();
+    }
+
+    // Replays buffered frames in order when the connection is up. Live frames are sent first,
+    // replaying is limited by both `max_replay_throughput` and `max_throughput_to_ingester`.
+    fn replay_disk_buffers(&mut self) {
+        if self.disk_buffers.iter().all(|(_, b)| b.is_empty()) {
+            return;
+        }
+        let mut buffers = mem::take(&mut self.disk_buffers);
+        let mut frame = mem::take(&mut self.replay_frame);
+        // a frame larger than the bucket capacity only takes the whole bucket
+        let cost = |bucket: &LeakyBucket, size: u64| size.min(bucket.capacity());
+
+        let mut conn = self.connection();
+        if self.connect(&mut conn) {
+            let mut replayed = 0;
+            'replay: for (key, buffer) in buffers.iter_mut() {
+                while replayed < Self::MAX_REPLAY_FRAMES && self.running.load(Ordering::Relaxed) {
+                    match buffer.front(&mut frame) {
+                        Ok(true) => (),
+                        Ok(false) => break,
+                        Err(e) if e.kind() == ErrorKind::InvalidData => {
+                            warn!(
+                                "{} sender skip corrupted frame in disk buffer {}: {}",
+                                self.name, key, e
+                            );
+                            self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+                            if buffer.pop_front().is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                        Err(e) => {
+                            warn!(
+                                "{} sender read disk buffer {} failed: {}",
+                                self.name, key, e
+                            );
+                            break;
+                        }
+                    }
+                    let size = frame.len() as u64;
+                    let shared = cost(&self.leaky_bucket, size);
+                    if !self.leaky_bucket.acquire(shared) {
+                        break 'replay;
+                    }
+                    if let Some(bucket) = self.replay_bucket.as_ref() {
+                        if !bucket.acquire(cost(bucket, size)) {
+                            // nothing is sent, give the shared tokens back to realtime sending
+                            self.leaky_bucket.release(shared);
+                            break 'replay;
+                        }
+                    }
+                    if !self.write_frame(&mut conn, &frame) {
+                        break 'replay;
+                    }
+                    if let Err(e) = buffer.pop_front() {
+                        warn!(
+                            "{} sender remove frame from disk buffer {} failed: {}",
+                            self.name, key, e
+                        );
+                        break;
+                    }
+                    self.counter
+                        .disk_buffer_replayed
+                        .fetch_add(1, Ordering::Relaxed);
+                    replayed += 1;
+                }
+            }
+        }
+        drop(conn);
+
+        self.disk_buffers = buffers;
+        self.replay_frame = frame;
+        self.sync_disk_buffers();
+        self.update_disk_buffer_bytes();
+    }
+
+    // Saves appended frames and read positions at most once per interval, frames replayed since
+    // the last sync are sent again after a crash. Buffers are also synced when dropped.
+    fn sync_disk_buffers(&mut self) {
+        if self.disk_buffer_synced.elapsed() < Duration::from_secs(Self::DISK_BUFFER_SYNC_INTERVAL)
+        {
+            return;
+        }
+        self.disk_buffer_synced = Instant::now();
+        for (key, buffer) in self.disk_buffers.iter_mut() {
+            if let Err(e) = buffer.sync() {
+                warn!(
+                    "{} sender sync disk buffer {} failed: {}",
+                    self.name, key, e
+                );
+            }
+        }
     }
 
     fn log_when_traffic_overflow(&mut self, config: &SenderConfig) {
@@ -696,7 +1000,6 @@ impl<T: Sendable> UniformSender<T> {
         } else {
             if !self.leaky_bucket.acquire(self.encoder.buffer_len() as u64) {
                 overflow = true;
-                self.counter.dropped.fetch_add(1, Ordering::Relaxed);
             }
         }
 
@@ -732,6 +1035,7 @@ impl<T: Sendable> UniformSender<T> {
                 self.leaky_bucket.set_rate(Some(max_throughput_mbps << 17)); // Mbit -> byte
                 self.max_throughput_mbps = max_throughput_mbps;
             }
+            self.update_disk_buffer(&config);
             match self.input.recv_all(
                 &mut batch,
                 Some(Duration::from_secs(Self::QUEUE_READ_TIMEOUT)),
@@ -771,6 +1075,9 @@ impl<T: Sendable> UniformSender<T> {
                             self.counter.dropped.fetch_add(1, Ordering::Relaxed);
                         }
                     }
+                    if socket_type != SocketType::File {
+                        self.replay_disk_buffers();
+                    }
                 }
                 Err(Error::Timeout) => match socket_type {
                     SocketType::File => self.flush_writer(),
@@ -778,6 +1085,7 @@ impl<T: Sendable> UniformSender<T> {
                         self.update_connection(&config);
                         self.encoder.update_header(self.name, self.id, &config);
                         self.flush_encoder(&config);
+                        self.replay_disk_buffers();
                     }
                 },
                 Err(Error::Terminated(..)) => {
@@ -790,6 +1098,11 @@ impl<T: Sendable> UniformSender<T> {
                 Err(Error::BatchTooLarge(_)) => unreachable!(),
             }
         }
+        // keep frames not sent yet for the next run
+        if self.disk_buffer_config.enabled {
+            self.flush_encoder(&self.config.load());
+        }
+        self.disk_buffers.clear();
     }
 
     pub fn flush_writer(&mut self) {
@@ -859,3 +1172,86 @@ impl<T: Sendable> UniformSender<T> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicI64;
+
+    use arc_swap::{access::Map, ArcSwap};
+
+    use crate::config::handler::ModuleConfig;
+
+    #[derive(Debug)]
+    struct Empty;
+
+    impl Sendable for Empty {
+        fn encode(self, _: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+            Ok(0)
+        }
+
+        fn message_type(&self) -> SendMessageType {
+            SendMessageType::TaggedFlow
+        }
+    }
+
+    #[test]
+    fn replay_large_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut module_config = ModuleConfig::default();
+        let config = &mut module_config.sender;
+        config.dest_ip = "127.0.0.1".to_owned();
+        config.dest_port = listener.local_addr().unwrap().port();
+        config.multiple_sockets_to_ingester = true;
+        config.ingester_disk_buffer = IngesterDiskBuffer {
+            enabled: true,
+            data_dir: dir.path().to_str().unwrap().to_owned(),
+            max_replay_throughput: 1,
+            ..Default::default()
+        };
+        let config = config.clone();
+        let access: SenderAccess = Map::new(
+            Arc::new(ArcSwap::from_pointee(module_config)),
+            |config| -> &SenderConfig { &config.sender },
+        );
+        let (_, receiver, _) = public::queue::bounded(1);
+        let mut sender = UniformSender::<Empty>::new(
+            0,
+            "test",
+            Arc::new(receiver),
+            access,
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(Collector::new("test", Arc::new(AtomicI64::new(0)))),
+            ExceptionHandler::default(),
+            None,
+            SenderEncoder::Raw,
+            Arc::new(LeakyBucket::new(Some(1 << 17))),
+        );
+        // both buckets are limited to 1Mbps, with capacities less than the frame
+        sender.max_throughput_mbps = 1;
+        sender.update_disk_buffer(&config);
+        sender.update_connection(&config);
+        let frame = vec![7; 300 << 10];
+        let index = sender.disk_buffer_index("test").unwrap();
+        sender.disk_buffers[index].1.push(&frame).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut received = vec![0; 300 << 10];
+            conn.read_exact(&mut received).unwrap();
+            received
+        });
+        // wait for the buckets to be filled
+        thread::sleep(Duration::from_millis(50));
+        sender.replay_disk_buffers();
+        assert_eq!(
+            sender.counter.disk_buffer_replayed.load(Ordering::Relaxed),
+            1
+        );
+        assert_eq!(server.join().unwrap(), frame);
+    }
+}
@@ -1046,7 +1046,6 @@ impl Trident {
                     }
 
                     let mut comp = Components::new(
-                        &version_info,
                         &config_handler,
                         stats_collector.clone(),
                         &session,
@@ -1269,7 +1268,6 @@ fn component_on_config_change(
                     components.stats_collector.clone(),
                     config_handler,
                     components.debugger.clone_queue(),
-                    components.is_ce_version,
                     synchronizer,
                     components.npb_bps_limit.clone(),
                     components.npb_arp_table.clone(),
@@ -1390,7 +1388,6 @@ fn component_on_config_change(
                     components.stats_collector.clone(),
                     config_handler,
                     debugger_queue.clone(),
-                    components.is_ce_version,
                     synchronizer,
                     components.npb_bps_limit.clone(),
                     components.npb_arp_table.clone(),
@@ -1784,7 +1781,6 @@ pub struct AgentComponents {
     pub npb_bandwidth_watcher: Box<Arc<NpbBandwidthWatcher>>,
     pub npb_arp_table: Arc<NpbArpTable>,
     pub vector_component: VectorComponent,
-    pub is_ce_version: bool, // Determine whether the current version is a ce version, CE-AGENT always set pcap-assembler disabled
     pub tap_interfaces: Vec<Link>,
     pub bpf_options: Arc<Mutex<BpfOptions>>,
     pub last_dispatcher_component_id: usize,
@@ -2071,7 +2067,6 @@ impl AgentComponents {
     }
 
     fn new(
-        version_info: &VersionInfo,
         config_handler: &ConfigHandler,
         stats_collector: Arc<stats::Collector>,
         session: &Arc<Session>,
@@ -2620,7 +2615,6 @@ impl AgentComponents {
                 stats_collector.clone(),
                 config_handler,
                 queue_debugger.clone(),
-                version_info.name != env!("AGENT_NAME"),
                 synchronizer,
                 npb_bps_limit.clone(),
                 npb_arp_table.clone(),
@@ -3061,10 +3055,12 @@ impl AgentComponents {
             prometheus_sender,
             telegraf_sender,
             profile_sender,
-            application_log_sender,
+            application_log_sender.clone(),
             skywalking_sender,
             datadog_sender,
             candidate_config.metric_server.port,
+            candidate_config.metric_server.otlp_grpc_port,
+            candidate_config.metric_server.prometheus_scrape.clone(),
             exception_handler.clone(),
             candidate_config.metric_server.compressed,
             candidate_config.metric_server.profile_compressed,
@@ -3121,6 +3117,11 @@ impl AgentComponents {
             user_config.inputs.vector.enabled,
             user_config.inputs.vector.config.clone(),
             runtime.clone(),
+            application_log_sender,
+        );
+        stats_collector.register_countable(
+            &stats::NoTagModule("vector"),
+            Countable::Ref(Arc::downgrade(&vector_component.counter()) as Weak<dyn RefCountable>),
         );
 
         Ok(AgentComponents {
@@ -3174,7 +3175,6 @@ impl AgentComponents {
             vector_component,
             runtime,
             dispatcher_components,
-            is_ce_version: version_info.name != env!("AGENT_NAME"),
             tap_interfaces,
             last_dispatcher_component_id: otel_dispatcher_id,
             bpf_options,
@@ -3377,7 +3377,6 @@ impl Components {
     }
 
     fn new(
-        version_info: &VersionInfo,
         config_handler: &ConfigHandler,
         stats_collector: Arc<stats::Collector>,
         session: &Arc<Session>,
@@ -3399,7 +3398,6 @@ impl Components {
             return Ok(Components::Watcher(components));
         }
         let components = AgentComponents::new(
-            version_info,
             config_handler,
             stats_collector,
             session,
@@ -3432,7 +3430,6 @@ impl Components {
 }
 
 fn build_pcap_assembler(
-    enabled: bool,
     config: &PcapStream,
     stats_collector: &stats::Collector,
     pcap_batch_sender: DebugSender<BoxedPcapBatch>,
@@ -3448,7 +3445,6 @@ fn build_pcap_assembler(
     );
     let pcap_assembler = PcapAssembler::new(
         id as u32,
-        enabled,
         config.total_buffer_size,
         config.buffer_size_per_flow,
         config.flush_interval,
@@ -3476,7 +3472,6 @@ fn build_dispatchers(
     stats_collector: Arc<stats::Collector>,
     config_handler: &ConfigHandler,
     queue_debugger: Arc<QueueDebugger>,
-    is_ce_version: bool,
     synchronizer: &Arc<Synchronizer>,
     npb_bps_limit: Arc<LeakyBucket>,
     npb_arp_table: Arc<NpbArpTable>,
@@ -3593,7 +3588,6 @@ fn build_dispatchers(
         id as u32,
     );
     let (pcap_assembler, mini_packet_sender) = build_pcap_assembler(
-        is_ce_version,
         &user_config.processors.packet.pcap_stream,
         &stats_collector,
         pcap_batch_sender.clone(),
@@ -3652,6 +3646,19 @@ fn build_dispatchers(
             controller_port: static_config.controller_port,
             controller_tls_port: static_config.controller_tls_port,
             libpcap_enabled: user_config.inputs.cbpf.special_network.libpcap.enabled,
+            libpcap_replay_file: user_config
+                .inputs
+                .cbpf
+                .special_network
+                .libpcap
+                .replay_file
+                .clone(),
+            libpcap_replay_pacing: user_config
+                .inputs
+                .cbpf
+                .special_network
+                .libpcap
+                .replay_pacing,
             snap_len: dispatcher_config.capture_packet_size as usize,
             dpdk_source: dispatcher_config.dpdk_source,
             dispatcher_queue: dispatcher_config.dispatcher_queue,
@@ -3699,7 +3706,13 @@ fn build_dispatchers(
                 .vlan_pcp_in_physical_mirror_traffic,
         )
         .tap_typer(tap_typer.clone())
-        .analyzer_dedup_disabled(user_config.inputs.cbpf.tunning.dispatcher_queue_enabled)
+        .analyzer_dedup_disabled(
+            user_config
+                .inputs
+                .cbpf
+                .physical_mirror
+                .packet_dedup_disabled,
+        )
         .flow_output_queue(flow_sender.clone())
         .l7_stats_output_queue(l7_stats_sender.clone())
         .log_output_queue(log_sender.clone())